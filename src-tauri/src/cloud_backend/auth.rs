@@ -8,14 +8,29 @@
 //!
 //! 所有命令返回 [`Result<T, String>`]（边界转换）以兼容 Tauri IPC。
 //! 内部统一返回 [`crate::cloud_backend::Result<T>`]。
+//!
+//! 携带 token 的请求收到 401/403 时发出 [`AUTH_EXPIRED_EVENT`]，前端据此提示重新登录。
 
 use serde::{Deserialize, Serialize};
-use tauri::AppHandle;
+use tauri::{AppHandle, Emitter};
 
-use crate::cloud_backend::client::{ensure_success, http_client, CloudBackendError, CbResult};
+use crate::cloud_backend::client::{
+    ensure_success, http_client, request_error, CloudBackendError, CbResult,
+};
 use crate::cloud_backend::config::api_url;
 use crate::core::secure_store;
 
+/// token 过期或被拒绝时发出的事件（载荷为 HTTP 状态码）
+pub const AUTH_EXPIRED_EVENT: &str = "auth-expired";
+
+/// 携带 token 的请求失败时的边界转换：鉴权失败额外通知前端
+pub(crate) fn token_request_error(app: &AppHandle, err: CloudBackendError) -> String {
+    if let CloudBackendError::AuthExpired(status) = err {
+        let _ = app.emit(AUTH_EXPIRED_EVENT, status);
+    }
+    err.to_string()
+}
+
 /// 云端登录成功响应
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -34,7 +49,11 @@ pub struct LoginResponse {
 
 /// 将用户头像同步至云端
 #[tauri::command]
-pub async fn sync_avatar_to_backend(token: String, avatar_data: String) -> Result<(), String> {
+pub async fn sync_avatar_to_backend(
+    app: AppHandle,
+    token: String,
+    avatar_data: String,
+) -> Result<(), String> {
     let client = http_client().map_err(|e| e.to_string())?;
     let res = client
         .post(api_url("/update-avatar"))
@@ -42,8 +61,10 @@ pub async fn sync_avatar_to_backend(token: String, avatar_data: String) -> Resul
         .json(&serde_json::json!({ "avatar": avatar_data }))
         .send()
         .await
-        .map_err(|e| e.to_string())?;
-    ensure_success(res).await.map_err(|e| e.to_string())?;
+        .map_err(|e| request_error(e).to_string())?;
+    ensure_success(res)
+        .await
+        .map_err(|e| token_request_error(&app, e))?;
     Ok(())
 }
 
@@ -63,8 +84,12 @@ pub async fn login_to_backend(
         }))
         .send()
         .await
-        .map_err(|e| e.to_string())?;
-    let resp = ensure_success(res).await.map_err(|e| e.to_string())?;
+        .map_err(|e| request_error(e).to_string())?;
+    // 登录接口的 401/403 表示凭据错误，而不是 token 过期
+    let resp = ensure_success(res).await.map_err(|e| match e {
+        CloudBackendError::AuthExpired(_) => "用户名或密码错误".to_string(),
+        e => e.to_string(),
+    })?;
     let user_data: LoginResponse = resp.json().await.map_err(|e| e.to_string())?;
 
     // 持久化 token 到 keyring（不写 localStorage）
@@ -91,22 +116,27 @@ pub async fn register_to_backend(
         }))
         .send()
         .await
-        .map_err(|e| e.to_string())?;
+        .map_err(|e| request_error(e).to_string())?;
     ensure_success(res).await.map_err(|e| e.to_string())?;
     Ok("注册成功".to_string())
 }
 
 /// 校验 token 有效性，返回当前用户信息
 #[tauri::command]
-pub async fn validate_token(token: String) -> std::result::Result<LoginResponse, String> {
+pub async fn validate_token(
+    app: AppHandle,
+    token: String,
+) -> std::result::Result<LoginResponse, String> {
     let client = http_client().map_err(|e| e.to_string())?;
     let res = client
         .get(api_url("/validate"))
         .header("Authorization", format!("Bearer {}", token))
         .send()
         .await
-        .map_err(|e| e.to_string())?;
-    let resp = ensure_success(res).await.map_err(|e| e.to_string())?;
+        .map_err(|e| request_error(e).to_string())?;
+    let resp = ensure_success(res)
+        .await
+        .map_err(|e| token_request_error(&app, e))?;
     resp.json::<LoginResponse>().await.map_err(|e| e.to_string())
 }
 
@@ -133,5 +163,9 @@ pub fn to_user_err<T>(r: CbResult<T>) -> std::result::Result<T, String> {
         }
         CloudBackendError::Request(_) => "网络异常，请检查网络或代理设置".to_string(),
         CloudBackendError::ClientBuild(_) => "本地 HTTP 客户端初始化失败".to_string(),
+        e @ (CloudBackendError::TlsConfig(_)
+        | CloudBackendError::TlsHandshake(_)
+        | CloudBackendError::AuthExpired(_)
+        | CloudBackendError::PayloadTooLarge) => e.to_string(),
     })
 }
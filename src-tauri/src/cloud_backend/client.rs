@@ -1,7 +1,11 @@
 //! 云端后端 HTTP 客户端与错误类型
 //!
 //! 所有 Tauri 命令都应通过 [`http_client`] 拿客户端，禁止散落构造 [`reqwest::Client`]。
+//! 配置了双向 TLS 证书（见 [`SyncTlsConfig`]）时，客户端会携带客户端证书并信任额外的 CA。
+//! 客户端构造一次后缓存，仅在保存设置时重建，请求路径上不再读取配置文件与证书。
 
+use crate::cloud_backend::config::SyncTlsConfig;
+use std::sync::Mutex;
 use std::time::Duration;
 use thiserror::Error;
 
@@ -14,31 +18,158 @@ pub enum CloudBackendError {
     #[error("网络请求失败: {0}")]
     Request(#[from] reqwest::Error),
 
+    #[error("TLS 证书配置错误: {0}")]
+    TlsConfig(String),
+
+    #[error("TLS 握手失败（请检查客户端证书与 CA 配置）: {0}")]
+    TlsHandshake(String),
+
+    #[error("登录状态已失效（HTTP {0}），请重新登录")]
+    AuthExpired(u16),
+
+    #[error("请求数据过大（HTTP 413），请尝试分批同步")]
+    PayloadTooLarge,
+
     #[error("服务端返回 HTTP {status}: {message}")]
     Server { status: u16, message: String },
 }
 
 pub type CbResult<T> = std::result::Result<T, CloudBackendError>;
 
-/// 构造一个带超时配置的 reqwest 客户端（单例式使用）
+/// 缓存的客户端：首次使用时按配置文件构造，保存设置时由 [`set_http_client`] 替换
+static CLIENT: Mutex<Option<reqwest::Client>> = Mutex::new(None);
+
+/// 取共享的云端客户端（内部为 Arc，共享同一连接池）
+pub fn http_client() -> CbResult<reqwest::Client> {
+    let mut cached = CLIENT.lock().unwrap();
+    if let Some(client) = cached.as_ref() {
+        return Ok(client.clone());
+    }
+    let client = build_client(&crate::commands::config::sync_tls_config())?;
+    *cached = Some(client.clone());
+    Ok(client)
+}
+
+/// 保存设置后替换缓存的客户端
+pub fn set_http_client(client: reqwest::Client) {
+    *CLIENT.lock().unwrap() = Some(client);
+}
+
+/// 构造一个带超时配置的 reqwest 客户端
 ///
 /// - 连接超时：5s
 /// - 请求总超时：15s
 /// - User-Agent：固定标识
-pub fn http_client() -> CbResult<reqwest::Client> {
-    reqwest::Client::builder()
+/// - 双向 TLS：按设置加载客户端证书 / 私钥与额外 CA
+pub fn build_client(config: &SyncTlsConfig) -> CbResult<reqwest::Client> {
+    let (identity, ca_certs) = load_tls(config)?;
+    let mut builder = reqwest::Client::builder()
         .connect_timeout(Duration::from_secs(5))
         .timeout(Duration::from_secs(15))
         .user_agent("AIO-Desktop/0.4 (cloud-backend)")
+        .tls_certs_merge(ca_certs);
+    if let Some(identity) = identity {
+        builder = builder.identity(identity);
+    }
+    builder
         .build()
         .map_err(|e| CloudBackendError::ClientBuild(e.to_string()))
 }
 
-/// 把非 2xx 响应统一翻译为 [`CloudBackendError::Server`]
+/// 读取 PEM 文件，错误信息带上用途与路径
+fn read_pem(path: &str, what: &str) -> CbResult<Vec<u8>> {
+    std::fs::read(path)
+        .map_err(|e| CloudBackendError::TlsConfig(format!("无法读取{} {}: {}", what, path, e)))
+}
+
+/// 加载双向 TLS 所需的客户端身份与额外 CA 证书；未配置的项返回空
+fn load_tls(
+    config: &SyncTlsConfig,
+) -> CbResult<(Option<reqwest::Identity>, Vec<reqwest::Certificate>)> {
+    let identity = match (config.client_cert.as_str(), config.client_key.as_str()) {
+        ("", "") => None,
+        ("", _) | (_, "") => {
+            return Err(CloudBackendError::TlsConfig(
+                "客户端证书与私钥需同时配置".to_string(),
+            ))
+        }
+        (cert, key) => {
+            // rustls 后端要求证书与私钥位于同一份 PEM 中
+            let mut pem = read_pem(cert, "客户端证书")?;
+            pem.push(b'\n');
+            pem.extend(read_pem(key, "客户端私钥")?);
+            let identity = reqwest::Identity::from_pem(&pem).map_err(|e| {
+                CloudBackendError::TlsConfig(format!(
+                    "客户端证书或私钥格式无效: {}",
+                    error_chain(&e)
+                ))
+            })?;
+            Some(identity)
+        }
+    };
+    let ca_certs = match config.ca_cert.as_str() {
+        "" => Vec::new(),
+        path => {
+            let certs = reqwest::Certificate::from_pem_bundle(&read_pem(path, "CA 证书")?)
+                .map_err(|e| {
+                    CloudBackendError::TlsConfig(format!(
+                        "CA 证书格式无效 {}: {}",
+                        path,
+                        error_chain(&e)
+                    ))
+                })?;
+            if certs.is_empty() {
+                return Err(CloudBackendError::TlsConfig(format!(
+                    "CA 证书文件中没有证书: {}",
+                    path
+                )));
+            }
+            certs
+        }
+    };
+    Ok((identity, ca_certs))
+}
+
+/// 拼接错误及其全部 source，reqwest 的顶层信息通常只有 "error sending request"
+fn error_chain(err: &dyn std::error::Error) -> String {
+    let mut parts = vec![err.to_string()];
+    let mut source = err.source();
+    while let Some(e) = source {
+        parts.push(e.to_string());
+        source = e.source();
+    }
+    parts.dedup();
+    parts.join(": ")
+}
+
+/// 把发送请求时的错误分类：TLS 握手 / 证书问题单独报告，其余归为 [`CloudBackendError::Request`]
+pub fn request_error(err: reqwest::Error) -> CloudBackendError {
+    let chain = error_chain(&err);
+    let lower = chain.to_lowercase();
+    let is_tls = err.is_connect()
+        && ["certificate", "handshake", "tls", "alert"]
+            .iter()
+            .any(|k| lower.contains(k));
+    if is_tls {
+        CloudBackendError::TlsHandshake(chain)
+    } else {
+        CloudBackendError::Request(err)
+    }
+}
+
+/// 把非 2xx 响应统一翻译为 [`CloudBackendError`]：401/403 为 [`CloudBackendError::AuthExpired`]，
+/// 413 为 [`CloudBackendError::PayloadTooLarge`]，其余为 [`CloudBackendError::Server`]
 pub async fn ensure_success(resp: reqwest::Response) -> CbResult<reqwest::Response> {
     let status = resp.status();
     if status.is_success() {
         Ok(resp)
+    } else if matches!(
+        status,
+        reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN
+    ) {
+        Err(CloudBackendError::AuthExpired(status.as_u16()))
+    } else if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE {
+        Err(CloudBackendError::PayloadTooLarge)
     } else {
         let body = resp.text().await.unwrap_or_default();
         // 截断长 body 避免日志/前端被巨型响应撑爆
@@ -1,9 +1,10 @@
 //! 云端后端配置层
 //!
 //! 唯一负责 base URL 与端点路径定义。修改 host / 切换 staging 环境仅需改此处。
+//! 用户在设置中填写的同步服务器地址（`syncServerUrl`）优先于环境变量与默认值。
 
 use std::env;
-use std::sync::OnceLock;
+use std::sync::{Mutex, OnceLock};
 
 /// 默认生产环境 base URL（HTTPS）
 const DEFAULT_BASE_URL: &str = "https://localhost:8443";
@@ -14,9 +15,15 @@ const ENV_BASE_URL: &str = "AIO_CLOUD_BACKEND_URL";
 /// API 路径前缀（与后端 Java 服务约定）
 pub const API_PREFIX: &str = "/api/auth";
 
+/// 同步相关端点的路径前缀
+pub const SYNC_API_PREFIX: &str = "/api/sync";
+
 /// 缓存首次校验后的 base URL
 static BASE_URL: OnceLock<String> = OnceLock::new();
 
+/// 缓存设置中的 `syncServerUrl`（空字符串表示未配置）：首次使用时读配置文件，保存设置时更新
+static SERVER_URL: Mutex<Option<String>> = Mutex::new(None);
+
 /// 启动时校验 + 缓存的 base URL
 /// - 优先读 `AIO_CLOUD_BACKEND_URL` 环境变量
 /// - 兜底用 `DEFAULT_BASE_URL`
@@ -37,6 +44,50 @@ pub fn base_url() -> &'static str {
     })
 }
 
+/// 校验用户填写的服务器地址：必须是带主机名的 http(s) URL，且不含查询串 / 片段。
+/// 返回去掉末尾 `/` 的规范形式
+pub fn validate_base_url(raw: &str) -> Result<String, String> {
+    let raw = raw.trim();
+    let parsed = url::Url::parse(raw).map_err(|e| format!("同步服务器地址无效: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!(
+            "同步服务器地址必须以 http:// 或 https:// 开头: {}",
+            raw
+        ));
+    }
+    if parsed.host_str().map_or(true, str::is_empty) {
+        return Err(format!("同步服务器地址缺少主机名: {}", raw));
+    }
+    if parsed.query().is_some() || parsed.fragment().is_some() {
+        return Err(format!("同步服务器地址不能包含查询参数或片段: {}", raw));
+    }
+    Ok(raw.trim_end_matches('/').to_string())
+}
+
+/// 双向 TLS 配置（各项为 PEM 文件路径，空字符串表示未配置）
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncTlsConfig {
+    pub client_cert: String,
+    pub client_key: String,
+    pub ca_cert: String,
+}
+
+/// 保存设置后更新缓存的 `syncServerUrl`
+pub fn set_server_url(url: String) {
+    *SERVER_URL.lock().unwrap() = Some(url);
+}
+
+/// 当前生效的 base URL：设置中的 `syncServerUrl` 优先，否则为 [`base_url`]
+fn effective_base_url() -> String {
+    let mut cached = SERVER_URL.lock().unwrap();
+    let configured = cached.get_or_insert_with(crate::commands::config::sync_server_url);
+    if configured.is_empty() {
+        base_url().to_string()
+    } else {
+        configured.clone()
+    }
+}
+
 /// 拼接完整 API URL
 ///
 /// # Example
@@ -45,12 +96,21 @@ pub fn base_url() -> &'static str {
 /// // => "https://localhost:8443/api/auth/login"
 /// ```
 pub fn api_url(path: &str) -> String {
+    join_url(&effective_base_url(), API_PREFIX, path)
+}
+
+/// 拼接同步端点的完整 URL（`{base}/api/sync{path}`）
+pub fn sync_api_url(path: &str) -> String {
+    join_url(&effective_base_url(), SYNC_API_PREFIX, path)
+}
+
+fn join_url(base: &str, prefix: &str, path: &str) -> String {
     let normalized = if path.starts_with('/') {
         path.to_string()
     } else {
         format!("/{}", path)
     };
-    format!("{}{}{}", base_url(), API_PREFIX, normalized)
+    format!("{}{}{}", base, prefix, normalized)
 }
 
 #[cfg(test)]
@@ -64,7 +124,30 @@ mod tests {
 
     #[test]
     fn api_url_joins_correctly() {
-        assert_eq!(api_url("/login"), format!("{}{}/login", base_url(), API_PREFIX));
-        assert_eq!(api_url("login"), format!("{}{}/login", base_url(), API_PREFIX));
+        let base = "https://sync.example.com";
+        assert_eq!(
+            join_url(base, API_PREFIX, "/login"),
+            format!("{}{}/login", base, API_PREFIX)
+        );
+        assert_eq!(
+            join_url(base, API_PREFIX, "login"),
+            format!("{}{}/login", base, API_PREFIX)
+        );
+        assert_eq!(
+            join_url(base, SYNC_API_PREFIX, "topics"),
+            format!("{}{}/topics", base, SYNC_API_PREFIX)
+        );
+    }
+
+    #[test]
+    fn validates_configured_server_url() {
+        assert_eq!(
+            validate_base_url(" https://sync.example.com/ ").as_deref(),
+            Ok("https://sync.example.com")
+        );
+        assert!(validate_base_url("http://192.168.1.10:8080").is_ok());
+        assert!(validate_base_url("ftp://sync.example.com").is_err());
+        assert!(validate_base_url("sync.example.com").is_err());
+        assert!(validate_base_url("https://sync.example.com/?a=1").is_err());
     }
 }
@@ -4,7 +4,7 @@
 //! 非本地 Rust 后端也非 LLM Provider）的 HTTP 调用。
 //!
 //! ## 设计目标
-//! - **唯一入口**：base URL 仅在 [`config`] 模块维护，支持 `AIO_CLOUD_BACKEND_URL` 环境变量覆盖
+//! - **唯一入口**：base URL 仅在 [`config`] 模块维护，支持设置项 `syncServerUrl` 与 `AIO_CLOUD_BACKEND_URL` 环境变量覆盖
 //! - **统一超时**：所有请求走 [`client::http_client`]，禁止命令层自建 `reqwest::Client`
 //! - **统一错误**：所有错误归并为 [`client::CloudBackendError`]，命令层在边界做 `to_string()`
 //! - **可扩展**：新增端点时，在 [`auth`]（或新增 `profile.rs`/`sync.rs`）中加函数，并到 [`mod.rs`] 暴露
@@ -18,12 +18,17 @@
 //! | `sync_avatar_to_backend` | POST | `/api/auth/update-avatar` | 同步头像到云端 |
 //! | `logout_clear` | - | - | 清本地 keyring 中 token |
 //! | `read_auth_token` | - | - | 读 keyring 中 token |
+//! | `list_remote_topics` | GET | `/api/sync/topics` | 浏览服务器上的话题列表（不写入本地） |
+//! | `pull_topics` | POST | `/api/sync/topics/pull` | 只拉取指定话题及其消息写入本地 |
 //!
 //! ## 安全约束
-//! - base URL 强制 HTTPS（`config::base_url` 启动时校验）
+//! - 默认 / 环境变量 base URL 强制 HTTPS（`config::base_url` 启动时校验）；
+//!   设置中的 `syncServerUrl` 由用户显式填写，保存时仅校验为合法的 http(s) URL
+//! - 可选双向 TLS：客户端证书 / 私钥与额外 CA 由设置项提供，保存时校验并重建客户端（[`client::build_client`]）
 //! - Token 仅在系统钥匙串中持久化（[`crate::core::secure_store`]），不写 localStorage
 //! - 错误信息做脱敏后回传前端
 
 pub mod auth;
 pub mod client;
 pub mod config;
+pub mod sync;
@@ -0,0 +1,230 @@
+//! 云端后端 - 同步相关端点
+//!
+//! 端点清单：
+//! - `GET  /api/sync/topics`      — 服务器上的话题列表（只读浏览，不写入本地数据库）
+//! - `POST /api/sync/topics/pull` — 拉取指定话题及其消息，按助手分组返回
+//!
+//! 旧版本服务器没有同步端点，404 时返回明确的「不支持」错误。
+//!
+//! 拉取结果的写入规则：
+//! - 本地缺少的助手整条插入；已有助手保持本地设置不变
+//! - 话题按 id 更新名称与摘要；消息按 id 插入，已存在的消息不覆盖
+//! - 消息沿用服务器提供的时间（缺失时按远端顺序沿用相邻消息的时间），与本地消息按时间合并排序；
+//!   话题的 updated_at 取其最新消息的时间
+//! - 本地独有的消息不删除；远端附件路径指向另一台设备，不关联本地附件
+
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use tauri::AppHandle;
+
+use crate::cloud_backend::auth::token_request_error;
+use crate::cloud_backend::client::{ensure_success, http_client, request_error};
+use crate::cloud_backend::config::sync_api_url;
+use crate::commands::config::insert_message;
+use crate::core::models::{Assistant, Message};
+use crate::core::state::DbState;
+
+/// 服务器不支持同步端点时的错误
+fn unsupported(what: &str) -> String {
+    format!("同步服务器不支持{}（/api/sync 端点不存在）", what)
+}
+
+/// 服务器上一个话题的概要
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RemoteTopicSummary {
+    pub id: String,
+    pub name: String,
+    /// 服务器记录的最后修改时间
+    #[serde(default)]
+    pub updated_at: Option<String>,
+    #[serde(default)]
+    pub message_count: u64,
+}
+
+/// 列出服务器上的话题（不拉取消息、不修改本地数据）
+#[tauri::command]
+pub async fn list_remote_topics(
+    app: AppHandle,
+    token: String,
+) -> Result<Vec<RemoteTopicSummary>, String> {
+    let client = http_client().map_err(|e| e.to_string())?;
+    let res = client
+        .get(sync_api_url("/topics"))
+        .header("Authorization", format!("Bearer {}", token))
+        .send()
+        .await
+        .map_err(|e| request_error(e).to_string())?;
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(unsupported("浏览远端话题"));
+    }
+    let resp = ensure_success(res)
+        .await
+        .map_err(|e| token_request_error(&app, e))?;
+    resp.json::<Vec<RemoteTopicSummary>>()
+        .await
+        .map_err(|e| format!("解析远端话题列表失败: {}", e))
+}
+
+/// 服务器未提供时间的消息沿用前一条（开头几条沿用之后第一条）的时间，保持远端顺序；
+/// 全部缺失时保持为空，写库时取当前时间
+fn fill_missing_timestamps(history: &mut [Message]) {
+    let mut last = history.iter().find_map(|m| m.timestamp.clone());
+    for msg in history.iter_mut() {
+        match &msg.timestamp {
+            Some(timestamp) => last = Some(timestamp.clone()),
+            None => msg.timestamp = last.clone(),
+        }
+    }
+}
+
+/// 把拉取到的助手 / 话题 / 消息写入本地数据库（单个事务），只处理 `wanted` 中的话题，返回写入的话题数
+fn apply_pulled_topics(
+    conn: &rusqlite::Connection,
+    assistants: Vec<Assistant>,
+    wanted: &HashSet<String>,
+) -> Result<usize, String> {
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut applied = 0;
+    for assistant in assistants {
+        let topics: Vec<_> = assistant
+            .topics
+            .into_iter()
+            .filter(|t| wanted.contains(&t.id))
+            .collect();
+        if topics.is_empty() {
+            continue;
+        }
+        let mcp_ids_json =
+            serde_json::to_string(&assistant.mcp_server_ids).unwrap_or_else(|_| "[]".to_string());
+        let skill_ids_json =
+            serde_json::to_string(&assistant.skill_ids).unwrap_or_else(|_| "[]".to_string());
+        tx.execute(
+            "INSERT INTO assistants (id, name, prompt, model_id, mcp_server_ids, skill_ids)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(id) DO NOTHING",
+            params![
+                assistant.id,
+                assistant.name,
+                assistant.prompt,
+                assistant.model_id,
+                mcp_ids_json,
+                skill_ids_json
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+
+        for mut topic in topics {
+            tx.execute(
+                "INSERT INTO topics (id, assistant_id, name, summary, renamed, last_model_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                 ON CONFLICT(id) DO UPDATE SET name=?3, summary=?4, renamed=?5, last_model_id=COALESCE(?6, last_model_id)",
+                params![topic.id, assistant.id, topic.name, topic.summary, topic.renamed as i64, topic.last_model_id],
+            )
+            .map_err(|e| e.to_string())?;
+            fill_missing_timestamps(&mut topic.history);
+            for msg in topic.history.iter().filter(|m| m.id.is_some()) {
+                insert_message(&tx, &topic.id, msg)?;
+            }
+            tx.execute(
+                "UPDATE topics SET updated_at = COALESCE(
+                     (SELECT MAX(timestamp) FROM messages WHERE topic_id = ?1), CURRENT_TIMESTAMP)
+                 WHERE id = ?1",
+                [&topic.id],
+            )
+            .map_err(|e| e.to_string())?;
+            applied += 1;
+        }
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(applied)
+}
+
+/// 只拉取指定话题（及其所属助手与消息）写入本地，返回写入的话题数
+#[tauri::command]
+pub async fn pull_topics(
+    app: AppHandle,
+    state: tauri::State<'_, DbState>,
+    token: String,
+    topic_ids: Vec<String>,
+) -> Result<usize, String> {
+    if topic_ids.is_empty() {
+        return Ok(0);
+    }
+    let client = http_client().map_err(|e| e.to_string())?;
+    let res = client
+        .post(sync_api_url("/topics/pull"))
+        .header("Authorization", format!("Bearer {}", token))
+        .json(&serde_json::json!({ "topicIds": topic_ids }))
+        .send()
+        .await
+        .map_err(|e| request_error(e).to_string())?;
+    if res.status() == reqwest::StatusCode::NOT_FOUND {
+        return Err(unsupported("按话题拉取"));
+    }
+    let resp = ensure_success(res)
+        .await
+        .map_err(|e| token_request_error(&app, e))?;
+    let assistants: Vec<Assistant> = resp
+        .json()
+        .await
+        .map_err(|e| format!("解析拉取结果失败: {}", e))?;
+
+    let wanted: HashSet<String> = topic_ids.into_iter().collect();
+    let conn = state.0.lock().map_err(|e| e.to_string())?;
+    apply_pulled_topics(&conn, assistants, &wanted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::config::load_topic_history;
+    use serde_json::json;
+
+    #[test]
+    fn pulled_messages_keep_remote_time_and_order() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::core::db::init_schema(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO assistants (id, name, prompt) VALUES ('a1', '助手', '');
+             INSERT INTO topics (id, assistant_id, name) VALUES ('t1', 'a1', '话题');
+             INSERT INTO messages (id, topic_id, role, content, timestamp)
+             VALUES ('local', 't1', 'user', '\"本地\"', '2024-06-01 10:00:00');",
+        )
+        .unwrap();
+        let assistants: Vec<Assistant> = serde_json::from_value(json!([{
+            "id": "a1",
+            "name": "助手",
+            "prompt": "",
+            "topics": [{
+                "id": "t1",
+                "name": "话题",
+                "history": [
+                    { "id": "r1", "role": "user", "content": "远端提问" },
+                    { "id": "r2", "role": "assistant", "content": "远端回答", "timestamp": "2024-01-01T08:00:00Z" },
+                    { "id": "r3", "role": "user", "content": "追问" }
+                ]
+            }]
+        }]))
+        .unwrap();
+        let wanted = HashSet::from(["t1".to_string()]);
+        assert_eq!(apply_pulled_topics(&conn, assistants, &wanted).unwrap(), 1);
+
+        let ids: Vec<_> = load_topic_history(&conn, "t1")
+            .unwrap()
+            .into_iter()
+            .filter_map(|m| m.id)
+            .collect();
+        assert_eq!(ids, vec!["r1", "r2", "r3", "local"]);
+        let timestamp = |id: &str| -> String {
+            conn.query_row("SELECT timestamp FROM messages WHERE id = ?1", [id], |r| r.get(0))
+                .unwrap()
+        };
+        assert_eq!(timestamp("r1"), "2024-01-01 08:00:00");
+        assert_eq!(timestamp("r3"), "2024-01-01 08:00:00");
+        let updated_at: String = conn
+            .query_row("SELECT updated_at FROM topics WHERE id = 't1'", [], |r| r.get(0))
+            .unwrap();
+        assert_eq!(updated_at, "2024-06-01 10:00:00");
+    }
+}
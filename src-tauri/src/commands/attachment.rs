@@ -1,4 +1,5 @@
 use crate::core::models::{FileMeta, StoredAttachment};
+use crate::core::paths;
 use crate::core::state::DbState;
 use crate::utils::file_parser::{
     attachment_mime_type, extract_file_content, validate_attachment_path,
@@ -6,17 +7,14 @@ use crate::utils::file_parser::{
 use rusqlite::{params, Connection};
 use sha2::{Digest, Sha256};
 use std::path::PathBuf;
-use tauri::{AppHandle, Manager};
+use tauri::AppHandle;
 
 fn attachment_storage_path(
     app: &AppHandle,
     sha256: &str,
     extension: &str,
 ) -> Result<PathBuf, String> {
-    let dir = app
-        .path()
-        .app_data_dir()
-        .map_err(|e| e.to_string())?
+    let dir = paths::app_data_root(app)?
         .join("attachments")
         .join(&sha256[..2]);
     std::fs::create_dir_all(&dir).map_err(|e| e.to_string())?;
@@ -0,0 +1,283 @@
+//! 音频相关命令
+//!
+//! - `transcribe_audio`：把本地音频文件以 multipart 上传到 OpenAI 兼容的 `/audio/transcriptions`，返回转写文本
+//! - `synthesize_speech`：调用 `/audio/speech` 朗读文本，音频写入应用缓存目录并返回路径
+//!
+//! base URL 推导与聊天接口一致（见 [`api_base_url`]），请求走全局共享的 HTTP 客户端。
+//! 合成结果按 (模型, 音色, 格式, 文本) 的哈希缓存，重复朗读同一条消息不再请求；
+//! 缓存总量超过上限时按修改时间删除最旧的文件。
+
+use crate::commands::llm::{api_base_url, provider_error};
+use crate::core::state::HttpClientState;
+use crate::utils::file_parser::path_in_sandbox;
+use reqwest::multipart::{Form, Part};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use tauri::{AppHandle, Manager};
+
+/// 转写请求总超时（上传 + 服务端识别，长音频可能需要几分钟）
+const TRANSCRIBE_TIMEOUT: Duration = Duration::from_secs(300);
+/// 服务商单个音频文件的大小上限（OpenAI 为 25 MB）
+const MAX_TRANSCRIPTION_BYTES: u64 = 25 * 1024 * 1024;
+/// 语音合成请求总超时
+const SPEECH_TIMEOUT: Duration = Duration::from_secs(120);
+/// 语音缓存目录（位于应用缓存目录下）
+const SPEECH_CACHE_DIR: &str = "speech";
+/// 语音缓存总大小上限
+const MAX_SPEECH_CACHE_BYTES: u64 = 200 * 1024 * 1024;
+
+/// 支持转写的音频扩展名对应的 MIME 类型
+fn audio_mime_type(extension: &str) -> Option<&'static str> {
+    Some(match extension {
+        "mp3" | "mpga" | "mpeg" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "m4a" => "audio/mp4",
+        "mp4" => "video/mp4",
+        "ogg" => "audio/ogg",
+        "webm" => "audio/webm",
+        "flac" => "audio/flac",
+        _ => return None,
+    })
+}
+
+/// 语音转写：上传音频文件（mp3/wav/m4a 等）到 `/audio/transcriptions`，返回识别出的文本
+#[tauri::command]
+pub async fn transcribe_audio(
+    http: tauri::State<'_, HttpClientState>,
+    api_url: String,
+    api_key: String,
+    model: String,
+    path: String,
+) -> Result<String, String> {
+    let path_obj = Path::new(&path);
+    path_in_sandbox(path_obj).map_err(|e| format!("文件路径沙箱拒绝: {}", e))?;
+
+    let extension = path_obj
+        .extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_lowercase();
+    let mime = audio_mime_type(&extension).ok_or_else(|| {
+        format!(
+            "扩展名 {:?} 不支持转写（支持 mp3/wav/m4a/mp4/mpeg/mpga/ogg/webm/flac）",
+            extension
+        )
+    })?;
+
+    let size = tokio::fs::metadata(path_obj)
+        .await
+        .map_err(|e| e.to_string())?
+        .len();
+    if size > MAX_TRANSCRIPTION_BYTES {
+        return Err(format!(
+            "音频文件过大（{:.1} MB），超过服务商 {} MB 的上限，请压缩或分段后再转写",
+            size as f64 / (1024.0 * 1024.0),
+            MAX_TRANSCRIPTION_BYTES / (1024 * 1024)
+        ));
+    }
+
+    let bytes = tokio::fs::read(path_obj).await.map_err(|e| e.to_string())?;
+    let file_name = path_obj
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| format!("audio.{}", extension));
+    let part = Part::bytes(bytes)
+        .file_name(file_name)
+        .mime_str(mime)
+        .map_err(|e| e.to_string())?;
+    let form = Form::new()
+        .text("model", model)
+        .text("response_format", "json")
+        .part("file", part);
+
+    let response = http
+        .client()
+        .post(format!("{}/audio/transcriptions", api_base_url(&api_url)))
+        .timeout(TRANSCRIBE_TIMEOUT)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let status = response.status();
+    if status == reqwest::StatusCode::PAYLOAD_TOO_LARGE {
+        return Err("音频文件超过服务商允许的大小上限，请压缩或分段后再转写".to_string());
+    }
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(provider_error(status, &body));
+    }
+
+    let val: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+    val["text"]
+        .as_str()
+        .map(|t| t.trim().to_string())
+        .ok_or_else(|| "转写响应中缺少 text 字段".to_string())
+}
+
+/// 缓存文件名：(模型, 音色, 格式, 文本) 的 SHA-256 + 扩展名
+fn speech_cache_name(model: &str, voice: &str, format: &str, text: &str) -> String {
+    let mut hasher = Sha256::new();
+    for field in [model, voice, format, text] {
+        hasher.update(field.as_bytes());
+        hasher.update([0u8]);
+    }
+    format!("{:x}.{}", hasher.finalize(), format)
+}
+
+/// 缓存总量超过 `cap` 时需要删除的文件：按修改时间从旧到新，跳过 `keep`
+fn files_to_evict(
+    mut entries: Vec<(PathBuf, u64, SystemTime)>,
+    cap: u64,
+    keep: &Path,
+) -> Vec<PathBuf> {
+    let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+    entries.sort_by_key(|(_, _, modified)| *modified);
+    let mut evict = Vec::new();
+    for (path, size, _) in entries {
+        if total <= cap {
+            break;
+        }
+        if path == keep {
+            continue;
+        }
+        total -= size;
+        evict.push(path);
+    }
+    evict
+}
+
+/// 清理语音缓存，使总大小不超过上限（失败仅忽略）
+fn prune_speech_cache(dir: &Path, keep: &Path) {
+    let Ok(read_dir) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let entries = read_dir
+        .flatten()
+        .filter_map(|entry| {
+            let meta = entry.metadata().ok().filter(|m| m.is_file())?;
+            Some((entry.path(), meta.len(), meta.modified().ok()?))
+        })
+        .collect();
+    for path in files_to_evict(entries, MAX_SPEECH_CACHE_BYTES, keep) {
+        let _ = std::fs::remove_file(path);
+    }
+}
+
+/// 语音合成：POST `/audio/speech`，音频写入缓存目录并返回文件路径供前端播放。
+/// `format` 支持 mp3（默认）与 opus
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn synthesize_speech(
+    app: AppHandle,
+    http: tauri::State<'_, HttpClientState>,
+    api_url: String,
+    api_key: String,
+    model: String,
+    voice: String,
+    text: String,
+    format: Option<String>,
+) -> Result<String, String> {
+    let text = text.trim();
+    if text.is_empty() {
+        return Err("朗读内容为空".to_string());
+    }
+    let format = format.unwrap_or_else(|| "mp3".to_string()).to_lowercase();
+    if !matches!(format.as_str(), "mp3" | "opus") {
+        return Err(format!("不支持的音频格式: {}（支持 mp3 / opus）", format));
+    }
+
+    let dir = app
+        .path()
+        .app_cache_dir()
+        .map_err(|e| e.to_string())?
+        .join(SPEECH_CACHE_DIR);
+    let path = dir.join(speech_cache_name(&model, &voice, &format, text));
+
+    // 命中缓存：刷新修改时间，避免被当作最旧的文件清理
+    if std::fs::metadata(&path).is_ok_and(|m| m.len() > 0) {
+        if let Ok(file) = std::fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        return Ok(path.to_string_lossy().to_string());
+    }
+
+    let response = http
+        .client()
+        .post(format!("{}/audio/speech", api_base_url(&api_url)))
+        .timeout(SPEECH_TIMEOUT)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({
+            "model": model,
+            "voice": voice,
+            "input": text,
+            "response_format": format,
+        }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(provider_error(status, &body));
+    }
+    let bytes = response.bytes().await.map_err(|e| e.to_string())?;
+    if bytes.is_empty() {
+        return Err("服务商返回了空的音频数据".to_string());
+    }
+
+    // 先写临时文件再改名，避免中断时留下半截缓存
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| e.to_string())?;
+    let tmp = path.with_extension("part");
+    tokio::fs::write(&tmp, &bytes)
+        .await
+        .map_err(|e| e.to_string())?;
+    tokio::fs::rename(&tmp, &path)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let (prune_dir, keep) = (dir.clone(), path.clone());
+    let _ = tokio::task::spawn_blocking(move || prune_speech_cache(&prune_dir, &keep)).await;
+    Ok(path.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_oldest_files_until_under_cap() {
+        let t = |secs| SystemTime::UNIX_EPOCH + Duration::from_secs(secs);
+        let entries = vec![
+            (PathBuf::from("new"), 40, t(30)),
+            (PathBuf::from("old"), 40, t(10)),
+            (PathBuf::from("mid"), 40, t(20)),
+        ];
+        assert_eq!(
+            files_to_evict(entries.clone(), 80, Path::new("new")),
+            vec![PathBuf::from("old")]
+        );
+        // 刚写入的文件即使最旧也保留
+        assert_eq!(
+            files_to_evict(entries.clone(), 40, Path::new("old")),
+            vec![PathBuf::from("mid"), PathBuf::from("new")]
+        );
+        assert!(files_to_evict(entries, 120, Path::new("new")).is_empty());
+    }
+
+    #[test]
+    fn cache_name_depends_on_every_field() {
+        let base = speech_cache_name("tts-1", "alloy", "mp3", "你好");
+        assert!(base.ends_with(".mp3"));
+        assert_ne!(base, speech_cache_name("tts-1", "nova", "mp3", "你好"));
+        assert_ne!(base, speech_cache_name("tts-1", "alloy", "mp3", "你好！"));
+        assert_ne!(
+            speech_cache_name("a", "bc", "mp3", ""),
+            speech_cache_name("ab", "c", "mp3", "")
+        );
+    }
+}
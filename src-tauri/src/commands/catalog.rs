@@ -17,6 +17,7 @@ use std::fs;
 use std::path::PathBuf;
 use std::time::Duration;
 use serde::Serialize;
+use crate::core::state::HttpClientState;
 use tauri::{Manager, Emitter};
 
 const DEFAULT_CATALOG_URL: &str =
@@ -90,7 +91,7 @@ fn parse_catalog_meta(json: &str) -> (Option<String>, Option<String>, usize, usi
 }
 
 fn appdata_catalog_path(app: &tauri::AppHandle) -> Option<PathBuf> {
-    let dir = app.path().app_data_dir().ok()?;
+    let dir = crate::core::paths::app_data_root(app).ok()?;
     if !dir.exists() {
         let _ = fs::create_dir_all(&dir);
     }
@@ -166,6 +167,71 @@ pub fn load_models_catalog_full(app: tauri::AppHandle) -> Result<CatalogResponse
     })
 }
 
+/// 在 catalog 中按 id 或 aliases（大小写不敏感）查找模型；
+/// 兼容 "provider/model" 形式的模型名（取最后一段再匹配一次）
+fn find_catalog_model(catalog_json: &str, model: &str) -> Option<serde_json::Value> {
+    let v: serde_json::Value = serde_json::from_str(catalog_json).ok()?;
+    let models = v.get("models")?.as_array()?;
+    let matches = |m: &serde_json::Value, name: &str| {
+        let id_hit = m
+            .get("id")
+            .and_then(|x| x.as_str())
+            .is_some_and(|id| id.eq_ignore_ascii_case(name));
+        id_hit
+            || m.get("aliases")
+                .and_then(|x| x.as_array())
+                .is_some_and(|aliases| {
+                    aliases
+                        .iter()
+                        .filter_map(|a| a.as_str())
+                        .any(|a| a.eq_ignore_ascii_case(name))
+                })
+    };
+    let short = model.rsplit('/').next().unwrap_or(model);
+    models
+        .iter()
+        .find(|m| matches(m, model))
+        .or_else(|| models.iter().find(|m| matches(m, short)))
+        .cloned()
+}
+
+/// 查询模型的上下文窗口长度（token 数）；catalog 未收录时返回 None
+pub fn catalog_context_window(app: &tauri::AppHandle, model: &str) -> Option<u64> {
+    let resp = load_models_catalog_full(app.clone()).ok()?;
+    find_catalog_model(&resp.json, model)?
+        .get("contextWindow")
+        .and_then(|x| x.as_u64())
+        .filter(|n| *n > 0)
+}
+
+/// catalog 中的模型价格（美元 / 百万 token）
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ModelPricing {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// 读取 catalog 模型条目的 `pricing.input` / `pricing.output`，缺任一项视为未知
+fn parse_pricing(model: &serde_json::Value) -> Option<ModelPricing> {
+    let pricing = model.get("pricing")?;
+    let price = |key: &str| {
+        pricing
+            .get(key)
+            .and_then(|x| x.as_f64())
+            .filter(|p| p.is_finite() && *p >= 0.0)
+    };
+    Some(ModelPricing {
+        input: price("input")?,
+        output: price("output")?,
+    })
+}
+
+/// 查询模型的 token 单价；catalog 未收录或无价格时返回 None
+pub fn catalog_pricing(app: &tauri::AppHandle, model: &str) -> Option<ModelPricing> {
+    let resp = load_models_catalog_full(app.clone()).ok()?;
+    parse_pricing(&find_catalog_model(&resp.json, model)?)
+}
+
 /// 校验 URL 是否指向白名单 host（H7 SSRF 防护）
 fn validate_catalog_url(target: &str) -> Result<(), String> {
     let parsed = url::Url::parse(target).map_err(|e| format!("URL 解析失败: {}", e))?;
@@ -202,15 +268,12 @@ pub async fn update_models_catalog(
     }
     let started = std::time::Instant::now();
 
-    let client = reqwest::Client::builder()
-        .user_agent("AIO-Desktop/0.4 (aio-models-data-updater)")
-        .timeout(Duration::from_secs(30))
-        .connect_timeout(Duration::from_secs(5))
-        .build()
-        .map_err(|e| format!("构造 HTTP 客户端失败: {}", e))?;
-
-    let resp = client
+    let resp = app
+        .state::<HttpClientState>()
+        .0
         .get(&target_url)
+        .header(reqwest::header::USER_AGENT, "AIO-Desktop/0.4 (aio-models-data-updater)")
+        .timeout(Duration::from_secs(30))
         .send()
         .await
         .map_err(|e| format!("拉取失败: {}", e))?;
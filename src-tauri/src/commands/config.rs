@@ -1,20 +1,103 @@
+use crate::cloud_backend::config::SyncTlsConfig;
+use crate::core::blobs::{
+    blob_dir, blob_refs_where, cleanup_blobs, externalize_images, restore_images, sweep_blobs,
+};
 use crate::core::models::*;
+use crate::core::paths;
 use crate::core::secure_store;
 use crate::core::state::DbState;
+use crate::utils::markdown::finalize_display_text;
 use crate::commands::attachment::{
     cleanup_attachment_ids, load_message_attachments, sync_message_attachments,
 };
 use base64::{engine::general_purpose, Engine as _};
-use rusqlite::params;
+use rusqlite::{params, OptionalExtension};
 use std::fs; // 导入标准库文件系统模块
-use tauri::{AppHandle, Manager};
+use tauri::AppHandle;
 
 /// 应用配置文件持久化结构：api_key 不入库，统一存到系统钥匙串
-#[derive(serde::Serialize, serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize, Default)]
 struct AppConfigDisk {
     api_url: String,
     default_model: String,
     local_model_path: String,
+    #[serde(default)]
+    keep_server_on_exit: bool,
+    #[serde(default)]
+    data_dir: String,
+    #[serde(default)]
+    local_max_ctx_size: u32,
+    #[serde(default)]
+    llama_download_url: String,
+    #[serde(default)]
+    auto_start_local_server: bool,
+    #[serde(default)]
+    dedup_stream_events: bool,
+    #[serde(default)]
+    sync_server_url: String,
+    #[serde(default)]
+    sync_client_cert_path: String,
+    #[serde(default)]
+    sync_client_key_path: String,
+    #[serde(default)]
+    sync_ca_cert_path: String,
+    #[serde(default)]
+    allow_private_network_fetch: bool,
+    #[serde(default)]
+    max_extracted_kb: u32,
+}
+
+impl AppConfigDisk {
+    /// 合并钥匙串中的 api_key，得到前端使用的完整配置
+    fn into_app_config(self, api_key: String) -> AppConfig {
+        AppConfig {
+            api_url: self.api_url,
+            api_key,
+            default_model: self.default_model,
+            local_model_path: self.local_model_path,
+            keep_server_on_exit: self.keep_server_on_exit,
+            data_dir: self.data_dir,
+            local_max_ctx_size: self.local_max_ctx_size,
+            llama_download_url: self.llama_download_url,
+            auto_start_local_server: self.auto_start_local_server,
+            dedup_stream_events: self.dedup_stream_events,
+            sync_server_url: self.sync_server_url,
+            sync_client_cert_path: self.sync_client_cert_path,
+            sync_client_key_path: self.sync_client_key_path,
+            sync_ca_cert_path: self.sync_ca_cert_path,
+            allow_private_network_fetch: self.allow_private_network_fetch,
+            max_extracted_kb: self.max_extracted_kb,
+        }
+    }
+}
+
+/// 丢弃 api_key，其余字段原样落盘
+impl From<AppConfig> for AppConfigDisk {
+    fn from(config: AppConfig) -> Self {
+        AppConfigDisk {
+            api_url: config.api_url,
+            default_model: config.default_model,
+            local_model_path: config.local_model_path,
+            keep_server_on_exit: config.keep_server_on_exit,
+            data_dir: config.data_dir,
+            local_max_ctx_size: config.local_max_ctx_size,
+            llama_download_url: config.llama_download_url,
+            auto_start_local_server: config.auto_start_local_server,
+            dedup_stream_events: config.dedup_stream_events,
+            sync_server_url: config.sync_server_url,
+            sync_client_cert_path: config.sync_client_cert_path,
+            sync_client_key_path: config.sync_client_key_path,
+            sync_ca_cert_path: config.sync_ca_cert_path,
+            allow_private_network_fetch: config.allow_private_network_fetch,
+            max_extracted_kb: config.max_extracted_kb,
+        }
+    }
+}
+
+/// 读取落盘配置；文件不存在或无法解析时返回 None
+fn read_disk_config() -> Option<AppConfigDisk> {
+    let content = fs::read_to_string(paths::config_file().ok()?).ok()?;
+    serde_json::from_str(&content).ok()
 }
 
 /// 保存应用程序通用配置
@@ -29,33 +112,51 @@ pub fn save_app_config(app: AppHandle, config: AppConfig) -> Result<(), String>
         let _ = secure_store::delete(&app, secure_store::accounts::APP_API_KEY);
     }
 
-    // 1. 获取操作系统的用户配置目录 (如 Windows 的 AppData/Roaming 或 Linux 的 ~/.config)
-    let mut path = dirs::config_dir().ok_or_else(|| "无法获取系统配置目录".to_string())?;
-
-    // 2. 在配置目录下创建 "AIO" 文件夹
-    path.push("com.loch.aio");
-    if !path.exists() {
-        fs::create_dir_all(&path).map_err(|e| e.to_string())?;
+    // 自定义数据目录：保存前校验可写（重启后生效）
+    let data_dir = config.data_dir.trim().to_string();
+    if !data_dir.is_empty() {
+        paths::check_writable(std::path::Path::new(&data_dir))?;
     }
 
-    // 3. 指定配置文件名为 config.json
-    path.push("config.json");
+    // 同步服务器地址：保存前校验为合法的 http(s) URL
+    let sync_server_url = match config.sync_server_url.trim() {
+        "" => String::new(),
+        raw => crate::cloud_backend::config::validate_base_url(raw)?,
+    };
+    // 双向 TLS 证书：保存前确认文件可读且能解析，落盘成功后替换缓存的云端客户端
+    let sync_tls = SyncTlsConfig {
+        client_cert: config.sync_client_cert_path.trim().to_string(),
+        client_key: config.sync_client_key_path.trim().to_string(),
+        ca_cert: config.sync_ca_cert_path.trim().to_string(),
+    };
+    let sync_client =
+        crate::cloud_backend::client::build_client(&sync_tls).map_err(|e| e.to_string())?;
+
+    // 配置文件位于系统配置目录（如 Windows 的 AppData/Roaming 或 Linux 的 ~/.config）下的
+    // com.loch.aio/config.json；便携模式（AIO_DATA_DIR）下位于数据目录
+    let path = paths::config_file()?;
 
+    let llama_download_url = config.llama_download_url.trim().to_string();
     let disk = AppConfigDisk {
-        api_url: config.api_url,
-        default_model: config.default_model,
-        local_model_path: config.local_model_path,
+        data_dir,
+        llama_download_url,
+        sync_server_url: sync_server_url.clone(),
+        sync_client_cert_path: sync_tls.client_cert,
+        sync_client_key_path: sync_tls.client_key,
+        sync_ca_cert_path: sync_tls.ca_cert,
+        ..AppConfigDisk::from(config)
     };
     let json = serde_json::to_string_pretty(&disk).map_err(|e| e.to_string())?;
     fs::write(path, json).map_err(|e| e.to_string())?;
+    crate::cloud_backend::config::set_server_url(sync_server_url);
+    crate::cloud_backend::client::set_http_client(sync_client);
     Ok(())
 }
 
 /// 读取应用程序通用配置
 #[tauri::command]
 pub fn load_app_config(app: AppHandle) -> Result<AppConfig, String> {
-    let mut path = dirs::config_dir().ok_or("无法获取配置目录")?;
-    path.push("com.loch.aio/config.json");
+    let path = paths::config_file()?;
 
     // 优先尝试 v2 schema（不含 api_key 字段）
     if path.exists() {
@@ -64,43 +165,78 @@ pub fn load_app_config(app: AppHandle) -> Result<AppConfig, String> {
                 let api_key = secure_store::get(&app, secure_store::accounts::APP_API_KEY)
                     .map_err(|e| e.to_string())?
                     .unwrap_or_default();
-                return Ok(AppConfig {
-                    api_url: disk.api_url,
-                    api_key,
-                    default_model: disk.default_model,
-                    local_model_path: disk.local_model_path,
-                });
+                return Ok(disk.into_app_config(api_key));
             }
             // 兼容旧 schema（含明文 api_key）：读出后迁出到 keyring
-            if let Ok(legacy) = serde_json::from_str::<AppConfig>(&content) {
+            if let Ok(mut legacy) = serde_json::from_str::<AppConfig>(&content) {
                 if !legacy.api_key.is_empty() {
                     let _ = secure_store::set(&app, secure_store::accounts::APP_API_KEY, &legacy.api_key);
                 }
-                let mut disk = AppConfigDisk {
-                    api_url: legacy.api_url.clone(),
-                    default_model: legacy.default_model.clone(),
-                    local_model_path: legacy.local_model_path.clone(),
-                };
-                disk.api_url = legacy.api_url;
-                disk.default_model = legacy.default_model;
-                disk.local_model_path = legacy.local_model_path;
+                let api_key = std::mem::take(&mut legacy.api_key);
+                let disk = AppConfigDisk::from(legacy);
                 let _ = fs::write(&path, serde_json::to_string_pretty(&disk).unwrap_or_default());
-                return Ok(AppConfig {
-                    api_url: disk.api_url,
-                    api_key: legacy.api_key,
-                    default_model: disk.default_model,
-                    local_model_path: disk.local_model_path,
-                });
+                return Ok(disk.into_app_config(api_key));
             }
         }
     }
 
-    Ok(AppConfig {
-        api_url: "".into(),
-        api_key: "".into(),
-        default_model: "".into(),
-        local_model_path: "".into(),
-    })
+    Ok(AppConfigDisk::default().into_app_config(String::new()))
+}
+
+/// 读取「退出时保留本地服务器」设置（供窗口销毁 / 应用退出时使用，无需 AppHandle）
+pub fn keep_server_on_exit() -> bool {
+    read_disk_config().map(|disk| disk.keep_server_on_exit).unwrap_or_default()
+}
+
+/// 读取「自动推断上下文长度上限」设置，未配置时返回 0
+pub fn local_max_ctx_size() -> u32 {
+    read_disk_config().map(|disk| disk.local_max_ctx_size).unwrap_or_default()
+}
+
+/// 读取已配置的本地模型路径（文件或目录），未配置时返回空字符串
+pub fn local_model_path() -> String {
+    read_disk_config().map(|disk| disk.local_model_path).unwrap_or_default()
+}
+
+/// 读取 llama.cpp 引擎下载地址模板（镜像），未配置时返回空字符串
+pub fn llama_download_url() -> String {
+    read_disk_config().map(|disk| disk.llama_download_url).unwrap_or_default()
+}
+
+/// 读取「启动时自动启动本地服务器」设置
+pub fn auto_start_local_server() -> bool {
+    read_disk_config().map(|disk| disk.auto_start_local_server).unwrap_or_default()
+}
+
+/// 读取「流式事件去重」设置
+pub fn dedup_stream_events() -> bool {
+    read_disk_config().map(|disk| disk.dedup_stream_events).unwrap_or_default()
+}
+
+/// 读取「允许抓取内网地址」设置（fetch_url_content 的 SSRF 防护开关）
+pub fn allow_private_network_fetch() -> bool {
+    read_disk_config().map(|disk| disk.allow_private_network_fetch).unwrap_or_default()
+}
+
+/// 读取「附件提取上限」设置（KB），未配置时返回 0
+pub fn max_extracted_kb() -> u32 {
+    read_disk_config().map(|disk| disk.max_extracted_kb).unwrap_or_default()
+}
+
+/// 读取云端同步服务器地址，未配置时返回空字符串
+pub fn sync_server_url() -> String {
+    read_disk_config().map(|disk| disk.sync_server_url).unwrap_or_default()
+}
+
+/// 读取云端同步服务器的双向 TLS 证书路径
+pub fn sync_tls_config() -> SyncTlsConfig {
+    read_disk_config()
+        .map(|disk| SyncTlsConfig {
+            client_cert: disk.sync_client_cert_path,
+            client_key: disk.sync_client_key_path,
+            ca_cert: disk.sync_ca_cert_path,
+        })
+        .unwrap_or_default()
 }
 
 /// 异步加载所有已保存的 AI 助手配置
@@ -141,7 +277,9 @@ pub async fn load_assistants(state: tauri::State<'_, DbState>) -> Result<Vec<Ass
 
         // 2. 为每个助手加载话题
         let mut t_stmt = conn
-            .prepare("SELECT id, name, summary, renamed FROM topics WHERE assistant_id = ?")
+            .prepare(
+                "SELECT id, name, summary, renamed, last_model_id FROM topics WHERE assistant_id = ?",
+            )
             .map_err(|e| e.to_string())?;
         let topic_iter = t_stmt
             .query_map([&asst.id], |row| {
@@ -151,6 +289,7 @@ pub async fn load_assistants(state: tauri::State<'_, DbState>) -> Result<Vec<Ass
                     summary: row.get(2)?,
                     // SQLite INTEGER (0/1) → bool
                     renamed: row.get::<_, i64>(3)? != 0,
+                    last_model_id: row.get(4)?,
                     history: vec![], // 大数据量下建议按需加载，此处暂时全量加载以兼容原有前端
                 })
             })
@@ -160,57 +299,168 @@ pub async fn load_assistants(state: tauri::State<'_, DbState>) -> Result<Vec<Ass
             let mut topic = topic.map_err(|e| e.to_string())?;
 
             // 3. 加载历史消息
-            let mut m_stmt = conn.prepare("SELECT id, role, content, model_id, display_files, display_text, reasoning FROM messages WHERE topic_id = ? ORDER BY timestamp ASC")
+            topic.history = load_topic_history(&conn, &topic.id)?;
+            asst.topics.push(topic);
+        }
+        assistants.push(asst);
+    }
+
+    Ok(assistants)
+}
+
+/// 记录话题最近一次生成回复所用的模型（前端 modelKey 复合键，区分不同 provider 的同名模型）
+pub(crate) fn remember_topic_model(
+    conn: &rusqlite::Connection,
+    topic_id: &str,
+    model_key: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE topics SET last_model_id = ?1 WHERE id = ?2",
+        params![model_key, topic_id],
+    )
     .map_err(|e| e.to_string())?;
+    Ok(())
+}
 
-            let msg_iter = m_stmt
-                .query_map([&topic.id], |row| {
-                    // 提取 display_files (在 index 4)
-                    let display_files_json: Option<String> = row.get(4)?;
-                    let display_files =
-                        display_files_json.and_then(|s| serde_json::from_str(&s).ok());
-
-                    // 提取 content (在 index 2)
-                    let content_json: String = row.get(2)?;
-                    let content_value = serde_json::from_str(&content_json)
-                        .unwrap_or(serde_json::Value::String(content_json));
-
-                    Ok(Message {
-                        id: row.get(0)?,           // index 0: id
-                        role: row.get(1)?,         // index 1: role
-                        content: content_value,    // index 2: content (JSON)
-                        model_id: row.get(3)?,     // index 3: model_id
-                        display_files,             // 已经解析好的 files
-                        display_text: row.get(5)?, // index 5: display_text
-                        tool_call_id: None,
-                        name: None,
-                        tool_calls: None,
-                        reasoning: row.get(6)?,    // index 6: reasoning
-                    })
-                })
-                .map_err(|e| e.to_string())?;
+/// 消息 content 的存储格式：整个 JSON 值（纯文本为 JSON 字符串，多模态为 parts 数组）。
+/// 大图片外置到数据库旁的 blobs 目录，外置失败时仍内嵌保存
+pub(crate) fn encode_content(conn: &rusqlite::Connection, content: &serde_json::Value) -> String {
+    let stored = blob_dir(conn).and_then(|dir| {
+        externalize_images(&dir, content)
+            .map_err(|e| tracing::warn!("图片外置失败，按内嵌保存: {}", e))
+            .ok()
+            .flatten()
+    });
+    serde_json::to_string(stored.as_ref().unwrap_or(content)).unwrap_or_default()
+}
 
-            for msg in msg_iter {
-                let mut message = msg.map_err(|e| e.to_string())?;
-                if let Some(message_id) = &message.id {
-                    let mut stored_files = load_message_attachments(&conn, message_id)?;
-                    if !stored_files.is_empty() {
-                        if let Some(display_files) = &message.display_files {
-                            for (stored, display) in stored_files.iter_mut().zip(display_files) {
-                                stored.name = display.name.clone();
-                            }
-                        }
-                        message.display_files = Some(stored_files);
+/// 解析 messages.content 列：只接受 JSON 字符串与 parts 数组两种形态。
+/// 空列视为空文本；无法解析或形态不对时按原始文本保留并记录告警，不丢内容
+pub(crate) fn decode_content(message_id: &str, raw: String) -> serde_json::Value {
+    if raw.is_empty() {
+        return serde_json::Value::String(raw);
+    }
+    match serde_json::from_str::<serde_json::Value>(&raw) {
+        Ok(value @ (serde_json::Value::String(_) | serde_json::Value::Array(_))) => value,
+        Ok(other) => {
+            tracing::warn!(
+                "消息 {} 的 content 不是文本或 parts 数组（{}），按原文保留",
+                message_id,
+                other
+            );
+            serde_json::Value::String(raw)
+        }
+        Err(e) => {
+            tracing::warn!("消息 {} 的 content 解析失败，按原文保留: {}", message_id, e);
+            serde_json::Value::String(raw)
+        }
+    }
+}
+
+/// 按时间顺序加载话题的全部历史消息（含附件元信息）
+pub(crate) fn load_topic_history(conn: &rusqlite::Connection, topic_id: &str) -> Result<Vec<Message>, String> {
+    let mut m_stmt = conn
+        .prepare("SELECT id, role, content, model_id, display_files, display_text, reasoning, status, is_pinned FROM messages WHERE topic_id = ? ORDER BY timestamp ASC, rowid ASC")
+        .map_err(|e| e.to_string())?;
+    let blob_dir = blob_dir(conn);
+
+    let msg_iter = m_stmt
+        .query_map([topic_id], |row| {
+            // 提取 display_files (在 index 4)
+            let display_files_json: Option<String> = row.get(4)?;
+            let display_files =
+                display_files_json.and_then(|s| serde_json::from_str(&s).ok());
+
+            // 提取 content (在 index 2)
+            let id: Option<String> = row.get(0)?;
+            let content_json: String = row.get(2)?;
+            let mut content_value = decode_content(id.as_deref().unwrap_or(""), content_json);
+            if let Some(dir) = &blob_dir {
+                restore_images(dir, &mut content_value);
+            }
+
+            Ok(Message {
+                id,                        // index 0: id
+                role: row.get(1)?,         // index 1: role
+                content: content_value,    // index 2: content (JSON)
+                model_id: row.get(3)?,     // index 3: model_id
+                display_files,             // 已经解析好的 files
+                display_text: row.get(5)?, // index 5: display_text
+                tool_call_id: None,
+                name: None,
+                tool_calls: None,
+                reasoning: row.get(6)?,    // index 6: reasoning
+                status: row.get(7)?,       // index 7: status
+                is_pinned: row.get(8)?,    // index 8: is_pinned
+                timestamp: None,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut history = Vec::new();
+    for msg in msg_iter {
+        let mut message = msg.map_err(|e| e.to_string())?;
+        if let Some(message_id) = &message.id {
+            let mut stored_files = load_message_attachments(conn, message_id)?;
+            if !stored_files.is_empty() {
+                if let Some(display_files) = &message.display_files {
+                    for (stored, display) in stored_files.iter_mut().zip(display_files) {
+                        stored.name = display.name.clone();
                     }
                 }
-                topic.history.push(message);
+                message.display_files = Some(stored_files);
             }
-            asst.topics.push(topic);
         }
-        assistants.push(asst);
+        history.push(message);
     }
+    Ok(history)
+}
 
-    Ok(assistants)
+/// 写入一条消息（id 已存在时跳过，不覆盖），返回消息 id。
+/// assistant 回复可能在代码块中途被停止，保存前补全未闭合的围栏；
+/// `msg.timestamp` 可被 SQLite `datetime()` 解析时沿用（统一为 UTC `YYYY-MM-DD HH:MM:SS`），否则取当前时间
+pub(crate) fn insert_message(
+    conn: &rusqlite::Connection,
+    topic_id: &str,
+    msg: &Message,
+) -> Result<String, String> {
+    let msg_id = msg
+        .id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let is_assistant = msg.role == "assistant";
+    let content = match &msg.content {
+        serde_json::Value::String(text) if is_assistant => {
+            serde_json::Value::String(finalize_display_text(text))
+        }
+        other => other.clone(),
+    };
+    let display_text = match &msg.display_text {
+        Some(text) if is_assistant => Some(finalize_display_text(text)),
+        other => other.clone(),
+    };
+    let files_json = serde_json::to_string(&msg.display_files).ok();
+
+    conn.execute(
+        "INSERT INTO messages (id, topic_id, role, content, model_id, display_files, display_text, reasoning, status, is_pinned, timestamp)
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, COALESCE(datetime(?11), CURRENT_TIMESTAMP))
+         ON CONFLICT(id) DO NOTHING", // 关键：已存在的 ID 不再重复写入
+        params![
+            msg_id,
+            topic_id,
+            msg.role,
+            encode_content(conn, &content),
+            msg.model_id,
+            files_json,
+            display_text,
+            msg.reasoning,
+            msg.resolved_status(),
+            msg.is_pinned,
+            msg.timestamp
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(msg_id)
 }
 
 #[tauri::command]
@@ -219,7 +469,11 @@ pub async fn save_assistant(
     assistant: Assistant,
 ) -> Result<(), String> {
     let conn = state.0.lock().unwrap();
+    save_assistant_to(&conn, assistant)
+}
 
+/// 保存助手及其话题、消息（增量：只插入新消息，删除前端已移除的话题与消息）
+fn save_assistant_to(conn: &rusqlite::Connection, assistant: Assistant) -> Result<(), String> {
     // 1. 保存/更新助手基本信息
     // mcp_server_ids 以 JSON 数组字符串持久化；空列表存 "[]"
     let mcp_ids_json = serde_json::to_string(&assistant.mcp_server_ids)
@@ -246,19 +500,21 @@ pub async fn save_assistant(
 
     for db_id in db_topic_ids {
         if !current_topic_ids.contains(&db_id) {
-            let attachment_ids = attachment_ids_for_topic(&conn, &db_id)?;
+            let attachment_ids = attachment_ids_for_topic(conn, &db_id)?;
+            let blob_refs = blob_refs_where(conn, "m.topic_id = ?1", &db_id)?;
             conn.execute("DELETE FROM topics WHERE id = ?", params![db_id])
                 .map_err(|e| e.to_string())?;
-            cleanup_attachment_ids(&conn, &attachment_ids)?;
+            cleanup_attachment_ids(conn, &attachment_ids)?;
+            cleanup_blobs(conn, &blob_refs)?;
         }
     }
 
     // 3. 遍历话题执行增量同步
     for topic in assistant.topics {
         conn.execute(
-            "INSERT INTO topics (id, assistant_id, name, summary, renamed) VALUES (?1, ?2, ?3, ?4, ?5)
-             ON CONFLICT(id) DO UPDATE SET name=?3, summary=?4, renamed=?5",
-            params![topic.id, assistant.id, topic.name, topic.summary, topic.renamed as i64],
+            "INSERT INTO topics (id, assistant_id, name, summary, renamed, last_model_id) VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT(id) DO UPDATE SET name=?3, summary=?4, renamed=?5, last_model_id=COALESCE(?6, last_model_id)",
+            params![topic.id, assistant.id, topic.name, topic.summary, topic.renamed as i64, topic.last_model_id],
         )
         .map_err(|e| e.to_string())?;
 
@@ -280,29 +536,18 @@ pub async fn save_assistant(
         drop(message_stmt);
         for db_message_id in db_message_ids {
             if !current_message_ids.contains(&db_message_id) {
-                let attachment_ids = attachment_ids_for_message(&conn, &db_message_id)?;
+                let attachment_ids = attachment_ids_for_message(conn, &db_message_id)?;
+                let blob_refs = blob_refs_where(conn, "m.id = ?1", &db_message_id)?;
                 conn.execute("DELETE FROM messages WHERE id = ?1", [&db_message_id])
                     .map_err(|e| e.to_string())?;
-                cleanup_attachment_ids(&conn, &attachment_ids)?;
+                cleanup_attachment_ids(conn, &attachment_ids)?;
+                cleanup_blobs(conn, &blob_refs)?;
             }
         }
 
         for msg in topic.history {
-            // 假设 Message 结构体现在也有了 id 字段
-            let msg_id = msg
-                .id
-                .clone()
-                .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
-            let files_json = serde_json::to_string(&msg.display_files).ok();
-            let content_json = serde_json::to_string(&msg.content).unwrap_or_default();
-
-            conn.execute(
-                "INSERT INTO messages (id, topic_id, role, content, model_id, display_files, display_text, reasoning) 
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
-                 ON CONFLICT(id) DO NOTHING", // 关键：已存在的 ID 不再重复写入
-                params![msg_id, topic.id, msg.role, content_json, msg.model_id, files_json, msg.display_text, msg.reasoning],
-            ).map_err(|e| e.to_string())?;
-            sync_message_attachments(&conn, &msg_id, msg.display_files.as_ref())?;
+            let msg_id = insert_message(conn, &topic.id, &msg)?;
+            sync_message_attachments(conn, &msg_id, msg.display_files.as_ref())?;
         }
     }
 
@@ -313,13 +558,230 @@ pub async fn save_assistant(
 pub async fn delete_assistant(state: tauri::State<'_, DbState>, id: String) -> Result<(), String> {
     let conn = state.0.lock().unwrap();
     let attachment_ids = attachment_ids_for_assistant(&conn, &id)?;
+    let blob_refs = blob_refs_where(&conn, "t.assistant_id = ?1", &id)?;
     // 由于设置了 ON DELETE CASCADE，会自动删除关联的话题和消息
     conn.execute("DELETE FROM assistants WHERE id = ?", params![id])
         .map_err(|e| e.to_string())?;
     cleanup_attachment_ids(&conn, &attachment_ids)?;
+    cleanup_blobs(&conn, &blob_refs)?;
     Ok(())
 }
 
+/// 只修改助手名称，不重写话题与消息；id 不存在时不做任何事
+#[tauri::command]
+pub async fn rename_assistant(
+    state: tauri::State<'_, DbState>,
+    id: String,
+    name: String,
+) -> Result<(), String> {
+    let conn = state.0.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE assistants SET name = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+        params![name, id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 只修改话题名称并标记为已手动命名（之后不再自动生成标题）；id 不存在时不做任何事
+#[tauri::command]
+pub async fn rename_topic(
+    state: tauri::State<'_, DbState>,
+    id: String,
+    name: String,
+) -> Result<(), String> {
+    let conn = state.0.lock().map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE topics SET name = ?1, renamed = 1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
+        params![name, id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 置顶 / 取消置顶消息。置顶消息在 `call_llm_stream` 中紧跟 system 提示发送，
+/// 不受 history_limit 与自动裁剪影响；只能置顶 user / assistant 消息
+#[tauri::command]
+pub async fn pin_message(
+    state: tauri::State<'_, DbState>,
+    id: String,
+    pinned: bool,
+) -> Result<(), String> {
+    let conn = state.0.lock().map_err(|e| e.to_string())?;
+    let role: String = conn
+        .query_row("SELECT role FROM messages WHERE id = ?1", [&id], |row| {
+            row.get(0)
+        })
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("消息不存在: {}", id))?;
+    if pinned && !matches!(role.as_str(), "user" | "assistant") {
+        return Err("只能置顶用户消息或模型回复".to_string());
+    }
+    conn.execute(
+        "UPDATE messages SET is_pinned = ?1 WHERE id = ?2",
+        params![pinned, id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 批量删除最后一条消息早于 `before` 的话题及其消息，可限定某个助手，返回删除的话题数。
+/// `before` 支持 `YYYY-MM-DD`、`YYYY-MM-DD HH:MM:SS`（UTC）与 RFC 3339（如 `2024-06-01T00:00:00Z`），
+/// 其他格式直接拒绝。
+///
+/// 以话题内最新消息的时间为准（而不是话题自身的 updated_at），仅重命名过的旧话题同样会被清理；
+/// 没有消息的话题才回退到话题的 updated_at，两者都没有时保留。
+/// 调用后前端需重新 load_assistants，否则下次 save_assistant 会把已删除的话题写回。
+#[tauri::command]
+pub async fn delete_topics_before(
+    state: tauri::State<'_, DbState>,
+    assistant_id: Option<String>,
+    before: String,
+) -> Result<u32, String> {
+    let before = parse_cutoff(&before)?;
+    let conn = state.0.lock().map_err(|e| e.to_string())?;
+    delete_topics_before_in(&conn, assistant_id.as_deref(), &before)
+}
+
+/// 把截止时间统一为数据库中的 UTC `YYYY-MM-DD HH:MM:SS`，保证按字符串比较时与时间先后一致
+fn parse_cutoff(raw: &str) -> Result<String, String> {
+    use chrono::{DateTime, NaiveDate, NaiveDateTime};
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Err("截止时间不能为空".to_string());
+    }
+    let parsed = DateTime::parse_from_rfc3339(raw)
+        .map(|dt| dt.naive_utc())
+        .ok()
+        .or_else(|| {
+            ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f"]
+                .iter()
+                .find_map(|fmt| NaiveDateTime::parse_from_str(raw, fmt).ok())
+        })
+        .or_else(|| {
+            NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .ok_or_else(|| format!("无法识别的截止时间: {}", raw))?;
+    Ok(parsed.format("%Y-%m-%d %H:%M:%S").to_string())
+}
+
+fn delete_topics_before_in(
+    conn: &rusqlite::Connection,
+    assistant_id: Option<&str>,
+    before: &str,
+) -> Result<u32, String> {
+    let topic_ids: Vec<String> = {
+        let mut stmt = conn
+            .prepare(
+                "SELECT t.id FROM topics t
+                 LEFT JOIN messages m ON m.topic_id = t.id
+                 WHERE (?1 IS NULL OR t.assistant_id = ?1)
+                 GROUP BY t.id
+                 HAVING COALESCE(MAX(m.timestamp), t.updated_at) < ?2",
+            )
+            .map_err(|e| e.to_string())?;
+        let ids = stmt
+            .query_map(params![assistant_id, before], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        ids
+    };
+
+    let tx = conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut attachment_ids = Vec::new();
+    let mut blob_refs = Vec::new();
+    for topic_id in &topic_ids {
+        attachment_ids.extend(attachment_ids_for_topic(&tx, topic_id)?);
+        blob_refs.extend(blob_refs_where(&tx, "m.topic_id = ?1", topic_id)?);
+        // ON DELETE CASCADE 会一并删除话题下的消息
+        tx.execute("DELETE FROM topics WHERE id = ?1", params![topic_id])
+            .map_err(|e| e.to_string())?;
+    }
+    attachment_ids.sort();
+    attachment_ids.dedup();
+    cleanup_attachment_ids(&tx, &attachment_ids)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    blob_refs.sort();
+    blob_refs.dedup();
+    cleanup_blobs(conn, &blob_refs)?;
+    Ok(topic_ids.len() as u32)
+}
+
+/// migrate_inline_images 的迁移结果
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageMigrationReport {
+    /// 改写的消息数
+    pub messages: u32,
+    /// 改写前这些消息 content 的总字节数
+    pub bytes_before: u64,
+    /// 改写后的总字节数
+    pub bytes_after: u64,
+}
+
+/// 把已有消息中内嵌的大图片迁移到 blobs 目录，content 中改存文件引用；
+/// 有改动时执行 VACUUM 回收空间，最后删除 blobs 目录中不再被引用的文件。内存数据库不做处理
+#[tauri::command]
+pub async fn migrate_inline_images(
+    state: tauri::State<'_, DbState>,
+) -> Result<ImageMigrationReport, String> {
+    let conn = state.0.lock().map_err(|e| e.to_string())?;
+    let mut report = ImageMigrationReport {
+        messages: 0,
+        bytes_before: 0,
+        bytes_after: 0,
+    };
+    let Some(dir) = blob_dir(&conn) else {
+        return Ok(report);
+    };
+    let ids: Vec<String> = {
+        let mut stmt = conn
+            .prepare("SELECT id FROM messages WHERE content LIKE '%data:image/%'")
+            .map_err(|e| e.to_string())?;
+        let ids = stmt
+            .query_map([], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+        ids
+    };
+
+    // 逐条读取改写，避免一次性把所有大图片载入内存
+    for id in ids {
+        let raw: String = conn
+            .query_row("SELECT content FROM messages WHERE id = ?1", [&id], |row| {
+                row.get(0)
+            })
+            .map_err(|e| e.to_string())?;
+        let content = decode_content(&id, raw.clone());
+        let Some(stored) = externalize_images(&dir, &content)? else {
+            continue;
+        };
+        let content_json = serde_json::to_string(&stored).map_err(|e| e.to_string())?;
+        conn.execute(
+            "UPDATE messages SET content = ?1 WHERE id = ?2",
+            params![content_json, id],
+        )
+        .map_err(|e| e.to_string())?;
+        report.messages += 1;
+        report.bytes_before += raw.len() as u64;
+        report.bytes_after += content_json.len() as u64;
+    }
+
+    if report.messages > 0 {
+        conn.execute_batch("VACUUM").map_err(|e| e.to_string())?;
+    }
+    let removed = sweep_blobs(&conn)?;
+    if removed > 0 {
+        tracing::info!("已删除 {} 个未被引用的图片 blob", removed);
+    }
+    Ok(report)
+}
+
 fn attachment_ids_for_message(
     conn: &rusqlite::Connection,
     message_id: &str,
@@ -379,11 +841,7 @@ fn attachment_ids_for_assistant(
 /// 保存“已激活模型”列表（用户在界面上勾选开启的模型）
 #[tauri::command]
 pub fn save_activated_models(models: Vec<ActivatedModel>) -> Result<(), String> {
-    let mut path = dirs::config_dir().unwrap();
-    path.push("com.loch.aio");
-    if !path.exists() {
-        std::fs::create_dir_all(&path).map_err(|e| e.to_string())?;
-    }
+    let mut path = paths::app_config_dir()?;
     path.push("activated_models.json");
     let json = serde_json::to_string_pretty(&models).map_err(|e| e.to_string())?;
     std::fs::write(path, json).map_err(|e| e.to_string())?;
@@ -393,8 +851,7 @@ pub fn save_activated_models(models: Vec<ActivatedModel>) -> Result<(), String>
 /// 加载“已激活模型”列表
 #[tauri::command]
 pub fn load_activated_models() -> Result<Vec<ActivatedModel>, String> {
-    let mut path = dirs::config_dir().unwrap();
-    path.push("com.loch.aio");
+    let mut path = paths::app_config_dir()?;
     path.push("activated_models.json");
 
     if !path.exists() {
@@ -408,11 +865,7 @@ pub fn load_activated_models() -> Result<Vec<ActivatedModel>, String> {
 /// 保存从云端或 API 获取的模型原始信息列表
 #[tauri::command]
 pub fn save_fetched_models(models: Vec<ModelInfo>) -> Result<(), String> {
-    let mut path = dirs::config_dir().unwrap();
-    path.push("com.loch.aio");
-    if !path.exists() {
-        std::fs::create_dir_all(&path).map_err(|e| e.to_string())?;
-    }
+    let mut path = paths::app_config_dir()?;
     path.push("fetched_models.json");
     let json = serde_json::to_string_pretty(&models).map_err(|e| e.to_string())?;
     std::fs::write(path, json).map_err(|e| e.to_string())?;
@@ -422,8 +875,7 @@ pub fn save_fetched_models(models: Vec<ModelInfo>) -> Result<(), String> {
 /// 加载之前获取过的模型信息列表
 #[tauri::command]
 pub fn load_fetched_models() -> Result<Vec<ModelInfo>, String> {
-    let mut path = dirs::config_dir().unwrap();
-    path.push("com.loch.aio");
+    let mut path = paths::app_config_dir()?;
     path.push("fetched_models.json");
 
     if !path.exists() {
@@ -447,7 +899,7 @@ pub async fn upload_avatar(app: tauri::AppHandle, data_url: String) -> Result<St
         ));
     }
 
-    let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let app_dir = paths::app_data_root(&app)?;
     let avatars_dir = app_dir.join("avatars");
 
     // 1. 确保目录存在
@@ -493,7 +945,7 @@ pub async fn upload_avatar(app: tauri::AppHandle, data_url: String) -> Result<St
 
 #[tauri::command]
 pub async fn clear_local_avatar_cache(app: tauri::AppHandle) -> Result<(), String> {
-    let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let app_dir = paths::app_data_root(&app)?;
     let avatars_dir = app_dir.join("avatars");
 
     if avatars_dir.exists() {
@@ -537,3 +989,100 @@ pub async fn read_avatar_source(path: String) -> Result<String, String> {
     let b64 = base64::engine::general_purpose::STANDARD.encode(&buf);
     Ok(format!("data:{};base64,{}", mime, b64))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn content_round_trips_through_storage_format() {
+        let multimodal = json!([
+            { "type": "text", "text": "这张图里是什么？" },
+            { "type": "image_url", "image_url": { "url": "data:image/png;base64,iVBORw0KGgo=" } }
+        ]);
+        let text = json!("纯文本 \"带引号\"");
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        for content in [multimodal, text, json!("")] {
+            assert_eq!(decode_content("m", encode_content(&conn, &content)), content);
+        }
+        // 旧数据：未编码的纯文本、空列与非文本 JSON 都按原文保留
+        assert_eq!(decode_content("m", "hello".into()), json!("hello"));
+        assert_eq!(decode_content("m", String::new()), json!(""));
+        assert_eq!(decode_content("m", "42".into()), json!("42"));
+    }
+
+    #[test]
+    fn save_assistant_closes_fences_of_stopped_reply() {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::core::db::init_schema(&conn).unwrap();
+        let stopped = "示例：\n\n```python\nprint(";
+        let assistant: Assistant = serde_json::from_value(json!({
+            "id": "a1",
+            "name": "助手",
+            "prompt": "",
+            "topics": [{
+                "id": "t1",
+                "name": "话题",
+                "history": [
+                    { "id": "m1", "role": "user", "content": "写个 ```代码" },
+                    { "id": "m2", "role": "assistant", "content": stopped, "displayText": stopped }
+                ]
+            }]
+        }))
+        .unwrap();
+        save_assistant_to(&conn, assistant).unwrap();
+
+        let history = load_topic_history(&conn, "t1").unwrap();
+        let closed = "示例：\n\n```python\nprint(\n```";
+        assert_eq!(history[0].content, json!("写个 ```代码"));
+        assert_eq!(history[1].content, json!(closed));
+        assert_eq!(history[1].display_text.as_deref(), Some(closed));
+    }
+
+    #[test]
+    fn deletes_topics_by_newest_message_or_updated_at() {
+        assert_eq!(
+            parse_cutoff("2024-06-01T00:00:00Z").unwrap(),
+            "2024-06-01 00:00:00"
+        );
+        assert_eq!(
+            parse_cutoff("2024-06-01T08:00:00+08:00").unwrap(),
+            "2024-06-01 00:00:00"
+        );
+        assert_eq!(parse_cutoff(" 2024-06-01 ").unwrap(), "2024-06-01 00:00:00");
+        assert!(parse_cutoff("June 1st").is_err());
+        assert!(parse_cutoff("").is_err());
+
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::core::db::init_schema(&conn).unwrap();
+        conn.execute_batch(
+            "INSERT INTO assistants (id, name, prompt) VALUES ('a1', '助手', '');
+             INSERT INTO topics (id, assistant_id, name, updated_at) VALUES
+                 ('old', 'a1', '旧话题', '2024-07-01 00:00:00'),
+                 ('recent', 'a1', '近期话题', '2024-01-01 00:00:00'),
+                 ('empty-old', 'a1', '空旧话题', '2024-05-01 00:00:00'),
+                 ('empty-new', 'a1', '空新话题', '2024-06-02 00:00:00');
+             INSERT INTO messages (id, topic_id, role, content, timestamp) VALUES
+                 ('m1', 'old', 'user', '\"hi\"', '2024-05-31 23:59:59'),
+                 ('m2', 'recent', 'user', '\"hi\"', '2024-05-01 00:00:00'),
+                 ('m3', 'recent', 'assistant', '\"hello\"', '2024-06-01 10:00:00');",
+        )
+        .unwrap();
+
+        let cutoff = parse_cutoff("2024-06-01T00:00:00Z").unwrap();
+        assert_eq!(
+            delete_topics_before_in(&conn, Some("a1"), &cutoff).unwrap(),
+            2
+        );
+        let mut remaining: Vec<String> = conn
+            .prepare("SELECT id FROM topics")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        remaining.sort();
+        assert_eq!(remaining, vec!["empty-new", "recent"]);
+    }
+}
@@ -0,0 +1,217 @@
+//! 配置一致性检查
+//!
+//! `validate_configuration` 只读地检查通用设置与激活模型列表之间的引用关系，
+//! 返回可操作的问题列表供前端提示修复：
+//! - 默认模型必须存在于激活模型中（删除服务商后常留下悬空的默认模型）
+//! - 云端模型的 API 地址不能为空，API 密钥为空时给出警告（本地无鉴权服务可忽略）
+//! - 同一 (api_url, model_id) 不应重复激活
+//! - 本地模型的文件路径仍然存在
+//!
+//! 不修改任何配置。
+
+use crate::commands::config::{load_activated_models, load_app_config};
+use crate::core::models::{ActivatedModel, AppConfig};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
+use tauri::AppHandle;
+
+/// 问题严重程度
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueSeverity {
+    /// 会导致功能不可用，需要修复
+    Error,
+    /// 可能是有意为之，提示确认
+    Warning,
+}
+
+/// 一条配置问题
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigIssue {
+    pub severity: IssueSeverity,
+    /// 问题类型：missing_default_model / empty_api_url / empty_api_key /
+    /// duplicate_model / missing_local_path
+    pub code: &'static str,
+    /// 面向用户的说明与修复建议
+    pub message: String,
+    /// 涉及的模型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_url: Option<String>,
+}
+
+impl ConfigIssue {
+    fn for_model(
+        severity: IssueSeverity,
+        code: &'static str,
+        model: &ActivatedModel,
+        message: String,
+    ) -> Self {
+        Self {
+            severity,
+            code,
+            message,
+            model_id: Some(model.model_id.clone()),
+            api_url: Some(model.api_url.clone()).filter(|u| !u.is_empty()),
+        }
+    }
+}
+
+/// 是否为本地模型（带本地路径或引擎类型）
+fn is_local(model: &ActivatedModel) -> bool {
+    model.local_path.is_some() || model.engine_type.is_some()
+}
+
+/// 默认模型可以是纯 model_id，也可以是前端的 "model_id@api_url" 复合键
+fn matches_default(model: &ActivatedModel, default_model: &str) -> bool {
+    model.model_id == default_model
+        || format!("{}@{}", model.model_id, model.api_url) == default_model
+}
+
+/// 检查配置；`path_exists` 用于判断本地模型路径是否存在（便于测试）
+fn check_configuration(
+    config: &AppConfig,
+    models: &[ActivatedModel],
+    path_exists: impl Fn(&str) -> bool,
+) -> Vec<ConfigIssue> {
+    let mut issues = Vec::new();
+
+    let default_model = config.default_model.trim();
+    if !default_model.is_empty() && !models.iter().any(|m| matches_default(m, default_model)) {
+        issues.push(ConfigIssue {
+            severity: IssueSeverity::Error,
+            code: "missing_default_model",
+            message: format!(
+                "默认模型 {} 不在已激活的模型中，请重新选择默认模型",
+                default_model
+            ),
+            model_id: Some(default_model.to_string()),
+            api_url: None,
+        });
+    }
+
+    let mut seen = HashSet::new();
+    for model in models {
+        if !seen.insert((model.api_url.trim_end_matches('/'), model.model_id.as_str())) {
+            issues.push(ConfigIssue::for_model(
+                IssueSeverity::Warning,
+                "duplicate_model",
+                model,
+                format!(
+                    "模型 {} 在同一地址下重复激活，可删除多余的条目",
+                    model.model_id
+                ),
+            ));
+        }
+
+        if is_local(model) {
+            if let Some(path) = model.local_path.as_deref().filter(|p| !path_exists(p)) {
+                issues.push(ConfigIssue::for_model(
+                    IssueSeverity::Error,
+                    "missing_local_path",
+                    model,
+                    format!(
+                        "本地模型 {} 的文件不存在: {}，请重新导入或移除该模型",
+                        model.model_id, path
+                    ),
+                ));
+            }
+            continue;
+        }
+
+        if model.api_url.trim().is_empty() {
+            issues.push(ConfigIssue::for_model(
+                IssueSeverity::Error,
+                "empty_api_url",
+                model,
+                format!(
+                    "模型 {} 缺少 API 地址，请在服务商设置中补全",
+                    model.model_id
+                ),
+            ));
+        }
+        if model.api_key.trim().is_empty() {
+            issues.push(ConfigIssue::for_model(
+                IssueSeverity::Warning,
+                "empty_api_key",
+                model,
+                format!(
+                    "模型 {} 未配置 API 密钥；若服务需要鉴权，请求会返回 401",
+                    model.model_id
+                ),
+            ));
+        }
+    }
+    issues
+}
+
+/// 检查通用设置与激活模型的一致性，返回发现的问题（只读，不做修改）
+#[tauri::command]
+pub fn validate_configuration(app: AppHandle) -> Result<Vec<ConfigIssue>, String> {
+    let config = load_app_config(app)?;
+    let models = load_activated_models()?;
+    Ok(check_configuration(&config, &models, |p| {
+        Path::new(p).exists()
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(api_url: &str, model_id: &str, api_key: &str) -> ActivatedModel {
+        ActivatedModel {
+            api_url: api_url.into(),
+            api_key: api_key.into(),
+            model_id: model_id.into(),
+            owned_by: String::new(),
+            local_path: None,
+            engine_type: None,
+        }
+    }
+
+    fn config(default_model: &str) -> AppConfig {
+        serde_json::from_value(serde_json::json!({
+            "apiUrl": "",
+            "apiKey": "",
+            "defaultModel": default_model,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn reports_dangling_references() {
+        let local = ActivatedModel {
+            local_path: Some("/models/gone.gguf".into()),
+            engine_type: Some("llama_cpp".into()),
+            ..model("", "qwen", "")
+        };
+        let models = vec![
+            model("https://a/v1", "gpt", "k"),
+            model("https://a/v1/", "gpt", "k"),
+            model("", "claude", ""),
+            local,
+        ];
+        let codes = |default: &str| -> Vec<&'static str> {
+            check_configuration(&config(default), &models, |_| false)
+                .into_iter()
+                .map(|i| i.code)
+                .collect()
+        };
+        assert_eq!(
+            codes("deleted-model"),
+            vec![
+                "missing_default_model",
+                "duplicate_model",
+                "empty_api_url",
+                "empty_api_key",
+                "missing_local_path",
+            ]
+        );
+        assert_eq!(codes("gpt@https://a/v1")[0], "duplicate_model");
+        assert!(check_configuration(&config("gpt"), &models[..1], |_| true).is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+//! 文档摘要命令
+//!
+//! `summarize_document` 先用与 `process_file_content` 相同的解析分支提取文档文本，
+//! 用 [`chunk_text`] 按段落 / 句子切分为若干块，
+//! 逐块生成摘要（map），再把各块摘要合并为一份完整摘要（reduce）。
+//! 每完成一步发送 `document-summary-progress` 事件；块数超过 [`MAX_DOCUMENT_CHUNKS`] 时直接报错，避免费用失控。
+
+use crate::commands::llm::{api_base_url, provider_error, resolve_api_key};
+use crate::core::state::{HttpClientState, LocalEngineState};
+use crate::utils::chunking::chunk_text;
+use crate::utils::file_parser::extract_content;
+use serde::Serialize;
+use serde_json::json;
+use std::time::Duration;
+use tauri::{Emitter, Window};
+
+/// 默认分块大小（估算 token）
+const DEFAULT_CHUNK_TOKENS: usize = 4000;
+/// 分块大小下限，过小会让块数暴增
+const MIN_CHUNK_TOKENS: usize = 250;
+/// 单个文档最多处理的块数
+const MAX_DOCUMENT_CHUNKS: usize = 20;
+/// 单次摘要请求超时
+const CHUNK_TIMEOUT: Duration = Duration::from_secs(120);
+/// 分块摘要与合并共用的 system 提示词
+const SUMMARY_SYSTEM_PROMPT: &str = "你是一个文档摘要助手，只输出摘要内容，不添加额外说明。";
+
+/// 文档摘要进度（发往前端用）
+#[derive(Serialize, Clone)]
+pub struct DocumentSummaryProgress {
+    pub path: String,
+    /// 已完成的步骤数（各块摘要 + 最终合并）
+    pub completed: usize,
+    pub total: usize,
+    /// 当前阶段：`chunk` 为逐块摘要，`merge` 为合并
+    pub stage: &'static str,
+}
+
+/// 非流式调用一次 `/chat/completions`，返回正文
+async fn complete_once(
+    client: &reqwest::Client,
+    endpoint: &str,
+    api_key: &str,
+    model: &str,
+    system: &str,
+    user: String,
+) -> Result<String, String> {
+    let response = client
+        .post(endpoint)
+        .timeout(CHUNK_TIMEOUT)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&json!({
+            "model": model,
+            "messages": [
+                { "role": "system", "content": system },
+                { "role": "user", "content": user }
+            ],
+            "stream": false
+        }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(provider_error(status, &body));
+    }
+    let val: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+    let content = val["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap_or("")
+        .trim()
+        .to_string();
+    if content.is_empty() {
+        return Err("模型未返回摘要内容".to_string());
+    }
+    Ok(content)
+}
+
+/// 文档摘要（map-reduce）：提取文本 → 分块逐一摘要 → 合并为一份摘要。
+/// `chunk_tokens` 为分块大小（估算 token），默认 4000
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn summarize_document(
+    window: Window,
+    engine_state: tauri::State<'_, LocalEngineState>,
+    http: tauri::State<'_, HttpClientState>,
+    api_url: String,
+    api_key: String,
+    model: String,
+    path: String,
+    chunk_tokens: Option<usize>,
+) -> Result<String, String> {
+    let text = extract_content(path.clone(), None, false, None).await?;
+    if text.starts_with("data:image/") {
+        return Err("图片无法生成文档摘要".to_string());
+    }
+    if text.trim().is_empty() {
+        return Err("文档中没有可提取的文本".to_string());
+    }
+
+    let chunk_tokens = chunk_tokens
+        .unwrap_or(DEFAULT_CHUNK_TOKENS)
+        .max(MIN_CHUNK_TOKENS);
+    // 各块分别摘要后再合并，不需要重叠
+    let chunks: Vec<String> = chunk_text(&text, chunk_tokens, 0)
+        .into_iter()
+        .map(|chunk| chunk.text)
+        .collect();
+    if chunks.len() > MAX_DOCUMENT_CHUNKS {
+        return Err(format!(
+            "文档过长：按每块约 {} token 需要 {} 块，超过上限 {} 块，请增大分块大小",
+            chunk_tokens,
+            chunks.len(),
+            MAX_DOCUMENT_CHUNKS
+        ));
+    }
+
+    let api_key = resolve_api_key(&engine_state, &api_url, api_key);
+    let client = http.client();
+    let endpoint = format!("{}/chat/completions", api_base_url(&api_url));
+    // 只有一块时无需合并
+    let total = if chunks.len() == 1 {
+        1
+    } else {
+        chunks.len() + 1
+    };
+    let progress = |completed: usize, stage: &'static str| {
+        let _ = window.emit(
+            "document-summary-progress",
+            DocumentSummaryProgress {
+                path: path.clone(),
+                completed,
+                total,
+                stage,
+            },
+        );
+    };
+
+    let mut summaries = Vec::with_capacity(chunks.len());
+    for (i, chunk) in chunks.iter().enumerate() {
+        let user = if chunks.len() == 1 {
+            format!("请总结以下文档的主要内容：\n\n{}", chunk)
+        } else {
+            format!(
+                "以下是一份文档的第 {}/{} 部分，请提炼这一部分的要点：\n\n{}",
+                i + 1,
+                chunks.len(),
+                chunk
+            )
+        };
+        let summary = complete_once(
+            &client,
+            &endpoint,
+            &api_key,
+            &model,
+            SUMMARY_SYSTEM_PROMPT,
+            user,
+        )
+        .await
+        .map_err(|e| format!("第 {} 块摘要失败: {}", i + 1, e))?;
+        summaries.push(summary);
+        progress(i + 1, "chunk");
+    }
+    if summaries.len() == 1 {
+        return Ok(summaries.remove(0));
+    }
+
+    let joined = summaries
+        .iter()
+        .enumerate()
+        .map(|(i, s)| format!("[第 {} 部分]\n{}", i + 1, s))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let merged = complete_once(
+        &client,
+        &endpoint,
+        &api_key,
+        &model,
+        SUMMARY_SYSTEM_PROMPT,
+        format!(
+            "以下是同一份文档各部分的要点，请整合为一份连贯、完整的文档摘要：\n\n{}",
+            joined
+        ),
+    )
+    .await
+    .map_err(|e| format!("合并摘要失败: {}", e))?;
+    progress(total, "merge");
+    Ok(merged)
+}
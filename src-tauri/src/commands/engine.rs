@@ -1,18 +1,48 @@
 /// 本地推理引擎管理相关的 Tauri 命令：启动、停止、检查状态以及引擎安装管理。
 
-use crate::core::state::LocalEngineState;
-use crate::plugins::engine::installer::{EngineInstaller, EngineStatus, EngineUpdateInfo};
-use crate::plugins::engine::EngineManager;
-use crate::utils::file_parser::validate_model_path;
-use tauri::{AppHandle, Emitter, State};
-use tokio::time::{sleep, Duration};
+use crate::core::state::{HttpClientState, LocalEngineInner, LocalEngineState};
+use crate::plugins::engine::installer::{BackendVariant, EngineInstaller, EngineStatus, EngineUpdateInfo};
+use crate::plugins::engine::last_launch::{self, LastLaunch};
+use crate::plugins::engine::backend_version::{self, BackendVersion};
+use crate::plugins::engine::benchmark::{BenchmarkManager, BenchmarkReport};
+use crate::plugins::engine::detached::{self, DetachedServer};
+use crate::plugins::engine::llama_cpp;
+use crate::plugins::engine::metrics::{self, MetricsPoller, ServerMetrics};
+use crate::plugins::engine::model_import;
+use crate::plugins::engine::port_owner::{self, PortConflict};
+use crate::plugins::engine::process_tree::{self, OrphanedServer};
+use crate::plugins::engine::resources::{ResourceMonitor, ResourceSample};
+use crate::plugins::engine::scan::{self, LocalModelScan};
+use crate::plugins::engine::server_log::{ServerLogBuffer, ServerLogLine};
+use crate::plugins::engine::options::LoraAdapter;
+use crate::plugins::engine::{options, EngineManager, LocalServerOptions};
+use crate::utils::file_parser::{path_in_sandbox, validate_model_path};
+use crate::utils::gguf::{self, GgufInfo};
+use serde::Serialize;
+use std::path::PathBuf;
+use tauri::path::BaseDirectory;
+use tauri::{AppHandle, Emitter, Manager, State};
+use tokio::time::{sleep, Duration, Instant};
 
 /// 启动本地大模型服务器
 /// @param model_path 模型文件的绝对路径（H8 沙箱校验）
 /// @param port 指定服务器运行的端口
 /// @param gpu_layers 卸载到 GPU 的模型层数
 /// @param engine_type 可选的引擎类型标识，不传时默认使用 llama_cpp（兼容旧配置）
+/// @param options 可选的启动选项（对话模板等）；传入时按模型路径持久化，
+///                不传时复用该模型上次保存的选项
+/// @param mmproj_path 可选的多模态投影文件路径（覆盖 options 中的同名字段），
+///                    加载后服务器可接收图片输入
+/// @param ctx_size 可选的上下文长度（覆盖 options 中的同名字段，不小于 512）；
+///                 都未指定时取 GGUF 元数据中的训练长度，并受 `localMaxCtxSize` 上限约束
+/// @param lora_adapters 可选的 LoRA 适配器列表（覆盖 options 中的同名字段），
+///                      每项为 `{ path, scale }`，scale 默认 1.0
+/// @param chat_template 可选的对话模板（覆盖 options 中的同名字段）：内置模板名（如 "chatml"，
+///                      传 `--chat-template`）、模板文件路径或 Jinja 模板正文（写入临时文件后传
+///                      `--chat-template-file`）；未知的模板名在启动前报错
+/// @returns 服务器地址与本次启动生成的 API key（options.disableApiKey 时为 None）
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn start_local_server(
     app: AppHandle,
     state: State<'_, LocalEngineState>,
@@ -21,7 +51,14 @@ pub async fn start_local_server(
     port: u16,
     gpu_layers: i32,
     engine_type: Option<String>,
-) -> Result<String, String> {
+    options: Option<LocalServerOptions>,
+    mmproj_path: Option<String>,
+    ctx_size: Option<u32>,
+    lora_adapters: Option<Vec<LoraAdapter>>,
+    chat_template: Option<String>,
+) -> Result<LocalServerInfo, String> {
+    // 同一时间只允许一次启动：第二个并发请求直接报错，而不是与正在加载的进程抢端口
+    let _switch = state.try_begin_switch()?;
     let engine_id = engine_type.unwrap_or_else(|| "llama_cpp".to_string());
 
     let plugin = engine_mgr
@@ -30,29 +67,212 @@ pub async fn start_local_server(
 
     // H8 沙箱：拒绝 home/AppData 外的模型路径
     let safe_path = validate_model_path(&model_path)?;
+    let path_key = safe_path.to_string_lossy().to_string();
 
-    // 启动前清理：如果已经有一个正在运行的服务器，先关闭它
-    stop_local_server(state.clone()).await?;
-    sleep(Duration::from_millis(500)).await;
+    // 下载中断的残缺 GGUF 会让 llama-server 以晦涩的错误退出，启动前先校验文件长度
+    let is_gguf = safe_path
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("gguf"));
+    if engine_id == "llama_cpp" && is_gguf {
+        let check_path = safe_path.clone();
+        let info = tokio::task::spawn_blocking(move || gguf::inspect_file(&check_path))
+            .await
+            .map_err(|e| e.to_string())??;
+        if let Some(e) = info.integrity_error {
+            return Err(e);
+        }
+    }
+
+    // 启动选项：显式传入时先校验再持久化，否则读取上次保存的值
+    // 单独传入的 mmproj_path / ctx_size / lora_adapters / chat_template 视为对选项的显式修改
+    let overridden = mmproj_path.is_some()
+        || ctx_size.is_some()
+        || lora_adapters.is_some()
+        || chat_template.is_some();
+    let options = if overridden {
+        let mut opts = options.unwrap_or_else(|| options::load_for_model(&path_key));
+        opts.mmproj_path = mmproj_path.or(opts.mmproj_path);
+        opts.ctx_size = ctx_size.or(opts.ctx_size);
+        opts.chat_template = chat_template.or(opts.chat_template);
+        if let Some(adapters) = lora_adapters {
+            opts.lora_adapters = adapters;
+        }
+        Some(opts)
+    } else {
+        options
+    };
+    let options = match options {
+        Some(opts) => {
+            opts.validate()?;
+            if let Err(e) = options::save_for_model(&path_key, &opts) {
+                tracing::warn!("保存本地模型启动选项失败: {}", e);
+            }
+            opts
+        }
+        None => {
+            let saved = options::load_for_model(&path_key);
+            saved.validate()?;
+            saved
+        }
+    };
+
+    // 草稿模型需与主模型共用分词器（读取双方 GGUF 元数据）
+    options.check_draft_model(&safe_path)?;
+    options.write_inline_template()?;
+
+    // 未指定上下文长度时按模型元数据推断（推断结果不持久化）
+    let launch_options = options.clone();
+    let options = options.with_default_ctx_size(&safe_path);
+
+    // 启动前清理：如果已经有一个正在运行的服务器，先关闭它并等待进程退出、端口释放
+    shutdown_server(&state).await;
+
+    // 端口仍被占用：找出占用进程写入错误信息，并通过事件告知前端（残留的 llama-server 可一键结束）
+    if !port_owner::is_port_free(port) {
+        let engine_dirs = engine_dirs(&app);
+        let conflict = tokio::task::spawn_blocking(move || port_owner::diagnose(port, &engine_dirs))
+            .await
+            .map_err(|e| e.to_string())?;
+        let _ = app.emit(port_owner::PORT_CONFLICT_EVENT, &conflict);
+        return Err(conflict.message);
+    }
 
     // 调用插件启动
     let url = plugin
-        .start(app, &state, safe_path.to_string_lossy().as_ref(), port, gpu_layers)
+        .start(app.clone(), &state, &path_key, port, gpu_layers, &options)
         .await?;
+    let api_key = state.lock().api_key.clone();
+    app.state::<ResourceMonitor>().start(app.clone());
+
+    // 记录本次成功启动的参数，供下次应用启动时自动启动
+    let launch = LastLaunch {
+        model_path: path_key,
+        port,
+        gpu_layers,
+        engine_type: engine_id,
+        options: launch_options,
+    };
+    if let Err(e) = last_launch::save(&launch) {
+        tracing::warn!("保存本地服务器启动参数失败: {}", e);
+    }
 
-    Ok(url)
+    Ok(LocalServerInfo { url, api_key })
 }
 
-/// 停止本地服务器
+/// start_local_server 的返回值
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalServerInfo {
+    /// OpenAI 兼容 API Base URL
+    pub url: String,
+    /// 请求时需携带的 Bearer key
+    pub api_key: Option<String>,
+}
+
+/// 对本地模型做基准测试：测量几个上下文深度下的提示词处理与生成速度（tokens/s），
+/// 进度通过 `local-benchmark-progress` 事件推送，可用 cancel_local_benchmark 取消
+/// @param model_path 模型文件的绝对路径
+/// @param gpu_layers 卸载到 GPU 的层数（默认全部）
+/// @param options 可选的启动选项，不传时使用该模型上次保存的选项
+#[tauri::command]
+pub async fn benchmark_local_model(
+    app: AppHandle,
+    manager: State<'_, BenchmarkManager>,
+    model_path: String,
+    gpu_layers: Option<i32>,
+    options: Option<LocalServerOptions>,
+) -> Result<BenchmarkReport, String> {
+    let safe_path = validate_model_path(&model_path)?;
+    let path_key = safe_path.to_string_lossy().to_string();
+    let options = options.unwrap_or_else(|| options::load_for_model(&path_key));
+    options.validate()?;
+    let exe_path = llama_cpp::resolve_exe_path(&app)?;
+    manager
+        .run(app, &exe_path, path_key, gpu_layers.unwrap_or(999), options)
+        .await
+}
+
+/// 取消正在进行的基准测试，返回是否有测试被取消
+#[tauri::command]
+pub fn cancel_local_benchmark(manager: State<'_, BenchmarkManager>) -> bool {
+    manager.cancel()
+}
+
+/// 读取某个本地模型上次保存的启动选项（未保存过时返回默认值）
+/// @param model_path 模型文件的绝对路径
+#[tauri::command]
+pub fn get_local_model_options(model_path: String) -> Result<LocalServerOptions, String> {
+    let safe_path = validate_model_path(&model_path)?;
+    Ok(options::load_for_model(&safe_path.to_string_lossy()))
+}
+
+/// 在某模型已保存的启动选项上套用低显存预设（KV cache 量化 + 不卸载 + 缩小上下文），
+/// 返回结果供前端展示或直接传给 start_local_server
+/// @param model_path 模型文件的绝对路径
+#[tauri::command]
+pub fn get_low_vram_options(model_path: String) -> Result<LocalServerOptions, String> {
+    let safe_path = validate_model_path(&model_path)?;
+    Ok(options::load_for_model(&safe_path.to_string_lossy()).low_vram_preset())
+}
+
+/// 停止本地服务器（有启动正在进行时等待其完成后再停止）
 #[tauri::command]
 pub async fn stop_local_server(state: State<'_, LocalEngineState>) -> Result<(), String> {
-    let mut inner = state.lock();
-    if let Some(mut child) = inner.child_process.take() {
+    let _switch = state.begin_switch().await;
+    shutdown_server(&state).await;
+    Ok(())
+}
+
+/// 等待旧进程退出 / 端口释放的上限
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 结束当前服务器，等待进程退出且端口可再次绑定（调用方需持有切换锁）
+async fn shutdown_server(state: &LocalEngineState) {
+    let (child, adopted, port) = {
+        let mut inner = state.lock();
+        let adopted = inner.adopted_pid.take().zip(inner.adopted_identity.take());
+        let taken = (inner.child_process.take(), adopted, inner.port);
+        clear_server_info(&mut inner);
+        taken
+    };
+    if let Some(child) = child {
         tracing::debug!("正在停止本地服务器...");
-        let _ = child.kill();
+        if !process_tree::kill_child_and_wait(child, SHUTDOWN_TIMEOUT).await {
+            tracing::warn!("本地服务器在 {:?} 内未退出", SHUTDOWN_TIMEOUT);
+        }
+    }
+    // 上次运行保留下来的服务器：核对身份后按 pid 结束并删除记录
+    if let Some((pid, identity)) = adopted {
+        tracing::debug!("正在停止接管的本地服务器 (pid {})...", pid);
+        detached::kill_process(pid, &identity);
+        detached::clear();
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while detached::is_same_process(pid, &identity) && Instant::now() < deadline {
+            sleep(process_tree::EXIT_POLL_INTERVAL).await;
+        }
+    }
+    // 进程退出后端口可能仍短暂占用，确认可绑定后再返回
+    if let Some(port) = port {
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        while std::net::TcpListener::bind(("127.0.0.1", port)).is_err() && Instant::now() < deadline {
+            sleep(process_tree::EXIT_POLL_INTERVAL).await;
+        }
     }
+}
+
+/// 清空服务器相关的状态字段（进程句柄由调用方处理）
+fn clear_server_info(inner: &mut LocalEngineInner) {
     inner.engine_type.clear();
-    Ok(())
+    inner.port = None;
+    inner.supports_images = false;
+    inner.output_detached = false;
+    inner.adopted_identity = None;
+    inner.api_key = None;
+    inner.ctx_size = None;
+    inner.cache_type_k = None;
+    inner.cache_type_v = None;
+    inner.lora_adapters.clear();
+    inner.draft_model_path = None;
 }
 
 /// 检查本地服务器是否正在运行
@@ -68,9 +288,426 @@ pub fn is_local_server_running(state: State<'_, LocalEngineState>) -> bool {
             }
         }
     }
+    if let Some(pid) = inner.adopted_pid {
+        if inner
+            .adopted_identity
+            .as_ref()
+            .is_some_and(|identity| detached::is_same_process(pid, identity))
+        {
+            return true;
+        }
+        inner.adopted_pid = None;
+        inner.adopted_identity = None;
+        detached::clear();
+    }
     false
 }
 
+/// 应用启动时接管上次「退出时保留」的本地服务器；
+/// 进程已不存在或 pid 已被其他程序复用（可执行文件 / 启动时间不一致）时删除记录
+pub fn adopt_detached_server(app: &AppHandle) {
+    let Some(server) = detached::load() else {
+        return;
+    };
+    let identity = server
+        .identity
+        .clone()
+        .filter(|identity| detached::is_same_process(server.pid, identity));
+    let Some(identity) = identity else {
+        tracing::info!("保留的本地服务器 (pid {}) 已不存在，删除记录", server.pid);
+        detached::clear();
+        return;
+    };
+    tracing::info!("接管已保留的本地服务器 (pid {}, 端口 {})", server.pid, server.port);
+    let api_key = detached::load_api_key(app, &server);
+    let state = app.state::<LocalEngineState>();
+    let mut inner = state.lock();
+    inner.adopted_pid = Some(server.pid);
+    inner.adopted_identity = Some(identity);
+    inner.port = Some(server.port);
+    inner.engine_type = server.engine_type;
+    inner.supports_images = server.supports_images;
+    inner.api_key = api_key;
+    inner.ctx_size = server.ctx_size;
+    inner.cache_type_k = server.cache_type_k;
+    inner.cache_type_v = server.cache_type_v;
+    inner.lora_adapters = server.lora_adapters;
+    inner.draft_model_path = server.draft_model_path;
+    inner.output_detached = true;
+}
+
+/// 应用启动时按上次成功的参数在后台启动本地服务器（需开启 `autoStartLocalServer`）。
+/// 已接管保留的服务器时跳过；失败只发送 [`last_launch::AUTO_START_ERROR_EVENT`]，不影响应用启动
+pub fn auto_start_local_server(app: &AppHandle) {
+    if !crate::commands::config::auto_start_local_server() {
+        return;
+    }
+    if app.state::<LocalEngineState>().lock().has_server() {
+        return;
+    }
+    let Some(launch) = last_launch::load() else {
+        return;
+    };
+    let app = app.clone();
+    tauri::async_runtime::spawn(async move {
+        tracing::info!("自动启动本地服务器: {}", launch.model_path);
+        let result = start_local_server(
+            app.clone(),
+            app.state(),
+            app.state(),
+            launch.model_path,
+            launch.port,
+            launch.gpu_layers,
+            Some(launch.engine_type),
+            Some(launch.options),
+            None,
+            None,
+            None,
+            None,
+        )
+        .await;
+        if let Err(e) = result {
+            tracing::warn!("自动启动本地服务器失败: {}", e);
+            let _ = app.emit(last_launch::AUTO_START_ERROR_EVENT, e);
+        }
+    });
+}
+
+/// 窗口销毁 / 应用退出时处理本地服务器：
+/// 开启 `keep_server_on_exit` 且输出已脱离管道时记录 pid/端口并保留进程，否则结束进程
+pub fn release_local_server(app: &AppHandle) {
+    let keep = crate::commands::config::keep_server_on_exit();
+    let state = app.state::<LocalEngineState>();
+    let mut inner = state.lock();
+    if let Some(mut child) = inner.child_process.take() {
+        let identity = (keep && inner.output_detached)
+            .then(|| detached::process_identity(child.id()))
+            .flatten();
+        if let Some(identity) = identity {
+            let record = DetachedServer {
+                pid: child.id(),
+                identity: Some(identity),
+                port: inner.port.unwrap_or_default(),
+                engine_type: inner.engine_type.clone(),
+                supports_images: inner.supports_images,
+                api_key_ref: None,
+                ctx_size: inner.ctx_size,
+                cache_type_k: inner.cache_type_k.clone(),
+                cache_type_v: inner.cache_type_v.clone(),
+                lora_adapters: inner.lora_adapters.clone(),
+                draft_model_path: inner.draft_model_path.clone(),
+            };
+            match detached::save(app, &record, inner.api_key.as_deref()) {
+                // 不 kill：drop Child 句柄不会结束子进程
+                Ok(()) => return,
+                Err(e) => tracing::warn!("记录保留的本地服务器失败，改为结束进程: {}", e),
+            }
+        } else if keep && inner.output_detached {
+            tracing::warn!("无法读取本地服务器的进程信息，退出时结束进程");
+        } else if keep {
+            tracing::warn!("本地服务器启动时未开启保留（输出仍接在管道上），退出时结束进程");
+        }
+        process_tree::kill_child(&mut child);
+    }
+    if let Some((pid, identity)) = inner.adopted_pid.take().zip(inner.adopted_identity.take()) {
+        if !keep {
+            detached::kill_process(pid, &identity);
+            detached::clear();
+        }
+    }
+}
+
+/// 本地服务器运行状态
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalServerStatus {
+    pub running: bool,
+    pub engine_type: String,
+    pub port: Option<u16>,
+    /// 是否加载了 mmproj，可接收图片输入
+    pub supports_images: bool,
+    /// 生效的上下文长度
+    pub ctx_size: Option<u32>,
+    /// 生效的 KV cache 类型（未指定时为引擎默认 f16）
+    pub cache_type_k: Option<String>,
+    pub cache_type_v: Option<String>,
+    /// 已加载的 LoRA 适配器
+    pub lora_adapters: Vec<LoraAdapter>,
+    /// 投机解码草稿模型，None 表示未启用
+    pub draft_model_path: Option<String>,
+    /// 最近一次资源采样（CPU / 内存 / 显存）
+    pub resources: Option<ResourceSample>,
+}
+
+/// 获取本地服务器运行状态（含是否支持图片输入）
+#[tauri::command]
+pub fn get_local_server_status(
+    state: State<'_, LocalEngineState>,
+    monitor: State<'_, ResourceMonitor>,
+) -> LocalServerStatus {
+    let running = is_local_server_running(state.clone());
+    let inner = state.lock();
+    LocalServerStatus {
+        running,
+        engine_type: inner.engine_type.clone(),
+        port: inner.port,
+        supports_images: running && inner.supports_images,
+        ctx_size: inner.ctx_size,
+        cache_type_k: inner.cache_type_k.clone(),
+        cache_type_v: inner.cache_type_v.clone(),
+        lora_adapters: inner.lora_adapters.clone(),
+        draft_model_path: inner.draft_model_path.clone(),
+        resources: monitor.latest().filter(|_| running),
+    }
+}
+
+/// 获取本地服务器最近的日志（stdout / stderr 合并，按来源标记）
+#[tauri::command]
+pub fn get_local_server_logs(logs: State<'_, ServerLogBuffer>) -> Vec<ServerLogLine> {
+    logs.snapshot()
+}
+
+/// 查询本地服务器的运行指标（槽位占用、吞吐、KV cache 等）；
+/// 旧版 llama-server 缺少的端点对应字段为空
+#[tauri::command]
+pub async fn get_local_server_metrics(
+    state: State<'_, LocalEngineState>,
+    http: State<'_, HttpClientState>,
+) -> Result<ServerMetrics, String> {
+    let (port, api_key) = metrics::current_server(&state).ok_or("本地服务器未运行")?;
+    metrics::fetch(&http.0, port, api_key.as_deref()).await
+}
+
+/// 开启 / 关闭指标后台轮询：开启后服务器运行期间定期发送 `local-server-metrics` 事件
+/// @param interval_ms 轮询间隔（毫秒），默认 3000，最小 500
+#[tauri::command]
+pub fn set_local_server_metrics_polling(
+    app: AppHandle,
+    poller: State<'_, MetricsPoller>,
+    enabled: bool,
+    interval_ms: Option<u64>,
+) {
+    if enabled {
+        poller.start(app, interval_ms.map(Duration::from_millis));
+    } else {
+        poller.stop();
+    }
+}
+
+/// 扫描目录（含一层子目录）下的 GGUF 模型，自动配对 `*-mmproj-*.gguf` 投影文件，
+/// LoRA 适配器（`general.type = "adapter"`）单独列在 `loraAdapters` 中
+/// @param dir 要扫描的目录绝对路径（H8 沙箱校验）
+#[tauri::command]
+pub async fn scan_local_models(dir: String) -> Result<LocalModelScan, String> {
+    let root = PathBuf::from(&dir);
+    path_in_sandbox(&root)?;
+    if !root.is_dir() {
+        return Err(format!("不是有效的目录: {}", dir));
+    }
+    tokio::task::spawn_blocking(move || scan::scan_dir(&root))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 解析 GGUF 模型文件的元数据（架构、参数量、量化类型、上下文长度、对话模板），
+/// 并按张量信息表校验文件是否完整；截断的文件通过 `integrityError` 返回，供界面在启动前提示
+/// @param path 模型文件绝对路径（H8 沙箱校验）
+#[tauri::command]
+pub async fn inspect_gguf(path: String) -> Result<GgufInfo, String> {
+    let safe_path = validate_model_path(&path)?;
+    tokio::task::spawn_blocking(move || gguf::inspect_file(&safe_path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// 把任意位置的 GGUF 模型导入到受管理的模型目录（`localModelPath` 所在目录，未配置时为数据目录下的 models/）
+///
+/// 同一磁盘上建立硬链接，否则复制并发送 `local-model-import-progress` 事件；
+/// 文件不以 GGUF 魔数开头时拒绝导入
+/// @param source_path 源文件绝对路径
+/// @returns 导入后的模型路径
+#[tauri::command]
+pub async fn register_local_model(app: AppHandle, source_path: String) -> Result<String, String> {
+    tokio::task::spawn_blocking(move || model_import::import(&app, &source_path))
+        .await
+        .map_err(|e| e.to_string())?
+        .map(|p| p.to_string_lossy().to_string())
+}
+
+/// 查找残留的 llama-server 进程（从本应用引擎目录启动、但不归当前应用管理），
+/// 例如应用崩溃或旧版本遗留下来、仍占用显存的实例
+/// @param terminate 为 true 时结束找到的进程（含子进程）
+/// @returns 找到的（或已结束的）进程列表
+#[tauri::command]
+pub async fn cleanup_orphaned_servers(
+    app: AppHandle,
+    state: State<'_, LocalEngineState>,
+    terminate: Option<bool>,
+) -> Result<Vec<OrphanedServer>, String> {
+    let engine_dirs = engine_dirs(&app);
+    let managed_pids: Vec<u32> = {
+        let inner = state.lock();
+        inner
+            .child_process
+            .as_ref()
+            .map(|c| c.id())
+            .into_iter()
+            .chain(inner.adopted_pid)
+            .collect()
+    };
+    let terminate = terminate.unwrap_or(false);
+    tokio::task::spawn_blocking(move || {
+        let orphans = process_tree::find_orphaned_servers(&engine_dirs, &managed_pids);
+        if terminate {
+            for orphan in &orphans {
+                tracing::info!("结束残留的本地服务器 (pid {}): {}", orphan.pid, orphan.exe_path);
+                process_tree::kill_tree(orphan.pid);
+            }
+        }
+        orphans
+    })
+    .await
+    .map_err(|e| e.to_string())
+}
+
+/// 结束占用端口的进程：只处理从本应用引擎目录启动的 llama-server（`killable` 为 true 的冲突）
+/// @returns 重新诊断的冲突信息；端口已空闲时为 None
+#[tauri::command]
+pub async fn kill_port_owner(
+    app: AppHandle,
+    state: State<'_, LocalEngineState>,
+    port: u16,
+) -> Result<Option<PortConflict>, String> {
+    if port_owner::is_port_free(port) {
+        return Ok(None);
+    }
+    let engine_dirs = engine_dirs(&app);
+    let conflict = tokio::task::spawn_blocking(move || port_owner::diagnose(port, &engine_dirs))
+        .await
+        .map_err(|e| e.to_string())?;
+    let Some(pid) = conflict.pid.filter(|_| conflict.killable) else {
+        return Err(conflict.message);
+    };
+    // 当前应用正在管理的服务器应通过 stop_local_server 停止
+    let managed = {
+        let inner = state.lock();
+        inner.child_process.as_ref().map(|c| c.id()) == Some(pid) || inner.adopted_pid == Some(pid)
+    };
+    if managed {
+        return Err("该端口由当前运行的本地服务器使用，请先停止服务器".into());
+    }
+    tracing::info!("结束占用端口 {} 的 llama-server (pid {})", port, pid);
+    tokio::task::spawn_blocking(move || process_tree::kill_tree(pid))
+        .await
+        .map_err(|e| e.to_string())?;
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while !port_owner::is_port_free(port) && Instant::now() < deadline {
+        sleep(process_tree::EXIT_POLL_INTERVAL).await;
+    }
+    if port_owner::is_port_free(port) {
+        Ok(None)
+    } else {
+        Ok(Some(conflict))
+    }
+}
+
+/// `force_reset_local_server` 的清理结果
+#[derive(Serialize, Clone, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct ForceResetReport {
+    /// 状态锁是否因 panic 中毒（中毒后状态照常可用，重置会清空其中可能不一致的进程记录）
+    pub recovered_poison: bool,
+    /// 被结束的子进程 pid
+    pub killed_child: Option<u32>,
+    /// 被结束的接管服务器 pid
+    pub killed_adopted: Option<u32>,
+    /// 检查的端口（当前状态中的端口，否则为上次启动记录中的端口）
+    pub port: Option<u16>,
+    /// 被结束的端口占用进程 pid（仅限本应用启动的 llama-server）
+    pub killed_port_owner: Option<u32>,
+    /// 清理后端口是否空闲
+    pub port_free: bool,
+}
+
+/// 强制重置卡住的本地服务器状态：不等待进行中的启动 / 停止，锁中毒时照常取回状态，
+/// 结束并清空记录的进程句柄，再结束仍占用端口的本应用 llama-server。
+/// 端口被其他程序占用时不会结束它，`port_free` 为 false
+#[tauri::command]
+pub async fn force_reset_local_server(
+    app: AppHandle,
+    state: State<'_, LocalEngineState>,
+) -> Result<ForceResetReport, String> {
+    let mut report = ForceResetReport {
+        recovered_poison: state.is_poisoned(),
+        ..Default::default()
+    };
+    let (child, adopted, port) = {
+        let mut inner = state.lock();
+        let adopted = inner.adopted_pid.take().zip(inner.adopted_identity.take());
+        let taken = (inner.child_process.take(), adopted, inner.port);
+        clear_server_info(&mut inner);
+        taken
+    };
+    if let Some(child) = child {
+        report.killed_child = Some(child.id());
+        if !process_tree::kill_child_and_wait(child, SHUTDOWN_TIMEOUT).await {
+            tracing::warn!("本地服务器在 {:?} 内未退出", SHUTDOWN_TIMEOUT);
+        }
+    }
+    if let Some((pid, identity)) = adopted {
+        if detached::kill_process(pid, &identity) {
+            report.killed_adopted = Some(pid);
+        }
+        detached::clear();
+    }
+
+    report.port = port.or_else(|| last_launch::load().map(|l| l.port));
+    let Some(port) = report.port else {
+        return Ok(report);
+    };
+    if !port_owner::is_port_free(port) {
+        let engine_dirs = engine_dirs(&app);
+        let conflict = tokio::task::spawn_blocking(move || port_owner::diagnose(port, &engine_dirs))
+            .await
+            .map_err(|e| e.to_string())?;
+        if let Some(pid) = conflict.pid.filter(|_| conflict.killable) {
+            tracing::info!("强制重置：结束占用端口 {} 的 llama-server (pid {})", port, pid);
+            tokio::task::spawn_blocking(move || process_tree::kill_tree(pid))
+                .await
+                .map_err(|e| e.to_string())?;
+            report.killed_port_owner = Some(pid);
+        }
+    }
+    // 结束了进程时端口可能仍短暂占用，等待释放
+    let killed_any = report.killed_child.is_some()
+        || report.killed_adopted.is_some()
+        || report.killed_port_owner.is_some();
+    let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+    while killed_any && !port_owner::is_port_free(port) && Instant::now() < deadline {
+        sleep(process_tree::EXIT_POLL_INTERVAL).await;
+    }
+    report.port_free = port_owner::is_port_free(port);
+    Ok(report)
+}
+
+/// 本应用可能启动 llama-server 的目录：自动安装的引擎目录与 bundled 资源目录
+fn engine_dirs(app: &AppHandle) -> Vec<PathBuf> {
+    let mut dirs = vec![EngineInstaller::get_engine_dir(app)];
+    if let Ok(dir) = app.path().resolve("resources/engines/llama-cpp", BaseDirectory::Resource) {
+        dirs.push(dir);
+    }
+    dirs
+}
+
+/// 获取 llama-server 的构建号 / commit，并检查是否满足应用使用的参数
+#[tauri::command]
+pub async fn get_backend_version(app: AppHandle) -> Result<BackendVersion, String> {
+    let exe_path = llama_cpp::resolve_exe_path(&app)?;
+    tokio::task::spawn_blocking(move || backend_version::detect(&exe_path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
 /// 获取所有引擎的安装状态
 #[tauri::command]
 pub async fn get_engines_status(app: AppHandle) -> Result<Vec<EngineStatus>, String> {
@@ -79,7 +716,7 @@ pub async fn get_engines_status(app: AppHandle) -> Result<Vec<EngineStatus>, Str
     // llama.cpp 状态
     let installed = EngineInstaller::is_installed(&app);
     let version = EngineInstaller::get_installed_version(&app);
-    let latest = EngineInstaller::fetch_latest_release()
+    let latest = EngineInstaller::fetch_latest_release(&app)
         .await
         .map(|r| r.tag_name)
         .ok();
@@ -125,13 +762,16 @@ pub async fn get_engines_status(app: AppHandle) -> Result<Vec<EngineStatus>, Str
 }
 
 /// 安装/更新 llama.cpp 引擎（后台任务，通过 Tauri Event 发射进度）
+///
+/// - `variant`: 构建变体（cpu / cuda / vulkan），不传时按平台与 GPU 自动选择；
+///   下载中断后再次调用会从断点继续
 #[tauri::command]
-pub async fn install_engine(app: AppHandle) -> Result<String, String> {
+pub async fn install_engine(app: AppHandle, variant: Option<BackendVariant>) -> Result<String, String> {
     let app_clone = app.clone();
     let progress = move |p: f64| {
         let _ = app_clone.emit("engine-install-progress", p);
     };
-    EngineInstaller::install(&app, progress).await
+    EngineInstaller::install(&app, variant, progress).await
 }
 
 /// 检查 llama.cpp 是否有更新
@@ -0,0 +1,182 @@
+//! 图像生成命令
+//!
+//! 调用 OpenAI 兼容的 `/images/generations`，同时兼容两种返回方式：
+//! - `b64_json`：解码为 PNG 写入应用缓存目录，返回本地路径
+//! - `url`：直接返回远程地址；`download` 为 true 时下载到缓存目录后返回本地路径
+//!
+//! base URL 推导与聊天接口一致（见 [`api_base_url`]）。
+
+use crate::commands::llm::{api_base_url, provider_error};
+use crate::core::state::HttpClientState;
+use base64::{engine::general_purpose, Engine as _};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// 生成请求总超时（高分辨率图片可能需要一分钟以上）
+const GENERATE_TIMEOUT: Duration = Duration::from_secs(180);
+/// 下载远程图片的超时
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(60);
+/// 生成图片的缓存目录（位于应用缓存目录下）
+const IMAGE_CACHE_DIR: &str = "images";
+/// 单次生成数量上限
+const MAX_IMAGES: u32 = 10;
+
+/// 响应 `data` 中的一张图片
+#[derive(Debug, PartialEq)]
+enum GeneratedImage {
+    Base64(String),
+    Url(String),
+}
+
+/// 解析 `{"data":[{"b64_json":...}|{"url":...}]}`
+fn parse_generated_images(body: &serde_json::Value) -> Result<Vec<GeneratedImage>, String> {
+    let data = body["data"]
+        .as_array()
+        .ok_or_else(|| "图像生成响应中缺少 data 字段".to_string())?;
+    let images: Vec<GeneratedImage> = data
+        .iter()
+        .filter_map(|item| {
+            if let Some(b64) = item["b64_json"].as_str() {
+                Some(GeneratedImage::Base64(b64.to_string()))
+            } else {
+                item["url"]
+                    .as_str()
+                    .map(|u| GeneratedImage::Url(u.to_string()))
+            }
+        })
+        .collect();
+    if images.is_empty() {
+        return Err("图像生成响应中没有图片".to_string());
+    }
+    Ok(images)
+}
+
+/// 写入缓存目录，返回路径字符串
+async fn write_image(dir: &Path, bytes: &[u8], extension: &str) -> Result<String, String> {
+    let path: PathBuf = dir.join(format!("{}.{}", uuid::Uuid::new_v4(), extension));
+    tokio::fs::write(&path, bytes)
+        .await
+        .map_err(|e| format!("保存图片失败: {}", e))?;
+    Ok(path.to_string_lossy().to_string())
+}
+
+/// 远程图片的扩展名：按 Content-Type 判断，默认 png
+fn image_extension(content_type: Option<&str>) -> &'static str {
+    match content_type.unwrap_or("") {
+        t if t.starts_with("image/jpeg") => "jpg",
+        t if t.starts_with("image/webp") => "webp",
+        _ => "png",
+    }
+}
+
+/// 图像生成：POST `/images/generations`，返回本地图片路径（或远程 URL）列表
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn generate_image(
+    app: AppHandle,
+    http: tauri::State<'_, HttpClientState>,
+    api_url: String,
+    api_key: String,
+    model: String,
+    prompt: String,
+    size: String,
+    n: u32,
+    download: Option<bool>,
+) -> Result<Vec<String>, String> {
+    if prompt.trim().is_empty() {
+        return Err("图像描述不能为空".to_string());
+    }
+    let n = n.clamp(1, MAX_IMAGES);
+    let client = http.client();
+
+    let response = client
+        .post(format!("{}/images/generations", api_base_url(&api_url)))
+        .timeout(GENERATE_TIMEOUT)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(&serde_json::json!({
+            "model": model,
+            "prompt": prompt,
+            "size": size,
+            "n": n,
+        }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await.unwrap_or_default();
+        return Err(provider_error(status, &body));
+    }
+    let body: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+    let images = parse_generated_images(&body)?;
+
+    let dir = app
+        .path()
+        .app_cache_dir()
+        .map_err(|e| e.to_string())?
+        .join(IMAGE_CACHE_DIR);
+    tokio::fs::create_dir_all(&dir)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut results = Vec::with_capacity(images.len());
+    for image in images {
+        match image {
+            GeneratedImage::Base64(b64) => {
+                let bytes = general_purpose::STANDARD
+                    .decode(b64.trim())
+                    .map_err(|e| format!("图片 base64 解码失败: {}", e))?;
+                results.push(write_image(&dir, &bytes, "png").await?);
+            }
+            GeneratedImage::Url(url) if download.unwrap_or(false) => {
+                let res = client
+                    .get(&url)
+                    .timeout(DOWNLOAD_TIMEOUT)
+                    .send()
+                    .await
+                    .map_err(|e| format!("下载图片失败: {}", e))?;
+                if !res.status().is_success() {
+                    return Err(format!("下载图片失败: HTTP {}", res.status()));
+                }
+                let extension = image_extension(
+                    res.headers()
+                        .get(reqwest::header::CONTENT_TYPE)
+                        .and_then(|v| v.to_str().ok()),
+                );
+                let bytes = res
+                    .bytes()
+                    .await
+                    .map_err(|e| format!("下载图片失败: {}", e))?;
+                results.push(write_image(&dir, &bytes, extension).await?);
+            }
+            GeneratedImage::Url(url) => results.push(url),
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn parses_both_response_modes() {
+        let body = json!({
+            "data": [
+                { "b64_json": "iVBORw0KGgo=" },
+                { "url": "https://example.com/a.png", "revised_prompt": "a cat" }
+            ]
+        });
+        assert_eq!(
+            parse_generated_images(&body).unwrap(),
+            vec![
+                GeneratedImage::Base64("iVBORw0KGgo=".into()),
+                GeneratedImage::Url("https://example.com/a.png".into()),
+            ]
+        );
+        assert!(parse_generated_images(&json!({ "data": [] })).is_err());
+        assert!(parse_generated_images(&json!({ "created": 1 })).is_err());
+    }
+}
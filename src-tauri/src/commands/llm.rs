@@ -3,22 +3,178 @@ use crate::commands::attachment::sync_message_attachments;
 use base64::{engine::general_purpose, Engine as _};
 use rusqlite::params;
 use crate::core::models::*;
-use crate::core::state::StreamManager;
+use crate::core::state::{
+    ChatRound, HttpClientState, LocalEngineState, StreamManager, ToolRoundManager,
+};
+use crate::utils::file_parser::path_in_sandbox;
+use crate::utils::llm_stream::{StreamDecoder, StreamFormat, StreamOutput, TokenLogprob};
+use crate::utils::markdown::finalize_display_text;
+use crate::utils::sse::SseParser;
+use crate::utils::tokens;
 use futures_util::StreamExt; // 用于处理流式数据
 use serde::Serialize;
 use serde_json::json;
+use std::collections::HashSet;
+use std::path::PathBuf;
 use std::time::Duration;
-use tauri::{Emitter, Window}; // Emitter 用于从后端向前端推送事件
+use tauri::{Emitter, Manager, Window}; // Emitter 用于从后端向前端推送事件
 
-/// 构造带超时的 reqwest 客户端（防止 DoS）
-fn http_client() -> reqwest::Client {
-    reqwest::Client::builder()
-        .connect_timeout(Duration::from_secs(5))
-        .timeout(Duration::from_secs(60))
-        .build()
-        .unwrap_or_else(|_| reqwest::Client::new())
+/// 请求发往本地推理服务器且包含图片时，要求该服务器已加载 mmproj
+fn ensure_image_capability(
+    engine: &LocalEngineState,
+    api_url: &str,
+    messages: &[serde_json::Value],
+) -> Result<(), String> {
+    let has_images = messages.iter().any(|m| {
+        m.get("content")
+            .and_then(|c| c.as_array())
+            .is_some_and(|parts| {
+                parts
+                    .iter()
+                    .any(|p| p.get("type").and_then(|t| t.as_str()) == Some("image_url"))
+            })
+    });
+    if !has_images {
+        return Ok(());
+    }
+    let inner = engine.lock();
+    if inner.serves_url(api_url) && !inner.supports_images {
+        return Err("当前本地模型未加载 mmproj 多模态投影文件，无法发送图片。请在启动本地服务器时指定 mmproj 文件。".into());
+    }
+    Ok(())
+}
+
+/// 请求发往本地推理服务器时使用其本次启动的 API key，
+/// 避免前端保存的旧 key（每次启动都会重新生成）导致 401
+pub(crate) fn resolve_api_key(engine: &LocalEngineState, api_url: &str, api_key: String) -> String {
+    let inner = engine.lock();
+    match &inner.api_key {
+        Some(local_key) if inner.serves_url(api_url) => local_key.clone(),
+        _ => api_key,
+    }
+}
+
+/// 请求发往本地推理服务器时，返回其启动时生效的上下文长度
+fn local_ctx_size(engine: &LocalEngineState, api_url: &str) -> Option<u32> {
+    let inner = engine.lock();
+    if inner.serves_url(api_url) {
+        inner.ctx_size
+    } else {
+        None
+    }
+}
+
+/// 发送前的上下文预算检查：已知上下文长度时估算请求 token 数，
+/// 超出则报错，或在 `auto_trim` 时丢弃最旧的历史消息（保留 system 与最新 user 消息）
+#[allow(clippy::too_many_arguments)]
+fn enforce_context_budget(
+    window: &Window,
+    model: &str,
+    assistant_id: &str,
+    topic_id: &str,
+    mut messages: Vec<serde_json::Value>,
+    pinned: usize,
+    context_length: Option<u32>,
+    auto_trim: bool,
+) -> Result<Vec<serde_json::Value>, String> {
+    let context_length = match context_length.map(u64::from).or_else(|| {
+        crate::commands::catalog::catalog_context_window(window.app_handle(), model)
+    }) {
+        Some(n) => n as usize,
+        None => return Ok(messages),
+    };
+    let budget = tokens::input_budget(context_length);
+    let estimated = tokens::estimate_messages_tokens(&messages);
+    if estimated <= budget {
+        return Ok(messages);
+    }
+    if !auto_trim {
+        return Err(format!(
+            "请求约 {} tokens，超出模型 {} 的上下文窗口（{} tokens，预留输出后可用 {}）。请精简对话或开启自动裁剪。",
+            estimated, model, context_length, budget
+        ));
+    }
+
+    let trimmed = tokens::trim_to_budget(&mut messages, budget, pinned);
+    let remaining = tokens::estimate_messages_tokens(&messages);
+    if remaining > budget {
+        return Err(format!(
+            "已丢弃 {} 条历史消息，但 system 提示与最新消息仍约 {} tokens，超出模型 {} 的可用上下文（{} tokens）。",
+            trimmed, remaining, model, budget
+        ));
+    }
+    let _ = window.emit(
+        "llm-context-trimmed",
+        ContextTrimmedPayload {
+            assistant_id: assistant_id.to_string(),
+            topic_id: topic_id.to_string(),
+            trimmed,
+            estimated_tokens: remaining,
+            context_length,
+        },
+    );
+    Ok(messages)
 }
 
+/// 由用户配置的 api_url 推导 OpenAI 兼容接口的 base URL：去掉末尾斜杠与 `/chat/completions` 后缀
+pub(crate) fn api_base_url(api_url: &str) -> String {
+    api_url
+        .trim_end_matches('/')
+        .replace("/chat/completions", "")
+}
+
+/// 模型名嵌入 URL 时需转义的字符（保留 RFC 3986 unreserved 字符）
+const MODEL_PATH_ESCAPE: &percent_encoding::AsciiSet = &percent_encoding::NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~');
+
+/// 对话接口地址：有 `endpoint_template` 时替换 `{model}` 并校验为合法的 http(s) URL，
+/// 否则在 api_url 后补全 `/chat/completions`
+fn chat_endpoint(candidate: &ModelRef) -> Result<String, String> {
+    let Some(template) = candidate
+        .endpoint_template
+        .as_deref()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+    else {
+        let api_url = candidate.api_url.trim_end_matches('/');
+        return Ok(if api_url.ends_with("/chat/completions") {
+            api_url.to_string()
+        } else {
+            format!("{}/chat/completions", api_url)
+        });
+    };
+    let model = percent_encoding::utf8_percent_encode(&candidate.model, MODEL_PATH_ESCAPE);
+    let endpoint = template.replace("{model}", &model.to_string());
+    let parsed = url::Url::parse(&endpoint)
+        .map_err(|e| format!("接口地址模板无效（{}）: {}", endpoint, e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none() {
+        return Err(format!("接口地址模板必须是 http(s) URL: {}", endpoint));
+    }
+    Ok(parsed.to_string())
+}
+
+/// 非 2xx 响应的可读错误：优先取 `{"error":{"message":...}}`，否则截断原始响应体
+pub(crate) fn provider_error(status: reqwest::StatusCode, body: &str) -> String {
+    let message = serde_json::from_str::<serde_json::Value>(body)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(String::from))
+        .unwrap_or_else(|| body.chars().take(512).collect());
+    format!("服务商返回 {}: {}", status, message)
+}
+
+/// OpenAI `top_logprobs` 的上限
+const MAX_TOP_LOGPROBS: u32 = 20;
+
+/// LLM 请求总超时（防止 DoS）；连接超时由共享客户端统一设置
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+/// 流式生成历史摘要的总超时（长历史可能超过一分钟）
+const SUMMARY_TIMEOUT: Duration = Duration::from_secs(300);
+/// 流式摘要写回 topics.summary 的最小间隔
+const SUMMARY_PERSIST_INTERVAL: Duration = Duration::from_secs(2);
+
 /// 流式 tool_call 累积载荷（发往前端用）
 #[derive(Serialize, Clone)]
 pub struct ToolCallPayload {
@@ -27,6 +183,237 @@ pub struct ToolCallPayload {
     pub tool_call_id: String,
     pub name: String,
     pub arguments: String,
+    /// 调用方指定的请求 ID（与 llm-chunk 相同，用于区分同一话题内的并发流）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// 流式 token 对数概率载荷（发往前端用）
+#[derive(Serialize, Clone)]
+pub struct LogprobPayload {
+    pub assistant_id: String,
+    pub topic_id: String,
+    pub tokens: Vec<TokenLogprob>,
+    /// 调用方指定的请求 ID（与 llm-chunk 相同，用于区分同一话题内的并发流）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// 工具调用状态事件：模型请求调用 → 本地执行中 → 完成 / 失败
+pub const TOOL_STATUS_EVENT: &str = "tool-status";
+
+/// 工具调用所处阶段
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ToolStatus {
+    /// 模型在回复末尾请求调用该工具
+    Requested,
+    /// 工具正在本地执行（由 call_mcp_tool 发出）
+    Running,
+    /// 结果已通过 continue_with_tool_result 送回
+    Done,
+    /// 执行失败，错误信息已作为 tool 消息送回
+    Error,
+}
+
+/// [`TOOL_STATUS_EVENT`] 载荷（发往前端用）
+#[derive(Serialize, Clone)]
+pub struct ToolStatusPayload {
+    pub assistant_id: String,
+    pub topic_id: String,
+    pub request_id: String,
+    pub tool_call_id: String,
+    pub name: String,
+    pub state: ToolStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 发出一条工具调用状态事件
+pub(crate) fn emit_tool_status<R: tauri::Runtime>(
+    emitter: &impl Emitter<R>,
+    round: &ChatRound,
+    request_id: &str,
+    tool_call_id: &str,
+    name: &str,
+    state: ToolStatus,
+    error: Option<String>,
+) {
+    let _ = emitter.emit(
+        TOOL_STATUS_EVENT,
+        ToolStatusPayload {
+            assistant_id: round.assistant_id.clone(),
+            topic_id: round.topic_id.clone(),
+            request_id: request_id.to_string(),
+            tool_call_id: tool_call_id.to_string(),
+            name: name.to_string(),
+            state,
+            error,
+        },
+    );
+}
+
+/// 流式任务的归属：决定任务键与事件中回传的标识
+#[derive(Clone, Copy)]
+struct StreamTarget<'a> {
+    assistant_id: &'a str,
+    topic_id: &'a str,
+    request_id: Option<&'a str>,
+    model_id: Option<&'a str>,
+}
+
+impl StreamTarget<'_> {
+    fn payload(&self, content: String, done: bool) -> StreamPayload {
+        StreamPayload {
+            assistant_id: self.assistant_id.to_string(),
+            topic_id: self.topic_id.to_string(),
+            content,
+            done,
+            request_id: self.request_id.map(str::to_string),
+            model_id: self.model_id.map(str::to_string),
+        }
+    }
+}
+
+/// StreamManager 中的任务键：有 request_id 时为 "req:<request_id>"，否则为 "助手ID-话题ID"；
+/// 同一话题的多个并发流（如多模型对比）需各自传入不同的 request_id
+fn stream_task_key(assistant_id: &str, topic_id: &str, request_id: Option<&str>) -> String {
+    match request_id {
+        Some(id) => request_task_key(id),
+        None => format!("{}-{}", assistant_id, topic_id),
+    }
+}
+
+/// 按 request_id 登记的任务键；加前缀以免调用方传入的 ID 恰好等于某个 "助手ID-话题ID"
+fn request_task_key(request_id: &str) -> String {
+    format!("req:{}", request_id)
+}
+
+/// 主模型请求失败、改用故障转移链中的下一个模型（发往前端用）
+#[derive(Serialize, Clone)]
+pub struct FallbackPayload {
+    pub assistant_id: String,
+    pub topic_id: String,
+    /// 失败的模型
+    pub failed_model: String,
+    /// 接下来尝试的模型
+    pub model: String,
+    pub reason: String,
+    /// 调用方指定的请求 ID（与 llm-chunk 相同，用于区分同一话题内的并发流）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// 发送前按上下文预算裁剪了历史消息（发往前端用）
+#[derive(Serialize, Clone)]
+pub struct ContextTrimmedPayload {
+    pub assistant_id: String,
+    pub topic_id: String,
+    pub trimmed: usize,
+    pub estimated_tokens: usize,
+    pub context_length: usize,
+}
+
+/// 历史摘要生成进度（发往前端用）
+#[derive(Serialize, Clone)]
+pub struct SummaryProgressPayload {
+    pub topic_id: Option<String>,
+    /// 已生成的摘要字数
+    pub chars: usize,
+    /// 与已有摘要合并后的当前内容
+    pub summary: String,
+    pub done: bool,
+}
+
+/// 历史消息中默认剥离的思维链包裹标签
+const DEFAULT_REASONING_TAGS: &[(&str, &str)] = &[("<think>", "</think>")];
+
+/// 去掉文本中的思维链标签块（含标签本身）。
+///
+/// - 成对的 `开始…结束` 整块删除
+/// - 只有结束标签（开始标签由对话模板注入、未出现在输出中）时，删除结束标签及之前的内容
+/// - 只有开始标签（输出被中断）时，删除开始标签及之后的内容
+fn strip_reasoning_tags(text: &str, tags: &[(String, String)]) -> String {
+    let mut out = text.to_string();
+    for (open, close) in tags {
+        if open.is_empty() || close.is_empty() {
+            continue;
+        }
+        if let Some(end) = out.find(close.as_str()) {
+            if out.find(open.as_str()).map_or(true, |start| start > end) {
+                out.replace_range(..end + close.len(), "");
+            }
+        }
+        while let Some(start) = out.find(open.as_str()) {
+            match out[start..].find(close.as_str()) {
+                Some(len) => out.replace_range(start..start + len + close.len(), ""),
+                None => {
+                    out.truncate(start);
+                    break;
+                }
+            }
+        }
+    }
+    if out.len() == text.len() {
+        out
+    } else {
+        out.trim().to_string()
+    }
+}
+
+/// 对消息 content（纯文本或 parts 数组中的文本部分）剥离思维链标签块
+fn strip_reasoning_content(
+    content: &serde_json::Value,
+    tags: &[(String, String)],
+) -> serde_json::Value {
+    match content {
+        serde_json::Value::String(text) => json!(strip_reasoning_tags(text, tags)),
+        serde_json::Value::Array(parts) => serde_json::Value::Array(
+            parts
+                .iter()
+                .map(|part| match part["text"].as_str() {
+                    Some(text) if part["type"] == "text" => {
+                        let mut part = part.clone();
+                        part["text"] = json!(strip_reasoning_tags(text, tags));
+                        part
+                    }
+                    _ => part.clone(),
+                })
+                .collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// 把置顶消息移到开头的 system 消息之后（保持原有顺序），返回重排后的消息与置顶条数。
+///
+/// `pinned` 来自数据库（前端可能只传了最近的消息），与前端列表中同 id 的消息去重；
+/// 最后一条 user 消息及其之后的消息属于本轮请求，即使被置顶也留在原位。
+fn hoist_pinned(messages: Vec<Message>, pinned: Vec<Message>) -> (Vec<Message>, usize) {
+    let last_user = messages
+        .iter()
+        .rposition(|m| m.role == "user")
+        .unwrap_or(messages.len());
+    let current_turn: HashSet<&str> = messages[last_user..]
+        .iter()
+        .filter_map(|m| m.id.as_deref())
+        .collect();
+    let pinned: Vec<Message> = pinned
+        .into_iter()
+        .filter(|m| m.is_pinned && matches!(m.role.as_str(), "user" | "assistant"))
+        .filter(|m| m.tool_calls.is_none() && m.resolved_status() != message_status::ERROR)
+        .filter(|m| m.id.as_deref().is_some_and(|id| !current_turn.contains(id)))
+        .collect();
+    let pinned_ids: HashSet<String> = pinned.iter().filter_map(|m| m.id.clone()).collect();
+
+    let system_prefix = messages.iter().take_while(|m| m.role == "system").count();
+    let count = pinned.len();
+    let mut result = Vec::with_capacity(messages.len() + count);
+    let mut rest = messages.into_iter();
+    result.extend(rest.by_ref().take(system_prefix));
+    result.extend(pinned);
+    result.extend(rest.filter(|m| m.id.as_ref().map_or(true, |id| !pinned_ids.contains(id))));
+    (result, count)
 }
 
 fn message_for_api(
@@ -115,285 +502,729 @@ fn message_for_api(
 /// 核心函数：调用 LLM 并分块回传结果（流式输出）
 /// #[tauri::command] 允许前端通过 invoke 调用
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn call_llm_stream(
     window: Window,                         // Tauri 窗口句柄，用于发送事件
     state: tauri::State<'_, StreamManager>, // 全局状态，用于管理正在进行的流任务
     db_state: tauri::State<'_, DbState>,
-    mut api_url: String,                    // API 地址
-    api_key: String,                        // API 密钥
-    model: String,                          // 模型名称（如 gpt-3.5-turbo）
+    engine_state: tauri::State<'_, LocalEngineState>,
+    primary: ModelRef,                      // 主模型（API 地址、密钥、模型名称，可带接口地址模板）
     assistant_id: String,                   // 助手 ID（用于前端匹配消息）
     topic_id: String,                       // 话题/会话 ID
     messages: Vec<Message>,                 // 历史上下文消息列表
     tools: Option<Vec<ToolSpec>>,           // 工具定义（MCP 工具，None 或空数组则不发送）
+    context_length: Option<u32>,            // 上下文窗口覆盖值（None 时依次查本地服务器、catalog）
+    auto_trim: Option<bool>,                // 超出上下文时是否自动丢弃最旧的历史消息
+    logprobs: Option<u32>,                  // 返回每个 token 的对数概率及前 n 个候选（0~20），通过 llm-logprob 事件推送
+    history_limit: Option<usize>,           // 只发送最近 N 条历史消息（开头的 system 消息与置顶消息始终保留），None 时发送全部
+    fallback_models: Option<Vec<ModelRef>>, // 故障转移链：主模型 429/5xx/超时且尚未输出内容时依次尝试
+    strip_reasoning_from_history: Option<bool>, // 发送前去掉历史 assistant 消息中的思维链标签块，默认开启
+    reasoning_tags: Option<Vec<(String, String)>>, // 思维链包裹标签（开始, 结束），默认 <think> / </think>
+    request_id: Option<String>,             // 请求 ID：传入时作为任务 Key 并在 llm-chunk 中回传，允许同一话题并发多个流
+    model_key: Option<String>,              // 前端的模型复合键（云端为 model_id@api_url），记为话题上次使用的模型；缺省时记 model
 ) -> Result<(), String> {
-    // 1. 生成唯一的任务 Key，格式为 "助手ID-话题ID"
-    let task_key = format!("{}-{}", assistant_id, topic_id);
+    // 1. 生成唯一的任务 Key：优先使用 request_id，否则为 "助手ID-话题ID"
+    let task_key = stream_task_key(&assistant_id, &topic_id, request_id.as_deref());
 
     // 2. 如果当前 Key 已有任务在运行，先终止旧任务（防止一个对话框出现两个回复）
     if let Some((_, old_handle)) = state.0.remove(&task_key) {
         old_handle.abort();
     }
 
-    // 3. 克隆变量以便进入异步线程（move 闭包）
-    let state_inner = state.0.clone();
-    let task_key_inner = task_key.clone();
-    let assistant_id_c = assistant_id.clone();
-    let topic_id_c = topic_id.clone();
-    let messages_for_api = {
+    let (mut messages_for_api, pinned) = {
         let conn = db_state.0.lock().map_err(|e| e.to_string())?;
-        messages
+        let has_pinned: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM messages WHERE topic_id = ?1 AND is_pinned = 1)",
+                [&topic_id],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        let pinned_history = if has_pinned {
+            crate::commands::config::load_topic_history(&conn, &topic_id)?
+        } else {
+            Vec::new()
+        };
+        let (mut messages, pinned) = hoist_pinned(messages, pinned_history);
+        if strip_reasoning_from_history.unwrap_or(true) {
+            let tags = reasoning_tags.unwrap_or_else(|| {
+                DEFAULT_REASONING_TAGS
+                    .iter()
+                    .map(|(open, close)| (open.to_string(), close.to_string()))
+                    .collect()
+            });
+            // 最后一条消息属于本轮请求，保持原样
+            let history_len = messages.len().saturating_sub(1);
+            for message in messages[..history_len]
+                .iter_mut()
+                .filter(|m| m.role == "assistant")
+            {
+                message.content = strip_reasoning_content(&message.content, &tags);
+            }
+        }
+        let messages = messages
             .iter()
             .map(|message| message_for_api(&conn, message))
-            .collect::<Result<Vec<_>, _>>()?
+            .collect::<Result<Vec<_>, _>>()?;
+        (messages, pinned)
     };
+    if let Some(limit) = history_limit {
+        tokens::trim_to_count(&mut messages_for_api, limit, pinned);
+    }
+    ensure_image_capability(&engine_state, &primary.api_url, &messages_for_api)?;
+    let context_length = context_length.or_else(|| local_ctx_size(&engine_state, &primary.api_url));
+    let messages_for_api = enforce_context_budget(
+        &window,
+        &primary.model,
+        &assistant_id,
+        &topic_id,
+        messages_for_api,
+        pinned,
+        context_length,
+        auto_trim.unwrap_or(false),
+    )?;
+    // 主模型在前，故障转移链依次在后；不支持图片的本地服务器不参与转移
+    let mut candidates = vec![ModelRef {
+        api_key: resolve_api_key(&engine_state, &primary.api_url, primary.api_key),
+        ..primary
+    }];
+    for fallback in fallback_models.unwrap_or_default() {
+        if ensure_image_capability(&engine_state, &fallback.api_url, &messages_for_api).is_err() {
+            continue;
+        }
+        candidates.push(ModelRef {
+            api_key: resolve_api_key(&engine_state, &fallback.api_url, fallback.api_key),
+            ..fallback
+        });
+    }
+    // 地址模板有误时直接报错，而不是在后台任务中失败
+    for candidate in &candidates {
+        chat_endpoint(candidate)?;
+    }
+    // 请求通过全部校验后才记为话题上次使用的模型
+    {
+        let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+        let model_key = model_key.as_deref().unwrap_or(&candidates[0].model);
+        crate::commands::config::remember_topic_model(&conn, &topic_id, model_key)?;
+    }
+
+    // 3. 创建异步任务执行请求，并登记句柄以便后续可以“手动停止”
+    spawn_chat_round(
+        window,
+        &state,
+        request_id,
+        ChatRound {
+            assistant_id,
+            topic_id,
+            candidates,
+            messages: messages_for_api,
+            tools,
+            logprobs,
+            pending_tools: Default::default(),
+            waiting_since: std::time::Instant::now(),
+        },
+    );
+    Ok(())
+}
+
+/// 在后台执行一轮流式对话，任务句柄登记在 StreamManager 中。
+///
+/// 传入 `request_id` 且回复以工具调用结束时，本轮上下文先登记到 [`ToolRoundManager`]，
+/// 再为每个工具调用发出 requested 状态；`continue_with_tool_result` 送回全部结果后续写。
+/// 超时未收齐结果的轮次在下次登记时清理。
+/// 未传入 `request_id` 时由前端自行重新调用 call_llm_stream 续写。
+fn spawn_chat_round(
+    window: Window,
+    state: &StreamManager,
+    request_id: Option<String>,
+    round: ChatRound,
+) {
+    let task_key = stream_task_key(&round.assistant_id, &round.topic_id, request_id.as_deref());
+    if let Some((_, old_handle)) = state.0.remove(&task_key) {
+        old_handle.abort();
+    }
+    let state_inner = state.0.clone();
+    let task_key_inner = task_key.clone();
 
-    // 4. 创建异步任务执行请求
     let handle = tokio::spawn(async move {
-        let result: Result<(), String> = async {
-            // 安全处理 URL，确保以 /chat/completions 结尾
-            api_url = api_url.trim_end_matches('/').to_string();
-            let final_url = if !api_url.ends_with("/chat/completions") {
-                format!("{}/chat/completions", api_url)
-            } else {
-                api_url
-            };
+        let mut round = round;
+        let target = StreamTarget {
+            assistant_id: &round.assistant_id,
+            topic_id: &round.topic_id,
+            request_id: request_id.as_deref(),
+            model_id: None,
+        };
+        let result = run_chat_stream(
+            &window,
+            target,
+            &round.candidates,
+            &round.messages,
+            round.tools.as_deref(),
+            round.logprobs,
+        )
+        .await;
 
-            let client = http_client();
-
-            // 构造符合 OpenAI API 标准的消息格式
-            // 支持 role="tool"（带 tool_call_id）和 assistant 携带 tool_calls
-            // 构造请求体，开启 stream 模式
-            // 若传入 tools 且非空，则附加到 body
-            let mut body_map = serde_json::Map::new();
-            body_map.insert("model".into(), json!(model));
-            body_map.insert("messages".into(), json!(messages_for_api));
-            body_map.insert("stream".into(), json!(true));
-            if let Some(tools) = &tools {
-                if !tools.is_empty() {
-                    body_map.insert("tools".into(), json!(tools));
-                    body_map.insert("tool_choice".into(), json!("auto"));
+        match result {
+            // 错误处理：如果请求失败，发送错误信息给前端
+            Err(e) => {
+                tracing::error!("Stream Error: {}", e);
+                let _ = window.emit("llm-chunk", target.payload(format!("\n[Error: {}]", e), true));
+            }
+            Ok(reply) if !reply.tool_calls.is_empty() => {
+                if let Some(request_id) = request_id.as_deref() {
+                    round.await_tools(&reply.content, &reply.tool_calls);
+                    // 先登记再通知：前端收到 requested 后立即送回结果时，轮次必须已可查到
+                    let rounds = window.state::<ToolRoundManager>();
+                    rounds.wait_for_results(request_id.to_string(), round);
+                    if let Some(round) = rounds.0.get(request_id) {
+                        for call in &reply.tool_calls {
+                            emit_tool_status(
+                                &window,
+                                &round,
+                                request_id,
+                                &call.id,
+                                &call.function.name,
+                                ToolStatus::Requested,
+                                None,
+                            );
+                        }
+                    };
                 }
             }
-            let body = serde_json::Value::Object(body_map);
-
-            // 发送 POST 请求
-            let response = client
-                .post(&final_url)
-                .header("Authorization", format!("Bearer {}", api_key))
-                .json(&body)
-                .send()
-                .await
-                .map_err(|e| e.to_string())?;
-
-            // 检查 HTTP 状态码：非 2xx 时提前报错，避免对错误 JSON 走 SSE 解析
-            let status = response.status();
-            if !status.is_success() {
-                let body_text = response.text().await.unwrap_or_default();
-                let truncated = if body_text.len() > 512 { &body_text[..512] } else { &body_text };
-                return Err(format!("LLM API {}: {}", status, truncated));
+            Ok(_) => {}
+        }
+
+        // 任务完成后，从全局状态中移除 handle
+        state_inner.remove(&task_key_inner);
+    });
+    state.0.insert(task_key, handle);
+}
+
+/// 送回一个工具调用的执行结果：追加 role="tool" 消息并发出 done / error 状态。
+/// 本轮全部工具调用都有结果后，以同一 `request_id` 续写回复（事件与 call_llm_stream 相同，
+/// 续写的回复再次请求工具时继续等待结果）。返回是否已开始续写
+#[tauri::command]
+pub async fn continue_with_tool_result(
+    window: Window,
+    state: tauri::State<'_, StreamManager>,
+    rounds: tauri::State<'_, ToolRoundManager>,
+    request_id: String,
+    tool_call_id: String,
+    result: String,
+    is_error: Option<bool>,
+) -> Result<bool, String> {
+    rounds.prune_expired();
+    {
+        let mut round = rounds
+            .0
+            .get_mut(&request_id)
+            .ok_or_else(|| format!("请求 {} 没有等待结果的工具调用", request_id))?;
+        let name = round.record_tool_result(&tool_call_id, &result)?;
+        let (status, error) = if is_error.unwrap_or(false) {
+            (ToolStatus::Error, Some(result))
+        } else {
+            (ToolStatus::Done, None)
+        };
+        emit_tool_status(&window, &round, &request_id, &tool_call_id, &name, status, error);
+    }
+
+    let Some((_, round)) = rounds
+        .0
+        .remove_if(&request_id, |_, round| round.ready_to_resume())
+    else {
+        return Ok(false);
+    };
+    spawn_chat_round(window, &state, Some(request_id), round);
+    Ok(true)
+}
+
+/// 重新生成一条失败（或不满意）的 AI 回复：从数据库重建该消息之前的上下文
+/// （助手提示词、启用的 Skill、话题摘要、之前的非失败消息），走与 call_llm_stream 相同的流式逻辑，
+/// 完成后原地更新该消息的内容与状态。
+/// 生成期间状态为 pending；被 stop_llm_stream 中止时保持 pending，可再次重试。
+/// 重试不注入 MCP 工具。
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn retry_message(
+    window: Window,
+    state: tauri::State<'_, StreamManager>,
+    db_state: tauri::State<'_, DbState>,
+    engine_state: tauri::State<'_, LocalEngineState>,
+    message_id: String,
+    api_url: String,
+    api_key: String,
+    model: String,
+    model_key: Option<String>,
+) -> Result<(), String> {
+    let (assistant_id, topic_id, messages_for_api) = {
+        let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+        let (topic_id, role) = conn
+            .query_row(
+                "SELECT topic_id, role FROM messages WHERE id = ?1",
+                [&message_id],
+                |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+            )
+            .map_err(|_| format!("消息不存在: {}", message_id))?;
+        if role != "assistant" {
+            return Err("只能重试 AI 回复消息".into());
+        }
+        let (assistant_id, prompt, skill_ids_json, summary) = conn
+            .query_row(
+                "SELECT t.assistant_id, a.prompt, a.skill_ids, t.summary
+                 FROM topics t JOIN assistants a ON a.id = t.assistant_id WHERE t.id = ?1",
+                [&topic_id],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, Option<String>>(1)?,
+                        row.get::<_, Option<String>>(2)?,
+                        row.get::<_, Option<String>>(3)?,
+                    ))
+                },
+            )
+            .map_err(|e| format!("读取话题失败: {}", e))?;
+
+        // 与前端发送消息时的上下文构造保持一致：system 提示词 → Skill → 摘要 → 历史
+        let mut context = Vec::new();
+        if let Some(prompt) = prompt.filter(|p| !p.trim().is_empty()) {
+            context.push(json!({ "role": "system", "content": prompt }));
+        }
+        let skill_ids: Vec<String> = skill_ids_json
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default();
+        for skill in crate::commands::skill::resolve_skills(window.app_handle(), &skill_ids) {
+            context.push(json!({
+                "role": "system",
+                "content": format!("[Skill: {}]\n{}", skill.name, skill.content),
+            }));
+        }
+        if let Some(summary) = summary.filter(|s| !s.trim().is_empty()) {
+            context.push(json!({
+                "role": "system",
+                "content": format!("这是之前对话的摘要记忆，请结合这些上下文回答：\n{}", summary),
+            }));
+        }
+        let history = crate::commands::config::load_topic_history(&conn, &topic_id)?;
+        for message in history
+            .iter()
+            .take_while(|m| m.id.as_deref() != Some(message_id.as_str()))
+            .filter(|m| matches!(m.role.as_str(), "user" | "assistant" | "system"))
+            .filter(|m| m.resolved_status() != message_status::ERROR)
+        {
+            context.push(message_for_api(&conn, message)?);
+        }
+
+        conn.execute(
+            "UPDATE messages SET status = ?1 WHERE id = ?2",
+            params![message_status::PENDING, message_id],
+        )
+        .map_err(|e| e.to_string())?;
+        (assistant_id, topic_id, context)
+    };
+
+    ensure_image_capability(&engine_state, &api_url, &messages_for_api)?;
+    let api_key = resolve_api_key(&engine_state, &api_url, api_key);
+    let context_length = local_ctx_size(&engine_state, &api_url);
+    let messages_for_api = enforce_context_budget(
+        &window,
+        &model,
+        &assistant_id,
+        &topic_id,
+        messages_for_api,
+        0,
+        context_length,
+        false,
+    )?;
+    {
+        let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+        let model_key = model_key.as_deref().unwrap_or(&model);
+        crate::commands::config::remember_topic_model(&conn, &topic_id, model_key)?;
+    }
+
+    let task_key = stream_task_key(&assistant_id, &topic_id, None);
+    if let Some((_, old_handle)) = state.0.remove(&task_key) {
+        old_handle.abort();
+    }
+    let state_inner = state.0.clone();
+    let task_key_inner = task_key.clone();
+
+    let handle = tokio::spawn(async move {
+        let target = StreamTarget {
+            assistant_id: &assistant_id,
+            topic_id: &topic_id,
+            request_id: None,
+            model_id: None,
+        };
+        let candidate = ModelRef {
+            api_url,
+            api_key,
+            model: model.clone(),
+            endpoint_template: None,
+            omit_model_field: false,
+        };
+        let result = run_chat_stream(
+            &window,
+            target,
+            std::slice::from_ref(&candidate),
+            &messages_for_api,
+            None,
+            None,
+        )
+        .await;
+
+        let (content, reasoning, status) = match result {
+            Ok(reply) => (finalize_display_text(&reply.content), Some(reply.reasoning).filter(|r| !r.is_empty()), message_status::COMPLETE),
+            Err(e) => {
+                tracing::error!("Retry Stream Error: {}", e);
+                let content = format!("[Error: {}]", e);
+                let _ = window.emit("llm-chunk", target.payload(format!("\n{}", content), true));
+                (content, None, message_status::ERROR)
+            }
+        };
+        let db = window.state::<DbState>();
+        if let Ok(conn) = db.0.lock() {
+            let content_json = crate::commands::config::encode_content(&conn, &json!(content));
+            if let Err(e) = conn.execute(
+                "UPDATE messages SET content = ?1, reasoning = ?2, status = ?3, model_id = ?4 WHERE id = ?5",
+                params![content_json, reasoning, status, model, message_id],
+            ) {
+                tracing::error!("保存重试结果失败: {}", e);
             }
+        }
+        state_inner.remove(&task_key_inner);
+    });
 
-            // 获取响应字节流
-            let mut stream = response.bytes_stream();
-            let mut line_buffer = String::new(); // 用于累积不完整的字节分块
+    state.0.insert(task_key, handle);
+    Ok(())
+}
 
-            // tool_call 累积状态：按 index 维护 id/name/arguments
-            // index → (id, name, arguments)
-            let mut tc_accum: std::collections::HashMap<usize, (String, String, String)> =
-                std::collections::HashMap::new();
+/// 一次流式请求累积得到的完整回复
+#[derive(Default)]
+struct StreamedReply {
+    content: String,
+    reasoning: String,
+    /// 回复末尾请求的工具调用
+    tool_calls: Vec<ToolCall>,
+}
 
-            // 5. 循环处理流式返回的数据块
-            while let Some(item) = stream.next().await {
-                let chunk = item.map_err(|e| e.to_string())?;
-                line_buffer.push_str(&String::from_utf8_lossy(&chunk));
+/// 请求阶段的失败；`retriable` 表示可以换下一个模型重试（见 [`should_fall_back`]）
+struct RequestFailure {
+    message: String,
+    retriable: bool,
+}
 
-                // LLM API 通常按行返回 (SSE 格式)
-                while let Some(pos) = line_buffer.find('\n') {
-                    let line = line_buffer[..pos].trim().to_string();
-                    line_buffer.drain(..pos + 1); // 从缓冲区移除已处理的行
+/// 请求失败的原因
+#[derive(Debug, Clone, Copy)]
+enum FailureCause {
+    Status(reqwest::StatusCode),
+    Timeout,
+    Connect,
+    Other,
+}
 
-                    if line.is_empty() {
-                        continue;
-                    }
+/// 能否换故障转移链中的下一个模型：仅限尚未向前端输出任何内容时的 429 / 5xx / 超时 / 连接失败；
+/// 其他 4xx（鉴权、参数错误）换模型也无济于事，已输出部分内容时重试会让回复重复
+fn should_fall_back(cause: FailureCause, output_started: bool) -> bool {
+    if output_started {
+        return false;
+    }
+    match cause {
+        FailureCause::Status(status) => {
+            status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+        }
+        FailureCause::Timeout | FailureCause::Connect => true,
+        FailureCause::Other => false,
+    }
+}
 
-                    // 检查是否流传输结束
-                    if line == "data: [DONE]" {
-                        // 在结束前 flush 累积中的 tool_calls
-                        for (_idx, (id, name, args)) in tc_accum.drain() {
-                            if !id.is_empty() && !name.is_empty() {
-                                let _ = window.emit(
-                                    "llm-tool-call",
-                                    ToolCallPayload {
-                                        assistant_id: assistant_id_c.clone(),
-                                        topic_id: topic_id_c.clone(),
-                                        tool_call_id: id,
-                                        name,
-                                        arguments: args,
-                                    },
-                                );
-                            }
-                        }
-                        let _ = window.emit(
-                            "llm-chunk",
-                            StreamPayload {
-                                assistant_id: assistant_id_c.clone(),
-                                topic_id: topic_id_c.clone(),
-                                content: "".into(),
-                                done: true,
-                            },
-                        );
-                        return Ok(());
-                    }
+/// 流式对话请求体：消息按引用序列化，每次尝试不必复制整段对话
+#[derive(Serialize)]
+struct ChatRequestBody<'a> {
+    messages: &'a [serde_json::Value],
+    #[serde(flatten)]
+    fields: &'a serde_json::Map<String, serde_json::Value>,
+}
 
-                    // 解析每行数据: data: {"choices":[{"delta":{"content":"..."}}]}
-                    if line.starts_with("data: ") {
-                        let json_str = &line[6..];
-                        if let Ok(val) = serde_json::from_str::<serde_json::Value>(json_str) {
-                            // 文本片段
-                            if let Some(content) = val["choices"][0]["delta"]["content"].as_str() {
-                                let _ = window.emit(
-                                    "llm-chunk",
-                                    StreamPayload {
-                                        assistant_id: assistant_id_c.clone(),
-                                        topic_id: topic_id_c.clone(),
-                                        content: content.to_string(),
-                                        done: false,
-                                    },
-                                );
-                            }
-                            // 思维链片段：GLM/DeepSeek-R1/Qwen3 等通过 reasoning_content 单独返回
-                            // 部分实现用 reasoning 作为别名，两者择一即可
-                            if let Some(reasoning) = val["choices"][0]["delta"]["reasoning_content"]
-                                .as_str()
-                                .or_else(|| val["choices"][0]["delta"]["reasoning"].as_str())
-                            {
-                                if !reasoning.is_empty() {
-                                    let _ = window.emit(
-                                        "llm-reasoning",
-                                        StreamPayload {
-                                            assistant_id: assistant_id_c.clone(),
-                                            topic_id: topic_id_c.clone(),
-                                            content: reasoning.to_string(),
-                                            done: false,
-                                        },
-                                    );
-                                }
-                            }
-                            // tool_calls 累积
-                            if let Some(tcs) = val["choices"][0]["delta"]["tool_calls"].as_array() {
-                                for tc in tcs {
-                                    let index = tc.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
-                                    let entry = tc_accum.entry(index).or_insert_with(|| {
-                                        (String::new(), String::new(), String::new())
-                                    });
-                                    if let Some(id) = tc.get("id").and_then(|v| v.as_str()) {
-                                        entry.0 = id.to_string();
-                                    }
-                                    if let Some(name) = tc
-                                        .get("function")
-                                        .and_then(|f| f.get("name"))
-                                        .and_then(|v| v.as_str())
-                                    {
-                                        entry.1 = name.to_string();
-                                    }
-                                    if let Some(args) = tc
-                                        .get("function")
-                                        .and_then(|f| f.get("arguments"))
-                                        .and_then(|v| v.as_str())
-                                    {
-                                        entry.2.push_str(args);
-                                    }
-                                }
-                            }
-                            // finish_reason="tool_calls" 触发 flush
-                            let finish = val["choices"][0]["finish_reason"]
-                                .as_str()
-                                .unwrap_or("");
-                            if finish == "tool_calls" {
-                                for (_idx, (id, name, args)) in tc_accum.drain() {
-                                    if !id.is_empty() && !name.is_empty() {
-                                        let _ = window.emit(
-                                            "llm-tool-call",
-                                            ToolCallPayload {
-                                                assistant_id: assistant_id_c.clone(),
-                                                topic_id: topic_id_c.clone(),
-                                                tool_call_id: id,
-                                                name,
-                                                arguments: args,
-                                            },
-                                        );
-                                    }
-                                }
-                            }
-                        }
-                    }
-                }
+/// 发送一次流式对话请求，返回已确认 2xx 的响应（尚未读取任何内容）
+async fn send_chat_request(
+    client: &reqwest::Client,
+    candidate: &ModelRef,
+    body: &ChatRequestBody<'_>,
+) -> Result<reqwest::Response, RequestFailure> {
+    let final_url = chat_endpoint(candidate).map_err(|message| RequestFailure {
+        message,
+        retriable: false,
+    })?;
+
+    // 发送 POST 请求
+    let response = client
+        .post(&final_url)
+        .timeout(REQUEST_TIMEOUT)
+        .header("Authorization", format!("Bearer {}", candidate.api_key))
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| {
+            let cause = if e.is_timeout() {
+                FailureCause::Timeout
+            } else if e.is_connect() {
+                FailureCause::Connect
+            } else {
+                FailureCause::Other
+            };
+            // 请求阶段尚未读取响应，前端还没有收到任何内容
+            RequestFailure {
+                retriable: should_fall_back(cause, false),
+                message: e.to_string(),
             }
-            // 流自然结束（未到 [DONE]）：flush 残余 tool_calls，然后 emit done
-            for (_idx, (id, name, args)) in tc_accum.drain() {
-                if !id.is_empty() && !name.is_empty() {
+        })?;
+
+    // 检查 HTTP 状态码：非 2xx 时提前报错，避免对错误 JSON 走 SSE 解析
+    let status = response.status();
+    if !status.is_success() {
+        let body_text = response.text().await.unwrap_or_default();
+        return Err(RequestFailure {
+            message: provider_error(status, &body_text),
+            retriable: should_fall_back(FailureCause::Status(status), false),
+        });
+    }
+    Ok(response)
+}
+
+/// 发送流式请求并把解码结果转发为前端事件，返回累积的正文与思维链
+/// （call_llm_stream 与 retry_message 共用）。
+/// `candidates` 依次尝试：前一个在请求阶段可重试地失败（尚未输出任何内容）时，
+/// 发送 `llm-fallback` 事件并改用下一个
+async fn run_chat_stream(
+    window: &Window,
+    target: StreamTarget<'_>,
+    candidates: &[ModelRef],
+    messages_for_api: &[serde_json::Value],
+    tools: Option<&[ToolSpec]>,
+    logprobs: Option<u32>,
+) -> Result<StreamedReply, String> {
+    let client = window.state::<HttpClientState>().client();
+
+    // 构造符合 OpenAI API 标准的消息格式
+    // 支持 role="tool"（带 tool_call_id）和 assistant 携带 tool_calls
+    // 构造请求体，开启 stream 模式
+    // 若传入 tools 且非空，则附加到 body
+    let mut body_map = serde_json::Map::new();
+    body_map.insert("stream".into(), json!(true));
+    if let Some(tools) = tools {
+        if !tools.is_empty() {
+            body_map.insert("tools".into(), json!(tools));
+            body_map.insert("tool_choice".into(), json!("auto"));
+        }
+    }
+    // 不支持 logprobs 的服务端会忽略这两个字段，响应中没有 logprobs 时不发送事件
+    if let Some(n) = logprobs {
+        body_map.insert("logprobs".into(), json!(true));
+        body_map.insert("top_logprobs".into(), json!(n.min(MAX_TOP_LOGPROBS)));
+    }
+
+    let mut response = None;
+    for (i, candidate) in candidates.iter().enumerate() {
+        if candidate.omit_model_field {
+            body_map.remove("model");
+        } else {
+            body_map.insert("model".into(), json!(candidate.model));
+        }
+        let body = ChatRequestBody {
+            messages: messages_for_api,
+            fields: &body_map,
+        };
+        match send_chat_request(&client, candidate, &body).await {
+            Ok(res) => {
+                response = Some(res);
+                break;
+            }
+            Err(failure) => match candidates.get(i + 1) {
+                Some(next) if failure.retriable => {
+                    tracing::warn!(
+                        "模型 {} 请求失败，转移到 {}: {}",
+                        candidate.model,
+                        next.model,
+                        failure.message
+                    );
                     let _ = window.emit(
-                        "llm-tool-call",
-                        ToolCallPayload {
-                            assistant_id: assistant_id_c.clone(),
-                            topic_id: topic_id_c.clone(),
-                            tool_call_id: id,
-                            name,
-                            arguments: args,
+                        "llm-fallback",
+                        FallbackPayload {
+                            assistant_id: target.assistant_id.to_string(),
+                            topic_id: target.topic_id.to_string(),
+                            failed_model: candidate.model.clone(),
+                            model: next.model.clone(),
+                            reason: failure.message,
+                            request_id: target.request_id.map(str::to_string),
                         },
                     );
                 }
-            }
+                _ => return Err(failure.message),
+            },
+        }
+    }
+    let response = response.ok_or_else(|| "没有可用的模型".to_string())?;
+
+    let mut reply = StreamedReply::default();
+    let mut forward = |output: StreamOutput| {
+        match &output {
+            StreamOutput::Chunk(text) => reply.content.push_str(text),
+            StreamOutput::Reasoning(text) => reply.reasoning.push_str(text),
+            StreamOutput::ToolCall { id, name, arguments } => reply.tool_calls.push(ToolCall {
+                id: id.clone(),
+                kind: "function".into(),
+                function: ToolCallFunction {
+                    name: name.clone(),
+                    arguments: arguments.clone(),
+                },
+            }),
+            _ => {}
+        }
+        emit_stream_output(window, target, output);
+    };
+
+    // 获取响应字节流
+    let mut stream = response.bytes_stream();
+    // SSE 解析器：按字节缓冲，处理注释心跳、event:/id: 字段，只把 data: 交给内容解析
+    let mut parser = SseParser::new();
+    // 解码器：累积 tool_calls，识别结束信号；按设置丢弃代理重发的重复事件
+    let mut decoder = StreamDecoder::new(StreamFormat::OpenAi)
+        .with_dedup(crate::commands::config::dedup_stream_events());
+
+    // 循环处理流式返回的数据块
+    while let Some(item) = stream.next().await {
+        for ev in parser.push(&item.map_err(|e| e.to_string())?) {
+            decoder.decode(&ev)?.into_iter().for_each(&mut forward);
+        }
+        // 收到 [DONE] 后不再等待连接关闭
+        if decoder.is_done() {
+            return Ok(reply);
+        }
+    }
+    // 流自然结束（未到 [DONE]）：处理缓冲中最后一个事件，flush 残余 tool_calls，然后 emit done
+    if let Some(ev) = parser.finish() {
+        decoder.decode(&ev)?.into_iter().for_each(&mut forward);
+    }
+    decoder.finish().into_iter().for_each(&mut forward);
+    Ok(reply)
+}
+
+/// 把解码结果转发为前端事件（llm-chunk / llm-reasoning / llm-tool-call / llm-logprob）
+fn emit_stream_output(window: &Window, target: StreamTarget<'_>, output: StreamOutput) {
+    let (event, content, done) = match output {
+        StreamOutput::Chunk(content) => ("llm-chunk", content, false),
+        StreamOutput::Reasoning(content) => ("llm-reasoning", content, false),
+        StreamOutput::Done => ("llm-chunk", String::new(), true),
+        StreamOutput::ToolCall { id, name, arguments } => {
             let _ = window.emit(
-                "llm-chunk",
-                StreamPayload {
-                    assistant_id: assistant_id_c.clone(),
-                    topic_id: topic_id_c.clone(),
-                    content: "".into(),
-                    done: true,
+                "llm-tool-call",
+                ToolCallPayload {
+                    assistant_id: target.assistant_id.to_string(),
+                    topic_id: target.topic_id.to_string(),
+                    tool_call_id: id,
+                    name,
+                    arguments,
+                    request_id: target.request_id.map(str::to_string),
                 },
             );
-            Ok(())
+            return;
         }
-        .await;
-
-        // 6. 错误处理：如果请求失败，发送错误信息给前端
-        if let Err(e) = result {
-            tracing::error!("Stream Error: {}", e);
+        StreamOutput::Logprobs(tokens) => {
             let _ = window.emit(
-                "llm-chunk",
-                StreamPayload {
-                    assistant_id: assistant_id_c,
-                    topic_id: topic_id_c,
-                    content: format!("\n[Error: {}]", e),
-                    done: true,
+                "llm-logprob",
+                LogprobPayload {
+                    assistant_id: target.assistant_id.to_string(),
+                    topic_id: target.topic_id.to_string(),
+                    tokens,
+                    request_id: target.request_id.map(str::to_string),
                 },
             );
+            return;
         }
+    };
+    let _ = window.emit(event, target.payload(content, done));
+}
 
-        // 任务完成后，从全局状态中移除 handle
+/// 回放文件大小上限（20MB）
+const MAX_REPLAY_BYTES: u64 = 20 * 1024 * 1024;
+
+/// 回放录制的 SSE 响应（复现问题 / 离线演示）
+/// 与 call_llm_stream 走同一套解析与 emit 逻辑，按内容自动识别 OpenAI / Anthropic 格式；
+/// 任务同样登记在 StreamManager 中，可用 stop_llm_stream 中止
+#[tauri::command]
+pub async fn replay_stream(
+    window: Window,
+    state: tauri::State<'_, StreamManager>,
+    assistant_id: String,
+    topic_id: String,
+    path: String,
+) -> Result<(), String> {
+    let file = PathBuf::from(&path);
+    path_in_sandbox(&file)?;
+    let size = std::fs::metadata(&file).map_err(|e| e.to_string())?.len();
+    if size > MAX_REPLAY_BYTES {
+        return Err(format!("回放文件过大: {} 字节（上限 {}）", size, MAX_REPLAY_BYTES));
+    }
+    let raw = std::fs::read(&file).map_err(|e| e.to_string())?;
+    let format = StreamFormat::sniff(&String::from_utf8_lossy(&raw));
+
+    let task_key = stream_task_key(&assistant_id, &topic_id, None);
+    if let Some((_, old_handle)) = state.0.remove(&task_key) {
+        old_handle.abort();
+    }
+    let state_inner = state.0.clone();
+    let task_key_inner = task_key.clone();
+
+    let handle = tokio::spawn(async move {
+        let target = StreamTarget {
+            assistant_id: &assistant_id,
+            topic_id: &topic_id,
+            request_id: None,
+            model_id: None,
+        };
+        let result: Result<(), String> = async {
+            let mut parser = SseParser::new();
+            let mut decoder = StreamDecoder::new(format);
+            let mut events = parser.push(&raw);
+            events.extend(parser.finish());
+            for ev in events {
+                // 模拟网络分块间隔：10~40ms，随数据长度变化
+                tokio::time::sleep(Duration::from_millis(10 + ev.data.len() as u64 % 31)).await;
+                for output in decoder.decode(&ev)? {
+                    emit_stream_output(&window, target, output);
+                }
+            }
+            for output in decoder.finish() {
+                emit_stream_output(&window, target, output);
+            }
+            Ok(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            let _ = window.emit("llm-chunk", target.payload(format!("\n[Error: {}]", e), true));
+        }
         state_inner.remove(&task_key_inner);
     });
 
-    // 7. 将当前正在执行的任务句柄存入全局状态，以便后续可以“手动停止”
     state.0.insert(task_key, handle);
     Ok(())
 }
 
 /// 辅助函数：从服务商获取可用的模型列表
 #[tauri::command]
-pub async fn fetch_models(api_url: String, api_key: String) -> Result<Vec<ModelInfo>, String> {
+pub async fn fetch_models(
+    http: tauri::State<'_, HttpClientState>,
+    api_url: String,
+    api_key: String,
+) -> Result<Vec<ModelInfo>, String> {
     // 构造模型获取地址，通常是基础 URL 后接 /models
-    let mut base_url = api_url.trim_end_matches('/').to_string();
-    if base_url.ends_with("/chat/completions") {
-        base_url = base_url.replace("/chat/completions", "");
-    }
-    let final_url = format!("{}/models", base_url);
+    let final_url = format!("{}/models", api_base_url(&api_url));
 
-    let client = http_client();
-    let response = client
+    let response = http
+        .0
         .get(&final_url)
+        .timeout(REQUEST_TIMEOUT)
         .header("Authorization", format!("Bearer {}", api_key))
         .send()
         .await
@@ -404,14 +1235,157 @@ pub async fn fetch_models(api_url: String, api_key: String) -> Result<Vec<ModelI
     Ok(res_data.data)
 }
 
-/// 停止函数：用户点击“停止生成”时调用
+/// 多模型对比中单个模型的流
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareStream {
+    /// 该模型流的请求 ID（llm-chunk 中回传，也可传给 stop_llm_stream 单独停止）
+    pub request_id: String,
+    pub model: String,
+}
+
+/// 一次多模型对比启动的全部流
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CompareStreams {
+    /// 对比批次 ID，传给 stop_llm_compare 可一并停止
+    pub compare_id: String,
+    pub streams: Vec<CompareStream>,
+}
+
+/// 多模型对比中各流的请求 ID："{compare_id}/{序号}"
+fn compare_request_id(compare_id: &str, index: usize) -> String {
+    format!("{}/{}", compare_id, index)
+}
+
+/// 多模型对比：把同一组消息同时发给多个模型，每个模型一个独立的流式任务。
+/// 各流的 llm-chunk 带有各自的 request_id 与 model_id，前端据此分栏渲染；
+/// 某个模型失败只会在其所在栏输出错误，不影响其他模型。不注入 MCP 工具。
 #[tauri::command]
-pub async fn stop_llm_stream(
+#[allow(clippy::too_many_arguments)]
+pub async fn call_llm_compare(
+    window: Window,
     state: tauri::State<'_, StreamManager>,
+    db_state: tauri::State<'_, DbState>,
+    engine_state: tauri::State<'_, LocalEngineState>,
     assistant_id: String,
     topic_id: String,
+    models: Vec<ModelRef>,
+    messages: Vec<Message>,
+) -> Result<CompareStreams, String> {
+    if models.is_empty() {
+        return Err("至少需要选择一个模型".into());
+    }
+    let messages_for_api = {
+        let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+        messages
+            .iter()
+            .map(|message| message_for_api(&conn, message))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+    // 所有模型都校验通过后再启动，避免部分栏已开始输出时才报错
+    let mut candidates = Vec::with_capacity(models.len());
+    for model in models {
+        ensure_image_capability(&engine_state, &model.api_url, &messages_for_api)?;
+        let candidate = ModelRef {
+            api_key: resolve_api_key(&engine_state, &model.api_url, model.api_key),
+            ..model
+        };
+        chat_endpoint(&candidate)?;
+        candidates.push(candidate);
+    }
+
+    let compare_id = uuid::Uuid::new_v4().to_string();
+    let mut streams = Vec::with_capacity(candidates.len());
+    for (index, candidate) in candidates.into_iter().enumerate() {
+        let request_id = compare_request_id(&compare_id, index);
+        streams.push(CompareStream {
+            request_id: request_id.clone(),
+            model: candidate.model.clone(),
+        });
+
+        let window = window.clone();
+        let state_inner = state.0.clone();
+        let assistant_id = assistant_id.clone();
+        let topic_id = topic_id.clone();
+        let messages_for_api = messages_for_api.clone();
+        let task_key = request_task_key(&request_id);
+        let task_key_inner = task_key.clone();
+        // 等句柄登记到 StreamManager 后再开始，保证任务结束时的清理一定发生在登记之后
+        let (registered_tx, registered_rx) = tokio::sync::oneshot::channel::<()>();
+        let handle = tokio::spawn(async move {
+            let _ = registered_rx.await;
+            let target = StreamTarget {
+                assistant_id: &assistant_id,
+                topic_id: &topic_id,
+                request_id: Some(&request_id),
+                model_id: Some(&candidate.model),
+            };
+            let result = run_chat_stream(
+                &window,
+                target,
+                std::slice::from_ref(&candidate),
+                &messages_for_api,
+                None,
+                None,
+            )
+            .await;
+            if let Err(e) = result {
+                tracing::error!("Compare Stream Error ({}): {}", candidate.model, e);
+                let _ = window.emit("llm-chunk", target.payload(format!("\n[Error: {}]", e), true));
+            }
+            state_inner.remove(&task_key_inner);
+        });
+        state.0.insert(task_key, handle);
+        let _ = registered_tx.send(());
+    }
+
+    Ok(CompareStreams { compare_id, streams })
+}
+
+/// 停止一次多模型对比中仍在运行的全部流，返回停止的流数量
+#[tauri::command]
+pub async fn stop_llm_compare(
+    state: tauri::State<'_, StreamManager>,
+    compare_id: String,
+) -> Result<usize, String> {
+    let prefix = request_task_key(&format!("{}/", compare_id));
+    let keys: Vec<String> = state
+        .0
+        .iter()
+        .filter(|entry| entry.key().starts_with(&prefix))
+        .map(|entry| entry.key().clone())
+        .collect();
+    let mut stopped = 0;
+    for key in keys {
+        if let Some((_, handle)) = state.0.remove(&key) {
+            handle.abort();
+            stopped += 1;
+        }
+    }
+    Ok(stopped)
+}
+
+/// 停止函数：用户点击“停止生成”时调用
+/// 传入 `request_id` 时停止对应的请求（并丢弃其等待中的工具调用），否则停止该话题下未指定 request_id 的流
+#[tauri::command]
+pub async fn stop_llm_stream(
+    state: tauri::State<'_, StreamManager>,
+    rounds: tauri::State<'_, ToolRoundManager>,
+    assistant_id: Option<String>,
+    topic_id: Option<String>,
+    request_id: Option<String>,
 ) -> Result<(), String> {
-    let task_key = format!("{}-{}", assistant_id, topic_id);
+    let task_key = match (request_id, assistant_id, topic_id) {
+        (Some(request_id), _, _) => {
+            rounds.0.remove(&request_id);
+            request_task_key(&request_id)
+        }
+        (None, Some(assistant_id), Some(topic_id)) => {
+            stream_task_key(&assistant_id, &topic_id, None)
+        }
+        _ => return Err("需要提供 request_id，或同时提供 assistant_id 与 topic_id".into()),
+    };
 
     // 从状态中取出对应的任务句柄并执行 abort() 强制停止任务
     if let Some((_, handle)) = state.0.remove(&task_key) {
@@ -420,14 +1394,34 @@ pub async fn stop_llm_stream(
     Ok(())
 }
 
+/// 合并已有摘要与新生成的片段（与前端 checkAndSummarize 的格式一致）
+fn merge_summary(previous: Option<&str>, snippet: &str) -> String {
+    match previous.map(str::trim).filter(|p| !p.is_empty()) {
+        Some(previous) => format!("[历史背景]: {}\n[近期增补]: {}", previous, snippet),
+        None => snippet.to_string(),
+    }
+}
+
+/// 流式生成历史摘要
+///
+/// 传入 `topic_id` 时，生成过程中每隔 [`SUMMARY_PERSIST_INTERVAL`] 把与已有摘要合并后的
+/// 部分内容写回 `topics.summary`，连接中断时保留已生成的部分；
+/// 进度通过 `llm-summary-progress` 事件推送。返回值为本次新生成的摘要片段。
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn summarize_history(
+    window: Window,
+    engine_state: tauri::State<'_, LocalEngineState>,
+    http: tauri::State<'_, HttpClientState>,
+    db_state: tauri::State<'_, DbState>,
     api_url: String,
     api_key: String,
     model: String,
     messages: Vec<Message>,
+    topic_id: Option<String>,
 ) -> Result<String, String> {
-    let client = http_client();
+    let api_key = resolve_api_key(&engine_state, &api_url, api_key);
+    let client = http.client();
 
     let mut messages_for_api: Vec<serde_json::Value> = messages
         .iter()
@@ -442,40 +1436,108 @@ pub async fn summarize_history(
     let body = json!({
         "model": model,
         "messages": messages_for_api,
-        "stream": false
+        "stream": true
     });
 
     // --- 修复后的 URL 拼接逻辑 ---
-    let base_url = api_url
-        .trim_end_matches('/')
-        .replace("/chat/completions", "");
-    let endpoint = format!("{}/chat/completions", base_url);
+    let endpoint = format!("{}/chat/completions", api_base_url(&api_url));
+
+    // 已有摘要：部分结果与其合并后写回，保持与成功时前端保存的格式一致
+    let previous: Option<String> = match &topic_id {
+        Some(id) => {
+            let conn = db_state.0.lock().map_err(|e| e.to_string())?;
+            conn.query_row("SELECT summary FROM topics WHERE id = ?1", params![id], |row| row.get(0))
+                .ok()
+                .flatten()
+        }
+        None => None,
+    };
+    let persist = |snippet: &str, done: bool| {
+        let summary = merge_summary(previous.as_deref(), snippet);
+        if let Some(id) = &topic_id {
+            let saved = db_state.0.lock().map_err(|e| e.to_string()).and_then(|conn| {
+                conn.execute("UPDATE topics SET summary = ?1 WHERE id = ?2", params![summary, id])
+                    .map_err(|e| e.to_string())
+            });
+            if let Err(e) = saved {
+                tracing::warn!("保存部分摘要失败: {}", e);
+            }
+        }
+        let _ = window.emit(
+            "llm-summary-progress",
+            SummaryProgressPayload {
+                topic_id: topic_id.clone(),
+                chars: snippet.chars().count(),
+                summary,
+                done,
+            },
+        );
+    };
 
-    let res = client
+    let response = client
         .post(endpoint)
+        .timeout(SUMMARY_TIMEOUT)
         .header("Authorization", format!("Bearer {}", api_key))
         .json(&body)
         .send()
         .await
         .map_err(|e| e.to_string())?;
 
-    let val: serde_json::Value = res.json().await.map_err(|e| e.to_string())?;
-
-    // 增加一个简单的错误检查
-    if let Some(err) = val.get("error") {
-        return Err(err
-            .get("message")
-            .and_then(|m| m.as_str())
-            .unwrap_or("API Error")
-            .to_string());
+    let status = response.status();
+    if !status.is_success() {
+        let body_text = response.text().await.unwrap_or_default();
+        return Err(provider_error(status, &body_text));
     }
 
-    let summary = val["choices"][0]["message"]["content"]
-        .as_str()
-        .unwrap_or("无法生成总结")
-        .to_string();
-
-    Ok(summary)
+    // 摘要只取正文，忽略思维链
+    fn collect(snippet: &mut String, outputs: Vec<StreamOutput>) {
+        for output in outputs {
+            if let StreamOutput::Chunk(text) = output {
+                snippet.push_str(&text);
+            }
+        }
+    }
+    let mut snippet = String::new();
+    let mut stream = response.bytes_stream();
+    let mut parser = SseParser::new();
+    let mut decoder = StreamDecoder::new(StreamFormat::OpenAi);
+    let mut last_persist = std::time::Instant::now();
+    let mut persisted_len = 0;
+    let outcome: Result<(), String> = async {
+        while let Some(item) = stream.next().await {
+            for ev in parser.push(&item.map_err(|e| e.to_string())?) {
+                collect(&mut snippet, decoder.decode(&ev)?);
+            }
+            if decoder.is_done() {
+                return Ok(());
+            }
+            if last_persist.elapsed() >= SUMMARY_PERSIST_INTERVAL && snippet.len() > persisted_len {
+                persist(&snippet, false);
+                persisted_len = snippet.len();
+                last_persist = std::time::Instant::now();
+            }
+        }
+        if let Some(ev) = parser.finish() {
+            collect(&mut snippet, decoder.decode(&ev)?);
+        }
+        collect(&mut snippet, decoder.finish());
+        Ok(())
+    }
+    .await;
+
+    let summary = snippet.trim().to_string();
+    match outcome {
+        Ok(()) if summary.is_empty() => Err("模型未返回摘要内容".to_string()),
+        Ok(()) => {
+            persist(&summary, true);
+            Ok(summary)
+        }
+        Err(e) if !summary.is_empty() && topic_id.is_some() => {
+            persist(&summary, true);
+            Err(format!("生成摘要中断，已保存部分摘要: {}", e))
+        }
+        Err(e) => Err(e),
+    }
 }
 
 #[tauri::command]
@@ -485,34 +1547,13 @@ pub async fn append_message(
     message: Message,
 ) -> Result<(), String> {
     let conn = (*state).0.lock().unwrap();
-    let message_id = message
-        .id
-        .clone()
-        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
-    let files_json = serde_json::to_string(&message.display_files).ok();
-    let content_json = serde_json::to_string(&message.content).unwrap_or_default();
-
-    conn.execute(
-        "INSERT INTO messages
-         (id, topic_id, role, content, model_id, display_files, display_text, reasoning)
-         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
-        params![
-            message_id,
-            topic_id,
-            message.role,
-            content_json,
-            message.model_id,
-            files_json,
-            message.display_text,
-            message.reasoning
-        ],
-    ).map_err(|e| e.to_string())?;
+    let message_id = crate::commands::config::insert_message(&conn, &topic_id, &message)?;
     sync_message_attachments(&conn, &message_id, message.display_files.as_ref())?;
     Ok(())
 }
 
 /// 从消息内容中提取纯文本，多模态数组（OpenAI vision 格式）只保留 text 部分。
-fn extract_text_content(content: &serde_json::Value) -> String {
+pub(crate) fn extract_text_content(content: &serde_json::Value) -> String {
     match content {
         serde_json::Value::String(s) => s.clone(),
         serde_json::Value::Array(arr) => arr
@@ -620,6 +1661,8 @@ fn clean_topic_title(raw: &str) -> String {
 /// 模型名与原始长度，便于排查。前端应在 catch 中走启发式后备方案。
 #[tauri::command]
 pub async fn generate_topic_title(
+    engine_state: tauri::State<'_, LocalEngineState>,
+    http: tauri::State<'_, HttpClientState>,
     api_url: String,
     api_key: String,
     model: String,
@@ -628,8 +1671,9 @@ pub async fn generate_topic_title(
     if messages.is_empty() {
         return Err("生成标题需要至少一条消息".to_string());
     }
+    let api_key = resolve_api_key(&engine_state, &api_url, api_key);
 
-    let client = http_client();
+    let client = http.client();
 
     // 消息顺序遵循 LLM 约定：system 指令 → 对话上下文 → user 明确任务请求
     // 将 system 放最前、user 任务请求放最后，能显著提升小模型 / 本地模型的格式遵循度
@@ -669,13 +1713,11 @@ pub async fn generate_topic_title(
     });
 
     // URL 处理：去掉末尾斜杠与可能的 /chat/completions 后缀
-    let base_url = api_url
-        .trim_end_matches('/')
-        .replace("/chat/completions", "");
-    let endpoint = format!("{}/chat/completions", base_url);
+    let endpoint = format!("{}/chat/completions", api_base_url(&api_url));
 
     let res = client
         .post(endpoint)
+        .timeout(REQUEST_TIMEOUT)
         .header("Authorization", format!("Bearer {}", api_key))
         .json(&body)
         .send()
@@ -721,3 +1763,193 @@ pub async fn generate_topic_title(
 
     Ok(truncated)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tool_round_resumes_only_after_every_result() {
+        let call = |id: &str, name: &str| ToolCall {
+            id: id.into(),
+            kind: "function".into(),
+            function: ToolCallFunction {
+                name: name.into(),
+                arguments: "{}".into(),
+            },
+        };
+        let mut round = ChatRound {
+            assistant_id: "a".into(),
+            topic_id: "t".into(),
+            candidates: Vec::new(),
+            messages: vec![json!({ "role": "user", "content": "查天气" })],
+            tools: None,
+            logprobs: None,
+            pending_tools: Default::default(),
+            waiting_since: std::time::Instant::now(),
+        };
+        round.await_tools("", &[call("c1", "weather"), call("c2", "time")]);
+        assert_eq!(round.messages[1]["content"], serde_json::Value::Null);
+        assert_eq!(round.pending_tools.len(), 2);
+        assert!(!round.ready_to_resume());
+
+        assert_eq!(round.record_tool_result("c1", "晴").unwrap(), "weather");
+        assert!(!round.ready_to_resume());
+        // 同一调用不能重复提交，未知调用被拒绝
+        assert!(round.record_tool_result("c1", "晴").is_err());
+        assert!(round.record_tool_result("c9", "?").is_err());
+
+        assert_eq!(round.record_tool_result("c2", "12:00").unwrap(), "time");
+        assert!(round.ready_to_resume());
+        let roles: Vec<_> = round.messages.iter().map(|m| m["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "tool", "tool"]);
+        assert_eq!(round.messages[3]["tool_call_id"], "c2");
+    }
+
+    #[test]
+    fn request_keys_do_not_collide_with_topic_keys() {
+        assert_eq!(stream_task_key("a", "t", None), "a-t");
+        assert_ne!(
+            stream_task_key("x", "y", Some("a-t")),
+            stream_task_key("a", "t", None)
+        );
+        assert!(request_task_key(&compare_request_id("c", 1)).starts_with(&request_task_key("c/")));
+    }
+
+    #[test]
+    fn merges_summary_like_frontend() {
+        assert_eq!(merge_summary(None, "新摘要"), "新摘要");
+        assert_eq!(merge_summary(Some("  "), "新摘要"), "新摘要");
+        assert_eq!(
+            merge_summary(Some("旧摘要"), "新摘要"),
+            "[历史背景]: 旧摘要\n[近期增补]: 新摘要"
+        );
+    }
+
+    #[test]
+    fn strips_reasoning_blocks() {
+        let tags = vec![("<think>".to_string(), "</think>".to_string())];
+        let strip = |text: &str| strip_reasoning_tags(text, &tags);
+        assert_eq!(strip("<think>推理</think>\n\n答案"), "答案");
+        assert_eq!(strip("a<think>x</think>b<think>y</think>c"), "abc");
+        // 开始标签由模板注入，输出中只有结束标签
+        assert_eq!(strip("推理过程</think>\n答案"), "答案");
+        // 输出被中断，只有开始标签
+        assert_eq!(strip("答案<think>未完成"), "答案");
+        assert_eq!(strip("  没有标签  "), "  没有标签  ");
+    }
+
+    #[test]
+    fn renders_endpoint_template() {
+        let candidate = |api_url: &str, model: &str, template: Option<&str>| ModelRef {
+            api_url: api_url.into(),
+            api_key: String::new(),
+            model: model.into(),
+            endpoint_template: template.map(Into::into),
+            omit_model_field: false,
+        };
+        assert_eq!(
+            chat_endpoint(&candidate("https://api.x.com/v1/", "m", None)).unwrap(),
+            "https://api.x.com/v1/chat/completions"
+        );
+        assert_eq!(
+            chat_endpoint(&candidate(
+                "",
+                "org/llama-3.1",
+                Some("https://gw.local/v1/chat/completions/{model}")
+            ))
+            .unwrap(),
+            "https://gw.local/v1/chat/completions/org%2Fllama-3.1"
+        );
+        assert_eq!(
+            chat_endpoint(&candidate("", "qwen", Some("http://{model}.gw.local/v1/chat"))).unwrap(),
+            "http://qwen.gw.local/v1/chat"
+        );
+        assert!(chat_endpoint(&candidate("", "m", Some("/v1/{model}"))).is_err());
+        assert!(chat_endpoint(&candidate("", "m", Some("ftp://h/{model}"))).is_err());
+    }
+
+    #[test]
+    fn hoists_pinned_after_system_prompt() {
+        let msg = |id: &str, role: &str, pinned: bool| -> Message {
+            serde_json::from_value(json!({
+                "id": id, "role": role, "content": id, "isPinned": pinned
+            }))
+            .unwrap()
+        };
+        // 前端只传了最近几条；p1 不在列表中，a2 在列表中且被置顶，q3 是本轮提问
+        let messages = vec![
+            msg("sys", "system", false),
+            msg("q2", "user", false),
+            msg("a2", "assistant", true),
+            msg("q3", "user", true),
+        ];
+        let pinned = vec![
+            msg("p1", "user", true),
+            msg("a2", "assistant", true),
+            msg("q3", "user", true),
+        ];
+        let (result, count) = hoist_pinned(messages, pinned);
+        let ids: Vec<_> = result.iter().map(|m| m.id.as_deref().unwrap()).collect();
+        assert_eq!(ids, ["sys", "p1", "a2", "q2", "q3"]);
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn falls_back_only_on_transient_failures_before_output() {
+        use reqwest::StatusCode;
+        for status in [
+            StatusCode::TOO_MANY_REQUESTS,
+            StatusCode::BAD_GATEWAY,
+            StatusCode::SERVICE_UNAVAILABLE,
+        ] {
+            assert!(should_fall_back(FailureCause::Status(status), false));
+            assert!(!should_fall_back(FailureCause::Status(status), true));
+        }
+        for status in [
+            StatusCode::BAD_REQUEST,
+            StatusCode::UNAUTHORIZED,
+            StatusCode::NOT_FOUND,
+        ] {
+            assert!(!should_fall_back(FailureCause::Status(status), false));
+        }
+        assert!(should_fall_back(FailureCause::Timeout, false));
+        assert!(should_fall_back(FailureCause::Connect, false));
+        assert!(!should_fall_back(FailureCause::Timeout, true));
+        assert!(!should_fall_back(FailureCause::Other, false));
+    }
+
+    #[test]
+    fn history_limit_keeps_system_and_pinned() {
+        let msg = |id: &str, role: &str, pinned: bool| -> Message {
+            serde_json::from_value(json!({
+                "id": id, "role": role, "content": id, "isPinned": pinned
+            }))
+            .unwrap()
+        };
+        let messages = vec![
+            msg("sys", "system", false),
+            msg("q1", "user", false),
+            msg("a1", "assistant", false),
+            msg("q2", "user", false),
+            msg("a2", "assistant", false),
+            msg("q3", "user", false),
+        ];
+        let (messages, pinned) = hoist_pinned(messages, vec![msg("p0", "user", true)]);
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::core::db::init_schema(&conn).unwrap();
+        let mut messages_for_api = messages
+            .iter()
+            .map(|message| message_for_api(&conn, message))
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+
+        let dropped = tokens::trim_to_count(&mut messages_for_api, 2, pinned);
+        let contents: Vec<_> = messages_for_api
+            .iter()
+            .map(|m| m["content"].as_str().unwrap())
+            .collect();
+        assert_eq!(contents, ["sys", "p0", "a2", "q3"]);
+        assert_eq!(dropped, 3);
+    }
+}
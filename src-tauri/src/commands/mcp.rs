@@ -4,7 +4,8 @@
 
 use crate::core::models::*;
 use crate::core::secure_store;
-use crate::core::state::{McpRequestManager, McpServerState};
+use crate::commands::llm::{emit_tool_status, ToolStatus};
+use crate::core::state::{McpRequestManager, McpServerState, ToolRoundManager};
 use crate::plugins::mcp::{self, McpServerManager, McpServerPlugin};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -337,15 +338,21 @@ pub async fn list_mcp_tools_for_assistant(
     })
 }
 
+/// 调用 MCP 工具。传入 `request_id` 与 `tool_call_id`（对应 call_llm_stream 中等待结果的工具调用）时，
+/// 执行前发出 running 状态的 `tool-status` 事件
 #[tauri::command]
+#[allow(clippy::too_many_arguments)]
 pub async fn call_mcp_tool(
     app: AppHandle,
     mgr: State<'_, McpServerManager>,
     state: State<'_, McpServerState>,
     requests: State<'_, McpRequestManager>,
+    rounds: State<'_, ToolRoundManager>,
     server_id: String,
     tool_name: String,
     arguments: Value,
+    request_id: Option<String>,
+    tool_call_id: Option<String>,
 ) -> Result<ToolResult, String> {
     let cfg = mcp::get_config(&app, &server_id)
         .ok_or_else(|| format!("未找到 MCP server: {}", server_id))?;
@@ -370,6 +377,20 @@ pub async fn call_mcp_tool(
             .ok_or_else(|| format!("MCP server 未连接: {}", server_id))?
     };
 
+    if let (Some(request_id), Some(tool_call_id)) = (&request_id, &tool_call_id) {
+        if let Some(round) = rounds.0.get(request_id) {
+            emit_tool_status(
+                &app,
+                &round,
+                request_id,
+                tool_call_id,
+                &tool_name,
+                ToolStatus::Running,
+                None,
+            );
+        }
+    }
+
     // 用 call_id 跟踪；stop_mcp_server / 用户停止时可 abort
     let call_id = format!("mcp:{}:{}", server_id, tool_name);
     let plugin_arc = plugin.clone();
@@ -5,6 +5,7 @@ use crate::core::models::{
     McpCatalogServer, McpServerConfig, McpTransport,
 };
 use crate::core::secure_store;
+use crate::core::state::HttpClientState;
 use crate::plugins::mcp;
 use serde_json::Value;
 use std::collections::{BTreeMap, HashMap};
@@ -280,12 +281,12 @@ async fn fetch_page(
             }
         }
     }
-    let response = reqwest::Client::builder()
-        .user_agent("AIO MCP Registry/0.4")
-        .timeout(Duration::from_secs(25))
-        .build()
-        .map_err(|error| error.to_string())?
+    let response = app
+        .state::<HttpClientState>()
+        .0
         .get(url)
+        .header(reqwest::header::USER_AGENT, "AIO MCP Registry/0.4")
+        .timeout(Duration::from_secs(25))
         .send()
         .await;
     let result = match response {
@@ -1,12 +1,21 @@
 // 鉴权相关命令已迁移到 `crate::cloud_backend::auth`
 // （统一管理预留云端后端的 HTTP 调用）
 pub mod attachment;
+pub mod audio;
 pub mod catalog;
 pub mod config;
+pub mod config_check;
+pub mod document;
 pub mod engine;
+pub mod image;
 pub mod llm;
 pub mod mcp;
 pub mod mcp_catalog;
+pub mod probe;
 pub mod provider_config;
+pub mod search;
+pub mod settings;
 pub mod skill;
+pub mod stats;
 pub mod update;
+pub mod web;
@@ -0,0 +1,194 @@
+//! 模型能力探测
+//!
+//! `probe_model_capabilities` 向 `/chat/completions` 发送几个极小的测试请求（`max_tokens: 1`），
+//! 根据服务商是否接受来判断模型是否支持工具调用、图片输入、JSON 模式与流式输出，
+//! 代替按模型名猜测。结果按 (api_url, model) 缓存在内存中，`force` 为 true 时重新探测。
+//!
+//! 判定规则：基础请求必须成功（否则返回错误，如密钥无效）；各项探测 2xx 视为支持，
+//! 4xx（通常是 400 参数不支持）视为不支持，其他错误原样返回。
+
+use crate::commands::llm::{api_base_url, provider_error, resolve_api_key};
+use crate::core::state::{HttpClientState, LocalEngineState};
+use dashmap::DashMap;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+/// 单个探测请求的超时
+const PROBE_TIMEOUT: Duration = Duration::from_secs(30);
+/// 1×1 像素 PNG（视觉探测用）
+const PIXEL_PNG: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+
+/// 探测结果
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCapabilities {
+    pub supports_tools: bool,
+    pub supports_vision: bool,
+    pub supports_json_mode: bool,
+    pub supports_streaming: bool,
+    /// 探测时间（Unix 秒）
+    pub probed_at: u64,
+}
+
+/// 探测结果缓存：键为 "{api_base_url}\n{model}"
+#[derive(Default)]
+pub struct ModelCapabilityCache(DashMap<String, ModelCapabilities>);
+
+/// 在基础请求体上叠加探测字段
+fn probe_body(model: &str, extra: Value) -> Value {
+    let mut body = json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": "hi" }],
+        "max_tokens": 1,
+        "stream": false,
+    });
+    if let (Some(base), Value::Object(extra)) = (body.as_object_mut(), extra) {
+        base.extend(extra);
+    }
+    body
+}
+
+/// 发送一个探测请求，返回状态码与响应体（探测响应很小，直接读完）
+async fn send_probe(
+    client: &reqwest::Client,
+    endpoint: &str,
+    api_key: &str,
+    body: &Value,
+) -> Result<(reqwest::StatusCode, String), String> {
+    let response = client
+        .post(endpoint)
+        .timeout(PROBE_TIMEOUT)
+        .header("Authorization", format!("Bearer {}", api_key))
+        .json(body)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    Ok((status, response.text().await.unwrap_or_default()))
+}
+
+/// 探测一项能力：Ok(true) 为接受，Ok(false) 为参数类 4xx 拒绝，鉴权 / 限流 / 服务端错误返回 Err
+async fn probe(
+    client: &reqwest::Client,
+    endpoint: &str,
+    api_key: &str,
+    body: Value,
+) -> Result<bool, String> {
+    let (status, text) = send_probe(client, endpoint, api_key, &body).await?;
+    if status.is_success() {
+        return Ok(true);
+    }
+    let rejected = status.is_client_error()
+        && ![
+            reqwest::StatusCode::UNAUTHORIZED,
+            reqwest::StatusCode::FORBIDDEN,
+            reqwest::StatusCode::TOO_MANY_REQUESTS,
+        ]
+        .contains(&status);
+    if rejected {
+        return Ok(false);
+    }
+    Err(provider_error(status, &text))
+}
+
+/// 探测模型支持的能力（工具 / 图片 / JSON 模式 / 流式），结果按 (api_url, model) 缓存
+#[tauri::command]
+pub async fn probe_model_capabilities(
+    engine_state: tauri::State<'_, LocalEngineState>,
+    http: tauri::State<'_, HttpClientState>,
+    cache: tauri::State<'_, ModelCapabilityCache>,
+    api_url: String,
+    api_key: String,
+    model: String,
+    force: Option<bool>,
+) -> Result<ModelCapabilities, String> {
+    let base_url = api_base_url(&api_url);
+    let key = format!("{}\n{}", base_url, model);
+    if !force.unwrap_or(false) {
+        if let Some(cached) = cache.0.get(&key) {
+            return Ok(cached.clone());
+        }
+    }
+
+    let api_key = resolve_api_key(&engine_state, &api_url, api_key);
+    let client = http.client();
+    let endpoint = format!("{}/chat/completions", base_url);
+
+    // 基础请求失败（密钥错误、模型不存在等）时其余探测没有意义
+    let (status, text) =
+        send_probe(&client, &endpoint, &api_key, &probe_body(&model, json!({}))).await?;
+    if !status.is_success() {
+        return Err(provider_error(status, &text));
+    }
+
+    let tools = probe_body(
+        &model,
+        json!({
+            "tools": [{
+                "type": "function",
+                "function": {
+                    "name": "ping",
+                    "description": "Connectivity check",
+                    "parameters": { "type": "object", "properties": {} }
+                }
+            }],
+            "tool_choice": "auto"
+        }),
+    );
+    let vision = probe_body(
+        &model,
+        json!({
+            "messages": [{
+                "role": "user",
+                "content": [
+                    { "type": "text", "text": "hi" },
+                    { "type": "image_url", "image_url": { "url": PIXEL_PNG } }
+                ]
+            }]
+        }),
+    );
+    // OpenAI 要求 JSON 模式下消息中出现 "json" 字样
+    let json_mode = probe_body(
+        &model,
+        json!({
+            "messages": [{ "role": "user", "content": "Reply with an empty JSON object." }],
+            "response_format": { "type": "json_object" }
+        }),
+    );
+    let streaming = probe_body(&model, json!({ "stream": true }));
+
+    let (supports_tools, supports_vision, supports_json_mode, supports_streaming) = tokio::join!(
+        probe(&client, &endpoint, &api_key, tools),
+        probe(&client, &endpoint, &api_key, vision),
+        probe(&client, &endpoint, &api_key, json_mode),
+        probe(&client, &endpoint, &api_key, streaming),
+    );
+    let capabilities = ModelCapabilities {
+        supports_tools: supports_tools?,
+        supports_vision: supports_vision?,
+        supports_json_mode: supports_json_mode?,
+        supports_streaming: supports_streaming?,
+        probed_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+    };
+    cache.0.insert(key, capabilities.clone());
+    Ok(capabilities)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn probe_body_overrides_base_fields() {
+        let body = probe_body("m", json!({ "stream": true, "tool_choice": "auto" }));
+        assert_eq!(body["model"], "m");
+        assert_eq!(body["max_tokens"], 1);
+        assert_eq!(body["stream"], true);
+        assert_eq!(body["tool_choice"], "auto");
+        assert_eq!(body["messages"][0]["content"], "hi");
+    }
+}
@@ -17,12 +17,12 @@ use std::path::PathBuf;
 use tauri::AppHandle;
 
 use crate::core::models::LiveModel;
+use crate::core::paths;
 use crate::core::secure_store;
 use crate::plugins::provider::{
     classify_reqwest_error, ProviderManager, TEST_TIMEOUT_SECS, DEFAULT_TIMEOUT_SECS,
 };
 
-const APPDATA_DIRNAME: &str = "com.loch.aio";
 const PROVIDER_FILE: &str = "provider-configs.json";
 const CURRENT_VERSION: u32 = 2;
 
@@ -82,16 +82,8 @@ pub struct FetchLiveModelsResult {
     pub elapsed_ms: u128,
 }
 
-fn config_dir() -> Option<PathBuf> {
-    let dir = dirs::config_dir()?.join(APPDATA_DIRNAME);
-    if !dir.exists() {
-        let _ = fs::create_dir_all(&dir);
-    }
-    Some(dir)
-}
-
 fn provider_path() -> Option<PathBuf> {
-    Some(config_dir()?.join(PROVIDER_FILE))
+    paths::app_config_dir().ok().map(|dir| dir.join(PROVIDER_FILE))
 }
 
 fn now_iso() -> String {
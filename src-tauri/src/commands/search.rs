@@ -0,0 +1,143 @@
+//! 话题内搜索
+//!
+//! `search_in_topic` 在单个话题的 user / assistant 消息中查找关键词（不区分大小写），
+//! 返回命中的消息 id 与各处匹配的位置，供前端滚动定位并高亮。
+//! 位置按 UTF-16 码元计算，与 JavaScript 字符串下标一致。
+//!
+//! 单个话题通常只有几百条消息，直接在内存中逐条扫描即可满足逐键搜索；
+//! SQLite 的 `LIKE` 只对 ASCII 不区分大小写，因此不用它做预过滤。
+
+use crate::commands::config::decode_content;
+use crate::commands::llm::extract_text_content;
+use crate::core::state::DbState;
+use serde::Serialize;
+
+/// 一处匹配在消息文本中的范围（UTF-16 码元，左闭右开）
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct MatchSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// 命中的消息
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct MessageHit {
+    pub message_id: String,
+    pub role: String,
+    pub matches: Vec<MatchSpan>,
+}
+
+/// 逐字符小写折叠（只取首个小写字符，保证与原文一一对应）
+fn fold(c: char) -> char {
+    c.to_lowercase().next().unwrap_or(c)
+}
+
+/// 不区分大小写地查找 `query` 在 `text` 中的全部（不重叠）出现位置
+fn find_matches(text: &str, query: &[char]) -> Vec<MatchSpan> {
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let chars: Vec<char> = text.chars().collect();
+    // utf16[i] 为第 i 个字符之前的 UTF-16 码元数
+    let mut utf16 = Vec::with_capacity(chars.len() + 1);
+    let mut offset = 0;
+    utf16.push(0);
+    for c in &chars {
+        offset += c.len_utf16();
+        utf16.push(offset);
+    }
+
+    let mut matches = Vec::new();
+    let mut i = 0;
+    while i + query.len() <= chars.len() {
+        if chars[i..i + query.len()]
+            .iter()
+            .zip(query)
+            .all(|(&c, &q)| fold(c) == q)
+        {
+            matches.push(MatchSpan {
+                start: utf16[i],
+                end: utf16[i + query.len()],
+            });
+            i += query.len();
+        } else {
+            i += 1;
+        }
+    }
+    matches
+}
+
+/// 在单个话题中搜索关键词，按消息时间顺序返回命中的消息及匹配位置。
+/// 匹配的是界面显示的文本：有 displayText 时用它，否则取 content 中的文本部分
+#[tauri::command]
+pub async fn search_in_topic(
+    state: tauri::State<'_, DbState>,
+    topic_id: String,
+    query: String,
+) -> Result<Vec<MessageHit>, String> {
+    let query: Vec<char> = query.trim().chars().map(fold).collect();
+    if query.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let conn = state.0.lock().map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, role, content, display_text FROM messages
+             WHERE topic_id = ?1 AND role IN ('user', 'assistant')
+             ORDER BY timestamp ASC, rowid ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([&topic_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut hits = Vec::new();
+    for row in rows {
+        let (message_id, role, content, display_text) = row.map_err(|e| e.to_string())?;
+        let text = match display_text.filter(|t| !t.is_empty()) {
+            Some(text) => text,
+            None => extract_text_content(&decode_content(&message_id, content)),
+        };
+        let matches = find_matches(&text, &query);
+        if !matches.is_empty() {
+            hits.push(MessageHit {
+                message_id,
+                role,
+                matches,
+            });
+        }
+    }
+    Ok(hits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spans(text: &str, query: &str) -> Vec<(usize, usize)> {
+        let query: Vec<char> = query.chars().map(fold).collect();
+        find_matches(text, &query)
+            .into_iter()
+            .map(|m| (m.start, m.end))
+            .collect()
+    }
+
+    #[test]
+    fn matches_case_insensitively_with_utf16_offsets() {
+        assert_eq!(spans("Rust and rust", "RUST"), vec![(0, 4), (9, 13)]);
+        assert_eq!(spans("aaaa", "aa"), vec![(0, 2), (2, 4)]);
+        // 中文与 emoji：emoji 占两个 UTF-16 码元
+        assert_eq!(spans("😀你好，世界你好", "你好"), vec![(2, 4), (7, 9)]);
+        assert!(spans("hello", "").is_empty());
+        assert!(spans("hi", "hello").is_empty());
+    }
+}
@@ -0,0 +1,167 @@
+//! 设置导出 / 导入
+//!
+//! 把 `AppConfig` 与已激活模型列表打包成一个 JSON 文件，用于在新设备上快速恢复配置；
+//! 不包含助手、话题与消息等数据。导出时可选择抹去 API 密钥。
+//!
+//! 导入规则：
+//! - 通用设置整体覆盖，但数据目录、本地模型路径与 TLS 证书路径属于本机，保留当前值
+//! - 文件中的 API 密钥为空（导出时已抹去）时保留本机已保存的密钥
+//! - 激活模型按 (api_url, model_id) 合并：本机已有的条目不变，只追加新条目
+
+use crate::commands::config::{
+    load_activated_models, load_app_config, save_activated_models, save_app_config,
+};
+use crate::core::models::{ActivatedModel, AppConfig};
+use crate::utils::file_parser::path_in_sandbox;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tauri::AppHandle;
+
+/// 当前导出格式版本
+const SETTINGS_FORMAT_VERSION: u32 = 1;
+/// 设置文件大小上限，防止误选大文件
+const MAX_SETTINGS_BYTES: u64 = 5 * 1024 * 1024;
+
+/// 设置文件内容
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SettingsBundle {
+    version: u32,
+    /// 导出时间（Unix 秒）
+    #[serde(default)]
+    exported_at: u64,
+    /// 导出时是否包含 API 密钥
+    #[serde(default)]
+    includes_keys: bool,
+    config: AppConfig,
+    #[serde(default)]
+    activated_models: Vec<ActivatedModel>,
+}
+
+/// 按 (api_url, model_id) 合并激活模型：保留本机条目，追加新条目，返回合并结果与新增条数
+fn merge_activated_models(
+    mut existing: Vec<ActivatedModel>,
+    imported: Vec<ActivatedModel>,
+) -> (Vec<ActivatedModel>, usize) {
+    let mut added = 0;
+    for model in imported {
+        let exists = existing
+            .iter()
+            .any(|m| m.api_url == model.api_url && m.model_id == model.model_id);
+        if !exists {
+            existing.push(model);
+            added += 1;
+        }
+    }
+    (existing, added)
+}
+
+/// 导入的通用设置：本机相关的路径与缺失的密钥沿用当前配置
+fn merge_app_config(current: AppConfig, imported: AppConfig) -> AppConfig {
+    AppConfig {
+        api_key: if imported.api_key.is_empty() {
+            current.api_key
+        } else {
+            imported.api_key
+        },
+        data_dir: current.data_dir,
+        local_model_path: current.local_model_path,
+        sync_client_cert_path: current.sync_client_cert_path,
+        sync_client_key_path: current.sync_client_key_path,
+        sync_ca_cert_path: current.sync_ca_cert_path,
+        ..imported
+    }
+}
+
+/// 导出设置到 `dest_path`；`include_keys` 为 false 时抹去所有 API 密钥
+#[tauri::command]
+pub fn export_settings(
+    app: AppHandle,
+    dest_path: String,
+    include_keys: bool,
+) -> Result<(), String> {
+    let dest = Path::new(&dest_path);
+    let parent = dest.parent().ok_or_else(|| "导出路径无效".to_string())?;
+    path_in_sandbox(parent).map_err(|e| format!("导出路径沙箱拒绝: {}", e))?;
+
+    let mut config = load_app_config(app)?;
+    let mut activated_models = load_activated_models()?;
+    if !include_keys {
+        config.api_key.clear();
+        for model in &mut activated_models {
+            model.api_key.clear();
+        }
+    }
+    let bundle = SettingsBundle {
+        version: SETTINGS_FORMAT_VERSION,
+        exported_at: std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        includes_keys: include_keys,
+        config,
+        activated_models,
+    };
+    let json = serde_json::to_string_pretty(&bundle).map_err(|e| e.to_string())?;
+    std::fs::write(dest, json).map_err(|e| format!("写入设置文件失败: {}", e))
+}
+
+/// 从 `src_path` 导入设置，返回新增的激活模型条数
+#[tauri::command]
+pub fn import_settings(app: AppHandle, src_path: String) -> Result<usize, String> {
+    let src = Path::new(&src_path);
+    path_in_sandbox(src).map_err(|e| format!("文件路径沙箱拒绝: {}", e))?;
+    let size = std::fs::metadata(src).map_err(|e| e.to_string())?.len();
+    if size > MAX_SETTINGS_BYTES {
+        return Err("设置文件过大，请确认选择的是导出的设置文件".to_string());
+    }
+    let content = std::fs::read_to_string(src).map_err(|e| format!("读取设置文件失败: {}", e))?;
+    let bundle: SettingsBundle =
+        serde_json::from_str(&content).map_err(|e| format!("设置文件格式错误: {}", e))?;
+    if bundle.version > SETTINGS_FORMAT_VERSION {
+        return Err(format!(
+            "设置文件版本 {} 高于当前支持的版本 {}，请先升级应用",
+            bundle.version, SETTINGS_FORMAT_VERSION
+        ));
+    }
+
+    let config = merge_app_config(load_app_config(app.clone())?, bundle.config);
+    let (models, added) = merge_activated_models(load_activated_models()?, bundle.activated_models);
+    save_app_config(app, config)?;
+    save_activated_models(models)?;
+    Ok(added)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model(api_url: &str, model_id: &str, api_key: &str) -> ActivatedModel {
+        ActivatedModel {
+            api_url: api_url.into(),
+            api_key: api_key.into(),
+            model_id: model_id.into(),
+            owned_by: String::new(),
+            local_path: None,
+            engine_type: None,
+        }
+    }
+
+    #[test]
+    fn merges_models_without_overwriting_local_entries() {
+        let existing = vec![model("https://a", "gpt", "local-key")];
+        let imported = vec![
+            model("https://a", "gpt", ""),
+            model("https://b", "gpt", "k"),
+        ];
+        let (merged, added) = merge_activated_models(existing, imported);
+        assert_eq!(added, 1);
+        assert_eq!(
+            merged,
+            vec![
+                model("https://a", "gpt", "local-key"),
+                model("https://b", "gpt", "k")
+            ]
+        );
+    }
+}
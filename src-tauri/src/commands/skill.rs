@@ -1,6 +1,8 @@
 //! Skill 配置管理命令。
 
 use crate::core::models::{MarketSkill, SkillConfig, SkillMarketCategory, SkillsFile};
+use crate::core::paths;
+use crate::core::state::HttpClientState;
 use regex::Regex;
 use serde_json::Value;
 use std::collections::{HashMap, HashSet};
@@ -27,10 +29,7 @@ struct MarketCacheEntry {
 }
 
 fn skills_file_path(app: &AppHandle) -> Result<PathBuf, String> {
-    app.path()
-        .app_data_dir()
-        .map(|dir| dir.join(SKILLS_FILE))
-        .map_err(|e| e.to_string())
+    paths::app_data_root(app).map(|dir| dir.join(SKILLS_FILE))
 }
 
 fn market_cache_path(app: &AppHandle) -> Result<PathBuf, String> {
@@ -94,17 +93,13 @@ fn cache_entry_is_fresh(entry: &MarketCacheEntry, now: u64) -> bool {
     now.saturating_sub(entry.fetched_at) < MARKET_CACHE_TTL_SECS
 }
 
-fn market_client() -> Result<reqwest::Client, String> {
-    reqwest::Client::builder()
-        .user_agent("AIO Skill Market/0.4")
-        .timeout(std::time::Duration::from_secs(25))
-        .build()
-        .map_err(|e| e.to_string())
-}
-
-async fn fetch_market_page(path: &str) -> Result<String, String> {
-    let response = market_client()?
+async fn fetch_market_page(app: &AppHandle, path: &str) -> Result<String, String> {
+    let response = app
+        .state::<HttpClientState>()
+        .0
         .get(format!("{}{}", SKILLS_SH, path))
+        .header(reqwest::header::USER_AGENT, "AIO Skill Market/0.4")
+        .timeout(std::time::Duration::from_secs(25))
         .send()
         .await
         .map_err(|e| format!("请求 skills.sh 失败: {}", e))?;
@@ -128,7 +123,7 @@ async fn fetch_market_page_cached(
         }
     }
 
-    match fetch_market_page(path).await {
+    match fetch_market_page(app, path).await {
         Ok(html) => {
             cache.entries.insert(
                 path.to_string(),
@@ -336,6 +331,12 @@ fn parse_categories(html: &str) -> Vec<SkillMarketCategory> {
         .collect()
 }
 
+/// 按 id 顺序取出助手启用的 Skill（已删除的 id 跳过）
+pub(crate) fn resolve_skills(app: &AppHandle, ids: &[String]) -> Vec<SkillConfig> {
+    let mut skills = load_file(app).skills;
+    ids.iter().filter_map(|id| skills.remove(id)).collect()
+}
+
 /// 返回全部 Skill 配置。
 #[tauri::command]
 pub fn list_skills(app: AppHandle) -> Result<Vec<SkillConfig>, String> {
@@ -428,7 +429,7 @@ pub async fn download_market_skill(
         return Err("非法的 Skill 路径".to_string());
     }
     let source_url = format!("{}/{}/{}/{}", SKILLS_SH, owner, repo, slug);
-    let html = fetch_market_page(&format!("/{}/{}/{}", owner, repo, slug)).await?;
+    let html = fetch_market_page(&app, &format!("/{}/{}/{}", owner, repo, slug)).await?;
 
     let json_ld = Regex::new(r#"(?s)<script type="application/ld\+json">(\{.*?"@type":"SoftwareApplication".*?\})</script>"#)
         .expect("valid json-ld regex");
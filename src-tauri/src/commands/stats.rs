@@ -0,0 +1,175 @@
+//! 使用统计命令
+//!
+//! `usage_stats` 按 `messages.model_id` 聚合消息数与估算 token 数（只读查询）。
+//! 数据库中没有保存服务商返回的真实 token 用量，token 数按 [`tokens::estimate_message_tokens`] 估算。
+//!
+//! `estimate_cost` 在发送前按 catalog 中的模型单价（美元 / 百万 token）估算本次请求的费用。
+
+use crate::commands::catalog::{catalog_pricing, ModelPricing};
+use crate::core::state::DbState;
+use crate::utils::tokens;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// 单个模型的使用统计
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelUsage {
+    pub model_id: String,
+    /// 消息条数（含用户消息与回复）
+    pub message_count: u64,
+    /// 其中模型回复的条数
+    pub reply_count: u64,
+    /// 估算的 token 总数
+    pub estimated_tokens: u64,
+    /// 范围内最早 / 最近一条消息的时间（`YYYY-MM-DD HH:MM:SS`，UTC）
+    pub first_used: Option<String>,
+    pub last_used: Option<String>,
+}
+
+/// 发送前的费用估算；catalog 中没有该模型价格时各项费用为 None
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CostEstimate {
+    pub model: String,
+    /// 估算的输入 token 数
+    pub input_tokens: u64,
+    /// 调用方给出的预计输出 token 数
+    pub output_tokens: u64,
+    /// 以下费用单位均为美元
+    pub input_cost: Option<f64>,
+    pub output_cost: Option<f64>,
+    pub total_cost: Option<f64>,
+}
+
+/// 按单价计算费用估算
+fn price_estimate(
+    model: String,
+    input_tokens: u64,
+    output_tokens: u64,
+    pricing: Option<ModelPricing>,
+) -> CostEstimate {
+    let per_million = |tokens: u64, price: f64| tokens as f64 * price / 1_000_000.0;
+    let input_cost = pricing.map(|p| per_million(input_tokens, p.input));
+    let output_cost = pricing.map(|p| per_million(output_tokens, p.output));
+    CostEstimate {
+        model,
+        input_tokens,
+        output_tokens,
+        input_cost,
+        output_cost,
+        total_cost: input_cost.zip(output_cost).map(|(i, o)| i + o),
+    }
+}
+
+/// 日期范围上界：只给出日期时包含当天全天
+fn range_end(to: &str) -> String {
+    let to = to.trim();
+    if to.len() == 10 {
+        format!("{} 23:59:59", to)
+    } else {
+        to.to_string()
+    }
+}
+
+/// 按模型统计消息数与估算 token 数；`from` / `to` 为可选的时间范围（`YYYY-MM-DD` 或 `YYYY-MM-DD HH:MM:SS`）
+#[tauri::command]
+pub async fn usage_stats(
+    state: tauri::State<'_, DbState>,
+    from: Option<String>,
+    to: Option<String>,
+) -> Result<Vec<ModelUsage>, String> {
+    let from = from.map(|f| f.trim().to_string()).filter(|f| !f.is_empty());
+    let to = to.filter(|t| !t.trim().is_empty()).map(|t| range_end(&t));
+
+    let conn = state.0.lock().map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT model_id, role, content, timestamp FROM messages
+             WHERE model_id IS NOT NULL AND model_id != ''
+               AND (?1 IS NULL OR timestamp >= ?1)
+               AND (?2 IS NULL OR timestamp <= ?2)",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(rusqlite::params![from, to], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, Option<String>>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut by_model: BTreeMap<String, ModelUsage> = BTreeMap::new();
+    for row in rows {
+        let (model_id, role, content, timestamp) = row.map_err(|e| e.to_string())?;
+        let content: serde_json::Value =
+            serde_json::from_str(&content).unwrap_or(serde_json::Value::String(content));
+        let usage = by_model
+            .entry(model_id.clone())
+            .or_insert_with(|| ModelUsage {
+                model_id,
+                ..Default::default()
+            });
+        usage.message_count += 1;
+        if role == "assistant" {
+            usage.reply_count += 1;
+        }
+        usage.estimated_tokens +=
+            tokens::estimate_message_tokens(&serde_json::json!({ "content": content })) as u64;
+        if let Some(ts) = timestamp {
+            if usage.first_used.as_ref().map_or(true, |f| ts < *f) {
+                usage.first_used = Some(ts.clone());
+            }
+            if usage.last_used.as_ref().map_or(true, |l| ts > *l) {
+                usage.last_used = Some(ts);
+            }
+        }
+    }
+
+    let mut result: Vec<ModelUsage> = by_model.into_values().collect();
+    result.sort_by_key(|u| std::cmp::Reverse(u.estimated_tokens));
+    Ok(result)
+}
+
+/// 估算一次请求的费用：输入 token 按消息列表估算，输出 token 由调用方给出（默认 0）
+#[tauri::command]
+pub fn estimate_cost(
+    app: tauri::AppHandle,
+    model: String,
+    messages: Vec<Value>,
+    expected_output_tokens: Option<u64>,
+) -> CostEstimate {
+    let input_tokens = tokens::estimate_messages_tokens(&messages) as u64;
+    let pricing = catalog_pricing(&app, &model);
+    price_estimate(
+        model,
+        input_tokens,
+        expected_output_tokens.unwrap_or(0),
+        pricing,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prices_per_million_tokens() {
+        let pricing = ModelPricing {
+            input: 2.5,
+            output: 10.0,
+        };
+        let estimate = price_estimate("m".into(), 2_000, 500, Some(pricing));
+        assert_eq!(estimate.input_cost, Some(0.005));
+        assert_eq!(estimate.output_cost, Some(0.005));
+        assert_eq!(estimate.total_cost, Some(0.01));
+
+        let unknown = price_estimate("m".into(), 2_000, 500, None);
+        assert_eq!(unknown.total_cost, None);
+        assert_eq!(unknown.input_tokens, 2_000);
+    }
+}
@@ -0,0 +1,338 @@
+//! 抓取网页作为附件
+//!
+//! `fetch_url_content` 下载网页并提取正文，返回标题、正文与最终地址，供用户在对话中讨论文章：
+//! - 仅允许 http / https；每一跳（包括重定向目标）都先解析域名，拒绝本机与内网地址，
+//!   除非开启「允许抓取内网地址」设置（SSRF 防护）。请求固定连接到校验过的地址（不走系统代理），防止 DNS 重绑定
+//! - 使用桌面浏览器 UA，整体超时 [`FETCH_TIMEOUT`]，最多跟随 [`MAX_REDIRECTS`] 次重定向，
+//!   响应体超过 [`MAX_PAGE_BYTES`] 时中止
+//! - HTML 走与 HTML 附件相同的正文提取；PDF 写入临时文件后走 PDF 解析；纯文本类原样返回
+
+use crate::commands::config::allow_private_network_fetch;
+use crate::utils::{file_parser, html_text, text_encoding};
+use reqwest::header::{ACCEPT, CONTENT_LENGTH, CONTENT_TYPE, LOCATION};
+use serde::Serialize;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+use url::{Host, Url};
+
+/// 桌面浏览器 UA：部分站点会对非浏览器 UA 返回精简页或拒绝访问
+const DESKTOP_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/126.0.0.0 Safari/537.36";
+/// 整体超时（含重定向与读取响应体）
+const FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+/// 单次连接超时
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// 最多跟随的重定向次数
+const MAX_REDIRECTS: usize = 5;
+/// 响应体大小上限
+const MAX_PAGE_BYTES: usize = 10 * 1024 * 1024;
+
+/// 抓取结果
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchedPage {
+    /// 跟随重定向后的最终地址
+    pub final_url: String,
+    /// 页面标题；没有标题时为文件名或主机名
+    pub title: String,
+    /// 提取出的正文
+    pub content: String,
+    /// 响应的 MIME 类型（不含 charset 等参数）
+    pub content_type: String,
+}
+
+/// IPv6 地址中内嵌的 IPv4 地址：IPv4 映射 `::ffff:a.b.c.d`、IPv4 兼容 `::a.b.c.d`、
+/// NAT64 `64:ff9b::/96` 与 6to4 `2002::/16`，访问这些地址最终会到达内嵌的 IPv4 地址
+fn embedded_ipv4(v6: Ipv6Addr) -> Option<Ipv4Addr> {
+    if let Some(v4) = v6.to_ipv4_mapped() {
+        return Some(v4);
+    }
+    let [_, _, _, _, _, _, _, _, _, _, _, _, a, b, c, d] = v6.octets();
+    match v6.segments() {
+        // :: 与 ::1 不是 IPv4 兼容地址，按 IPv6 规则判断
+        [0, 0, 0, 0, 0, 0, 0, 0 | 1] => None,
+        [0, 0, 0, 0, 0, 0, _, _] | [0x64, 0xff9b, 0, 0, 0, 0, _, _] => {
+            Some(Ipv4Addr::new(a, b, c, d))
+        }
+        [0x2002, high, low, ..] => Some(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low))),
+        _ => None,
+    }
+}
+
+/// 是否为公网地址：回环、私有、链路本地、CGNAT、未指定、广播、文档保留、组播等均视为非公网；
+/// 内嵌 IPv4 的 IPv6 地址按内嵌的 IPv4 地址判断
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                // 运营商级 NAT 100.64.0.0/10
+                || (a == 100 && (64..128).contains(&b))
+                // 基准测试保留 198.18.0.0/15
+                || (a == 198 && (b == 18 || b == 19))
+                || a >= 240)
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = embedded_ipv4(v6) {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                // 唯一本地地址 fc00::/7
+                || (first & 0xfe00) == 0xfc00
+                // 链路本地地址 fe80::/10
+                || (first & 0xffc0) == 0xfe80
+                // 站点本地地址 fec0::/10（已废弃，但仍可能被路由到内网）
+                || (first & 0xffc0) == 0xfec0)
+        }
+    }
+}
+
+/// 校验协议并解析目标地址；未开启内网访问时，任一解析结果不是公网地址即拒绝
+async fn resolve_target(url: &Url, allow_private: bool) -> Result<Vec<SocketAddr>, String> {
+    match url.scheme() {
+        "http" | "https" => {}
+        s => return Err(format!("仅支持 http / https 地址，当前: {}", s)),
+    }
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = match url.host() {
+        Some(Host::Domain(domain)) => tokio::net::lookup_host((domain, port))
+            .await
+            .map_err(|e| format!("域名解析失败: {}", e))?
+            .collect(),
+        Some(Host::Ipv4(ip)) => vec![SocketAddr::new(IpAddr::V4(ip), port)],
+        Some(Host::Ipv6(ip)) => vec![SocketAddr::new(IpAddr::V6(ip), port)],
+        None => return Err("地址缺少主机名".into()),
+    };
+    if addrs.is_empty() {
+        return Err("域名解析失败: 没有可用地址".into());
+    }
+    if !allow_private {
+        if let Some(addr) = addrs.iter().find(|a| !is_public_ip(a.ip())) {
+            return Err(format!(
+                "拒绝访问本机或内网地址 {}（可在设置中开启「允许抓取内网地址」）",
+                addr.ip()
+            ));
+        }
+    }
+    Ok(addrs)
+}
+
+/// 拆分 Content-Type 为 (小写 MIME, charset)
+fn parse_content_type(value: &str) -> (String, Option<String>) {
+    let mut parts = value.split(';');
+    let mime = parts.next().unwrap_or("").trim().to_ascii_lowercase();
+    let charset = parts.find_map(|p| {
+        let (key, value) = p.split_once('=')?;
+        key.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"').to_string())
+    });
+    (mime, charset)
+}
+
+/// 按 charset 解码文本（BOM 优先）；未声明或无法识别时自动检测编码
+fn decode_text(bytes: &[u8], charset: Option<&str>) -> String {
+    match charset.and_then(|c| encoding_rs::Encoding::for_label(c.as_bytes())) {
+        Some(encoding) => encoding.decode(bytes).0.into_owned(),
+        None => text_encoding::decode_text(bytes).0,
+    }
+}
+
+/// 地址的最后一段路径（文件名），没有时用主机名
+fn url_label(url: &Url) -> String {
+    url.path_segments()
+        .and_then(|mut segments| segments.next_back())
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            percent_encoding::percent_decode_str(s)
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+        .or_else(|| url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// 逐跳请求并手动处理重定向，返回 (最终地址, Content-Type, 响应体)
+async fn download(mut current: Url, allow_private: bool) -> Result<(Url, String, Vec<u8>), String> {
+    let mut redirects = 0;
+    let mut response = loop {
+        let addrs = resolve_target(&current, allow_private).await?;
+        // 不用全局共享客户端：每一跳都要把域名固定解析到刚校验过的地址（resolve_to_addrs），
+        // 并关闭自动重定向以便逐跳校验，这两项都只能在构造客户端时设置
+        let mut builder = reqwest::Client::builder()
+            .user_agent(DESKTOP_USER_AGENT)
+            .redirect(reqwest::redirect::Policy::none())
+            // 走代理时由代理解析域名，固定的地址不再生效
+            .no_proxy()
+            .connect_timeout(CONNECT_TIMEOUT);
+        if let Some(Host::Domain(domain)) = current.host() {
+            builder = builder.resolve_to_addrs(domain, &addrs);
+        }
+        let client = builder.build().map_err(|e| e.to_string())?;
+        let response = client
+            .get(current.clone())
+            .header(
+                ACCEPT,
+                "text/html,application/xhtml+xml,application/pdf;q=0.9,text/plain;q=0.8,*/*;q=0.5",
+            )
+            .send()
+            .await
+            .map_err(|e| format!("请求失败: {}", e))?;
+        if !response.status().is_redirection() {
+            break response;
+        }
+        redirects += 1;
+        if redirects > MAX_REDIRECTS {
+            return Err(format!("重定向次数超过 {} 次", MAX_REDIRECTS));
+        }
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|v| v.to_str().ok())
+            .ok_or_else(|| "重定向响应缺少 Location".to_string())?;
+        current = current
+            .join(location)
+            .map_err(|e| format!("重定向地址无效: {}", e))?;
+    };
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("网页返回错误状态: {}", status));
+    }
+    let declared_len = response
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<usize>().ok());
+    if declared_len.is_some_and(|len| len > MAX_PAGE_BYTES) {
+        return Err(format!("网页过大（上限 {} 字节）", MAX_PAGE_BYTES));
+    }
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_string();
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("读取网页失败: {}", e))?
+    {
+        if body.len() + chunk.len() > MAX_PAGE_BYTES {
+            return Err(format!("网页过大（上限 {} 字节）", MAX_PAGE_BYTES));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok((current, content_type, body))
+}
+
+/// PDF 写入临时文件后走附件的 PDF 解析
+async fn extract_pdf(bytes: Vec<u8>) -> Result<String, String> {
+    let path = std::env::temp_dir().join(format!("aio-fetch-{}.pdf", uuid::Uuid::new_v4()));
+    tokio::fs::write(&path, bytes)
+        .await
+        .map_err(|e| format!("写入临时文件失败: {}", e))?;
+    let pdf_path = path.clone();
+    let result =
+        tokio::task::spawn_blocking(move || file_parser::extract_file_content(&pdf_path, "pdf"))
+            .await
+            .map_err(|e| e.to_string());
+    let _ = tokio::fs::remove_file(&path).await;
+    Ok(result??.unwrap_or_default())
+}
+
+/// 抓取网页并提取正文（HTML / PDF / 纯文本）
+#[tauri::command]
+pub async fn fetch_url_content(url: String) -> Result<FetchedPage, String> {
+    let parsed = Url::parse(url.trim()).map_err(|e| format!("URL 解析失败: {}", e))?;
+    let allow_private = allow_private_network_fetch();
+    let (final_url, content_type, bytes) =
+        tokio::time::timeout(FETCH_TIMEOUT, download(parsed, allow_private))
+            .await
+            .map_err(|_| format!("抓取超时（{} 秒）", FETCH_TIMEOUT.as_secs()))??;
+
+    let (mime, charset) = parse_content_type(&content_type);
+    let label = url_label(&final_url);
+    let is_pdf = mime == "application/pdf"
+        || (mime == "application/octet-stream" && label.to_ascii_lowercase().ends_with(".pdf"))
+        || bytes.starts_with(b"%PDF-");
+    let (title, content) = if is_pdf {
+        (label, extract_pdf(bytes).await?)
+    } else if mime == "text/html" || mime == "application/xhtml+xml" || mime.is_empty() {
+        let page = html_text::extract_html(&decode_text(&bytes, charset.as_deref()));
+        let title = if page.title.is_empty() {
+            label
+        } else {
+            page.title
+        };
+        (title, page.text)
+    } else if mime.starts_with("text/") || mime.ends_with("json") || mime.ends_with("xml") {
+        (label, decode_text(&bytes, charset.as_deref()))
+    } else {
+        return Err(format!("不支持的网页内容类型: {}", mime));
+    };
+
+    Ok(FetchedPage {
+        final_url: final_url.to_string(),
+        title,
+        content,
+        content_type: if is_pdf {
+            "application/pdf".into()
+        } else {
+            mime
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_non_public_addresses() {
+        for ip in [
+            "127.0.0.1",
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+            "0.0.0.0",
+            "::1",
+            "fd00::1",
+            "fe80::1",
+            "::ffff:192.168.0.1",
+            "::127.0.0.1",
+            "64:ff9b::a00:1",
+            "2002:c0a8:101::1",
+            "fec0::1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in [
+            "93.184.216.34",
+            "8.8.8.8",
+            "2606:4700::1111",
+            "64:ff9b::808:808",
+            "2002:808:808::1",
+        ] {
+            assert!(is_public_ip(ip.parse().unwrap()), "{}", ip);
+        }
+
+        assert_eq!(
+            parse_content_type("Text/HTML; Charset=\"GBK\""),
+            ("text/html".to_string(), Some("GBK".to_string()))
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! 消息内嵌图片的外置存储
+//!
+//! 多模态消息的 content 是 parts 数组，图片以 base64 DataURL 内嵌其中，图片多的话题会让数据库迅速膨胀。
+//! 写库时把不小于 [`MIN_BLOB_BYTES`] 的图片解码后存入数据库旁的 `blobs/` 目录（按 SHA-256 去重），
+//! content 中只保留 `aio-blob:<mime>;<sha256>` 引用；读库时再还原为 DataURL，对前端与 LLM 请求透明。
+//!
+//! 删除消息后，不再被任何消息引用的 blob 文件随之删除（见 [`cleanup_blobs`]）；
+//! `migrate_inline_images` 结束时再整体清扫一次，回收旧版本遗留的文件（见 [`sweep_blobs`]）。
+//!
+//! 内存数据库没有所在目录，图片保持内嵌。
+
+use base64::{engine::general_purpose, Engine as _};
+use rusqlite::Connection;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+/// content 中 blob 引用的前缀
+const BLOB_SCHEME: &str = "aio-blob:";
+/// DataURL 不小于此长度的图片才外置，小图标之类仍内嵌
+pub const MIN_BLOB_BYTES: usize = 8 * 1024;
+
+/// 数据库文件旁的 `blobs/` 目录；内存数据库返回 None
+pub fn blob_dir(conn: &Connection) -> Option<PathBuf> {
+    let db_path = conn.path().filter(|p| !p.is_empty())?;
+    Some(Path::new(db_path).parent()?.join("blobs"))
+}
+
+/// blob 文件路径：按哈希前两位分目录
+fn blob_path(dir: &Path, sha256: &str) -> PathBuf {
+    dir.join(&sha256[..2]).join(sha256)
+}
+
+/// 引用中的哈希必须是 64 位十六进制（同步拉取的数据不可信，防止借引用读取任意文件）
+fn is_sha256(value: &str) -> bool {
+    value.len() == 64 && value.bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+/// 拆分 `data:<mime>;base64,<data>`
+fn parse_data_url(url: &str) -> Option<(&str, &str)> {
+    url.strip_prefix("data:")?.split_once(";base64,")
+}
+
+/// content（parts 数组）中所有图片 part 的 url 字段
+fn image_urls_mut(content: &mut Value) -> impl Iterator<Item = &mut Value> {
+    content
+        .as_array_mut()
+        .into_iter()
+        .flatten()
+        .filter(|part| part["type"] == "image_url")
+        .filter_map(|part| part.get_mut("image_url")?.get_mut("url"))
+}
+
+/// 是否有需要外置的内嵌图片
+fn has_large_inline_image(content: &Value) -> bool {
+    content.as_array().is_some_and(|parts| {
+        parts.iter().any(|part| {
+            part["type"] == "image_url"
+                && part["image_url"]["url"]
+                    .as_str()
+                    .is_some_and(|url| url.len() >= MIN_BLOB_BYTES && url.starts_with("data:"))
+        })
+    })
+}
+
+/// 把 content 中的大图片写入 `dir`，返回替换为引用后的 content；没有需要外置的图片时返回 None。
+/// base64 无法解码的图片保持内嵌
+pub fn externalize_images(dir: &Path, content: &Value) -> Result<Option<Value>, String> {
+    if !has_large_inline_image(content) {
+        return Ok(None);
+    }
+    let mut stored = content.clone();
+    for url in image_urls_mut(&mut stored) {
+        let Some((mime, data)) = url
+            .as_str()
+            .filter(|u| u.len() >= MIN_BLOB_BYTES)
+            .and_then(parse_data_url)
+        else {
+            continue;
+        };
+        let Ok(bytes) = general_purpose::STANDARD.decode(data) else {
+            continue;
+        };
+        let sha256 = format!("{:x}", Sha256::digest(&bytes));
+        let path = blob_path(dir, &sha256);
+        if !path.exists() {
+            let parent = path.parent().ok_or("blob 路径无效")?;
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            // 先写临时文件再改名，避免中途失败留下残缺的 blob；临时文件名各不相同，并发写入互不覆盖
+            let tmp = path.with_extension(format!("{}.tmp", uuid::Uuid::new_v4().simple()));
+            std::fs::write(&tmp, &bytes).map_err(|e| format!("写入图片 blob 失败: {}", e))?;
+            std::fs::rename(&tmp, &path).map_err(|e| format!("写入图片 blob 失败: {}", e))?;
+        }
+        let reference = format!("{}{};{}", BLOB_SCHEME, mime, sha256);
+        *url = Value::String(reference);
+    }
+    Ok(Some(stored))
+}
+
+/// 把 content 中的 blob 引用还原为 DataURL；文件缺失时保留引用并记录告警
+pub fn restore_images(dir: &Path, content: &mut Value) {
+    for url in image_urls_mut(content) {
+        let Some((mime, sha256)) = url
+            .as_str()
+            .and_then(|u| u.strip_prefix(BLOB_SCHEME))
+            // mime 可能带参数（`image/png;charset=...`），哈希在最后一个分号之后
+            .and_then(|r| r.rsplit_once(';'))
+            .filter(|(_, sha256)| is_sha256(sha256))
+        else {
+            continue;
+        };
+        match std::fs::read(blob_path(dir, sha256)) {
+            Ok(bytes) => {
+                let data_url = format!(
+                    "data:{};base64,{}",
+                    mime,
+                    general_purpose::STANDARD.encode(bytes)
+                );
+                *url = Value::String(data_url);
+            }
+            Err(e) => tracing::warn!("图片 blob {} 读取失败: {}", sha256, e),
+        }
+    }
+}
+
+/// 消息 content 列原文中引用的 blob 哈希
+fn blob_refs(raw: &str) -> impl Iterator<Item = &str> {
+    raw.split(BLOB_SCHEME).skip(1).filter_map(|rest| {
+        let reference = &rest[..rest.find('"').unwrap_or(rest.len())];
+        let (_, sha256) = reference.rsplit_once(';')?;
+        is_sha256(sha256).then_some(sha256)
+    })
+}
+
+/// 满足 `condition` 的消息引用的 blob 哈希（删除消息前调用，删除后交给 [`cleanup_blobs`]）。
+/// `condition` 中 `m` 为 messages、`t` 为 topics，唯一参数为 `?1`
+pub fn blob_refs_where(
+    conn: &Connection,
+    condition: &str,
+    param: &str,
+) -> Result<Vec<String>, String> {
+    let sql = format!(
+        "SELECT m.content FROM messages m JOIN topics t ON t.id = m.topic_id
+         WHERE {} AND m.content LIKE '%{}%'",
+        condition, BLOB_SCHEME
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let contents = stmt
+        .query_map([param], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let mut hashes: Vec<String> = contents
+        .iter()
+        .flat_map(|raw| blob_refs(raw))
+        .map(str::to_string)
+        .collect();
+    hashes.sort();
+    hashes.dedup();
+    Ok(hashes)
+}
+
+/// 删除 `hashes` 中已不被任何消息引用的 blob 文件
+pub fn cleanup_blobs(conn: &Connection, hashes: &[String]) -> Result<(), String> {
+    let Some(dir) = blob_dir(conn) else {
+        return Ok(());
+    };
+    for sha256 in hashes {
+        let referenced: bool = conn
+            .query_row(
+                "SELECT EXISTS(SELECT 1 FROM messages WHERE instr(content, ?1) > 0)",
+                [sha256],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if !referenced {
+            let _ = std::fs::remove_file(blob_path(&dir, sha256));
+        }
+    }
+    Ok(())
+}
+
+/// 删除 blobs 目录中所有未被引用的 blob 文件，返回删除的文件数
+pub fn sweep_blobs(conn: &Connection) -> Result<usize, String> {
+    let Some(dir) = blob_dir(conn) else {
+        return Ok(0);
+    };
+    let sql = format!(
+        "SELECT content FROM messages WHERE content LIKE '%{}%'",
+        BLOB_SCHEME
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let contents = stmt
+        .query_map([], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let referenced: HashSet<&str> = contents.iter().flat_map(|raw| blob_refs(raw)).collect();
+
+    let mut removed = 0;
+    let Ok(shards) = std::fs::read_dir(&dir) else {
+        return Ok(0);
+    };
+    for shard in shards.flatten().filter(|e| e.path().is_dir()) {
+        let Ok(files) = std::fs::read_dir(shard.path()) else {
+            continue;
+        };
+        for file in files.flatten() {
+            let name = file.file_name();
+            let Some(sha256) = name.to_str().filter(|n| is_sha256(n)) else {
+                continue;
+            };
+            if !referenced.contains(sha256) && std::fs::remove_file(file.path()).is_ok() {
+                removed += 1;
+            }
+        }
+    }
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn moves_large_images_out_and_back() {
+        let dir = std::env::temp_dir().join(format!("aio-blobs-{}", uuid::Uuid::new_v4()));
+        let large = format!(
+            "data:image/png;base64,{}",
+            general_purpose::STANDARD.encode(vec![7u8; MIN_BLOB_BYTES])
+        );
+        let small = "data:image/png;base64,iVBORw0KGgo=";
+        let content = json!([
+            { "type": "text", "text": "两张图" },
+            { "type": "image_url", "image_url": { "url": large } },
+            { "type": "image_url", "image_url": { "url": small } }
+        ]);
+
+        let mut stored = externalize_images(&dir, &content).unwrap().unwrap();
+        let reference = stored[1]["image_url"]["url"].as_str().unwrap().to_string();
+        assert!(reference.starts_with("aio-blob:image/png;"));
+        assert_eq!(stored[2]["image_url"]["url"], small);
+        assert!(serde_json::to_string(&stored).unwrap().len() < 1024);
+
+        restore_images(&dir, &mut stored);
+        assert_eq!(stored, content);
+        assert_eq!(externalize_images(&dir, &json!("纯文本")).unwrap(), None);
+
+        // 带参数的 mime 也能还原
+        let with_params = json!([{ "type": "image_url", "image_url": {
+            "url": format!("data:image/png;charset=binary;base64,{}", general_purpose::STANDARD.encode(vec![9u8; MIN_BLOB_BYTES]))
+        } }]);
+        let mut stored = externalize_images(&dir, &with_params).unwrap().unwrap();
+        let raw = serde_json::to_string(&stored).unwrap();
+        assert_eq!(blob_refs(&raw).count(), 1);
+        restore_images(&dir, &mut stored);
+        assert_eq!(stored, with_params);
+
+        // 非法哈希不会被当作路径读取
+        let mut forged = json!([
+            { "type": "image_url", "image_url": { "url": "aio-blob:image/png;../../secret" } }
+        ]);
+        let before = forged.clone();
+        restore_images(&dir, &mut forged);
+        assert_eq!(forged, before);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -3,11 +3,10 @@
 use rusqlite::{Connection, Result};
 use std::fs;
 use tauri::AppHandle;
-use tauri::Manager;
 
 pub fn init_db(app: &AppHandle) -> Result<Connection, String> {
 
-    let app_dir = app.path().app_data_dir().map_err(|e| e.to_string())?;
+    let app_dir = crate::core::paths::app_data_root(app)?;
     
     if !app_dir.exists() {
         fs::create_dir_all(&app_dir).map_err(|e| e.to_string())?;
@@ -15,7 +14,12 @@ pub fn init_db(app: &AppHandle) -> Result<Connection, String> {
     
     let db_path = app_dir.join("chat_history.db");
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    init_schema(&conn)?;
+    Ok(conn)
+}
 
+/// 建表并执行全部迁移（测试中也用于初始化内存数据库）
+pub(crate) fn init_schema(conn: &Connection) -> Result<(), String> {
     // 启用外键支持
     conn.execute("PRAGMA foreign_keys = ON;", []).map_err(|e| e.to_string())?;
 
@@ -32,6 +36,7 @@ pub fn init_db(app: &AppHandle) -> Result<Connection, String> {
         name TEXT NOT NULL,
         summary TEXT,
         renamed INTEGER NOT NULL DEFAULT 0,
+        last_model_id TEXT,
         FOREIGN KEY(assistant_id) REFERENCES assistants(id) ON DELETE CASCADE
     );
     CREATE TABLE IF NOT EXISTS messages (
@@ -111,25 +116,59 @@ pub fn init_db(app: &AppHandle) -> Result<Connection, String> {
     }
 
     // 迁移：MCP 工具调用支持（向后兼容）
-    add_column_if_missing(&conn, "messages", "tool_call_id", "TEXT")?;
-    add_column_if_missing(&conn, "messages", "name", "TEXT")?;
-    add_column_if_missing(&conn, "messages", "tool_calls_json", "TEXT")?;
+    add_column_if_missing(conn, "messages", "tool_call_id", "TEXT")?;
+    add_column_if_missing(conn, "messages", "name", "TEXT")?;
+    add_column_if_missing(conn, "messages", "tool_calls_json", "TEXT")?;
 
     // 迁移：模型原生思维链（reasoning_content）持久化（向后兼容）
-    add_column_if_missing(&conn, "messages", "reasoning", "TEXT")?;
+    add_column_if_missing(conn, "messages", "reasoning", "TEXT")?;
+
+    // 迁移：置顶消息（作为固定上下文始终发送）
+    add_column_if_missing(conn, "messages", "is_pinned", "INTEGER NOT NULL DEFAULT 0")?;
+
+    // 迁移：消息状态（pending / complete / error），供后端重试失败的回复
+    // 旧数据中以 "[Error:" 开头的 assistant 消息（content 为 JSON 字符串）回填为 error
+    let has_status: i32 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('messages') WHERE name='status'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap_or(0);
+    if has_status == 0 {
+        conn.execute(
+            "ALTER TABLE messages ADD COLUMN status TEXT NOT NULL DEFAULT 'complete'",
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.execute(
+            r#"UPDATE messages SET status = 'error'
+               WHERE role = 'assistant' AND (content LIKE '"[Error:%' OR content LIKE '"\n[Error:%')"#,
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+    }
 
     // 迁移：助手绑定首选模型（向后兼容）
     // 旧助手行缺少 model_id 列，反序列化时按 None 处理，视为使用全局默认模型
-    add_column_if_missing(&conn, "assistants", "model_id", "TEXT")?;
+    add_column_if_missing(conn, "assistants", "model_id", "TEXT")?;
 
     // 迁移：助手独立配置 MCP 服务器（向后兼容）
     // 旧助手行缺少 mcp_server_ids 列，加载时按空 vec 处理，等价于「该助手不使用任何 MCP 工具」
-    add_column_if_missing(&conn, "assistants", "mcp_server_ids", "TEXT")?;
+    add_column_if_missing(conn, "assistants", "mcp_server_ids", "TEXT")?;
 
     // 迁移：助手独立配置 Skill。旧助手默认不启用任何 Skill。
-    add_column_if_missing(&conn, "assistants", "skill_ids", "TEXT")?;
+    add_column_if_missing(conn, "assistants", "skill_ids", "TEXT")?;
 
-    Ok(conn)
+    // 迁移：最后修改时间（rename_assistant / rename_topic 更新）。
+    // ALTER TABLE 不支持非常量默认值，旧行保持 NULL
+    add_column_if_missing(conn, "assistants", "updated_at", "DATETIME")?;
+    add_column_if_missing(conn, "topics", "updated_at", "DATETIME")?;
+
+    // 迁移：话题记住最近使用的模型。旧话题保持 NULL，沿用助手 / 全局模型
+    add_column_if_missing(conn, "topics", "last_model_id", "TEXT")?;
+
+    Ok(())
 }
 
 /// 若指定表缺少指定列，则执行 ALTER TABLE ADD COLUMN。
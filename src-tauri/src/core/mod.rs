@@ -1,4 +1,6 @@
+pub mod blobs;
 pub mod db;
 pub mod models;
+pub mod paths;
 pub mod secure_store;
 pub mod state;
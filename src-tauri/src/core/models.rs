@@ -25,6 +25,12 @@ pub struct StreamPayload {
     pub topic_id: String,
     pub content: String,
     pub done: bool,
+    /// 调用方指定的请求 ID（call_llm_stream 传入 request_id 时回传，用于区分同一话题内的并发流）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// 产生该片段的模型（仅多模型对比 call_llm_compare 回传）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model_id: Option<String>,
 }
 
 /// 从 provider 实时拉取的单个模型信息（OpenAI-兼容 /v1/models 或厂商自定义端点）。
@@ -89,6 +95,52 @@ pub struct Message {
     /// 模型原生思维链（GLM/DeepSeek-R1/Qwen3 等的 reasoning_content），仅 assistant 消息可能携带
     #[serde(default, skip_serializing_if = "Option::is_none")]
     pub reasoning: Option<String>,
+    /// 消息状态（见 [`message_status`]）；前端未传时按内容推断
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// 置顶消息：作为固定上下文始终发送，不受 history_limit 与自动裁剪影响
+    #[serde(
+        rename = "isPinned",
+        default,
+        skip_serializing_if = "std::ops::Not::not"
+    )]
+    pub is_pinned: bool,
+    /// 消息时间（同步拉取时由服务器提供）；写库时为空则取当前时间。
+    /// 只在反序列化时读取，不回传前端、不随请求发给模型
+    #[serde(default, skip_serializing)]
+    pub timestamp: Option<String>,
+}
+
+/// messages.status 列的取值
+pub mod message_status {
+    /// 正在由后端重新生成
+    pub const PENDING: &str = "pending";
+    pub const COMPLETE: &str = "complete";
+    /// 请求失败，内容为错误信息
+    pub const ERROR: &str = "error";
+}
+
+impl Message {
+    /// 写库时使用的状态：显式传入的合法值优先；
+    /// 否则 assistant 消息以 `[Error:` 开头（流式错误事件的内容）视为失败
+    pub fn resolved_status(&self) -> &'static str {
+        match self.status.as_deref() {
+            Some(message_status::PENDING) => return message_status::PENDING,
+            Some(message_status::COMPLETE) => return message_status::COMPLETE,
+            Some(message_status::ERROR) => return message_status::ERROR,
+            _ => {}
+        }
+        let failed = self.role == "assistant"
+            && self
+                .content
+                .as_str()
+                .is_some_and(|text| text.trim_start().starts_with("[Error:"));
+        if failed {
+            message_status::ERROR
+        } else {
+            message_status::COMPLETE
+        }
+    }
 }
 
 /// OpenAI 风格的工具调用（assistant 消息中）
@@ -123,6 +175,21 @@ pub struct ToolFunctionSpec {
     pub parameters: serde_json::Value,
 }
 
+/// 一个可用于对话请求的模型（服务商地址 + 密钥 + 模型名），用作故障转移链中的一项
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelRef {
+    pub api_url: String,
+    pub api_key: String,
+    pub model: String,
+    /// 完整的对话接口地址模板，`{model}` 会被替换为模型名；为空时使用 `{api_url}/chat/completions`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoint_template: Option<String>,
+    /// 模型已由地址指定时，请求体中不再携带 `model` 字段
+    #[serde(default)]
+    pub omit_model_field: bool,
+}
+
 /// 按助手视角聚合的 MCP 工具集：扁平 `tools` 喂给 LLM，`tool_server_map` 供前端解析 toolName → serverId。
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -183,6 +250,10 @@ pub struct Topic {
     /// 由数据迁移在加载时统一修复。
     #[serde(default)]
     pub renamed: bool,
+    /// 该话题最近一次生成回复所用的模型（modelKey 复合键，早期数据为 model_id），前端切换到话题时据此预选模型；
+    /// 旧数据库行为 NULL → None（使用助手 / 全局模型）
+    #[serde(rename = "lastModelId", default, skip_serializing_if = "Option::is_none")]
+    pub last_model_id: Option<String>,
 }
 
 /// AI 助手预设模型，包含系统提示词和相关的对话列表。
@@ -234,6 +305,42 @@ pub struct AppConfig {
     pub default_model: String,
     #[serde(rename = "localModelPath", default)]
     pub local_model_path: String,
+    /// 关闭窗口 / 退出应用时保留本地推理服务器（不 kill，下次启动重新接管）
+    #[serde(rename = "keepServerOnExit", default)]
+    pub keep_server_on_exit: bool,
+    /// 自定义数据目录（数据库、头像、附件、配置列表），空字符串表示默认目录；重启后生效
+    #[serde(rename = "dataDir", default)]
+    pub data_dir: String,
+    /// 本地服务器自动推断上下文长度时的上限（0 表示默认值）
+    #[serde(rename = "localMaxCtxSize", default)]
+    pub local_max_ctx_size: u32,
+    /// llama.cpp 引擎下载地址模板（镜像），支持 `{tag}` / `{asset}` 占位符；空字符串表示 GitHub 官方地址
+    #[serde(rename = "llamaDownloadUrl", default)]
+    pub llama_download_url: String,
+    /// 应用启动时按上次成功的参数自动启动本地服务器
+    #[serde(rename = "autoStartLocalServer", default)]
+    pub auto_start_local_server: bool,
+    /// 丢弃与上一个完全相同的 SSE 事件（应对重连时重发的代理；可能误删重复 token，默认关闭）
+    #[serde(rename = "dedupStreamEvents", default)]
+    pub dedup_stream_events: bool,
+    /// 云端同步 / 鉴权服务器地址，空字符串表示默认地址（或 `AIO_CLOUD_BACKEND_URL`）
+    #[serde(rename = "syncServerUrl", default)]
+    pub sync_server_url: String,
+    /// 双向 TLS 客户端证书（PEM）路径，空字符串表示不使用
+    #[serde(rename = "syncClientCertPath", default)]
+    pub sync_client_cert_path: String,
+    /// 客户端证书对应的私钥（PEM）路径
+    #[serde(rename = "syncClientKeyPath", default)]
+    pub sync_client_key_path: String,
+    /// 额外信任的 CA 证书（PEM，可含多张）路径
+    #[serde(rename = "syncCaCertPath", default)]
+    pub sync_ca_cert_path: String,
+    /// 允许 fetch_url_content 抓取本机 / 内网地址（默认拒绝，防止 SSRF）
+    #[serde(rename = "allowPrivateNetworkFetch", default)]
+    pub allow_private_network_fetch: bool,
+    /// 附件提取文本的上限（KB），超出时保留开头与结尾、省略中间；0 表示默认值
+    #[serde(rename = "maxExtractedKb", default)]
+    pub max_extracted_kb: u32,
 }
 
 // ====== MCP 服务器配置 ======
@@ -0,0 +1,216 @@
+//! 数据目录解析（便携模式 / 自定义数据目录）
+//!
+//! 优先级：环境变量 `AIO_DATA_DIR` > `config.json` 中的 `dataDir` > 系统默认目录。
+//! 启动时解析一次并校验可写，不可写时告警并回退默认目录；修改 `dataDir` 需重启生效。
+//!
+//! - 环境变量模式（U 盘便携）：所有数据包括 `config.json` 都放在该目录
+//! - `dataDir` 模式：`config.json` 仍留在默认位置作为引导，其余数据迁到该目录
+//!
+//! 配置目录统一为 Tauri identifier（`com.loch.aio`）；旧版本写在 `$CONFIG/AIO` 下的配置文件
+//! 会在启动时一次性迁移过来（见 [`migrate_legacy_config_dir`]）。`AIO` 这个目录名并不专属于本应用，
+//! 因此只移动 [`LEGACY_FILES`] 中列出的文件，其他内容保持不动。
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use tauri::{AppHandle, Manager};
+
+/// 便携模式环境变量
+pub const DATA_DIR_ENV: &str = "AIO_DATA_DIR";
+const APPDATA_DIRNAME: &str = "com.loch.aio";
+/// 旧版本使用的配置目录名（同样位于系统配置目录下）
+const LEGACY_DIRNAME: &str = "AIO";
+const CONFIG_FILE: &str = "config.json";
+/// 旧版本在 `$CONFIG/AIO` 下写过的文件
+const LEGACY_FILES: &[&str] = &[
+    CONFIG_FILE,
+    "activated_models.json",
+    "fetched_models.json",
+    "provider-configs.json",
+];
+/// 系统配置目录不可用时的错误提示
+const NO_CONFIG_DIR: &str =
+    "无法获取系统配置目录，请通过环境变量 AIO_DATA_DIR 指定一个可写的数据目录";
+
+/// 启动时解析出的有效覆盖目录（None 表示使用默认目录）
+static DATA_DIR_OVERRIDE: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+fn default_config_root() -> Option<PathBuf> {
+    Some(dirs::config_dir()?.join(APPDATA_DIRNAME))
+}
+
+fn env_override() -> Option<PathBuf> {
+    std::env::var_os(DATA_DIR_ENV)
+        .map(PathBuf::from)
+        .filter(|p| !p.as_os_str().is_empty())
+}
+
+/// 目录是否可用：绝对路径、可创建、可写入
+pub fn check_writable(dir: &Path) -> Result<(), String> {
+    if !dir.is_absolute() {
+        return Err("数据目录必须为绝对路径".into());
+    }
+    fs::create_dir_all(dir).map_err(|e| format!("无法创建数据目录: {}", e))?;
+    let probe = dir.join(".aio-write-test");
+    fs::write(&probe, b"ok").map_err(|e| format!("数据目录不可写: {}", e))?;
+    let _ = fs::remove_file(probe);
+    Ok(())
+}
+
+/// 读取默认位置 config.json 中的 `dataDir`（只解析这一个字段，避免与 AppConfig 结构耦合）
+fn configured_data_dir() -> Option<PathBuf> {
+    let content = fs::read_to_string(default_config_root()?.join(CONFIG_FILE)).ok()?;
+    let value: serde_json::Value = serde_json::from_str(&content).ok()?;
+    value
+        .get("data_dir")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+}
+
+/// 把 `from` 下名为 `names` 的文件移动到 `to`，`to` 中已存在的同名文件保留不动（旧文件留在原处）。
+/// `from` 因此变空时删除它，返回移动的文件数
+fn move_files(from: &Path, to: &Path, names: &[&str]) -> std::io::Result<usize> {
+    let mut moved = 0;
+    for name in names {
+        let source = from.join(name);
+        if !source.is_file() {
+            continue;
+        }
+        let target = to.join(name);
+        if target.exists() {
+            tracing::warn!(
+                "旧配置 {} 与新目录中的文件重名，保留新目录版本",
+                source.display()
+            );
+            continue;
+        }
+        fs::create_dir_all(to)?;
+        fs::rename(&source, &target)?;
+        moved += 1;
+    }
+    // 目录中还有其他内容时删除失败即保留
+    if moved > 0 {
+        let _ = fs::remove_dir(from);
+    }
+    Ok(moved)
+}
+
+/// 一次性迁移：旧版本写在 `$CONFIG/AIO` 下的配置（激活模型、provider 配置等）移动到 `$CONFIG/com.loch.aio`
+fn migrate_legacy_config_dir() {
+    let Some(config) = dirs::config_dir() else {
+        return;
+    };
+    let legacy = config.join(LEGACY_DIRNAME);
+    let current = config.join(APPDATA_DIRNAME);
+    if !legacy.is_dir() {
+        return;
+    }
+    match move_files(&legacy, &current, LEGACY_FILES) {
+        Ok(0) => {}
+        Ok(n) => tracing::info!(
+            "已将 {} 项旧配置从 {} 迁移到 {}",
+            n,
+            legacy.display(),
+            current.display()
+        ),
+        Err(e) => tracing::warn!("迁移旧配置目录 {} 失败: {}", legacy.display(), e),
+    }
+}
+
+/// 启动时解析数据目录（需在 init_db 之前调用）
+pub fn init() {
+    migrate_legacy_config_dir();
+    DATA_DIR_OVERRIDE.get_or_init(|| {
+        let (source, dir) = match (env_override(), configured_data_dir()) {
+            (Some(dir), _) => (DATA_DIR_ENV, dir),
+            (None, Some(dir)) => ("dataDir", dir),
+            (None, None) => return None,
+        };
+        match check_writable(&dir) {
+            Ok(()) => {
+                tracing::info!("使用自定义数据目录 ({}): {}", source, dir.display());
+                Some(dir)
+            }
+            Err(e) => {
+                tracing::warn!("自定义数据目录 {} 不可用，回退默认目录: {}", dir.display(), e);
+                None
+            }
+        }
+    });
+}
+
+/// 当前生效的自定义数据目录
+pub fn data_dir_override() -> Option<PathBuf> {
+    DATA_DIR_OVERRIDE.get().cloned().flatten()
+}
+
+/// 目录不存在时创建
+fn ensure_dir(dir: PathBuf) -> Result<PathBuf, String> {
+    if !dir.exists() {
+        fs::create_dir_all(&dir)
+            .map_err(|e| format!("无法创建配置目录 {}: {}", dir.display(), e))?;
+    }
+    Ok(dir)
+}
+
+/// 配置类 JSON 文件（模型列表、provider 配置等）所在目录，不存在时创建。
+/// 所有配置文件都应通过这里（或 [`config_file`]）定位，目录名只在本模块定义
+pub fn app_config_dir() -> Result<PathBuf, String> {
+    let dir = data_dir_override()
+        .or_else(default_config_root)
+        .ok_or(NO_CONFIG_DIR)?;
+    ensure_dir(dir)
+}
+
+/// `config.json` 路径：仅环境变量模式下随数据目录迁移
+pub fn config_file() -> Result<PathBuf, String> {
+    let dir = match data_dir_override() {
+        Some(dir) if env_override().is_some() => dir,
+        _ => default_config_root().ok_or(NO_CONFIG_DIR)?,
+    };
+    Ok(ensure_dir(dir)?.join(CONFIG_FILE))
+}
+
+/// 数据库、头像、附件等应用数据所在目录
+pub fn app_data_root(app: &AppHandle) -> Result<PathBuf, String> {
+    match data_dir_override() {
+        Some(dir) => Ok(dir),
+        None => app.path().app_data_dir().map_err(|e| e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_only_known_legacy_files_without_overwriting() {
+        let root = std::env::temp_dir().join(format!("aio-paths-test-{}", uuid::Uuid::new_v4()));
+        let (legacy, current) = (root.join("AIO"), root.join("com.loch.aio"));
+        fs::create_dir_all(&legacy).unwrap();
+        fs::create_dir_all(&current).unwrap();
+        fs::write(legacy.join("activated_models.json"), b"old").unwrap();
+        fs::write(legacy.join("config.json"), b"old").unwrap();
+        fs::write(legacy.join("other-app.db"), b"other").unwrap();
+        fs::write(current.join("config.json"), b"new").unwrap();
+
+        assert_eq!(move_files(&legacy, &current, LEGACY_FILES).unwrap(), 1);
+        assert_eq!(
+            fs::read(current.join("activated_models.json")).unwrap(),
+            b"old"
+        );
+        assert_eq!(fs::read(current.join("config.json")).unwrap(), b"new");
+        // 重名的旧文件与不属于本应用的文件都保留在原处
+        assert!(legacy.join("config.json").exists());
+        assert!(legacy.join("other-app.db").exists());
+        assert!(!current.join("other-app.db").exists());
+
+        fs::remove_file(legacy.join("other-app.db")).unwrap();
+        fs::remove_file(current.join("config.json")).unwrap();
+        assert_eq!(move_files(&legacy, &current, LEGACY_FILES).unwrap(), 1);
+        assert!(!legacy.exists());
+        let _ = fs::remove_dir_all(&root);
+    }
+}
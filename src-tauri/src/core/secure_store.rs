@@ -13,6 +13,7 @@
 //! - `auth-token`: 后端登录 JWT
 //! - `app-api-url`: 全局 API URL（仅当用户选择加密存储时）
 //! - `app-api-key`: 全局 API Key
+//! - `local-server-api-key`: 退出时保留的本地服务器的鉴权 key
 //! - `provider-{provider_id}-api-key`: 每个 provider 的 API Key
 //! - `mcp-server-{server_id}-env-{env_key}`: 每个 MCP server 的环境变量密钥
 
@@ -150,6 +151,8 @@ pub fn delete(app: &AppHandle, account: &str) -> Result<()> {
 pub mod accounts {
     pub const AUTH_TOKEN: &str = "auth-token";
     pub const APP_API_KEY: &str = "app-api-key";
+    /// 退出时保留的本地服务器的鉴权 key（local-server.json 中只记录此名称）
+    pub const LOCAL_SERVER_API_KEY: &str = "local-server-api-key";
     pub fn provider_key(id: &str) -> String {
         format!("provider-{}-api-key", id)
     }
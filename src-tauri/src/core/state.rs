@@ -1,11 +1,15 @@
 /// 全局 Tauri 状态定义
 
+use crate::plugins::engine::detached::ProcessIdentity;
+use crate::plugins::engine::options::LoraAdapter;
 use dashmap::DashMap;
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use tokio::task::JoinHandle;
 
 /// 管理活跃的 LLM 流式任务
-/// 键格式为 "{assistant_id}-{topic_id}"
+/// 键格式为 "{assistant_id}-{topic_id}"；调用方传入 request_id 时为 "req:{request_id}"
+/// （多模型对比的各流为 "req:{compare_id}/{序号}"）
 pub struct StreamManager(pub Arc<DashMap<String, JoinHandle<()>>>);
 
 /// 包装 SQLite 数据库连接
@@ -18,18 +22,110 @@ pub struct LocalEngineInner {
     pub engine_type: String,
     /// 子进程句柄
     pub child_process: Option<std::process::Child>,
+    /// 服务器监听端口（127.0.0.1）
+    pub port: Option<u16>,
+    /// 是否加载了 mmproj，可接收图片输入
+    pub supports_images: bool,
+    /// 子进程输出已改写到日志文件（可安全脱离应用继续运行）
+    pub output_detached: bool,
+    /// 上次运行保留下来并在本次启动时接管的服务器 pid（无 Child 句柄）
+    pub adopted_pid: Option<u32>,
+    /// 接管服务器的进程身份；结束进程前据此确认 pid 未被复用
+    pub adopted_identity: Option<ProcessIdentity>,
+    /// 本次启动随机生成的 `--api-key`；None 表示未启用鉴权
+    pub api_key: Option<String>,
+    /// 生效的上下文长度（`-c` / `--max-model-len`）
+    pub ctx_size: Option<u32>,
+    /// 启动时指定的 KV cache 类型；None 表示引擎默认（f16）
+    pub cache_type_k: Option<String>,
+    pub cache_type_v: Option<String>,
+    /// 启动时加载的 LoRA 适配器
+    pub lora_adapters: Vec<LoraAdapter>,
+    /// 投机解码使用的草稿模型路径
+    pub draft_model_path: Option<String>,
+}
+
+impl LocalEngineInner {
+    /// 是否有（自己启动的或接管的）服务器
+    pub fn has_server(&self) -> bool {
+        self.child_process.is_some() || self.adopted_pid.is_some()
+    }
+
+    /// 请求地址是否指向当前运行的本地服务器（127.0.0.1 / localhost + 同端口）
+    pub fn serves_url(&self, api_url: &str) -> bool {
+        let Ok(url) = url::Url::parse(api_url) else {
+            return false;
+        };
+        let is_loopback = matches!(url.host_str(), Some("127.0.0.1") | Some("localhost"));
+        is_loopback
+            && self.has_server()
+            && self.port.is_some()
+            && url.port_or_known_default() == self.port
+    }
 }
 
 /// 当前运行的本地推理引擎进程状态
-pub struct LocalEngineState(pub Mutex<LocalEngineInner>);
+pub struct LocalEngineState {
+    inner: Mutex<LocalEngineInner>,
+    /// 串行化启动 / 停止：切换模型期间持有，直到旧进程退出、新进程就绪
+    lifecycle: tokio::sync::Mutex<()>,
+}
 
 impl LocalEngineState {
     pub fn new() -> Self {
-        Self(Mutex::new(LocalEngineInner::default()))
+        Self {
+            inner: Mutex::new(LocalEngineInner::default()),
+            lifecycle: tokio::sync::Mutex::new(()),
+        }
     }
 
     pub fn lock(&self) -> std::sync::MutexGuard<'_, LocalEngineInner> {
-        self.0.lock().unwrap_or_else(|e| e.into_inner())
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 状态锁是否已中毒（持锁线程 panic 过）；[`Self::lock`] 总会取回内部数据继续使用
+    pub fn is_poisoned(&self) -> bool {
+        self.inner.is_poisoned()
+    }
+
+    /// 开始一次模型切换；已有启动在进行时直接拒绝，不排队
+    pub fn try_begin_switch(&self) -> Result<tokio::sync::MutexGuard<'_, ()>, String> {
+        self.lifecycle
+            .try_lock()
+            .map_err(|_| "模型切换正在进行中，请等待当前启动完成".to_string())
+    }
+
+    /// 等待进行中的启动 / 停止完成后再操作
+    pub async fn begin_switch(&self) -> tokio::sync::MutexGuard<'_, ()> {
+        self.lifecycle.lock().await
+    }
+}
+
+// ====== HTTP 客户端 ======
+
+/// 全局共享的 reqwest 客户端：复用连接池（keep-alive / TLS 会话），避免每次请求重新握手。
+///
+/// 不设请求总超时（流式响应可能持续很久），需要时在请求上用 `RequestBuilder::timeout` 指定；
+/// 代理沿用系统环境变量（HTTP_PROXY / HTTPS_PROXY / ALL_PROXY），
+/// 需要单独代理的服务商仍通过 `ProviderPlugin::build_client` 构造专用客户端。
+pub struct HttpClientState(pub reqwest::Client);
+
+impl HttpClientState {
+    pub fn new() -> Result<Self, String> {
+        let client = reqwest::Client::builder()
+            .user_agent("AIO-Desktop/0.4")
+            .connect_timeout(Duration::from_secs(5))
+            .pool_idle_timeout(Duration::from_secs(90))
+            .pool_max_idle_per_host(8)
+            .tcp_keepalive(Duration::from_secs(60))
+            .build()
+            .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
+        Ok(Self(client))
+    }
+
+    /// 克隆句柄（内部为 Arc，共享同一连接池）
+    pub fn client(&self) -> reqwest::Client {
+        self.0.clone()
     }
 }
 
@@ -56,6 +152,88 @@ impl McpServerState {
     }
 }
 
+/// 进行中的文件解析任务：调用方提供的 job_id → 取消标志
+#[derive(Default)]
+pub struct FileJobManager(pub DashMap<String, Arc<std::sync::atomic::AtomicBool>>);
+
+/// 以工具调用结束、等待工具结果的对话轮次：request_id → 续写所需的上下文
+#[derive(Default)]
+pub struct ToolRoundManager(pub DashMap<String, ChatRound>);
+
+/// 等待工具结果的轮次超过此时长仍未收齐结果即丢弃（前端关闭或未送回结果）
+const TOOL_ROUND_TTL: Duration = Duration::from_secs(30 * 60);
+
+impl ToolRoundManager {
+    /// 登记等待工具结果的轮次，顺带清理超时未收齐结果的旧轮次
+    pub fn wait_for_results(&self, request_id: String, round: ChatRound) {
+        self.prune_expired();
+        self.0.insert(request_id, round);
+    }
+
+    /// 丢弃超过 [`TOOL_ROUND_TTL`] 仍未收齐结果的轮次
+    pub fn prune_expired(&self) {
+        self.0
+            .retain(|_, round| round.waiting_since.elapsed() < TOOL_ROUND_TTL);
+    }
+}
+
+/// 一轮流式对话的完整上下文（续写工具调用时原样重发）
+pub struct ChatRound {
+    pub assistant_id: String,
+    pub topic_id: String,
+    /// 主模型在前，故障转移链依次在后
+    pub candidates: Vec<crate::core::models::ModelRef>,
+    /// 发给模型的消息；等待工具结果时已追加 assistant 的 tool_calls 消息与收到的 tool 消息
+    pub messages: Vec<serde_json::Value>,
+    pub tools: Option<Vec<crate::core::models::ToolSpec>>,
+    pub logprobs: Option<u32>,
+    /// 尚未收到结果的工具调用：tool_call_id → 工具名
+    pub pending_tools: std::collections::HashMap<String, String>,
+    /// 开始等待工具结果的时间
+    pub waiting_since: Instant,
+}
+
+impl ChatRound {
+    /// 回复以工具调用结束：追加 assistant 的 tool_calls 消息，登记每个调用等待结果
+    pub fn await_tools(&mut self, content: &str, tool_calls: &[crate::core::models::ToolCall]) {
+        let content = if content.is_empty() {
+            serde_json::Value::Null
+        } else {
+            serde_json::json!(content)
+        };
+        self.messages.push(serde_json::json!({
+            "role": "assistant",
+            "content": content,
+            "tool_calls": tool_calls,
+        }));
+        self.pending_tools = tool_calls
+            .iter()
+            .map(|call| (call.id.clone(), call.function.name.clone()))
+            .collect();
+        self.waiting_since = Instant::now();
+    }
+
+    /// 记录一个工具调用的结果（追加 role="tool" 消息），返回工具名
+    pub fn record_tool_result(&mut self, tool_call_id: &str, result: &str) -> Result<String, String> {
+        let name = self
+            .pending_tools
+            .remove(tool_call_id)
+            .ok_or_else(|| format!("工具调用 {} 不存在或已提交结果", tool_call_id))?;
+        self.messages.push(serde_json::json!({
+            "role": "tool",
+            "tool_call_id": tool_call_id,
+            "name": name,
+            "content": result,
+        }));
+        Ok(name)
+    }
+
+    /// 本轮全部工具调用都已有结果，可以续写
+    pub fn ready_to_resume(&self) -> bool {
+        self.pending_tools.is_empty()
+    }
+}
+
 /// 在途 MCP 工具调用：call_id → JoinHandle<Result<ToolResult, McpError>>
 /// 用户点停止时遍历 abort 所有
 pub struct McpRequestManager(
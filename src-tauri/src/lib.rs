@@ -11,9 +11,15 @@ mod core;
 mod plugins;
 mod utils;
 
+use crate::commands::probe::ModelCapabilityCache;
 use crate::core::state::{
-    DbState, LocalEngineState, McpRequestManager, McpServerState, StreamManager,
+    DbState, FileJobManager, HttpClientState, LocalEngineState, McpRequestManager, McpServerState,
+    StreamManager, ToolRoundManager,
 };
+use crate::plugins::engine::benchmark::BenchmarkManager;
+use crate::plugins::engine::metrics::MetricsPoller;
+use crate::plugins::engine::resources::ResourceMonitor;
+use crate::plugins::engine::server_log::ServerLogBuffer;
 use crate::plugins::engine::EngineManager;
 use crate::plugins::mcp::McpServerManager;
 use crate::utils::process_file_content;
@@ -36,8 +42,14 @@ pub fn run() {
     init_tracing();
     tauri::Builder::default()
         .setup(|app| {
+            // 先解析自定义数据目录（便携模式），数据库与配置路径都依赖它
+            core::paths::init();
+            app.manage(HttpClientState::new()?);
             let conn = core::db::init_db(app.handle())?;
             app.manage(DbState(std::sync::Mutex::new(conn)));
+            commands::engine::adopt_detached_server(app.handle());
+            app.state::<ResourceMonitor>().start(app.handle().clone());
+            commands::engine::auto_start_local_server(app.handle());
             Ok(())
         })
         .plugin(tauri_plugin_dialog::init())
@@ -47,13 +59,25 @@ pub fn run() {
         .manage(StreamManager(Arc::new(dashmap::DashMap::new())))
         .manage(LocalEngineState::new())
         .manage(EngineManager::new())
+        .manage(ServerLogBuffer::default())
+        .manage(MetricsPoller::default())
+        .manage(BenchmarkManager::default())
+        .manage(ResourceMonitor::default())
+        .manage(ModelCapabilityCache::default())
+        .manage(FileJobManager::default())
         .manage(McpServerManager::builtin())
         .manage(McpServerState::default())
         .manage(McpRequestManager::new())
+        .manage(ToolRoundManager::default())
         .invoke_handler(tauri::generate_handler![
             commands::config::load_assistants,
             commands::config::save_assistant,
             commands::config::delete_assistant,
+            commands::config::rename_assistant,
+            commands::config::rename_topic,
+            commands::config::pin_message,
+            commands::config::delete_topics_before,
+            commands::config::migrate_inline_images,
             commands::config::save_app_config,
             commands::config::load_app_config,
             commands::config::save_activated_models,
@@ -63,15 +87,52 @@ pub fn run() {
             commands::attachment::store_chat_attachment,
             commands::attachment::discard_chat_attachment,
             commands::llm::call_llm_stream,
+            commands::llm::retry_message,
             commands::llm::stop_llm_stream,
+            commands::llm::continue_with_tool_result,
+            commands::llm::call_llm_compare,
+            commands::llm::stop_llm_compare,
+            commands::llm::replay_stream,
             commands::llm::fetch_models,
             commands::engine::start_local_server,
             commands::engine::stop_local_server,
             commands::engine::is_local_server_running,
+            commands::engine::get_local_model_options,
+            commands::engine::get_low_vram_options,
+            commands::engine::get_local_server_status,
+            commands::engine::get_local_server_logs,
+            commands::engine::get_local_server_metrics,
+            commands::engine::set_local_server_metrics_polling,
+            commands::engine::scan_local_models,
+            commands::engine::inspect_gguf,
+            commands::engine::register_local_model,
+            commands::engine::benchmark_local_model,
+            commands::engine::cancel_local_benchmark,
+            commands::engine::cleanup_orphaned_servers,
+            commands::engine::kill_port_owner,
+            commands::engine::force_reset_local_server,
             commands::engine::get_engines_status,
+            commands::engine::get_backend_version,
             commands::engine::install_engine,
             commands::engine::check_llama_update,
             process_file_content,
+            utils::file_parser::process_file_content_v2,
+            utils::file_parser::chunk_document,
+            utils::file_parser::preview_file_extraction,
+            utils::file_parser::cancel_file_processing,
+            utils::file_parser::process_files,
+            commands::web::fetch_url_content,
+            commands::audio::transcribe_audio,
+            commands::audio::synthesize_speech,
+            commands::image::generate_image,
+            commands::document::summarize_document,
+            commands::stats::usage_stats,
+            commands::stats::estimate_cost,
+            commands::search::search_in_topic,
+            commands::settings::export_settings,
+            commands::settings::import_settings,
+            commands::config_check::validate_configuration,
+            commands::probe::probe_model_capabilities,
             commands::config::upload_avatar,
             commands::llm::summarize_history,
             commands::llm::append_message,
@@ -83,6 +144,8 @@ pub fn run() {
             cloud_backend::auth::sync_avatar_to_backend,
             cloud_backend::auth::logout_clear,
             cloud_backend::auth::read_auth_token,
+            cloud_backend::sync::list_remote_topics,
+            cloud_backend::sync::pull_topics,
             commands::config::clear_local_avatar_cache,
             commands::config::read_avatar_source,
             commands::update::check_app_update,
@@ -125,15 +188,8 @@ pub fn run() {
         ])
         .on_window_event(|window, event| {
             if let tauri::WindowEvent::Destroyed = event {
-                // 清理本地引擎子进程
-                let state = window.state::<LocalEngineState>();
-                let child_opt = {
-                    let mut inner = state.lock();
-                    inner.child_process.take()
-                };
-                if let Some(mut child) = child_opt {
-                    let _ = child.kill();
-                }
+                // 清理本地引擎子进程（开启 keep_server_on_exit 时保留并记录）
+                commands::engine::release_local_server(window.app_handle());
                 // 清理 MCP 状态（在途调用 abort + 连接池清空）
                 let req_mgr = window.state::<McpRequestManager>();
                 req_mgr.abort_all();
@@ -141,6 +197,12 @@ pub fn run() {
                 mcp_state.lock().clear();
             }
         })
-        .run(tauri::generate_context!())
-        .expect("运行 tauri 应用程序时发生错误");
+        .build(tauri::generate_context!())
+        .expect("运行 tauri 应用程序时发生错误")
+        .run(|app, event| {
+            // 托盘模式下可能没有窗口销毁事件，应用退出时再兜底处理一次本地服务器
+            if let tauri::RunEvent::Exit = event {
+                commands::engine::release_local_server(app);
+            }
+        });
 }
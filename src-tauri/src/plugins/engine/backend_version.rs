@@ -0,0 +1,207 @@
+//! llama-server 版本检测与参数兼容性检查
+//!
+//! `llama-server --version` 输出形如 `version: 4567 (abc1234)`（打印到 stderr）。
+//! 启动前对照下表检查本次要传的参数：旧版本不认识的参数会让进程直接退出，
+//! 错误只出现在用户看不到的 stderr 里，因此提前发 `local-server-warning` 事件说明原因。
+
+use crate::plugins::engine::LocalServerOptions;
+use serde::Serialize;
+use std::path::Path;
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+/// 兼容性告警事件名
+pub const WARNING_EVENT: &str = "local-server-warning";
+
+/// 参数 → 首个支持该参数的 llama.cpp 构建号（取保守值）
+const FLAG_MIN_BUILDS: &[(&str, u64)] = &[
+    ("--metrics", 1500),
+    // 鉴权 key 通过环境变量传入（不出现在命令行里），参数支持环境变量始于 b3800 前后
+    ("LLAMA_API_KEY", 3800),
+    ("--no-kv-offload", 1600),
+    ("--cache-type-k", 1700),
+    ("--cache-type-v", 1700),
+    // 量化 V cache 需要的 flash attention，同样经环境变量开启
+    ("LLAMA_ARG_FLASH_ATTN", 3800),
+    ("--chat-template", 2600),
+    ("--chat-template-file", 3800),
+    ("--mmproj", 5423),
+    ("--model-draft", 4200),
+    ("--draft-max", 4200),
+    ("--draft-min", 4200),
+];
+
+/// 应用每次启动都会传的参数（`--metrics`）要求的最低构建号
+pub const MIN_SUPPORTED_BUILD: u64 = 1500;
+
+/// llama-server 版本信息
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BackendVersion {
+    /// 构建号，如 4567（对应 release tag `b4567`）
+    pub build: Option<u64>,
+    /// git commit 短哈希
+    pub commit: Option<String>,
+    /// `--version` 原始输出
+    pub raw: String,
+    /// 是否满足应用使用的基础参数
+    pub compatible: bool,
+    pub min_build: u64,
+}
+
+/// `local-server-warning` 事件载荷
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CompatWarning {
+    pub flag: &'static str,
+    pub required_build: u64,
+    pub current_build: u64,
+    pub message: String,
+}
+
+/// 解析 `version: 4567 (abc1234)`
+fn parse_version_output(text: &str) -> (Option<u64>, Option<String>) {
+    let Some(rest) = text
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("version:"))
+    else {
+        return (None, None);
+    };
+    let mut parts = rest.split_whitespace();
+    let build = parts.next().and_then(|b| b.parse().ok());
+    let commit = parts
+        .next()
+        .map(|c| c.trim_matches(|ch| ch == '(' || ch == ')').to_string())
+        .filter(|c| !c.is_empty());
+    (build, commit)
+}
+
+/// 运行 `llama-server --version` 读取版本
+pub fn detect(exe_path: &Path) -> Result<BackendVersion, String> {
+    let mut cmd = std::process::Command::new(exe_path);
+    cmd.arg("--version");
+    if let Some(dir) = exe_path.parent() {
+        cmd.current_dir(dir);
+    }
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(0x08000000);
+    let output = cmd.output().map_err(|e| format!("无法运行 llama-server: {}", e))?;
+    let raw = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    )
+    .trim()
+    .to_string();
+    let (build, commit) = parse_version_output(&raw);
+    Ok(BackendVersion {
+        compatible: build.map_or(true, |b| b >= MIN_SUPPORTED_BUILD),
+        build,
+        commit,
+        raw,
+        min_build: MIN_SUPPORTED_BUILD,
+    })
+}
+
+/// 参数要求的最低构建号；不在表中的参数视为始终支持
+pub fn min_build_for(flag: &str) -> u64 {
+    FLAG_MIN_BUILDS
+        .iter()
+        .find(|(f, _)| *f == flag)
+        .map_or(0, |(_, b)| *b)
+}
+
+/// 当前版本是否支持某参数；版本未知时按支持处理
+pub fn supports(build: Option<u64>, flag: &str) -> bool {
+    build.map_or(true, |b| b >= min_build_for(flag))
+}
+
+/// 检查用户在选项中显式要求的功能，返回当前版本不支持的参数
+pub fn unsupported_options(build: Option<u64>, options: &LocalServerOptions) -> Vec<CompatWarning> {
+    let Some(current) = build else {
+        return Vec::new();
+    };
+    let mut flags = Vec::new();
+    if let Ok(Some(template)) = options.chat_template_arg() {
+        flags.push(template.flag());
+    }
+    if matches!(options.mmproj_arg(), Ok(Some(_))) {
+        flags.push("--mmproj");
+    }
+    if let Ok((cache_k, cache_v)) = options.cache_type_args() {
+        if cache_k.is_some() {
+            flags.push("--cache-type-k");
+        }
+        if cache_v.is_some() {
+            flags.push("--cache-type-v");
+        }
+    }
+    if options.needs_flash_attn() {
+        flags.push("LLAMA_ARG_FLASH_ATTN");
+    }
+    if options.no_kv_offload {
+        flags.push("--no-kv-offload");
+    }
+    if matches!(options.draft_model_arg(), Ok(Some(_))) {
+        flags.push("--model-draft");
+        if options.draft_max.is_some() {
+            flags.push("--draft-max");
+        }
+        if options.draft_min.is_some() {
+            flags.push("--draft-min");
+        }
+    }
+    flags
+        .into_iter()
+        .filter(|flag| !supports(build, flag))
+        .map(|flag| {
+            let required = min_build_for(flag);
+            CompatWarning {
+                flag,
+                required_build: required,
+                current_build: current,
+                message: format!(
+                    "当前 llama.cpp 版本 b{} 不支持 {}（需要 b{} 或更高），请在设置中更新引擎",
+                    current, flag, required
+                ),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_version_banner() {
+        let out = "load_backend: loaded CPU backend\nversion: 4567 (abc1234)\nbuilt with cc for x86_64-linux-gnu";
+        assert_eq!(parse_version_output(out), (Some(4567), Some("abc1234".into())));
+        assert_eq!(parse_version_output("garbage"), (None, None));
+    }
+
+    #[test]
+    fn flags_options_newer_than_build() {
+        let opts = LocalServerOptions {
+            cache_type_k: Some("q8_0".into()),
+            no_kv_offload: true,
+            ..Default::default()
+        };
+        let warnings = unsupported_options(Some(1650), &opts);
+        assert_eq!(warnings.iter().map(|w| w.flag).collect::<Vec<_>>(), vec!["--cache-type-k"]);
+        assert!(unsupported_options(None, &opts).is_empty());
+
+        let quantized_v = LocalServerOptions {
+            cache_type_v: Some("q8_0".into()),
+            ..Default::default()
+        };
+        let flags: Vec<_> = unsupported_options(Some(2000), &quantized_v)
+            .into_iter()
+            .map(|w| w.flag)
+            .collect();
+        assert_eq!(flags, vec!["LLAMA_ARG_FLASH_ATTN"]);
+        assert!(!supports(Some(1650), "LLAMA_API_KEY"));
+        assert!(supports(Some(4000), "LLAMA_API_KEY"));
+    }
+}
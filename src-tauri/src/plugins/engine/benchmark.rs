@@ -0,0 +1,422 @@
+//! 本地模型基准测试
+//!
+//! 引擎目录中带有 `llama-bench` 时直接调用它（`-o json`）；否则在临时端口上单独启动一个
+//! llama-server（不影响当前运行的服务器，同样以随机 key 鉴权），按几个上下文深度发送标准提示词，
+//! 读取 `/completion` 返回的 `timings`。两种方式的结果统一为提示词处理与生成的 tokens/s，便于比较不同量化版本。
+//! 测试任务登记在 [`BenchmarkManager`] 中可随时取消，子进程设置了 kill_on_drop，随任务一起结束。
+
+use crate::core::state::HttpClientState;
+use crate::plugins::engine::llama_cpp::{generate_api_key, LlamaCppPlugin};
+use crate::plugins::engine::{LocalEnginePlugin, LocalServerOptions};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::task::AbortHandle;
+use tokio::time::{sleep, Duration, Instant};
+
+/// 进度事件名
+pub const PROGRESS_EVENT: &str = "local-benchmark-progress";
+/// 测试的上下文深度（提示词 token 数）
+const DEPTHS: &[u32] = &[512, 2048];
+/// 每轮生成的 token 数
+const GEN_TOKENS: u32 = 128;
+/// 临时服务器启动的最长等待时间
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+/// 单轮推理请求的超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+/// 标准提示词的重复单元
+const PROMPT_UNIT: &str = "The quick brown fox jumps over the lazy dog while the curious cat watches quietly. ";
+
+/// 测试方式
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BenchmarkMethod {
+    LlamaBench,
+    Server,
+}
+
+/// 测试项类型
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BenchmarkKind {
+    /// 提示词处理（prefill）
+    Prompt,
+    /// 生成（decode）
+    Generation,
+}
+
+/// 单个测试项的结果
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchmarkEntry {
+    pub kind: BenchmarkKind,
+    /// 开始本项时上下文中已有的 token 数
+    pub depth: u32,
+    /// 本项处理 / 生成的 token 数
+    pub tokens: u32,
+    pub tokens_per_second: f64,
+}
+
+/// 基准测试报告
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchmarkReport {
+    pub model_path: String,
+    pub method: BenchmarkMethod,
+    pub entries: Vec<BenchmarkEntry>,
+    pub elapsed_ms: u64,
+}
+
+/// 进度事件载荷
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BenchmarkProgress {
+    stage: String,
+    /// 0~1
+    progress: f64,
+}
+
+fn emit_progress(app: &AppHandle, stage: impl Into<String>, progress: f64) {
+    let _ = app.emit(
+        PROGRESS_EVENT,
+        BenchmarkProgress {
+            stage: stage.into(),
+            progress,
+        },
+    );
+}
+
+/// 正在进行的基准测试（Tauri 托管状态），同一时间只允许一个
+#[derive(Default)]
+pub struct BenchmarkManager(Mutex<Option<AbortHandle>>);
+
+impl BenchmarkManager {
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<AbortHandle>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 在后台任务中运行测试并等待结果；任务被 [`cancel`](Self::cancel) 中止时返回错误
+    pub async fn run(
+        &self,
+        app: AppHandle,
+        exe_path: &Path,
+        model_path: String,
+        gpu_layers: i32,
+        options: LocalServerOptions,
+    ) -> Result<BenchmarkReport, String> {
+        let exe_path = exe_path.to_path_buf();
+        let handle = {
+            let mut current = self.lock();
+            if current.as_ref().is_some_and(|h| !h.is_finished()) {
+                return Err("已有基准测试正在进行".into());
+            }
+            let handle = tokio::spawn(async move {
+                benchmark(&app, &exe_path, model_path, gpu_layers, options).await
+            });
+            *current = Some(handle.abort_handle());
+            handle
+        };
+        let result = handle.await;
+        let mut current = self.lock();
+        if current.as_ref().is_some_and(|h| h.is_finished()) {
+            current.take();
+        }
+        drop(current);
+        match result {
+            Ok(report) => report,
+            Err(e) if e.is_cancelled() => Err("基准测试已取消".into()),
+            Err(e) => Err(format!("基准测试任务异常: {}", e)),
+        }
+    }
+
+    /// 取消正在进行的测试，返回是否有测试被取消
+    pub fn cancel(&self) -> bool {
+        match self.lock().take() {
+            Some(handle) => {
+                handle.abort();
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// 与 llama-server 同目录的 llama-bench
+fn llama_bench_path(exe_path: &Path) -> Option<std::path::PathBuf> {
+    let name = if cfg!(target_os = "windows") {
+        "llama-bench.exe"
+    } else {
+        "llama-bench"
+    };
+    Some(exe_path.with_file_name(name)).filter(|p| p.is_file())
+}
+
+async fn benchmark(
+    app: &AppHandle,
+    exe_path: &Path,
+    model_path: String,
+    gpu_layers: i32,
+    options: LocalServerOptions,
+) -> Result<BenchmarkReport, String> {
+    let started = Instant::now();
+    let (method, entries) = match llama_bench_path(exe_path) {
+        Some(bench) => (
+            BenchmarkMethod::LlamaBench,
+            run_llama_bench(app, &bench, &model_path, gpu_layers, &options).await?,
+        ),
+        None => (
+            BenchmarkMethod::Server,
+            run_server_bench(app, exe_path, &model_path, gpu_layers, options).await?,
+        ),
+    };
+    emit_progress(app, "完成", 1.0);
+    Ok(BenchmarkReport {
+        model_path,
+        method,
+        entries,
+        elapsed_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+/// 与服务器方式对齐的两次 llama-bench：提示词处理从空上下文开始；
+/// 生成用 `-d` 先填充到同样的上下文深度再测
+async fn run_llama_bench(
+    app: &AppHandle,
+    bench: &Path,
+    model_path: &str,
+    gpu_layers: i32,
+    options: &LocalServerOptions,
+) -> Result<Vec<BenchmarkEntry>, String> {
+    let depths = DEPTHS.iter().map(u32::to_string).collect::<Vec<_>>().join(",");
+    emit_progress(app, "运行 llama-bench（提示词处理）", 0.1);
+    let mut entries = run_llama_bench_once(
+        bench,
+        model_path,
+        gpu_layers,
+        options,
+        &["-p", &depths, "-n", "0"],
+    )
+    .await?;
+    emit_progress(app, "运行 llama-bench（生成）", 0.55);
+    entries.extend(
+        run_llama_bench_once(
+            bench,
+            model_path,
+            gpu_layers,
+            options,
+            &["-p", "0", "-n", &GEN_TOKENS.to_string(), "-d", &depths],
+        )
+        .await?,
+    );
+    Ok(entries)
+}
+
+async fn run_llama_bench_once(
+    bench: &Path,
+    model_path: &str,
+    gpu_layers: i32,
+    options: &LocalServerOptions,
+    test_args: &[&str],
+) -> Result<Vec<BenchmarkEntry>, String> {
+    let mut cmd = tokio::process::Command::new(bench);
+    cmd.args(["-m", model_path])
+        .args(test_args)
+        .args(["-ngl", &gpu_layers.to_string()])
+        .args(["-r", "2", "-o", "json"])
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(dir) = bench.parent() {
+        cmd.current_dir(dir);
+    }
+    if let Ok((cache_k, cache_v)) = options.cache_type_args() {
+        if let Some(k) = cache_k {
+            cmd.args(["-ctk", k]);
+        }
+        if let Some(v) = cache_v {
+            cmd.args(["-ctv", v]);
+        }
+    }
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(0x08000000);
+    let output = cmd.output().await.map_err(|e| format!("无法运行 llama-bench: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let tail = stderr.lines().rev().take(5).collect::<Vec<_>>();
+        return Err(format!(
+            "llama-bench 运行失败（{}）: {}",
+            output.status,
+            tail.into_iter().rev().collect::<Vec<_>>().join("\n")
+        ));
+    }
+    let entries = parse_llama_bench(&String::from_utf8_lossy(&output.stdout));
+    if entries.is_empty() {
+        return Err("无法解析 llama-bench 输出".into());
+    }
+    Ok(entries)
+}
+
+/// 解析 `llama-bench -o json`：每项含 n_prompt / n_gen / avg_ts（以及新版本的 n_depth）
+fn parse_llama_bench(text: &str) -> Vec<BenchmarkEntry> {
+    let Ok(Value::Array(items)) = serde_json::from_str::<Value>(text.trim()) else {
+        return Vec::new();
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let n_prompt = item["n_prompt"].as_u64().unwrap_or(0) as u32;
+            let n_gen = item["n_gen"].as_u64().unwrap_or(0) as u32;
+            let depth = item["n_depth"].as_u64().unwrap_or(0) as u32;
+            let tokens_per_second = item["avg_ts"].as_f64()?;
+            let (kind, depth, tokens) = match (n_prompt, n_gen) {
+                (p, 0) if p > 0 => (BenchmarkKind::Prompt, depth, p),
+                (p, g) if g > 0 => (BenchmarkKind::Generation, depth + p, g),
+                _ => return None,
+            };
+            Some(BenchmarkEntry {
+                kind,
+                depth,
+                tokens,
+                tokens_per_second,
+            })
+        })
+        .collect()
+}
+
+/// 读取 `/completion` 响应中的 `timings`，得到提示词处理与生成两项
+fn parse_timings(response: &Value) -> Option<(BenchmarkEntry, BenchmarkEntry)> {
+    let timings = &response["timings"];
+    let prompt_n = timings["prompt_n"].as_u64()? as u32;
+    let predicted_n = timings["predicted_n"].as_u64()? as u32;
+    Some((
+        BenchmarkEntry {
+            kind: BenchmarkKind::Prompt,
+            depth: 0,
+            tokens: prompt_n,
+            tokens_per_second: timings["prompt_per_second"].as_f64()?,
+        },
+        BenchmarkEntry {
+            kind: BenchmarkKind::Generation,
+            depth: prompt_n,
+            tokens: predicted_n,
+            tokens_per_second: timings["predicted_per_second"].as_f64()?,
+        },
+    ))
+}
+
+/// 约 `tokens` 个 token 的标准提示词
+fn standard_prompt(tokens: u32) -> String {
+    let unit_tokens = crate::utils::tokens::estimate_text_tokens(PROMPT_UNIT).max(1);
+    PROMPT_UNIT.repeat((tokens as usize).div_ceil(unit_tokens))
+}
+
+async fn run_server_bench(
+    app: &AppHandle,
+    exe_path: &Path,
+    model_path: &str,
+    gpu_layers: i32,
+    mut options: LocalServerOptions,
+) -> Result<Vec<BenchmarkEntry>, String> {
+    emit_progress(app, "启动临时服务器", 0.05);
+    let port = std::net::TcpListener::bind(("127.0.0.1", 0))
+        .and_then(|l| l.local_addr())
+        .map(|addr| addr.port())
+        .map_err(|e| format!("无法分配临时端口: {}", e))?;
+    // 上下文需容纳最深一轮的提示词与生成
+    let needed = DEPTHS.iter().max().copied().unwrap_or(0) + GEN_TOKENS + 256;
+    options.ctx_size = Some(options.ctx_size.unwrap_or(0).max(needed));
+    let std_cmd = LlamaCppPlugin.build_command(exe_path, model_path, port, gpu_layers, &options);
+    let mut cmd = tokio::process::Command::from(std_cmd);
+    // 临时服务器同样监听本机端口，用一次性 key 防止其他进程借用
+    let api_key = generate_api_key();
+    cmd.env("LLAMA_API_KEY", &api_key);
+    cmd.stdout(Stdio::null()).stderr(Stdio::null()).kill_on_drop(true);
+    let mut child = cmd.spawn().map_err(|e| format!("无法启动 llama-server: {}", e))?;
+
+    let client = app.state::<HttpClientState>().client();
+    let base = format!("http://127.0.0.1:{}", port);
+    let deadline = Instant::now() + STARTUP_TIMEOUT;
+    loop {
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("临时服务器启动后立即退出，退出码: {}", status));
+        }
+        let healthy = client
+            .get(format!("{}/health", base))
+            .send()
+            .await
+            .is_ok_and(|r| r.status().is_success());
+        if healthy {
+            break;
+        }
+        if Instant::now() >= deadline {
+            return Err("临时服务器未在规定时间内就绪".into());
+        }
+        sleep(Duration::from_millis(500)).await;
+    }
+
+    let mut entries = Vec::new();
+    for (i, depth) in DEPTHS.iter().enumerate() {
+        emit_progress(
+            app,
+            format!("测试上下文深度 {}", depth),
+            0.2 + 0.8 * i as f64 / DEPTHS.len() as f64,
+        );
+        let body = json!({
+            "prompt": standard_prompt(*depth),
+            "n_predict": GEN_TOKENS,
+            "cache_prompt": false,
+            "ignore_eos": true,
+            "temperature": 0,
+        });
+        let response: Value = client
+            .post(format!("{}/completion", base))
+            .bearer_auth(&api_key)
+            .timeout(REQUEST_TIMEOUT)
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| format!("基准测试请求失败: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("基准测试响应解析失败: {}", e))?;
+        let (prompt, generation) =
+            parse_timings(&response).ok_or_else(|| "服务器响应中没有 timings 字段".to_string())?;
+        entries.push(prompt);
+        entries.push(generation);
+    }
+    let _ = child.kill().await;
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_llama_bench_json() {
+        let text = r#"[
+            {"n_prompt": 512, "n_gen": 0, "avg_ts": 1520.5},
+            {"n_prompt": 0, "n_gen": 128, "avg_ts": 42.0},
+            {"n_prompt": 0, "n_gen": 128, "n_depth": 2048, "avg_ts": 35.5}
+        ]"#;
+        let entries = parse_llama_bench(text);
+        assert_eq!(entries.len(), 3);
+        assert_eq!((entries[0].kind, entries[0].tokens), (BenchmarkKind::Prompt, 512));
+        assert_eq!((entries[2].kind, entries[2].depth), (BenchmarkKind::Generation, 2048));
+        assert!(parse_llama_bench("not json").is_empty());
+    }
+
+    #[test]
+    fn parses_completion_timings() {
+        let response = serde_json::json!({
+            "content": "...",
+            "timings": {"prompt_n": 530, "prompt_per_second": 900.0, "predicted_n": 128, "predicted_per_second": 30.0}
+        });
+        let (prompt, generation) = parse_timings(&response).unwrap();
+        assert_eq!(prompt.tokens, 530);
+        assert_eq!((generation.depth, generation.tokens_per_second), (530, 30.0));
+        assert!(parse_timings(&serde_json::json!({})).is_none());
+    }
+}
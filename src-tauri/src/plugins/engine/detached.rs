@@ -0,0 +1,155 @@
+//! 退出时保留本地服务器（`keep_server_on_exit`）
+//!
+//! 开启后窗口销毁 / 应用退出时不 kill 子进程，而是把 pid/端口写入
+//! `$CONFIG/com.loch.aio/local-server.json`；下次启动时若进程仍存活则重新接管，
+//! 前端可通过 `is_local_server_running` / `stop_local_server` 查看或停止它。
+//! 重启或进程退出后 pid 可能被其他程序复用，因此同时记录可执行文件路径与启动时间，
+//! 接管、检查存活与结束进程前都先核对，不一致时删除记录且不动该进程。
+//! 为避免应用退出后管道断开导致服务器收到 SIGPIPE，开启时子进程输出改写到日志文件。
+//! 服务器的鉴权 key 存入系统凭据管理器，记录文件中只保存凭据名称。
+
+use crate::commands::config::keep_server_on_exit;
+use crate::core::paths;
+use crate::core::secure_store::{self, accounts};
+use crate::plugins::engine::process_tree;
+use crate::plugins::engine::options::LoraAdapter;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System, UpdateKind};
+use tauri::AppHandle;
+
+const STATE_FILE: &str = "local-server.json";
+const LOG_FILE: &str = "local-server.log";
+
+/// 进程身份：可执行文件路径与启动时间（Unix 秒）
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessIdentity {
+    pub exe_path: String,
+    pub start_time: u64,
+}
+
+/// 被保留下来、脱离应用生命周期运行的服务器
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DetachedServer {
+    pub pid: u32,
+    /// 旧版本记录没有身份信息，无法确认 pid 未被复用，按失效处理
+    #[serde(default)]
+    pub identity: Option<ProcessIdentity>,
+    pub port: u16,
+    pub engine_type: String,
+    #[serde(default)]
+    pub supports_images: bool,
+    /// 鉴权 key 在安全存储中的凭据名称；接管后据此取回同一个 key 继续访问
+    #[serde(default)]
+    pub api_key_ref: Option<String>,
+    #[serde(default)]
+    pub ctx_size: Option<u32>,
+    #[serde(default)]
+    pub cache_type_k: Option<String>,
+    #[serde(default)]
+    pub cache_type_v: Option<String>,
+    #[serde(default)]
+    pub lora_adapters: Vec<LoraAdapter>,
+    #[serde(default)]
+    pub draft_model_path: Option<String>,
+}
+
+fn appdata_dir() -> Option<PathBuf> {
+    paths::app_config_dir().ok()
+}
+
+/// 开启保留时把子进程 stdout/stderr 改写到日志文件，返回是否已改写
+/// （改写后调用方拿不到 stderr 管道，需跳过日志进度解析）
+pub fn redirect_output_if_detachable(cmd: &mut Command) -> bool {
+    if !keep_server_on_exit() {
+        return false;
+    }
+    let Some(log_path) = appdata_dir().map(|d| d.join(LOG_FILE)) else {
+        return false;
+    };
+    let Ok(log) = fs::File::create(&log_path) else {
+        return false;
+    };
+    let Ok(log_err) = log.try_clone() else {
+        return false;
+    };
+    cmd.stdout(Stdio::from(log)).stderr(Stdio::from(log_err));
+    true
+}
+
+/// 记录被保留的服务器；`api_key` 写入安全存储，记录中只保存凭据名称
+pub fn save(app: &AppHandle, server: &DetachedServer, api_key: Option<&str>) -> Result<(), String> {
+    let path = appdata_dir()
+        .ok_or_else(|| "无法获取系统配置目录".to_string())?
+        .join(STATE_FILE);
+    let mut server = server.clone();
+    server.api_key_ref = match api_key {
+        Some(key) => {
+            secure_store::set(app, accounts::LOCAL_SERVER_API_KEY, key)
+                .map_err(|e| e.to_string())?;
+            Some(accounts::LOCAL_SERVER_API_KEY.to_string())
+        }
+        None => None,
+    };
+    let json = serde_json::to_string_pretty(&server).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+/// 取回被保留服务器的鉴权 key
+pub fn load_api_key(app: &AppHandle, server: &DetachedServer) -> Option<String> {
+    let account = server.api_key_ref.as_deref()?;
+    secure_store::get(app, account).ok().flatten()
+}
+
+/// 读取上次保留的服务器记录
+pub fn load() -> Option<DetachedServer> {
+    let path = appdata_dir()?.join(STATE_FILE);
+    serde_json::from_str(&fs::read_to_string(path).ok()?).ok()
+}
+
+/// 删除记录
+pub fn clear() {
+    if let Some(dir) = appdata_dir() {
+        let _ = fs::remove_file(dir.join(STATE_FILE));
+    }
+}
+
+/// 读取 pid 当前对应进程的身份；进程不存在或无法读取时返回 None
+pub fn process_identity(pid: u32) -> Option<ProcessIdentity> {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes_specifics(
+        ProcessesToUpdate::Some(&[pid]),
+        true,
+        ProcessRefreshKind::nothing().with_exe(UpdateKind::Always),
+    );
+    let process = system.process(pid)?;
+    Some(ProcessIdentity {
+        exe_path: process.exe()?.to_string_lossy().to_string(),
+        start_time: process.start_time(),
+    })
+}
+
+/// pid 仍在运行，且仍是记录中的那个进程
+pub fn is_same_process(pid: u32, identity: &ProcessIdentity) -> bool {
+    process_identity(pid).as_ref() == Some(identity)
+}
+
+/// 结束被保留的服务器进程（含其子进程），返回是否执行了结束。
+/// pid 已对应其他进程时不做任何操作
+pub fn kill_process(pid: u32, identity: &ProcessIdentity) -> bool {
+    if !is_same_process(pid, identity) {
+        tracing::warn!(
+            "pid {} 已不是记录中的本地服务器（{}），不结束该进程",
+            pid,
+            identity.exe_path
+        );
+        return false;
+    }
+    process_tree::kill_tree(pid);
+    true
+}
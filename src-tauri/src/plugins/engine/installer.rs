@@ -3,15 +3,19 @@
 ///
 /// 策略：
 /// 1. 查询 GitHub API 获取最新 release 信息
-/// 2. 根据当前平台 + GPU 情况自动选择对应的 asset
-/// 3. 流式下载（支持进度回调）
-/// 4. 解压到 app data 目录
+/// 2. 根据当前平台 + GPU 情况自动选择对应的 asset（也可指定 cpu / cuda / vulkan 变体）
+///    CUDA 构建额外下载同版本的 `cudart-*` 运行库包，与主包解压到同一目录
+/// 3. 流式下载（支持进度回调与断点续传，下载地址可通过 `llamaDownloadUrl` 模板替换为镜像）
+/// 4. 校验 SHA-256（GitHub asset 提供 digest 时）后解压到 app data 目录
 /// 5. 记录版本信息，支持版本对比和更新
 
-use std::io::Write;
-use std::path::PathBuf;
+use sha2::{Digest, Sha256};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use tauri::{AppHandle, Manager};
 
+use crate::core::state::HttpClientState;
+
 /// GitHub release 信息
 #[derive(serde::Serialize, serde::Deserialize, Clone, Debug)]
 pub struct ReleaseInfo {
@@ -26,8 +30,33 @@ pub struct AssetInfo {
     pub name: String,
     pub size: u64,
     pub browser_download_url: String,
+    /// GitHub 提供的摘要（形如 `sha256:<hex>`），旧 release 可能没有
+    #[serde(default)]
+    pub digest: Option<String>,
+}
+
+/// llama-server 构建变体
+#[derive(serde::Serialize, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendVariant {
+    Cpu,
+    Cuda,
+    Vulkan,
 }
 
+impl BackendVariant {
+    fn as_str(self) -> &'static str {
+        match self {
+            BackendVariant::Cpu => "cpu",
+            BackendVariant::Cuda => "cuda",
+            BackendVariant::Vulkan => "vulkan",
+        }
+    }
+}
+
+/// asset 名中表示 GPU 加速后端的标记；都不含时视为 CPU 构建
+const ACCELERATOR_MARKERS: &[&str] = &["cuda", "vulkan", "hip", "sycl", "opencl", "kompute", "radeon"];
+
 /// 引擎安装状态（返回给前端）
 #[derive(serde::Serialize, Clone, Debug)]
 pub struct EngineStatus {
@@ -47,6 +76,8 @@ pub struct EngineUpdateInfo {
     pub current_version: Option<String>,
     pub latest_version: String,
     pub has_update: bool,
+    /// 已安装的构建变体（自动选择安装的旧版本为 None）
+    pub variant: Option<BackendVariant>,
 }
 
 /// 本地版本记录（存储在 app data 中）
@@ -54,6 +85,8 @@ pub struct EngineUpdateInfo {
 struct VersionInfo {
     tag: String,
     installed_at: String,
+    #[serde(default)]
+    variant: Option<BackendVariant>,
 }
 
 const LLAMA_CPP_OWNER: &str = "ggml-org";
@@ -91,17 +124,13 @@ impl EngineInstaller {
     }
 
     /// 查询 GitHub 最新 release
-    pub async fn fetch_latest_release() -> Result<ReleaseInfo, String> {
+    pub async fn fetch_latest_release(app: &AppHandle) -> Result<ReleaseInfo, String> {
         let url = format!(
             "https://api.github.com/repos/{}/{}/releases/latest",
             LLAMA_CPP_OWNER, LLAMA_CPP_REPO
         );
 
-        let client = reqwest::Client::builder()
-            .user_agent("AIO-App/0.3.1")
-            .build()
-            .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
-
+        let client = app.state::<HttpClientState>().client();
         let resp = client
             .get(&url)
             .send()
@@ -140,6 +169,7 @@ impl EngineInstaller {
                         .as_str()
                         .unwrap_or("")
                         .to_string(),
+                    digest: a["digest"].as_str().map(String::from),
                 });
             }
         }
@@ -161,26 +191,8 @@ impl EngineInstaller {
             let has_nvidia = Self::check_nvidia_gpu();
 
             if has_nvidia {
-                // 优先 CUDA 12.4（最稳定兼容），回退到 13.3
-                assets
-                    .iter()
-                    .find(|a| {
-                        a.name.contains("cudart")
-                            && a.name.contains("win")
-                            && a.name.contains("cuda-12.4")
-                    })
-                    .or_else(|| {
-                        assets.iter().find(|a| {
-                            a.name.contains("cudart")
-                                && a.name.contains("win")
-                                && a.name.contains("cuda-13.3")
-                        })
-                    })
-                    .or_else(|| {
-                        assets
-                            .iter()
-                            .find(|a| a.name.contains("cudart") && a.name.contains("win"))
-                    })
+                // 选 CUDA 构建（版本号最小、兼容性最好的），其 cudart 运行库包在安装时一并下载
+                Self::select_asset_for(release, BackendVariant::Cuda)
             } else {
                 // 无 NVIDIA GPU → 选 AVX2 版本（纯 CPU）
                 assets
@@ -197,20 +209,19 @@ impl EngineInstaller {
         #[cfg(target_os = "linux")]
         {
             let has_nvidia = Self::check_nvidia_gpu();
-            if has_nvidia {
-                assets
-                    .iter()
-                    .find(|a| a.name.contains("cudart") && a.name.contains("ubuntu"))
-            } else {
-                assets
-                    .iter()
-                    .find(|a| a.name.contains("linux") && a.name.contains("avx2"))
-                    .or_else(|| {
-                        assets
-                            .iter()
-                            .find(|a| a.name.contains("linux") && a.name.ends_with(".zip"))
-                    })
-            }
+            has_nvidia
+                .then(|| Self::select_asset_for(release, BackendVariant::Cuda))
+                .flatten()
+                .or_else(|| {
+                    assets
+                        .iter()
+                        .find(|a| a.name.contains("linux") && a.name.contains("avx2"))
+                })
+                .or_else(|| {
+                    assets
+                        .iter()
+                        .find(|a| a.name.contains("linux") && a.name.ends_with(".zip"))
+                })
         }
 
         #[cfg(target_os = "macos")]
@@ -226,6 +237,17 @@ impl EngineInstaller {
         }
     }
 
+    /// 按指定变体选择当前平台 / 架构的 asset
+    pub fn select_asset_for(release: &ReleaseInfo, variant: BackendVariant) -> Option<&AssetInfo> {
+        pick_asset(&release.assets, std::env::consts::OS, std::env::consts::ARCH, variant)
+    }
+
+    /// 实际下载地址：配置了 `llamaDownloadUrl` 模板时替换 `{tag}` / `{asset}`，否则用 GitHub 地址
+    fn download_url(tag: &str, asset: &AssetInfo) -> String {
+        let template = crate::commands::config::llama_download_url();
+        render_download_url(&template, tag, asset)
+    }
+
     /// 检测 NVIDIA GPU 是否可用
     fn check_nvidia_gpu() -> bool {
         #[cfg(target_os = "windows")]
@@ -250,8 +272,7 @@ impl EngineInstaller {
         }
     }
 
-    /// 获取已安装的版本
-    pub fn get_installed_version(app: &AppHandle) -> Option<String> {
+    fn read_version_info(app: &AppHandle) -> Option<VersionInfo> {
         let version_file = Self::get_engine_dir(app).join("version.json");
         if !version_file.exists() {
             return None;
@@ -259,7 +280,16 @@ impl EngineInstaller {
         std::fs::read_to_string(version_file)
             .ok()
             .and_then(|s| serde_json::from_str::<VersionInfo>(&s).ok())
-            .map(|v| v.tag)
+    }
+
+    /// 获取已安装的版本
+    pub fn get_installed_version(app: &AppHandle) -> Option<String> {
+        Self::read_version_info(app).map(|v| v.tag)
+    }
+
+    /// 获取已安装的构建变体
+    pub fn get_installed_variant(app: &AppHandle) -> Option<BackendVariant> {
+        Self::read_version_info(app).and_then(|v| v.variant)
     }
 
     /// 检查引擎是否已安装（exe 和 version.json 都存在）
@@ -267,207 +297,105 @@ impl EngineInstaller {
         Self::get_exe_path(app).exists() && Self::get_installed_version(app).is_some()
     }
 
-    /// 下载并安装引擎
+    /// 下载并安装引擎；`variant` 为 None 时按平台 + GPU 自动选择
     /// 通过 `on_progress` 回调报告进度 (0.0 ~ 1.0)
     pub async fn install(
         app: &AppHandle,
+        variant: Option<BackendVariant>,
         on_progress: impl Fn(f64) + Send + 'static,
     ) -> Result<String, String> {
         // 1. 查询最新 release
         on_progress(0.01);
-        let release = Self::fetch_latest_release().await?;
+        let release = Self::fetch_latest_release(app).await?;
         let tag = release.tag_name.clone();
         on_progress(0.05);
 
         // 2. 选择 asset
-        let asset = Self::select_asset(&release).ok_or_else(|| {
+        let asset = match variant {
+            Some(v) => Self::select_asset_for(&release, v),
+            None => Self::select_asset(&release),
+        }
+        .ok_or_else(|| {
             format!(
-                "未找到当前平台 ({}) 对应的下载文件",
-                std::env::consts::OS
+                "未找到当前平台 ({}{}) 对应的下载文件",
+                std::env::consts::OS,
+                variant.map(|v| format!(", {}", v.as_str())).unwrap_or_default()
             )
         })?;
         on_progress(0.1);
 
+        // CUDA 构建的 CUDA 运行库在单独的 cudart 包里，需一并下载解压
+        let mut archives = vec![asset];
+        if asset.name.to_lowercase().contains("cuda") {
+            match cuda_runtime_asset(&release.assets, asset) {
+                Some(runtime) => archives.push(runtime),
+                None => tracing::warn!("未找到 {} 对应的 cudart 运行库包", asset.name),
+            }
+        }
+
         // 3. 创建目标目录
         let engine_dir = Self::get_engine_dir(app);
         std::fs::create_dir_all(&engine_dir)
             .map_err(|e| format!("创建引擎目录失败: {}", e))?;
 
-        // 4. 下载到临时文件
+        // 4. 下载到临时文件（按 tag 固定目录，中断后再次安装从 .part 断点续传）
         let temp_dir = std::env::temp_dir().join(format!("aio-llama-{}", tag));
-        if temp_dir.exists() {
-            let _ = std::fs::remove_dir_all(&temp_dir);
-        }
         std::fs::create_dir_all(&temp_dir)
             .map_err(|e| format!("创建临时目录失败: {}", e))?;
 
-        let zip_path = temp_dir.join(&asset.name);
+        let client = app.state::<HttpClientState>().client();
         let fallback_progress = on_progress;
-
-        // 流式下载
-        let client = reqwest::Client::builder()
-            .user_agent("AIO-App/0.3.1")
-            .build()
-            .map_err(|e| format!("创建 HTTP 客户端失败: {}", e))?;
-
-        let resp = client
-            .get(&asset.browser_download_url)
-            .send()
-            .await
-            .map_err(|e| format!("下载请求失败: {} (请检查网络连接)", e))?;
-
-        if !resp.status().is_success() {
-            return Err(format!("下载失败: HTTP {}", resp.status()));
-        }
-
-        let total_size = resp.content_length().unwrap_or(0);
-        let mut downloaded: u64 = 0;
-
-        let mut file = std::fs::File::create(&zip_path)
-            .map_err(|e| format!("创建临时文件失败: {}", e))?;
-
-        let mut stream = resp.bytes_stream();
-        use futures_util::StreamExt;
-        while let Some(chunk) = stream.next().await {
-            let chunk = chunk.map_err(|e| format!("下载数据流中断: {}", e))?;
-            file.write_all(&chunk)
-                .map_err(|e| format!("写入临时文件失败: {}", e))?;
-            downloaded += chunk.len() as u64;
-
-            // 报告下载进度 (10% ~ 70%)
-            if total_size > 0 {
-                let p = 0.1 + (downloaded.min(total_size) as f64 / total_size as f64) * 0.6;
-                fallback_progress(p.min(0.7));
+        // 下载进度 (10% ~ 70%) 按各包大小分配
+        let total_size = archives.iter().map(|a| a.size).sum::<u64>().max(1) as f64;
+        let mut done_size = 0u64;
+        let mut zip_paths = Vec::new();
+        for archive in &archives {
+            let zip_path = temp_dir.join(&archive.name);
+            let part_path = temp_dir.join(format!("{}.part", archive.name));
+            if !zip_path.exists() {
+                let url = Self::download_url(&tag, archive);
+                let (base, size) = (done_size as f64, archive.size as f64);
+                Self::download_resumable(&client, &url, &part_path, archive.size, |p| {
+                    fallback_progress((0.1 + (base + p * size) / total_size * 0.6).min(0.7));
+                })
+                .await?;
+                std::fs::rename(&part_path, &zip_path)
+                    .map_err(|e| format!("保存下载文件失败: {}", e))?;
             }
+            done_size += archive.size;
+
+            // 校验 SHA-256：不匹配时删除文件，下次重新下载
+            match archive.digest.as_deref().and_then(|d| d.strip_prefix("sha256:")) {
+                Some(expected) => {
+                    let actual = sha256_file(&zip_path)?;
+                    if !actual.eq_ignore_ascii_case(expected) {
+                        let _ = std::fs::remove_file(&zip_path);
+                        return Err(format!(
+                            "下载文件校验失败（SHA-256 不匹配），已删除，请重试: {}",
+                            archive.name
+                        ));
+                    }
+                }
+                None => tracing::warn!("release asset {} 未提供 SHA-256 摘要，跳过校验", archive.name),
+            }
+            zip_paths.push(zip_path);
         }
-        drop(file);
         fallback_progress(0.72);
 
-        // 5. 解压到引擎目录
-        let zip_file =
-            std::fs::File::open(&zip_path).map_err(|e| format!("打开下载的 ZIP 失败: {}", e))?;
-        let mut archive = zip::ZipArchive::new(zip_file)
-            .map_err(|e| format!("解压 ZIP 失败 (文件可能损坏): {}", e))?;
-
-        // 计算需要提取的文件总数（用于解压进度）
-        let total_files = archive.len();
-        // 先清空旧文件（如有）
+        // 5. 解压到引擎目录：先清空旧文件（如有）
         if engine_dir.exists() {
             let _ = std::fs::remove_dir_all(&engine_dir);
         }
         std::fs::create_dir_all(&engine_dir)
             .map_err(|e| format!("创建引擎目录失败: {}", e))?;
 
-        for i in 0..total_files {
-            let mut entry = archive.by_index(i).map_err(|e| {
-                format!("读取 ZIP 条目失败: {}", e)
+        // 解压进度 (72% ~ 90%)
+        let share = 0.18 / zip_paths.len() as f64;
+        for (i, zip_path) in zip_paths.iter().enumerate() {
+            let base = 0.72 + i as f64 * share;
+            extract_zip(zip_path, &engine_dir, |p| {
+                fallback_progress((base + p * share).min(0.9))
             })?;
-
-            // 安全路径检查：使用 zip crate 的 enclosed_name() 自动拒绝绝对路径和 .. 段
-            let safe_name = match entry.enclosed_name() {
-                Some(p) => p.to_path_buf(),
-                None => {
-                    return Err(format!(
-                        "ZIP 条目包含非法路径 (绝对路径或路径逃逸): {:?}",
-                        entry.name()
-                    ));
-                }
-            };
-
-            // 去除顶层目录前缀（与原逻辑保持一致）
-            let relative_name = match safe_name.iter().next() {
-                Some(_first) => safe_name.iter().skip(1).collect::<std::path::PathBuf>(),
-                None => continue,
-            };
-
-            if relative_name.as_os_str().is_empty() {
-                continue; // 目录条目
-            }
-
-            // 二次校验：解析后路径必须落在 engine_dir 内
-            let dest_path = engine_dir.join(&relative_name);
-            let canonical_engine = std::fs::canonicalize(&engine_dir)
-                .unwrap_or_else(|_| engine_dir.clone());
-            if let Ok(canonical_dest) = std::fs::canonicalize(&dest_path) {
-                if !canonical_dest.starts_with(&canonical_engine) {
-                    return Err(format!(
-                        "ZIP 条目路径逃逸被拦截: {:?}",
-                        relative_name
-                    ));
-                }
-            } else {
-                // 目标文件尚不存在，通过 components 检查防 .. 段
-                let mut normalized = std::path::PathBuf::new();
-                for comp in relative_name.components() {
-                    match comp {
-                        std::path::Component::Normal(c) => normalized.push(c),
-                        std::path::Component::ParentDir => {
-                            return Err(format!(
-                                "ZIP 条目含 ParentDir 段: {:?}",
-                                relative_name
-                            ));
-                        }
-                        _ => {
-                            return Err(format!(
-                                "ZIP 条目含非 Normal 段: {:?}",
-                                relative_name
-                            ));
-                        }
-                    }
-                }
-                let dest_path = engine_dir.join(&normalized);
-                let dest_str = dest_path.to_string_lossy().to_string();
-
-                if let Some(parent) = dest_path.parent() {
-                    std::fs::create_dir_all(parent)
-                        .map_err(|e| format!("创建目录 {} 失败: {}", parent.display(), e))?;
-                }
-
-                let mut outfile = std::fs::File::create(&dest_path)
-                    .map_err(|e| format!("创建文件 {} 失败: {}", dest_str, e))?;
-                std::io::copy(&mut entry, &mut outfile)
-                    .map_err(|e| format!("写入文件 {} 失败: {}", dest_str, e))?;
-
-                #[cfg(not(target_os = "windows"))]
-                {
-                    if dest_path.extension().is_none() || dest_path.extension().unwrap() == "" {
-                        let _ = std::fs::set_permissions(
-                            &dest_path,
-                            std::fs::Permissions::from_mode(0o755),
-                        );
-                    }
-                }
-
-                let p = 0.72 + (i as f64 / total_files as f64) * 0.18;
-                fallback_progress(p.min(0.9));
-                continue;
-            }
-
-            let dest_str = dest_path.to_string_lossy().to_string();
-
-            if let Some(parent) = dest_path.parent() {
-                std::fs::create_dir_all(parent)
-                    .map_err(|e| format!("创建目录 {} 失败: {}", parent.display(), e))?;
-            }
-
-            let mut outfile = std::fs::File::create(&dest_path)
-                .map_err(|e| format!("创建文件 {} 失败: {}", dest_str, e))?;
-            std::io::copy(&mut entry, &mut outfile)
-                .map_err(|e| format!("写入文件 {} 失败: {}", dest_str, e))?;
-
-            #[cfg(not(target_os = "windows"))]
-            {
-                if dest_path.extension().is_none() || dest_path.extension().unwrap() == "" {
-                    let _ = std::fs::set_permissions(
-                        &dest_path,
-                        std::fs::Permissions::from_mode(0o755),
-                    );
-                }
-            }
-
-            // 解压进度 (72% ~ 90%)
-            let p = 0.72 + (i as f64 / total_files as f64) * 0.18;
-            fallback_progress(p.min(0.9));
         }
 
         fallback_progress(0.92);
@@ -480,6 +408,7 @@ impl EngineInstaller {
         let version_info = VersionInfo {
             tag: tag.clone(),
             installed_at: now.to_string(),
+            variant,
         };
         let version_json =
             serde_json::to_string_pretty(&version_info).map_err(|e| format!("序列化版本信息失败: {}", e))?;
@@ -497,7 +426,7 @@ impl EngineInstaller {
     /// 检查是否有新版本
     pub async fn check_update(app: &AppHandle) -> Result<EngineUpdateInfo, String> {
         let current = Self::get_installed_version(app);
-        let release = Self::fetch_latest_release().await?;
+        let release = Self::fetch_latest_release(app).await?;
         let latest = release.tag_name;
 
         let has_update = match &current {
@@ -513,9 +442,74 @@ impl EngineInstaller {
             current_version: current,
             latest_version: latest,
             has_update,
+            variant: Self::get_installed_variant(app),
         })
     }
 
+    /// 断点续传下载到 `part_path`：已有部分时带 Range 请求，服务器不支持续传（返回 200）时从头下载
+    /// `on_progress` 报告下载比例 (0.0 ~ 1.0)
+    async fn download_resumable(
+        client: &reqwest::Client,
+        url: &str,
+        part_path: &Path,
+        expected_size: u64,
+        on_progress: impl Fn(f64),
+    ) -> Result<(), String> {
+        let existing = std::fs::metadata(part_path).map(|m| m.len()).unwrap_or(0);
+        let mut req = client.get(url);
+        if existing > 0 {
+            req = req.header(reqwest::header::RANGE, format!("bytes={}-", existing));
+        }
+        let resp = req
+            .send()
+            .await
+            .map_err(|e| format!("下载请求失败: {} (请检查网络连接)", e))?;
+
+        let status = resp.status();
+        let resumed = existing > 0 && status == reqwest::StatusCode::PARTIAL_CONTENT;
+        if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE && existing == expected_size {
+            // 上次已下载完整，只是未来得及改名
+            return Ok(());
+        }
+        if !status.is_success() {
+            return Err(format!("下载失败: HTTP {}", status));
+        }
+
+        let mut downloaded = if resumed { existing } else { 0 };
+        let total_size = resp
+            .content_length()
+            .map(|len| len + downloaded)
+            .filter(|&len| len > 0)
+            .unwrap_or(expected_size);
+
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(resumed)
+            .truncate(!resumed)
+            .open(part_path)
+            .map_err(|e| format!("创建临时文件失败: {}", e))?;
+
+        let mut stream = resp.bytes_stream();
+        use futures_util::StreamExt;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(|e| format!("下载数据流中断: {}（再次安装可继续下载）", e))?;
+            file.write_all(&chunk)
+                .map_err(|e| format!("写入临时文件失败: {}", e))?;
+            downloaded += chunk.len() as u64;
+            if total_size > 0 {
+                on_progress(downloaded.min(total_size) as f64 / total_size as f64);
+            }
+        }
+        if expected_size > 0 && downloaded != expected_size {
+            return Err(format!(
+                "下载不完整: {} / {} 字节（再次安装可继续下载）",
+                downloaded, expected_size
+            ));
+        }
+        Ok(())
+    }
+
     /// 比较两个版本号 (bXXXX)
     fn compare_versions(current: &str, latest: &str) -> bool {
         let cur_num = current
@@ -530,6 +524,205 @@ impl EngineInstaller {
     }
 }
 
+/// 计算文件 SHA-256（十六进制小写）
+fn sha256_file(path: &Path) -> Result<String, String> {
+    let mut file = std::fs::File::open(path).map_err(|e| format!("读取下载文件失败: {}", e))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf).map_err(|e| format!("读取下载文件失败: {}", e))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// 渲染下载地址模板；模板为空时使用 asset 自带的 GitHub 地址
+fn render_download_url(template: &str, tag: &str, asset: &AssetInfo) -> String {
+    let template = template.trim();
+    if template.is_empty() {
+        return asset.browser_download_url.clone();
+    }
+    template.replace("{tag}", tag).replace("{asset}", &asset.name)
+}
+
+/// 按平台 / 架构 / 变体挑选 asset（llama.cpp 命名如 `llama-b6000-bin-win-cuda-12.4-x64.zip`）
+fn pick_asset<'a>(
+    assets: &'a [AssetInfo],
+    os: &str,
+    arch: &str,
+    variant: BackendVariant,
+) -> Option<&'a AssetInfo> {
+    let os_marker = match os {
+        "windows" => "-win-",
+        "linux" => "-ubuntu-",
+        "macos" => "-macos-",
+        _ => return None,
+    };
+    let arch_marker = if arch == "aarch64" { "arm64" } else { "x64" };
+    let candidates = assets.iter().filter(|a| {
+        let name = a.name.to_lowercase();
+        name.starts_with("llama-")
+            && name.ends_with(".zip")
+            && name.contains(os_marker)
+            && name.contains(arch_marker)
+    });
+    match variant {
+        BackendVariant::Cpu => candidates
+            .filter(|a| {
+                let name = a.name.to_lowercase();
+                !ACCELERATOR_MARKERS.iter().any(|m| name.contains(m))
+            })
+            // Windows 同时有 cpu / avx2 等多个 CPU 包，优先通用的 cpu 包
+            .min_by_key(|a| !a.name.contains("-cpu-")),
+        BackendVariant::Cuda | BackendVariant::Vulkan => {
+            let marker = variant.as_str();
+            // CUDA 可能有多个版本，取名称排序最小的（通常为兼容性更好的旧版本 CUDA）
+            candidates
+                .filter(|a| a.name.to_lowercase().contains(marker))
+                .min_by(|a, b| a.name.cmp(&b.name))
+        }
+    }
+}
+
+/// CUDA 构建对应的 cudart 运行库包（`llama-b6000-bin-win-cuda-12.4-x64.zip` → `cudart-llama-bin-win-cuda-12.4-x64.zip`）
+fn cuda_runtime_asset<'a>(assets: &'a [AssetInfo], build: &AssetInfo) -> Option<&'a AssetInfo> {
+    let (_, platform) = build.name.split_once("-bin-")?;
+    let expected = format!("cudart-llama-bin-{}", platform);
+    assets.iter().find(|a| a.name.eq_ignore_ascii_case(&expected))
+}
+
+/// 所有条目都位于同一顶层目录下时返回该目录名（解压时去掉这一层）
+fn common_top_dir<'a>(mut names: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    let first = names.next()?.split_once('/')?.0;
+    names
+        .all(|n| n.split_once('/').is_some_and(|(top, _)| top == first))
+        .then_some(first)
+}
+
+/// 解压 ZIP 到 `dest`，拒绝绝对路径与 `..` 逃逸；`on_progress` 报告解压比例 (0.0 ~ 1.0)
+fn extract_zip(zip_path: &Path, dest: &Path, on_progress: impl Fn(f64)) -> Result<(), String> {
+    let zip_file =
+        std::fs::File::open(zip_path).map_err(|e| format!("打开下载的 ZIP 失败: {}", e))?;
+    let mut archive = zip::ZipArchive::new(zip_file)
+        .map_err(|e| format!("解压 ZIP 失败 (文件可能损坏): {}", e))?;
+    let strip = common_top_dir(archive.file_names()).map(PathBuf::from);
+    let canonical_dest = std::fs::canonicalize(dest).unwrap_or_else(|_| dest.to_path_buf());
+
+    let total_files = archive.len();
+    for i in 0..total_files {
+        let mut entry = archive
+            .by_index(i)
+            .map_err(|e| format!("读取 ZIP 条目失败: {}", e))?;
+        if entry.is_dir() {
+            continue;
+        }
+        // enclosed_name() 已拒绝绝对路径和 .. 段
+        let safe_name = entry.enclosed_name().ok_or_else(|| {
+            format!("ZIP 条目包含非法路径 (绝对路径或路径逃逸): {:?}", entry.name())
+        })?;
+        let relative_name = match &strip {
+            Some(top) => safe_name.strip_prefix(top).unwrap_or(&safe_name).to_path_buf(),
+            None => safe_name,
+        };
+        if relative_name
+            .components()
+            .any(|c| !matches!(c, std::path::Component::Normal(_)))
+        {
+            return Err(format!("ZIP 条目含非法路径段: {:?}", relative_name));
+        }
+
+        let dest_path = dest.join(&relative_name);
+        // 二次校验：已存在的目标（如符号链接）解析后必须落在 dest 内
+        if let Ok(canonical) = std::fs::canonicalize(&dest_path) {
+            if !canonical.starts_with(&canonical_dest) {
+                return Err(format!("ZIP 条目路径逃逸被拦截: {:?}", relative_name));
+            }
+        }
+        let dest_str = dest_path.to_string_lossy().to_string();
+        if let Some(parent) = dest_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("创建目录 {} 失败: {}", parent.display(), e))?;
+        }
+        let mut outfile = std::fs::File::create(&dest_path)
+            .map_err(|e| format!("创建文件 {} 失败: {}", dest_str, e))?;
+        std::io::copy(&mut entry, &mut outfile)
+            .map_err(|e| format!("写入文件 {} 失败: {}", dest_str, e))?;
+
+        #[cfg(not(target_os = "windows"))]
+        {
+            if dest_path.extension().unwrap_or_default().is_empty() {
+                let _ =
+                    std::fs::set_permissions(&dest_path, std::fs::Permissions::from_mode(0o755));
+            }
+        }
+
+        on_progress((i + 1) as f64 / total_files as f64);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(name: &str) -> AssetInfo {
+        AssetInfo {
+            name: name.into(),
+            size: 1,
+            browser_download_url: format!("https://github.com/dl/{}", name),
+            digest: None,
+        }
+    }
+
+    #[test]
+    fn picks_asset_by_variant() {
+        let assets = vec![
+            asset("cudart-llama-bin-win-cuda-12.4-x64.zip"),
+            asset("llama-b6000-bin-win-cuda-12.4-x64.zip"),
+            asset("llama-b6000-bin-win-cuda-13.1-x64.zip"),
+            asset("llama-b6000-bin-win-vulkan-x64.zip"),
+            asset("llama-b6000-bin-win-avx2-x64.zip"),
+            asset("llama-b6000-bin-win-cpu-x64.zip"),
+            asset("llama-b6000-bin-ubuntu-x64.zip"),
+            asset("llama-b6000-bin-ubuntu-vulkan-x64.zip"),
+        ];
+        let name = |os, v| pick_asset(&assets, os, "x86_64", v).map(|a| a.name.as_str());
+        assert_eq!(name("windows", BackendVariant::Cuda), Some("llama-b6000-bin-win-cuda-12.4-x64.zip"));
+        assert_eq!(name("windows", BackendVariant::Cpu), Some("llama-b6000-bin-win-cpu-x64.zip"));
+        assert_eq!(name("linux", BackendVariant::Vulkan), Some("llama-b6000-bin-ubuntu-vulkan-x64.zip"));
+        assert_eq!(name("linux", BackendVariant::Cpu), Some("llama-b6000-bin-ubuntu-x64.zip"));
+        assert_eq!(name("linux", BackendVariant::Cuda), None);
+
+        let cuda = pick_asset(&assets, "windows", "x86_64", BackendVariant::Cuda).unwrap();
+        assert_eq!(
+            cuda_runtime_asset(&assets, cuda).map(|a| a.name.as_str()),
+            Some("cudart-llama-bin-win-cuda-12.4-x64.zip")
+        );
+        assert!(cuda_runtime_asset(&assets, &assets[2]).is_none());
+    }
+
+    #[test]
+    fn strips_only_a_shared_top_dir() {
+        let names = ["build/bin/llama-server", "build/ggml.dll", "build/"];
+        assert_eq!(common_top_dir(names.into_iter()), Some("build"));
+        assert_eq!(common_top_dir(["llama-server", "ggml.dll"].into_iter()), None);
+        assert_eq!(common_top_dir(["a/x", "b/y"].into_iter()), None);
+    }
+
+    #[test]
+    fn renders_mirror_template() {
+        let a = asset("llama-b6000-bin-win-cpu-x64.zip");
+        assert_eq!(render_download_url("  ", "b6000", &a), a.browser_download_url);
+        assert_eq!(
+            render_download_url("https://mirror.example/{tag}/{asset}", "b6000", &a),
+            "https://mirror.example/b6000/llama-b6000-bin-win-cpu-x64.zip"
+        );
+    }
+}
+
 // 为非 Windows 平台添加 PermissionsExt
 #[cfg(not(target_os = "windows"))]
 use std::os::unix::fs::PermissionsExt;
@@ -0,0 +1,43 @@
+//! 上次成功启动本地服务器的参数（`autoStartLocalServer`）
+//!
+//! `start_local_server` 成功后把模型路径、端口、GPU 层数、引擎与选项写入
+//! `$CONFIG/com.loch.aio/last-local-launch.json`；开启自动启动时，应用启动后按该记录在后台重新拉起服务器。
+
+use crate::core::paths;
+use crate::plugins::engine::LocalServerOptions;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+
+const STATE_FILE: &str = "last-local-launch.json";
+
+/// 自动启动失败事件名（载荷为错误信息字符串）
+pub const AUTO_START_ERROR_EVENT: &str = "local-server-auto-start-error";
+
+/// 一次成功启动的参数
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LastLaunch {
+    pub model_path: String,
+    pub port: u16,
+    pub gpu_layers: i32,
+    pub engine_type: String,
+    #[serde(default)]
+    pub options: LocalServerOptions,
+}
+
+fn state_path() -> Option<PathBuf> {
+    paths::app_config_dir().ok().map(|dir| dir.join(STATE_FILE))
+}
+
+/// 记录本次成功启动的参数
+pub fn save(launch: &LastLaunch) -> Result<(), String> {
+    let path = state_path().ok_or_else(|| "无法获取系统配置目录".to_string())?;
+    let json = serde_json::to_string_pretty(launch).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+/// 读取上次成功启动的参数
+pub fn load() -> Option<LastLaunch> {
+    serde_json::from_str(&fs::read_to_string(state_path()?).ok()?).ok()
+}
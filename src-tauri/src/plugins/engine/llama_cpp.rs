@@ -4,20 +4,128 @@
 /// 1. 优先使用 app data 下通过自动安装的引擎（EngineInstaller）
 /// 2. 回退到 resources/engines/llama-cpp/ 下的 bundled 版本（旧版打包兼容）
 
-use crate::core::state::LocalEngineState;
+use crate::core::state::{HttpClientState, LocalEngineState};
+use crate::plugins::engine::backend_version::{self, CompatWarning};
 use crate::plugins::engine::installer::EngineInstaller;
-use crate::plugins::engine::LocalEnginePlugin;
-use std::io::{BufRead, BufReader};
+use crate::plugins::engine::server_log::{self, LogChunk, LogSource, ServerLogBuffer};
+use crate::plugins::engine::options::DEFAULT_CTX_SIZE;
+use crate::plugins::engine::{detached, process_tree, LocalEnginePlugin, LocalServerOptions};
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use tauri::path::BaseDirectory;
 use tauri::{AppHandle, Emitter, Manager};
-use tokio::task;
-use tokio::time::{sleep, Duration};
+use serde::Serialize;
+use tokio::sync::mpsc;
+use tokio::time::{sleep, Duration, Instant};
 use tracing::debug;
 
 #[cfg(target_os = "windows")]
 use std::os::windows::process::CommandExt;
 
+/// 日志行事件名
+const LOG_EVENT: &str = "llama-log";
+/// 启动进度事件名
+const PROGRESS_EVENT: &str = "llama-progress";
+/// 张量加载进度事件名
+const LOADING_EVENT: &str = "local-server-loading";
+/// 服务器就绪事件名
+const READY_EVENT: &str = "local-server-ready";
+/// 等待服务器就绪的最长时间（大模型加载可能较慢）
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// 从日志行推断启动进度
+fn llama_progress(line: &str) -> Option<f64> {
+    if line.contains("build info") || line.contains("system info") {
+        Some(0.1)
+    } else if line.contains("loading model") {
+        Some(0.2)
+    } else if line.contains("model loaded") || line.contains("done") {
+        Some(0.5)
+    } else if is_listening_line(line) {
+        Some(0.8)
+    } else {
+        None
+    }
+}
+
+/// 从（可能尚未换行的）日志片段解析张量加载百分比：
+/// 默认进度回调每 1% 打印一个 `.`，部分版本打印 `load_tensors: ... NN%`
+fn tensor_load_percent(text: &str) -> Option<u32> {
+    let trimmed = text.trim();
+    if !trimmed.is_empty() && trimmed.chars().all(|c| c == '.') {
+        return Some((trimmed.len() as u32).min(100));
+    }
+    if !trimmed.contains("load") {
+        return None;
+    }
+    let before_pct = &trimmed[..trimmed.rfind('%')?];
+    let digits: String = before_pct
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+        .collect();
+    digits.parse::<f64>().ok().map(|p| p.clamp(0.0, 100.0) as u32)
+}
+
+/// 生成本地服务器 API key（两个 UUIDv4 拼接，122×2 位随机）
+pub(crate) fn generate_api_key() -> String {
+    format!(
+        "aio-{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+/// llama-server 开始监听 HTTP 的横幅行
+fn is_listening_line(line: &str) -> bool {
+    line.contains("HTTP server listening") || line.contains("listening on")
+}
+
+/// 读取线程 → 启动等待循环的信号
+enum StartupSignal {
+    /// 张量加载百分比（0~100）
+    Loading(u32),
+    /// 出现 listening 横幅
+    Listening,
+}
+
+/// `local-server-loading` 事件载荷
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct LoadingPayload {
+    percent: u32,
+    elapsed_ms: u64,
+}
+
+/// `local-server-ready` 事件载荷
+#[derive(Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+struct ReadyPayload {
+    port: u16,
+    /// 从启动进程到就绪的总耗时
+    load_ms: u64,
+}
+
+/// 定位 llama-server：优先使用自动安装的引擎，再回退到 bundled 路径
+pub fn resolve_exe_path(app: &AppHandle) -> Result<PathBuf, String> {
+    let installed = EngineInstaller::get_exe_path(app);
+    if installed.exists() {
+        return Ok(installed);
+    }
+    let resource_dir = app
+        .path()
+        .resolve("resources/engines/llama-cpp", BaseDirectory::Resource)
+        .map_err(|e| format!("无法解析资源路径: {}", e))?;
+    let fallback = resource_dir.join("llama-server.exe");
+    if !fallback.exists() {
+        return Err("找不到 llama.cpp 引擎。请先在设置页面中安装引擎。".to_string());
+    }
+    Ok(fallback)
+}
+
 pub struct LlamaCppPlugin;
 
 impl LocalEnginePlugin for LlamaCppPlugin {
@@ -46,7 +154,7 @@ impl LocalEnginePlugin for LlamaCppPlugin {
     }
 
     fn progress_event_name(&self) -> &'static str {
-        "llama-progress"
+        PROGRESS_EVENT
     }
 
     fn build_command(
@@ -55,8 +163,10 @@ impl LocalEnginePlugin for LlamaCppPlugin {
         model_path: &str,
         port: u16,
         gpu_layers: i32,
+        options: &LocalServerOptions,
     ) -> std::process::Command {
         let resource_dir = exe_path.parent().unwrap_or_else(|| Path::new("."));
+        let ctx_size = options.ctx_size.unwrap_or(DEFAULT_CTX_SIZE);
         let mut cmd = std::process::Command::new(exe_path);
         cmd.current_dir(resource_dir)
             .args([
@@ -67,30 +177,59 @@ impl LocalEnginePlugin for LlamaCppPlugin {
                 "-ngl",
                 &gpu_layers.to_string(),
                 "-c",
-                "4096",
+                &ctx_size.to_string(),
                 "--host",
                 "127.0.0.1",
             ])
             .stdout(std::process::Stdio::piped())
             .stderr(std::process::Stdio::piped());
 
+        // 对话模板已在 start_local_server 入口处校验
+        if let Ok(Some(template)) = options.chat_template_arg() {
+            template.apply(&mut cmd);
+        }
+        if let Ok(Some(mmproj)) = options.mmproj_arg() {
+            cmd.arg("--mmproj").arg(mmproj);
+        }
+        if let Ok((cache_k, cache_v)) = options.cache_type_args() {
+            if let Some(k) = cache_k {
+                cmd.args(["--cache-type-k", k]);
+            }
+            if let Some(v) = cache_v {
+                cmd.args(["--cache-type-v", v]);
+            }
+        }
+        if options.needs_flash_attn() {
+            // 经环境变量开启：新旧版本都接受 "on"（旧版为布尔参数 -fa，新版为 -fa on|off|auto）
+            cmd.env("LLAMA_ARG_FLASH_ATTN", "on");
+        }
+        if options.no_kv_offload {
+            cmd.arg("--no-kv-offload");
+        }
+        if let Ok(Some(draft)) = options.draft_model_arg() {
+            cmd.arg("--model-draft").arg(draft);
+            if let Some(max) = options.draft_max {
+                cmd.args(["--draft-max", &max.to_string()]);
+            }
+            if let Some(min) = options.draft_min {
+                cmd.args(["--draft-min", &min.to_string()]);
+            }
+        }
+        for (path, scale) in options.lora_args().unwrap_or_default() {
+            if scale == 1.0 {
+                cmd.arg("--lora").arg(path);
+            } else {
+                cmd.arg("--lora-scaled").arg(path).arg(scale.to_string());
+            }
+        }
+
         #[cfg(target_os = "windows")]
         cmd.creation_flags(0x08000000);
         cmd
     }
 
     fn parse_progress_from_log(&self, line: &str) -> Option<f64> {
-        if line.contains("build info") || line.contains("system info") {
-            Some(0.1)
-        } else if line.contains("loading model") {
-            Some(0.2)
-        } else if line.contains("model loaded") || line.contains("done") {
-            Some(0.5)
-        } else if line.contains("HTTP server listening") || line.contains("listening on") {
-            Some(0.8)
-        } else {
-            None
-        }
+        llama_progress(line)
     }
 
     fn start<'a>(
@@ -100,6 +239,7 @@ impl LocalEnginePlugin for LlamaCppPlugin {
         model_path: &'a str,
         port: u16,
         gpu_layers: i32,
+        options: &'a LocalServerOptions,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, String>> + Send + 'a>> {
         Box::pin(async move {
             debug!(
@@ -111,99 +251,250 @@ impl LocalEnginePlugin for LlamaCppPlugin {
                 return Err("GPU 层数必须大于 0，建议设置为 99 或 999".to_string());
             }
 
-            // 优先使用自动安装的引擎，再回退到 bundled 路径
-            let exe_path = if EngineInstaller::get_exe_path(&app).exists() {
-                EngineInstaller::get_exe_path(&app)
-            } else {
-                let resource_dir = app
-                    .path()
-                    .resolve("resources/engines/llama-cpp", BaseDirectory::Resource)
-                    .map_err(|e| format!("无法解析资源路径: {}", e))?;
-                let fallback = resource_dir.join("llama-server.exe");
-                if !fallback.exists() {
-                    return Err(
-                        "找不到 llama.cpp 引擎。请先在设置页面中安装引擎。".to_string()
-                    );
-                }
-                fallback
-            };
+            let exe_path = resolve_exe_path(&app)?;
 
             if !Path::new(model_path).exists() {
                 return Err(format!("模型文件不存在: {}", model_path));
             }
 
-            let mut cmd = self.build_command(&exe_path, model_path, port, gpu_layers);
+            // 旧版本不认识的参数会让进程直接退出：用户显式要求的功能不支持时提前报错，
+            // 应用默认附加的参数（鉴权、指标）则按版本跳过
+            let exe_for_version = exe_path.clone();
+            let build = tokio::task::spawn_blocking(move || backend_version::detect(&exe_for_version))
+                .await
+                .ok()
+                .and_then(Result::ok)
+                .and_then(|v| v.build);
+            let unsupported = backend_version::unsupported_options(build, options);
+            if !unsupported.is_empty() {
+                for warning in &unsupported {
+                    let _ = app.emit(backend_version::WARNING_EVENT, warning);
+                }
+                let messages: Vec<String> = unsupported.into_iter().map(|w| w.message).collect();
+                return Err(messages.join("；"));
+            }
+
+            let mut cmd = self.build_command(&exe_path, model_path, port, gpu_layers, options);
+            if backend_version::supports(build, "--metrics") {
+                // 开启 Prometheus /metrics，供性能浮窗使用
+                cmd.arg("--metrics");
+            }
+            // 每次启动生成随机 key，防止本机其他进程直接使用已加载的模型；
+            // key 经环境变量传入，避免通过 ps / /proc/<pid>/cmdline 被其他用户读到
+            let key_supported = backend_version::supports(build, "LLAMA_API_KEY");
+            if !key_supported && !options.disable_api_key {
+                let required = backend_version::min_build_for("LLAMA_API_KEY");
+                let current = build.unwrap_or_default();
+                let _ = app.emit(
+                    backend_version::WARNING_EVENT,
+                    CompatWarning {
+                        flag: "LLAMA_API_KEY",
+                        required_build: required,
+                        current_build: current,
+                        message: format!(
+                            "当前 llama.cpp 版本 b{} 不支持通过 LLAMA_API_KEY 设置鉴权（需要 b{} 或更高），本次启动未启用鉴权",
+                            current, required
+                        ),
+                    },
+                );
+            }
+            let api_key = (key_supported && !options.disable_api_key).then(generate_api_key);
+            if let Some(key) = &api_key {
+                cmd.env("LLAMA_API_KEY", key);
+            }
+            let output_detached = detached::redirect_output_if_detachable(&mut cmd);
+            let started = Instant::now();
             let mut child = match cmd.spawn() {
                 Ok(c) => c,
                 Err(e) => return Err(format!("启动失败: {}", e)),
             };
+            if !output_detached {
+                process_tree::bind_to_app_lifetime(&child);
+            }
 
             let _ = app.emit(self.progress_event_name(), 0.05);
+            if let Some(buffer) = app.try_state::<ServerLogBuffer>() {
+                buffer.clear();
+            }
 
-            let stderr = match child.stderr.take() {
-                Some(s) => s,
-                None => return Err("无法获取子进程 stderr".to_string()),
-            };
-            let app_clone = app.clone();
-            let event_name = self.progress_event_name().to_string();
-            task::spawn_blocking(move || {
-                let reader = BufReader::new(stderr);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        // 只在调试时记录子进程日志，避免泄露
-                        debug!("[llama-server] {}", line);
-                        let progress = if line.contains("build info") || line.contains("system info") {
-                            Some(0.1)
-                        } else if line.contains("loading model") {
-                            Some(0.2)
-                        } else if line.contains("model loaded") || line.contains("done") {
-                            Some(0.5)
-                        } else if line.contains("HTTP server listening") || line.contains("listening on") {
-                            Some(0.8)
-                        } else {
-                            None
-                        };
-                        if let Some(p) = progress {
-                            let _ = app_clone.emit(&event_name, p);
-                        }
+            // stdout / stderr 都接入日志缓冲；读取线程通过 channel 把加载进度与
+            // listening 横幅（新版 llama.cpp 打到 stdout）交给下面的启动等待循环
+            // （输出改写到日志文件时没有管道，只能靠 HTTP 轮询）
+            let (signal_tx, mut signal_rx) = mpsc::unbounded_channel::<StartupSignal>();
+            let pipes = [
+                (LogSource::Stdout, child.stdout.take().map(|p| Box::new(p) as Box<dyn Read + Send>)),
+                (LogSource::Stderr, child.stderr.take().map(|p| Box::new(p) as Box<dyn Read + Send>)),
+            ];
+            for (source, pipe) in pipes {
+                let Some(pipe) = pipe else { continue };
+                let app_clone = app.clone();
+                let signal_tx = signal_tx.clone();
+                server_log::spawn_reader(app.clone(), LOG_EVENT, source, pipe, move |chunk| {
+                    let text = match chunk {
+                        LogChunk::Line(line) | LogChunk::Partial(line) => line,
+                    };
+                    if let Some(percent) = tensor_load_percent(text) {
+                        let _ = signal_tx.send(StartupSignal::Loading(percent));
                     }
-                }
-            });
-
-            sleep(Duration::from_millis(2000)).await;
-            match child.try_wait() {
-                Ok(None) => {}
-                Ok(Some(status)) => {
-                    return Err(format!("进程启动后立即退出，退出码: {}", status));
-                }
-                Err(e) => return Err(format!("无法检查进程状态: {}", e)),
+                    let LogChunk::Line(line) = chunk else { return };
+                    // 只在调试时记录子进程日志，避免泄露
+                    debug!("[llama-server:{:?}] {}", source, line);
+                    if is_listening_line(line) {
+                        let _ = signal_tx.send(StartupSignal::Listening);
+                    }
+                    if let Some(p) = llama_progress(line) {
+                        let _ = app_clone.emit(PROGRESS_EVENT, p);
+                    }
+                });
             }
+            drop(signal_tx);
 
-            let client = reqwest::Client::builder()
-                .timeout(Duration::from_secs(5))
-                .connect_timeout(Duration::from_secs(2))
-                .build()
-                .unwrap_or_else(|_| reqwest::Client::new());
+            let client = app.state::<HttpClientState>().client();
             let health_url = format!("http://127.0.0.1:{}/health", port);
-            match client
-                .get(&health_url)
-                .send()
-                .await
-            {
-                Ok(_) => {
+            let deadline = Instant::now() + STARTUP_TIMEOUT;
+            let mut listening = false;
+            let mut last_percent = 0;
+            loop {
+                match child.try_wait() {
+                    Ok(None) => {}
+                    Ok(Some(status)) => {
+                        return Err(format!("进程启动后立即退出，退出码: {}", status));
+                    }
+                    Err(e) => return Err(format!("无法检查进程状态: {}", e)),
+                }
+                while let Ok(signal) = signal_rx.try_recv() {
+                    match signal {
+                        StartupSignal::Loading(percent) if percent > last_percent => {
+                            last_percent = percent;
+                            let _ = app.emit(
+                                LOADING_EVENT,
+                                LoadingPayload {
+                                    percent,
+                                    elapsed_ms: started.elapsed().as_millis() as u64,
+                                },
+                            );
+                        }
+                        StartupSignal::Loading(_) => {}
+                        StartupSignal::Listening => listening = true,
+                    }
+                }
+                // 日志中的 listening 行是最早的成功信号；否则回退到 HTTP 健康检查
+                // （加载中 /health 返回 503，只有 2xx 才算就绪）
+                let healthy = listening
+                    || client
+                        .get(&health_url)
+                        .timeout(Duration::from_secs(2))
+                        .send()
+                        .await
+                        .is_ok_and(|r| r.status().is_success());
+                if healthy {
                     let _ = app.emit(self.progress_event_name(), 1.0);
+                    let _ = app.emit(
+                        READY_EVENT,
+                        ReadyPayload {
+                            port,
+                            load_ms: started.elapsed().as_millis() as u64,
+                        },
+                    );
+                    break;
                 }
-                Err(_) => {
-                    let _ = child.kill();
+                if Instant::now() >= deadline {
+                    process_tree::kill_child(&mut child);
                     return Err("服务未响应健康检查，可能启动失败".to_string());
                 }
+                sleep(Duration::from_millis(500)).await;
             }
 
             let mut inner = state.lock();
             inner.engine_type = self.identifier().to_string();
             inner.child_process = Some(child);
+            inner.port = Some(port);
+            inner.output_detached = output_detached;
+            inner.supports_images = options.supports_images();
+            inner.api_key = api_key;
+            inner.ctx_size = Some(options.ctx_size.unwrap_or(DEFAULT_CTX_SIZE));
+            let (cache_k, cache_v) = options.cache_type_args().unwrap_or_default();
+            inner.cache_type_k = cache_k.map(String::from);
+            inner.cache_type_v = cache_v.map(String::from);
+            inner.lora_adapters = options.lora_adapters.clone();
+            inner.draft_model_path = options
+                .draft_model_arg()
+                .ok()
+                .flatten()
+                .map(|p| p.to_string_lossy().to_string());
 
             Ok(format!("http://127.0.0.1:{}/v1", port))
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_startup_log_lines() {
+        let progress = [
+            (
+                "system info: n_threads = 8, n_threads_batch = 8, total_threads = 16",
+                Some(0.1),
+            ),
+            (
+                "srv    load_model: loading model '/models/qwen2.5-7b-q4_k_m.gguf'",
+                Some(0.2),
+            ),
+            ("main: model loaded", Some(0.5)),
+            (
+                "main: server is listening on http://127.0.0.1:8080 - starting the main loop",
+                Some(0.8),
+            ),
+            (
+                "llama_model_loader: - kv   0: general.architecture str = qwen2",
+                None,
+            ),
+            (
+                "llm_load_tensors:        CUDA0 buffer size =  3992.51 MiB",
+                None,
+            ),
+        ];
+        for (line, expected) in progress {
+            assert_eq!(llama_progress(line), expected, "{}", line);
+        }
+
+        let listening = [
+            (
+                "main: server is listening on http://127.0.0.1:8080 - starting the main loop",
+                true,
+            ),
+            (
+                r#"{"tid":"1","level":"INFO","function":"main","msg":"HTTP server listening","port":"8080"}"#,
+                true,
+            ),
+            // 新版本在加载模型之前先打印这一行，此时服务尚不可用
+            (
+                "main: HTTP server is listening, hostname: 127.0.0.1, port: 8080, http threads: 15",
+                false,
+            ),
+            ("srv  update_slots: all slots are idle", false),
+        ];
+        for (line, expected) in listening {
+            assert_eq!(is_listening_line(line), expected, "{}", line);
+        }
+
+        let percent = [
+            ("..........", Some(10)),
+            (&".".repeat(120)[..], Some(100)),
+            ("load_tensors: loading model tensors 42%", Some(42)),
+            ("llama_model_load: progress 99.6%", Some(99)),
+            (
+                "load_tensors: loading model tensors, this can take a while... (mmap = true)",
+                None,
+            ),
+            ("srv  update_slots: prompt processing progress 50%", None),
+            ("", None),
+        ];
+        for (text, expected) in percent {
+            assert_eq!(tensor_load_percent(text), expected, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,261 @@
+//! llama-server 运行指标（/slots 与 Prometheus 格式的 /metrics）
+//!
+//! 两个端点在旧版本中可能不存在（404）或未开启（501），此时对应字段为 None，
+//! 不视为错误。投机解码的草稿接受率只有部分版本在 /metrics 中提供，
+//! 按 [`DRAFTED_METRICS`] / [`ACCEPTED_METRICS`] 中的名称读取，找不到时 `draftAcceptanceAvailable` 为 false。
+//! 可选的后台轮询在服务器运行期间定期发送 `local-server-metrics` 事件，供前端性能浮窗展示。
+
+use crate::core::state::{HttpClientState, LocalEngineState};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+/// 指标事件名
+pub const METRICS_EVENT: &str = "local-server-metrics";
+/// 草稿 token 累计数的指标名（不含 `llamacpp:` 前缀），不同版本命名不一
+const DRAFTED_METRICS: &[&str] = &["n_draft_total", "n_drafted_total"];
+/// 被接受的草稿 token 累计数的指标名
+const ACCEPTED_METRICS: &[&str] = &["n_draft_accepted_total", "n_drafted_accepted_total"];
+/// 默认轮询间隔
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(3);
+/// 最短轮询间隔，避免前端传入过小的值拖慢推理
+const MIN_POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// 单个指标端点的请求超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// 本地服务器运行指标（端点不可用的字段为 None）
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerMetrics {
+    /// /slots 是否可用
+    pub slots_available: bool,
+    /// /metrics 是否可用
+    pub metrics_available: bool,
+    pub slots_busy: Option<u32>,
+    pub slots_idle: Option<u32>,
+    /// 提示词处理速度（tokens/s）
+    pub prompt_tokens_per_second: Option<f64>,
+    /// 生成速度（tokens/s）
+    pub gen_tokens_per_second: Option<f64>,
+    /// 累计处理的提示词 token 数
+    pub prompt_tokens_total: Option<f64>,
+    /// 累计生成的 token 数
+    pub gen_tokens_total: Option<f64>,
+    /// KV cache 占用比例（0~1）
+    pub kv_cache_usage_ratio: Option<f64>,
+    /// KV cache 中的 token 数
+    pub kv_cache_tokens: Option<f64>,
+    /// 正在处理的请求数
+    pub requests_processing: Option<f64>,
+    /// 排队中的请求数
+    pub requests_deferred: Option<f64>,
+    /// 是否启用了投机解码（/slots 的 `speculative` 字段，旧版本没有时为 None）
+    pub speculative: Option<bool>,
+    /// /metrics 是否提供草稿 token 计数
+    pub draft_acceptance_available: bool,
+    /// 草稿 token 接受率（0~1）
+    pub draft_acceptance_rate: Option<f64>,
+}
+
+/// 解析 Prometheus 文本格式：`name{labels} value`，忽略注释与标签
+fn parse_prometheus(text: &str) -> HashMap<String, f64> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (name, rest) = match line.find('{') {
+                Some(brace) => (&line[..brace], &line[line.find('}')? + 1..]),
+                None => line.split_once(char::is_whitespace)?,
+            };
+            let value = rest.split_whitespace().next()?.parse::<f64>().ok()?;
+            Some((name.trim().to_string(), value))
+        })
+        .collect()
+}
+
+/// 把 /metrics 的数值填入结构体
+fn apply_prometheus(metrics: &mut ServerMetrics, text: &str) {
+    let values = parse_prometheus(text);
+    let get = |name: &str| values.get(&format!("llamacpp:{}", name)).copied();
+    metrics.metrics_available = true;
+    metrics.prompt_tokens_per_second = get("prompt_tokens_seconds");
+    metrics.gen_tokens_per_second = get("predicted_tokens_seconds");
+    metrics.prompt_tokens_total = get("prompt_tokens_total");
+    metrics.gen_tokens_total = get("tokens_predicted_total");
+    metrics.kv_cache_usage_ratio = get("kv_cache_usage_ratio");
+    metrics.kv_cache_tokens = get("kv_cache_tokens");
+    metrics.requests_processing = get("requests_processing");
+    metrics.requests_deferred = get("requests_deferred");
+
+    let first_of = |names: &[&str]| names.iter().find_map(|name| get(name));
+    if let (Some(drafted), Some(accepted)) = (first_of(DRAFTED_METRICS), first_of(ACCEPTED_METRICS))
+    {
+        metrics.draft_acceptance_available = true;
+        metrics.draft_acceptance_rate = (drafted > 0.0).then(|| accepted / drafted);
+    }
+}
+
+/// 统计 /slots 中忙碌 / 空闲的槽位：
+/// 新版本为 `is_processing: bool`，旧版本为 `state: 0 空闲 / 1 处理中`
+fn apply_slots(metrics: &mut ServerMetrics, slots: &Value) {
+    let Some(slots) = slots.as_array() else {
+        return;
+    };
+    let busy = slots
+        .iter()
+        .filter(|slot| {
+            slot["is_processing"]
+                .as_bool()
+                .unwrap_or_else(|| slot["state"].as_u64().is_some_and(|s| s != 0))
+        })
+        .count() as u32;
+    metrics.slots_available = true;
+    metrics.speculative = slots
+        .iter()
+        .filter_map(|slot| slot["speculative"].as_bool())
+        .reduce(|a, b| a || b);
+    metrics.slots_busy = Some(busy);
+    metrics.slots_idle = Some(slots.len() as u32 - busy);
+}
+
+/// GET 指标端点；404 / 501（旧版本或未开启）返回 Ok(None)
+async fn fetch_endpoint(
+    client: &reqwest::Client,
+    url: &str,
+    api_key: Option<&str>,
+) -> Result<Option<String>, String> {
+    let mut req = client.get(url).timeout(REQUEST_TIMEOUT);
+    if let Some(key) = api_key {
+        req = req.bearer_auth(key);
+    }
+    let resp = req.send().await.map_err(|e| format!("无法连接本地服务器: {}", e))?;
+    let status = resp.status();
+    if status == reqwest::StatusCode::NOT_FOUND || status == reqwest::StatusCode::NOT_IMPLEMENTED {
+        return Ok(None);
+    }
+    if !status.is_success() {
+        return Err(format!("指标端点返回错误状态: {}", status));
+    }
+    resp.text().await.map(Some).map_err(|e| e.to_string())
+}
+
+/// 查询 llama-server 的 /slots 与 /metrics
+pub async fn fetch(
+    client: &reqwest::Client,
+    port: u16,
+    api_key: Option<&str>,
+) -> Result<ServerMetrics, String> {
+    let slots_url = format!("http://127.0.0.1:{}/slots", port);
+    let metrics_url = format!("http://127.0.0.1:{}/metrics", port);
+    let (slots, prom) = tokio::join!(
+        fetch_endpoint(client, &slots_url, api_key),
+        fetch_endpoint(client, &metrics_url, api_key),
+    );
+
+    let mut metrics = ServerMetrics::default();
+    if let Some(body) = slots? {
+        if let Ok(value) = serde_json::from_str::<Value>(&body) {
+            apply_slots(&mut metrics, &value);
+        }
+    }
+    if let Some(body) = prom? {
+        apply_prometheus(&mut metrics, &body);
+    }
+    Ok(metrics)
+}
+
+/// 当前运行的本地服务器的端口与 key；没有服务器时为 None
+pub fn current_server(state: &LocalEngineState) -> Option<(u16, Option<String>)> {
+    let inner = state.lock();
+    if !inner.has_server() {
+        return None;
+    }
+    Some((inner.port?, inner.api_key.clone()))
+}
+
+/// 后台指标轮询任务（Tauri 托管状态）
+#[derive(Default)]
+pub struct MetricsPoller(Mutex<Option<JoinHandle<()>>>);
+
+impl MetricsPoller {
+    /// 启动轮询（已有任务时先停止）；服务器停止后任务自行退出
+    pub fn start(&self, app: AppHandle, interval: Option<Duration>) {
+        let interval = interval.unwrap_or(DEFAULT_POLL_INTERVAL).max(MIN_POLL_INTERVAL);
+        let handle = tokio::spawn(async move {
+            let client = app.state::<HttpClientState>().client();
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                let Some((port, api_key)) = current_server(&app.state::<LocalEngineState>()) else {
+                    break;
+                };
+                match fetch(&client, port, api_key.as_deref()).await {
+                    Ok(metrics) => {
+                        let _ = app.emit(METRICS_EVENT, metrics);
+                    }
+                    Err(e) => tracing::debug!("读取本地服务器指标失败: {}", e),
+                }
+            }
+        });
+        if let Some(old) = self.lock().replace(handle) {
+            old.abort();
+        }
+    }
+
+    /// 停止轮询
+    pub fn stop(&self) {
+        if let Some(handle) = self.lock().take() {
+            handle.abort();
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<JoinHandle<()>>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_llama_metrics() {
+        let text = "\
+# HELP llamacpp:prompt_tokens_seconds Average prompt throughput in tokens/s.
+# TYPE llamacpp:prompt_tokens_seconds gauge
+llamacpp:prompt_tokens_seconds 512.5
+llamacpp:predicted_tokens_seconds 31.25
+llamacpp:kv_cache_usage_ratio 0.125
+llamacpp:requests_deferred{slot=\"0\"} 2
+";
+        let mut metrics = ServerMetrics::default();
+        apply_prometheus(&mut metrics, text);
+        assert!(metrics.metrics_available);
+        assert_eq!(metrics.prompt_tokens_per_second, Some(512.5));
+        assert_eq!(metrics.gen_tokens_per_second, Some(31.25));
+        assert_eq!(metrics.kv_cache_usage_ratio, Some(0.125));
+        assert_eq!(metrics.requests_deferred, Some(2.0));
+        assert_eq!(metrics.kv_cache_tokens, None);
+        assert!(!metrics.draft_acceptance_available);
+
+        apply_prometheus(
+            &mut metrics,
+            "llamacpp:n_draft_max 16\nllamacpp:n_draft_total 200\nllamacpp:n_draft_accepted_total 150\n",
+        );
+        assert_eq!(metrics.draft_acceptance_rate, Some(0.75));
+    }
+
+    #[test]
+    fn counts_slots_old_and_new_format() {
+        let mut metrics = ServerMetrics::default();
+        apply_slots(&mut metrics, &serde_json::json!([{ "is_processing": true }, { "is_processing": false }]));
+        assert_eq!((metrics.slots_busy, metrics.slots_idle), (Some(1), Some(1)));
+
+        apply_slots(&mut metrics, &serde_json::json!([{ "state": 0 }, { "state": 1 }, { "state": 1 }]));
+        assert_eq!((metrics.slots_busy, metrics.slots_idle), (Some(2), Some(1)));
+    }
+}
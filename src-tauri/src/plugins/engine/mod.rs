@@ -1,8 +1,20 @@
 /// 本地推理引擎插件系统
 /// 提供统一的 LocalEnginePlugin trait 和 EngineManager 注册中心
 
+pub mod backend_version;
+pub mod benchmark;
+pub mod detached;
 pub mod installer;
+pub mod last_launch;
 pub mod llama_cpp;
+pub mod metrics;
+pub mod model_import;
+pub mod options;
+pub mod port_owner;
+pub mod resources;
+pub mod process_tree;
+pub mod scan;
+pub mod server_log;
 pub mod vllm;
 
 use std::collections::HashMap;
@@ -11,6 +23,8 @@ use std::pin::Pin;
 use std::future::Future;
 use tauri::AppHandle;
 
+pub use options::LocalServerOptions;
+
 /// 本地推理引擎插件 trait
 /// 所有本地推理后端（llama.cpp, vLLM 等）必须实现此 trait
 #[allow(dead_code)]
@@ -41,6 +55,7 @@ pub trait LocalEnginePlugin: Send + Sync {
         model_path: &'a str,
         port: u16,
         gpu_layers: i32,
+        options: &'a LocalServerOptions,
     ) -> Pin<Box<dyn Future<Output = Result<String, String>> + Send + 'a>>;
 
     /// 发送进度事件的事件名
@@ -55,6 +70,7 @@ pub trait LocalEnginePlugin: Send + Sync {
         model_path: &str,
         port: u16,
         gpu_layers: i32,
+        options: &LocalServerOptions,
     ) -> std::process::Command;
 
     /// 解析 stderr 日志并返回进度值 (0.0~1.0)
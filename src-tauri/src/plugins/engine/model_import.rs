@@ -0,0 +1,159 @@
+//! 把任意位置的 GGUF 模型导入到受管理的模型目录
+//!
+//! 目标目录为配置中的 `localModelPath`（指向文件时取其所在目录），未配置时为数据目录下的 `models/`。
+//! 同一文件系统内优先建立硬链接（不占额外空间），否则分块复制并发送
+//! `local-model-import-progress` 事件；复制先写 `.part` 临时文件，完成后再改名。
+
+use crate::commands::config::local_model_path;
+use crate::core::paths;
+use crate::utils::file_parser::validate_model_path;
+use crate::utils::gguf;
+use serde::Serialize;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tauri::{AppHandle, Emitter};
+
+/// 导入进度事件名
+pub const PROGRESS_EVENT: &str = "local-model-import-progress";
+/// 复制缓冲区大小
+const COPY_CHUNK: usize = 8 * 1024 * 1024;
+
+/// `local-model-import-progress` 事件载荷
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportProgress {
+    pub source_path: String,
+    pub target_path: String,
+    pub copied_bytes: u64,
+    pub total_bytes: u64,
+    /// 0.0 ~ 1.0
+    pub progress: f64,
+}
+
+/// 受管理的模型目录（不存在时创建）
+fn managed_dir(app: &AppHandle) -> Result<PathBuf, String> {
+    let configured = local_model_path();
+    let configured = Path::new(configured.trim());
+    let dir = if configured.as_os_str().is_empty() {
+        paths::app_data_root(app)?.join("models")
+    } else if configured.is_file() {
+        configured
+            .parent()
+            .map(Path::to_path_buf)
+            .ok_or_else(|| "无法确定模型目录".to_string())?
+    } else {
+        configured.to_path_buf()
+    };
+    fs::create_dir_all(&dir).map_err(|e| format!("创建模型目录失败: {}", e))?;
+    Ok(dir)
+}
+
+/// 目录中不与已有文件冲突的目标路径：`name.gguf` → `name-1.gguf` → `name-2.gguf` ...
+fn unique_target(dir: &Path, file_name: &str) -> PathBuf {
+    let candidate = dir.join(file_name);
+    if !candidate.exists() {
+        return candidate;
+    }
+    let (stem, ext) = file_name.rsplit_once('.').unwrap_or((file_name, ""));
+    (1..)
+        .map(|i| dir.join(format!("{}-{}.{}", stem, i, ext)))
+        .find(|p| !p.exists())
+        .expect("unbounded range always yields a free name")
+}
+
+/// 分块复制到 `.part` 文件并按百分比报告进度，完成后改名为目标文件
+fn copy_with_progress(source: &Path, target: &Path, on_progress: impl Fn(u64, u64)) -> Result<(), String> {
+    let total = fs::metadata(source).map_err(|e| e.to_string())?.len();
+    let part = target.with_extension("gguf.part");
+    let result = (|| {
+        let mut reader = fs::File::open(source).map_err(|e| format!("读取模型文件失败: {}", e))?;
+        let mut writer = fs::File::create(&part).map_err(|e| format!("创建目标文件失败: {}", e))?;
+        let mut buf = vec![0u8; COPY_CHUNK];
+        let mut copied = 0u64;
+        let mut reported_percent = None;
+        loop {
+            let n = reader.read(&mut buf).map_err(|e| format!("读取模型文件失败: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            writer
+                .write_all(&buf[..n])
+                .map_err(|e| format!("写入目标文件失败（磁盘空间不足？）: {}", e))?;
+            copied += n as u64;
+            let percent = copied.saturating_mul(100) / total.max(1);
+            if reported_percent != Some(percent) {
+                reported_percent = Some(percent);
+                on_progress(copied, total);
+            }
+        }
+        writer.flush().map_err(|e| e.to_string())?;
+        fs::rename(&part, target).map_err(|e| format!("保存模型文件失败: {}", e))
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&part);
+    }
+    result
+}
+
+/// 导入模型：校验 GGUF 魔数后硬链接或复制到受管理目录，返回新路径；
+/// 源文件已在受管理目录中时直接返回原路径
+pub fn import(app: &AppHandle, source_path: &str) -> Result<PathBuf, String> {
+    let source = validate_model_path(source_path)?;
+    if !source.is_file() {
+        return Err(format!("模型文件不存在: {}", source_path));
+    }
+    gguf::check_magic(&source).map_err(|e| format!("{}，只能导入 GGUF 模型", e))?;
+
+    let dir = managed_dir(app)?;
+    let canonical_dir = fs::canonicalize(&dir).map_err(|e| e.to_string())?;
+    let canonical_source = fs::canonicalize(&source).map_err(|e| e.to_string())?;
+    if canonical_source.parent() == Some(canonical_dir.as_path()) {
+        return Ok(source);
+    }
+
+    let file_name = source
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| "无效的文件名".to_string())?;
+    let target = unique_target(&dir, file_name);
+    let source_str = source.to_string_lossy().to_string();
+    let target_str = target.to_string_lossy().to_string();
+    let emit = |copied: u64, total: u64| {
+        let _ = app.emit(
+            PROGRESS_EVENT,
+            ImportProgress {
+                source_path: source_str.clone(),
+                target_path: target_str.clone(),
+                copied_bytes: copied,
+                total_bytes: total,
+                progress: if total > 0 { copied as f64 / total as f64 } else { 1.0 },
+            },
+        );
+    };
+
+    // 同一文件系统：硬链接，瞬间完成且不占额外空间
+    if fs::hard_link(&source, &target).is_ok() {
+        let size = fs::metadata(&target).map(|m| m.len()).unwrap_or(0);
+        emit(size, size);
+        return Ok(target);
+    }
+    copy_with_progress(&source, &target, emit)?;
+    Ok(target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_free_file_name() {
+        let dir = std::env::temp_dir().join(format!("aio-import-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(unique_target(&dir, "m.gguf"), dir.join("m.gguf"));
+        fs::write(dir.join("m.gguf"), b"GGUF").unwrap();
+        fs::write(dir.join("m-1.gguf"), b"GGUF").unwrap();
+        assert_eq!(unique_target(&dir, "m.gguf"), dir.join("m-2.gguf"));
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,564 @@
+//! 本地推理服务器启动选项
+//!
+//! 前端在 `start_local_server` 中可选传入；未传时按模型路径读取上次保存的选项。
+//! 持久化在 `$CONFIG/com.loch.aio/local-model-options.json`（或自定义数据目录），键为模型绝对路径。
+
+use crate::commands::config::local_max_ctx_size;
+use crate::core::paths;
+use crate::utils::file_parser::{path_in_sandbox, validate_model_path};
+use crate::utils::gguf;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const OPTIONS_FILE: &str = "local-model-options.json";
+/// 内联对话模板文件所在子目录（位于应用配置目录下）
+const INLINE_TEMPLATE_DIR: &str = "chat-templates";
+
+/// llama-server `--chat-template` 支持的内置模板名（与 llama.cpp `LLM_CHAT_TEMPLATES` 对齐）
+pub const BUILTIN_CHAT_TEMPLATES: &[&str] = &[
+    "chatml",
+    "llama2",
+    "llama2-sys",
+    "llama2-sys-bos",
+    "llama2-sys-strip",
+    "llama3",
+    "llama4",
+    "mistral-v1",
+    "mistral-v3",
+    "mistral-v3-tekken",
+    "mistral-v7",
+    "mistral-v7-tekken",
+    "phi3",
+    "phi4",
+    "falcon3",
+    "zephyr",
+    "monarch",
+    "gemma",
+    "orion",
+    "openchat",
+    "vicuna",
+    "vicuna-orca",
+    "deepseek",
+    "deepseek2",
+    "deepseek3",
+    "command-r",
+    "chatglm3",
+    "chatglm4",
+    "glmedge",
+    "minicpm",
+    "exaone3",
+    "exaone4",
+    "rwkv-world",
+    "granite",
+    "gigachat",
+    "megrez",
+    "yandex",
+    "bailing",
+    "smolvlm",
+    "hunyuan-moe",
+    "hunyuan-dense",
+    "kimi-k2",
+    "gpt-oss",
+    "seed_oss",
+];
+
+/// `--cache-type-k` / `--cache-type-v` 允许的 KV cache 类型
+pub const KV_CACHE_TYPES: &[&str] = &["f16", "q8_0", "q4_0"];
+
+/// 上下文长度下限
+pub const MIN_CTX_SIZE: u32 = 512;
+/// 无法从模型元数据推断时使用的上下文长度
+pub const DEFAULT_CTX_SIZE: u32 = 4096;
+/// 从 GGUF 元数据推断上下文长度时的默认上限（可在配置中用 `localMaxCtxSize` 覆盖），
+/// 避免 128K 模型默认就申请巨大的 KV cache
+const DEFAULT_MAX_CTX_SIZE: u32 = 32768;
+
+/// 低显存预设使用的上下文长度
+const LOW_VRAM_CTX_SIZE: u32 = 2048;
+
+/// 草稿模型与主模型词表大小允许的最大差值（与 llama.cpp `SPEC_VOCAB_MAX_SIZE_DIFFERENCE` 一致）
+const DRAFT_VOCAB_MAX_DIFF: u64 = 128;
+
+/// LoRA 缩放系数默认值（等同于 `--lora`）
+const DEFAULT_LORA_SCALE: f32 = 1.0;
+
+fn default_lora_scale() -> f32 {
+    DEFAULT_LORA_SCALE
+}
+
+/// 挂载到基础模型上的 LoRA 适配器
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LoraAdapter {
+    /// 适配器 GGUF 文件的绝对路径
+    pub path: String,
+    /// 缩放系数，1.0 时传 `--lora`，否则传 `--lora-scaled`
+    #[serde(default = "default_lora_scale")]
+    pub scale: f32,
+}
+
+/// 单个本地模型的启动选项
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalServerOptions {
+    /// 对话模板：内置模板名（如 "llama3"）、模板文件的绝对路径或 Jinja 模板正文。
+    /// None 表示使用 GGUF 自带模板。
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chat_template: Option<String>,
+    /// 多模态投影文件（`*-mmproj-*.gguf`）的绝对路径；设置后服务器可接收图片输入
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mmproj_path: Option<String>,
+    /// 不设置鉴权 key（旧版 llama-server 不支持 `LLAMA_API_KEY` 时关闭鉴权）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disable_api_key: bool,
+    /// 上下文长度（`-c`）；None 时按 GGUF 元数据推断（受上限约束）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ctx_size: Option<u32>,
+    /// K cache 类型（`--cache-type-k`），None 为 llama.cpp 默认的 f16
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_type_k: Option<String>,
+    /// V cache 类型（`--cache-type-v`）；量化 V cache 需要 flash attention，启动时自动开启（见 [`Self::needs_flash_attn`]）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_type_v: Option<String>,
+    /// KV cache 留在内存而不卸载到显存（`--no-kv-offload`）
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_kv_offload: bool,
+    /// 按顺序加载的 LoRA 适配器
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lora_adapters: Vec<LoraAdapter>,
+    /// 投机解码草稿模型（`--model-draft`）的绝对路径，需与主模型使用同一分词器
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draft_model_path: Option<String>,
+    /// 每轮最多草稿 token 数（`--draft-max`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draft_max: Option<u32>,
+    /// 每轮最少草稿 token 数（`--draft-min`）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub draft_min: Option<u32>,
+}
+
+/// 校验后的对话模板参数
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChatTemplateArg {
+    /// `--chat-template <name>`
+    Builtin(String),
+    /// `--chat-template-file <path>`
+    File(PathBuf),
+    /// Jinja 模板正文：启动前由 [`LocalServerOptions::write_inline_template`] 写入应用目录下的文件，
+    /// 再以 `--chat-template-file` 传入
+    Inline(String),
+}
+
+/// 看起来像 Jinja 模板正文（而不是模板名或路径）
+fn is_inline_template(value: &str) -> bool {
+    value.contains("{%") || value.contains("{{")
+}
+
+/// 内联模板的文件路径：位于应用配置目录（而非共享的系统临时目录），按内容哈希命名，
+/// 同一模板多次启动复用同一文件
+fn inline_template_path(template: &str) -> Result<PathBuf, String> {
+    use sha2::{Digest, Sha256};
+    let digest = Sha256::digest(template.as_bytes());
+    let hex: String = digest.iter().map(|b| format!("{:02x}", b)).collect();
+    Ok(paths::app_config_dir()?
+        .join(INLINE_TEMPLATE_DIR)
+        .join(format!("{}.jinja", hex)))
+}
+
+/// 写入内联模板：以 `create_new` 新建，不跟随已存在的文件或链接；
+/// 已存在且内容一致时直接复用，不一致时删除后重建
+fn write_template_file(path: &Path, template: &str) -> Result<(), String> {
+    use std::io::Write;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent).map_err(|e| format!("创建对话模板目录失败: {}", e))?;
+    }
+    for _ in 0..2 {
+        match fs::OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(mut file) => {
+                return file
+                    .write_all(template.as_bytes())
+                    .map_err(|e| format!("写入对话模板文件失败: {}", e));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                let is_same = fs::symlink_metadata(path).is_ok_and(|m| m.is_file())
+                    && fs::read_to_string(path).is_ok_and(|existing| existing == template);
+                if is_same {
+                    return Ok(());
+                }
+                fs::remove_file(path).map_err(|e| format!("替换对话模板文件失败: {}", e))?;
+            }
+            Err(e) => return Err(format!("写入对话模板文件失败: {}", e)),
+        }
+    }
+    Err("写入对话模板文件失败: 文件被并发修改".to_string())
+}
+
+impl ChatTemplateArg {
+    /// 对应的 llama-server 参数名
+    pub fn flag(&self) -> &'static str {
+        match self {
+            ChatTemplateArg::Builtin(_) => "--chat-template",
+            ChatTemplateArg::File(_) | ChatTemplateArg::Inline(_) => "--chat-template-file",
+        }
+    }
+
+    /// 追加到 llama-server 命令行
+    pub fn apply(&self, cmd: &mut std::process::Command) {
+        match self {
+            ChatTemplateArg::Builtin(name) => {
+                cmd.args(["--chat-template", name]);
+            }
+            ChatTemplateArg::File(path) => {
+                cmd.arg("--chat-template-file").arg(path);
+            }
+            ChatTemplateArg::Inline(template) => {
+                // 路径在 write_inline_template 时已成功解析过
+                if let Ok(path) = inline_template_path(template) {
+                    cmd.arg("--chat-template-file").arg(path);
+                }
+            }
+        }
+    }
+}
+
+impl LocalServerOptions {
+    /// 解析对话模板：内置名按白名单校验，含 `{%` / `{{` 的视为模板正文，
+    /// 否则视为模板文件路径，校验存在并与模型路径一样通过沙箱校验
+    pub fn chat_template_arg(&self) -> Result<Option<ChatTemplateArg>, String> {
+        let Some(raw) = self.chat_template.as_deref() else {
+            return Ok(None);
+        };
+        let value = raw.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        if BUILTIN_CHAT_TEMPLATES.contains(&value) {
+            return Ok(Some(ChatTemplateArg::Builtin(value.to_string())));
+        }
+        if is_inline_template(value) {
+            return Ok(Some(ChatTemplateArg::Inline(raw.to_string())));
+        }
+        let path = Path::new(value);
+        if path.is_absolute() && path.is_file() {
+            path_in_sandbox(path).map_err(|e| format!("对话模板文件路径被拒绝: {}", e))?;
+            return Ok(Some(ChatTemplateArg::File(path.to_path_buf())));
+        }
+        Err(format!(
+            "未知的对话模板 {:?}：既不是内置模板名，也不是存在的模板文件路径。可用的内置模板: {}",
+            value,
+            BUILTIN_CHAT_TEMPLATES.join(", ")
+        ))
+    }
+
+    /// 对话模板为 Jinja 正文时写入模板文件（启动 llama-server 前调用）
+    pub fn write_inline_template(&self) -> Result<(), String> {
+        if let Some(ChatTemplateArg::Inline(template)) = self.chat_template_arg()? {
+            write_template_file(&inline_template_path(&template)?, &template)?;
+        }
+        Ok(())
+    }
+
+    /// 解析 mmproj 路径：需通过模型路径沙箱校验且文件存在
+    pub fn mmproj_arg(&self) -> Result<Option<PathBuf>, String> {
+        let Some(raw) = self.mmproj_path.as_deref() else {
+            return Ok(None);
+        };
+        let value = raw.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        let path = validate_model_path(value)?;
+        if !path.is_file() {
+            return Err(format!("mmproj 文件不存在: {}", value));
+        }
+        Ok(Some(path))
+    }
+
+    /// 校验 KV cache 类型，返回 (K, V)；未设置的为 None
+    pub fn cache_type_args(&self) -> Result<(Option<&str>, Option<&str>), String> {
+        fn check<'a>(flag: &str, value: Option<&'a str>) -> Result<Option<&'a str>, String> {
+            match value.map(str::trim).filter(|v| !v.is_empty()) {
+                Some(v) if !KV_CACHE_TYPES.contains(&v) => Err(format!(
+                    "不支持的 {} 类型 {:?}，可选值: {}",
+                    flag,
+                    v,
+                    KV_CACHE_TYPES.join(" / ")
+                )),
+                other => Ok(other),
+            }
+        }
+        Ok((
+            check("cache-type-k", self.cache_type_k.as_deref())?,
+            check("cache-type-v", self.cache_type_v.as_deref())?,
+        ))
+    }
+
+    /// V cache 为量化类型时 llama-server 必须开启 flash attention，否则拒绝启动
+    pub fn needs_flash_attn(&self) -> bool {
+        matches!(self.cache_type_args(), Ok((_, Some(v))) if v != "f16")
+    }
+
+    /// 校验 LoRA 适配器：路径需通过模型路径沙箱校验且文件存在，缩放系数为有限值
+    pub fn lora_args(&self) -> Result<Vec<(PathBuf, f32)>, String> {
+        self.lora_adapters
+            .iter()
+            .map(|adapter| {
+                let path = validate_model_path(adapter.path.trim())?;
+                if !path.is_file() {
+                    return Err(format!("LoRA 适配器文件不存在: {}", adapter.path));
+                }
+                if !adapter.scale.is_finite() {
+                    return Err(format!("LoRA 缩放系数无效: {}", adapter.scale));
+                }
+                Ok((path, adapter.scale))
+            })
+            .collect()
+    }
+
+    /// 解析草稿模型路径：需通过模型路径沙箱校验且文件存在
+    pub fn draft_model_arg(&self) -> Result<Option<PathBuf>, String> {
+        let Some(raw) = self.draft_model_path.as_deref() else {
+            return Ok(None);
+        };
+        let value = raw.trim();
+        if value.is_empty() {
+            return Ok(None);
+        }
+        let path = validate_model_path(value)?;
+        if !path.is_file() {
+            return Err(format!("草稿模型文件不存在: {}", value));
+        }
+        Ok(Some(path))
+    }
+
+    /// 检查草稿模型与主模型的分词器是否一致（读取双方 GGUF 元数据）
+    pub fn check_draft_model(&self, model_path: &Path) -> Result<(), String> {
+        let Some(draft_path) = self.draft_model_arg()? else {
+            return Ok(());
+        };
+        let main = gguf::read_file(model_path)?;
+        let draft = gguf::read_file(&draft_path).map_err(|e| format!("草稿模型: {}", e))?;
+        check_tokenizer_compat(&main, &draft)
+    }
+
+    /// 低显存预设：KV cache 量化为 q8_0、留在内存，并缩小上下文
+    /// （让 13B 模型能在 6 GB 显卡上运行）；其余选项保持不变
+    pub fn low_vram_preset(self) -> Self {
+        Self {
+            ctx_size: Some(self.ctx_size.map_or(LOW_VRAM_CTX_SIZE, |c| c.min(LOW_VRAM_CTX_SIZE))),
+            cache_type_k: Some("q8_0".to_string()),
+            cache_type_v: Some("q8_0".to_string()),
+            no_kv_offload: true,
+            ..self
+        }
+    }
+
+    /// 未指定上下文长度时，取 GGUF 元数据中的训练长度并以配置上限截断；
+    /// 读取失败（非 GGUF、vLLM 目录等）时使用 [`DEFAULT_CTX_SIZE`]
+    pub fn with_default_ctx_size(mut self, model_path: &Path) -> Self {
+        if self.ctx_size.is_none() {
+            let max = match local_max_ctx_size() {
+                0 => DEFAULT_MAX_CTX_SIZE,
+                n => n.max(MIN_CTX_SIZE),
+            };
+            let trained = gguf::read_file(model_path)
+                .ok()
+                .and_then(|h| h.context_length())
+                .map(|n| n.min(u64::from(u32::MAX)) as u32);
+            self.ctx_size = Some(trained.map_or(DEFAULT_CTX_SIZE, |n| n.clamp(MIN_CTX_SIZE, max)));
+        }
+        self
+    }
+
+    /// 以当前选项启动的服务器是否支持图片输入
+    pub fn supports_images(&self) -> bool {
+        matches!(self.mmproj_arg(), Ok(Some(_)))
+    }
+
+    /// 启动前统一校验所有选项
+    pub fn validate(&self) -> Result<(), String> {
+        self.chat_template_arg()?;
+        self.mmproj_arg()?;
+        self.cache_type_args()?;
+        self.lora_args()?;
+        let has_draft = self.draft_model_arg()?.is_some();
+        if !has_draft && (self.draft_max.is_some() || self.draft_min.is_some()) {
+            return Err("设置 draftMax / draftMin 时需要同时指定草稿模型".to_string());
+        }
+        if self.draft_max == Some(0) {
+            return Err("draftMax 必须大于 0".to_string());
+        }
+        if let (Some(min), Some(max)) = (self.draft_min, self.draft_max) {
+            if min > max {
+                return Err(format!("draftMin（{}）不能大于 draftMax（{}）", min, max));
+            }
+        }
+        if let Some(ctx) = self.ctx_size.filter(|&c| c < MIN_CTX_SIZE) {
+            return Err(format!("上下文长度不能小于 {}（当前为 {}）", MIN_CTX_SIZE, ctx));
+        }
+        Ok(())
+    }
+}
+
+/// 草稿模型必须与主模型共用分词器：类型、预分词规则一致，词表大小相差不超过
+/// [`DRAFT_VOCAB_MAX_DIFF`]；元数据缺失的项不做比较
+fn check_tokenizer_compat(main: &gguf::GgufHeader, draft: &gguf::GgufHeader) -> Result<(), String> {
+    let mismatch = |field: &str, a: &str, b: &str| {
+        Err(format!(
+            "草稿模型与主模型的分词器不一致（{}: {} / {}），无法用于投机解码",
+            field, a, b
+        ))
+    };
+    if let (Some(a), Some(b)) = (main.tokenizer_model(), draft.tokenizer_model()) {
+        if a != b {
+            return mismatch("tokenizer.ggml.model", a, b);
+        }
+    }
+    if let (Some(a), Some(b)) = (main.tokenizer_pre(), draft.tokenizer_pre()) {
+        if a != b {
+            return mismatch("tokenizer.ggml.pre", a, b);
+        }
+    }
+    if let (Some(a), Some(b)) = (main.vocab_size(), draft.vocab_size()) {
+        if a.abs_diff(b) > DRAFT_VOCAB_MAX_DIFF {
+            return mismatch("词表大小", &a.to_string(), &b.to_string());
+        }
+    }
+    Ok(())
+}
+
+fn options_path() -> Option<PathBuf> {
+    paths::app_config_dir().ok().map(|dir| dir.join(OPTIONS_FILE))
+}
+
+fn load_all() -> BTreeMap<String, LocalServerOptions> {
+    options_path()
+        .and_then(|p| fs::read_to_string(p).ok())
+        .and_then(|s| serde_json::from_str(&s).ok())
+        .unwrap_or_default()
+}
+
+/// 读取某模型上次保存的启动选项；不存在时返回默认值
+pub fn load_for_model(model_path: &str) -> LocalServerOptions {
+    load_all().remove(model_path).unwrap_or_default()
+}
+
+/// 保存某模型的启动选项（下次启动同一模型时复用）
+pub fn save_for_model(model_path: &str, options: &LocalServerOptions) -> Result<(), String> {
+    let path = options_path().ok_or_else(|| "无法获取系统配置目录".to_string())?;
+    let mut all = load_all();
+    if *options == LocalServerOptions::default() {
+        all.remove(model_path);
+    } else {
+        all.insert(model_path.to_string(), options.clone());
+    }
+    let json = serde_json::to_string_pretty(&all).map_err(|e| e.to_string())?;
+    fs::write(path, json).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validates_cache_types() {
+        let opts = LocalServerOptions {
+            cache_type_k: Some("q8_0".into()),
+            cache_type_v: Some(" ".into()),
+            ..Default::default()
+        };
+        assert_eq!(opts.cache_type_args(), Ok((Some("q8_0"), None)));
+        assert!(!opts.needs_flash_attn());
+        let quantized_v = LocalServerOptions {
+            cache_type_v: Some("q4_0".into()),
+            ..Default::default()
+        };
+        assert!(quantized_v.needs_flash_attn());
+
+        let bad = LocalServerOptions {
+            cache_type_v: Some("q3_k".into()),
+            ..Default::default()
+        };
+        assert!(bad.validate().is_err());
+    }
+
+    #[test]
+    fn resolves_chat_template_kinds() {
+        let arg = |template: &str| {
+            LocalServerOptions {
+                chat_template: Some(template.into()),
+                ..Default::default()
+            }
+            .chat_template_arg()
+        };
+        assert_eq!(arg(" chatml "), Ok(Some(ChatTemplateArg::Builtin("chatml".into()))));
+        let jinja = "{% for m in messages %}{{ m.content }}{% endfor %}";
+        assert_eq!(arg(jinja), Ok(Some(ChatTemplateArg::Inline(jinja.into()))));
+        assert_eq!(arg(jinja).unwrap().unwrap().flag(), "--chat-template-file");
+        assert!(arg("chatml2").unwrap_err().contains("llama3"));
+        assert_eq!(arg("  "), Ok(None));
+
+        // 沙箱外的模板文件（临时目录）同样拒绝
+        let outside =
+            std::env::temp_dir().join(format!("aio-template-{}.jinja", uuid::Uuid::new_v4()));
+        fs::write(&outside, "{{ a }}").unwrap();
+        assert!(arg(&outside.to_string_lossy())
+            .unwrap_err()
+            .contains("对话模板文件路径被拒绝"));
+        let _ = fs::remove_file(&outside);
+    }
+
+    #[test]
+    fn rewrites_template_file_only_when_content_differs() {
+        let dir = std::env::temp_dir().join(format!("aio-template-test-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("t.jinja");
+        write_template_file(&path, "{{ a }}").unwrap();
+        write_template_file(&path, "{{ a }}").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{{ a }}");
+        write_template_file(&path, "{{ b }}").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{{ b }}");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn low_vram_preset_keeps_smaller_context() {
+        let opts = LocalServerOptions {
+            ctx_size: Some(1024),
+            ..Default::default()
+        }
+        .low_vram_preset();
+        assert_eq!(opts.ctx_size, Some(1024));
+        assert!(opts.no_kv_offload);
+        assert_eq!(LocalServerOptions::default().low_vram_preset().ctx_size, Some(LOW_VRAM_CTX_SIZE));
+    }
+
+    #[test]
+    fn lora_scale_defaults_to_one() {
+        let opts: LocalServerOptions =
+            serde_json::from_str(r#"{"loraAdapters":[{"path":"/models/a.gguf"},{"path":"/models/b.gguf","scale":0.5}]}"#)
+                .unwrap();
+        let scales: Vec<f32> = opts.lora_adapters.iter().map(|a| a.scale).collect();
+        assert_eq!(scales, vec![1.0, 0.5]);
+    }
+
+    #[test]
+    fn draft_tokenizer_must_match() {
+        use gguf::{GgufHeader, GgufValue};
+        let header = |pre: &str, vocab: u64| GgufHeader {
+            version: 3,
+            tensor_count: 0,
+            metadata: [
+                ("tokenizer.ggml.model".to_string(), GgufValue::String("gpt2".into())),
+                ("tokenizer.ggml.pre".to_string(), GgufValue::String(pre.into())),
+                ("tokenizer.ggml.tokens".to_string(), GgufValue::Array { len: vocab }),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        assert!(check_tokenizer_compat(&header("qwen2", 151936), &header("qwen2", 151665)).is_err());
+        assert!(check_tokenizer_compat(&header("qwen2", 151936), &header("qwen2", 151900)).is_ok());
+        assert!(check_tokenizer_compat(&header("qwen2", 151936), &header("llama-bpe", 151936)).is_err());
+    }
+}
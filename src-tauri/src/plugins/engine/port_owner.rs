@@ -0,0 +1,190 @@
+//! 端口占用诊断
+//!
+//! 本地服务器启动前检查端口能否绑定；被占用时找出监听该端口的进程（pid 与可执行文件），
+//! 写入错误信息并发送 `local-server-port-conflict` 事件。若占用者是从本应用引擎目录启动的
+//! llama-server（通常是上次崩溃残留的实例），事件中 `killable` 为 true，前端可调用 `kill_port_owner` 结束它。
+//!
+//! - Windows：`netstat -ano` 取 pid，再用 PowerShell `Get-Process` 取路径
+//! - Linux：`/proc/net/tcp{,6}` 找到监听 socket 的 inode，再扫描 `/proc/*/fd`
+//! - 其他平台：`lsof -t` 取 pid，`ps -o comm=` 取路径
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+/// 端口冲突事件名
+pub const PORT_CONFLICT_EVENT: &str = "local-server-port-conflict";
+
+/// 端口冲突事件载荷
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PortConflict {
+    pub port: u16,
+    /// 占用进程 pid（无法识别时为 None）
+    pub pid: Option<u32>,
+    pub exe_path: Option<String>,
+    pub process_name: Option<String>,
+    /// 占用者是本应用引擎目录下的 llama-server，可安全结束
+    pub killable: bool,
+    pub message: String,
+}
+
+/// 端口当前能否在 127.0.0.1 上绑定
+pub fn is_port_free(port: u16) -> bool {
+    std::net::TcpListener::bind(("127.0.0.1", port)).is_ok()
+}
+
+/// 解析 `/proc/net/tcp` / `/proc/net/tcp6`，返回监听 `port` 的 socket inode
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_proc_net_tcp(text: &str, port: u16) -> Option<u64> {
+    const TCP_LISTEN: &str = "0A";
+    text.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let local_port = fields.get(1)?.rsplit_once(':')?.1;
+        let matches = u16::from_str_radix(local_port, 16).ok() == Some(port)
+            && *fields.get(3)? == TCP_LISTEN;
+        matches.then(|| fields.get(9)?.parse().ok()).flatten()
+    })
+}
+
+/// 解析 Windows `netstat -ano -p TCP` 输出，返回监听 `port` 的 pid。
+/// 状态列会随系统语言翻译，因此以远端地址端口为 0 判断监听
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn parse_netstat(text: &str, port: u16) -> Option<u32> {
+    let suffix = format!(":{}", port);
+    text.lines().find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [proto, local, remote, _state, pid] = fields.as_slice() else {
+            return None;
+        };
+        let listening = proto.eq_ignore_ascii_case("TCP")
+            && local.ends_with(&suffix)
+            && remote.ends_with(":0");
+        listening.then(|| pid.parse().ok()).flatten()
+    })
+}
+
+/// 查找监听 `port` 的进程：(pid, 可执行文件路径)
+fn find_owner(port: u16) -> Option<(u32, Option<String>)> {
+    #[cfg(target_os = "windows")]
+    {
+        let output = std::process::Command::new("netstat")
+            .args(["-ano", "-p", "TCP"])
+            .creation_flags(0x08000000)
+            .output()
+            .ok()?;
+        let pid = parse_netstat(&String::from_utf8_lossy(&output.stdout), port)?;
+        let path = std::process::Command::new("powershell")
+            .args([
+                "-NoProfile",
+                "-NonInteractive",
+                "-Command",
+                &format!("(Get-Process -Id {} -ErrorAction SilentlyContinue).Path", pid),
+            ])
+            .creation_flags(0x08000000)
+            .output()
+            .ok()
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+            .filter(|p| !p.is_empty());
+        Some((pid, path))
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let inode = ["/proc/net/tcp", "/proc/net/tcp6"].iter().find_map(|file| {
+            parse_proc_net_tcp(&std::fs::read_to_string(file).ok()?, port)
+        })?;
+        let target = format!("socket:[{}]", inode);
+        std::fs::read_dir("/proc").ok()?.flatten().find_map(|entry| {
+            let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+            let owns = std::fs::read_dir(entry.path().join("fd"))
+                .ok()?
+                .flatten()
+                .any(|fd| std::fs::read_link(fd.path()).is_ok_and(|link| link.as_os_str() == target.as_str()));
+            let exe = std::fs::read_link(entry.path().join("exe"))
+                .ok()
+                .map(|p| p.to_string_lossy().to_string());
+            owns.then_some((pid, exe))
+        })
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        let output = std::process::Command::new("lsof")
+            .args(["-nP", &format!("-iTCP:{}", port), "-sTCP:LISTEN", "-t"])
+            .output()
+            .ok()?;
+        let pid: u32 = String::from_utf8_lossy(&output.stdout).lines().next()?.trim().parse().ok()?;
+        let path = std::process::Command::new("ps")
+            .args(["-p", &pid.to_string(), "-o", "comm="])
+            .output()
+            .ok()
+            .map(|o| String::from_utf8_lossy(&o.stdout).trim().to_string())
+            .filter(|p| !p.is_empty());
+        Some((pid, path))
+    }
+}
+
+/// 诊断端口占用情况；`engine_dirs` 用于识别本应用启动的 llama-server
+pub fn diagnose(port: u16, engine_dirs: &[PathBuf]) -> PortConflict {
+    let owner = find_owner(port);
+    let pid = owner.as_ref().map(|(pid, _)| *pid);
+    let exe_path = owner.and_then(|(_, exe)| exe);
+    let process_name = exe_path.as_deref().and_then(|p| {
+        Path::new(p)
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+    });
+    let killable = pid.is_some()
+        && exe_path.as_deref().is_some_and(|exe| {
+            let is_llama = process_name
+                .as_deref()
+                .is_some_and(|n| n.trim_end_matches(".exe") == "llama-server");
+            is_llama && crate::plugins::engine::process_tree::is_under(Path::new(exe), engine_dirs)
+        });
+    let message = match (pid, &exe_path) {
+        (Some(pid), _) if killable => format!(
+            "端口 {} 已被本应用之前启动的 llama-server 占用 (pid {})，可结束该进程后重试",
+            port, pid
+        ),
+        (Some(pid), Some(exe)) => format!("端口 {} 已被占用: {} (pid {})", port, exe, pid),
+        (Some(pid), None) => format!("端口 {} 已被 pid {} 占用", port, pid),
+        (None, _) => format!("端口 {} 已被占用，无法确定占用进程", port),
+    };
+    PortConflict {
+        port,
+        pid,
+        exe_path,
+        process_name,
+        killable,
+        message,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_listening_inode_in_proc_net_tcp() {
+        let text = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode
+   0: 0100007F:1F90 00000000:0000 0A 00000000:00000000 00:00000000 00000000  1000        0 45678 1 0000000000000000 100 0 0 10 0
+   1: 0100007F:1F90 0100007F:D3A2 01 00000000:00000000 00:00000000 00000000  1000        0 45699 1 0000000000000000 20 4 30 10 -1";
+        assert_eq!(parse_proc_net_tcp(text, 8080), Some(45678));
+        assert_eq!(parse_proc_net_tcp(text, 8081), None);
+    }
+
+    #[test]
+    fn finds_listening_pid_in_netstat() {
+        let text = "
+Active Connections
+
+  Proto  Local Address          Foreign Address        State           PID
+  TCP    127.0.0.1:8080         127.0.0.1:52011        ESTABLISHED     4321
+  TCP    127.0.0.1:8080         0.0.0.0:0              LISTENING       1234
+  TCP    [::]:18080             [::]:0                 LISTENING       999";
+        assert_eq!(parse_netstat(text, 8080), Some(1234));
+        assert_eq!(parse_netstat(text, 18080), Some(999));
+        assert_eq!(parse_netstat(text, 80), None);
+    }
+}
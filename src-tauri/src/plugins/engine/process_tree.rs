@@ -0,0 +1,254 @@
+//! 本地服务器进程树管理
+//!
+//! Windows 上 `Child::kill()` 只结束 llama-server 本身，CUDA 运行时拉起的辅助进程可能残留并占用显存。
+//! 因此启动后把子进程放进一个设置了 kill-on-close 的 Job Object：应用退出（包括崩溃）时
+//! 系统关闭 Job 句柄，整棵进程树随之结束；主动停止时再用 `taskkill /T /F` 兜底。
+//! 「退出时保留」的服务器需要在应用退出后继续运行，不放入 Job。
+//!
+//! 另外提供残留进程扫描：查找从本应用引擎目录启动、但不归当前应用管理的 llama-server。
+
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::process::Child;
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+/// 等待进程退出时的轮询间隔
+pub const EXIT_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// 残留的本地服务器进程
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OrphanedServer {
+    pub pid: u32,
+    pub exe_path: String,
+}
+
+#[cfg(target_os = "windows")]
+mod job {
+    use std::os::windows::io::AsRawHandle;
+    use std::sync::OnceLock;
+    use windows_sys::Win32::Foundation::HANDLE;
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation,
+        SetInformationJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION,
+        JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    /// 进程级 Job 句柄：不主动关闭，随应用进程退出由系统回收
+    struct Job(HANDLE);
+    // SAFETY: Job 句柄可在任意线程使用，且创建后不再修改
+    unsafe impl Send for Job {}
+    unsafe impl Sync for Job {}
+
+    static JOB: OnceLock<Option<Job>> = OnceLock::new();
+
+    fn create() -> Option<Job> {
+        // SAFETY: 参数均为合法指针 / null，返回的句柄在使用前检查
+        unsafe {
+            let handle = CreateJobObjectW(std::ptr::null(), std::ptr::null());
+            if handle.is_null() {
+                return None;
+            }
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = std::mem::zeroed();
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            let ok = SetInformationJobObject(
+                handle,
+                JobObjectExtendedLimitInformation,
+                &info as *const _ as *const core::ffi::c_void,
+                std::mem::size_of::<JOBOBJECT_EXTENDED_LIMIT_INFORMATION>() as u32,
+            );
+            if ok == 0 {
+                return None;
+            }
+            Some(Job(handle))
+        }
+    }
+
+    /// 把子进程加入 Job，返回是否成功
+    pub fn assign(child: &std::process::Child) -> bool {
+        let Some(job) = JOB.get_or_init(create) else {
+            return false;
+        };
+        // SAFETY: 两个句柄在调用期间均有效
+        unsafe { AssignProcessToJobObject(job.0, child.as_raw_handle() as HANDLE) != 0 }
+    }
+}
+
+/// 让子进程及其后代随应用退出（包括崩溃）一起结束；目前只在 Windows 上生效
+pub fn bind_to_app_lifetime(child: &Child) {
+    #[cfg(target_os = "windows")]
+    if !job::assign(child) {
+        tracing::warn!("无法将本地服务器加入 Job Object，应用异常退出时可能残留进程");
+    }
+    #[cfg(not(target_os = "windows"))]
+    let _ = child;
+}
+
+/// 结束 pid 对应的整棵进程树
+pub fn kill_tree(pid: u32) {
+    #[cfg(target_os = "windows")]
+    {
+        let _ = std::process::Command::new("taskkill")
+            .args(["/PID", &pid.to_string(), "/T", "/F"])
+            .creation_flags(0x08000000)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+    #[cfg(not(target_os = "windows"))]
+    {
+        let _ = std::process::Command::new("kill")
+            .arg(pid.to_string())
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+}
+
+/// 结束自己启动的子进程：Windows 上先按进程树结束，再 kill 句柄兜底
+pub fn kill_child(child: &mut Child) {
+    #[cfg(target_os = "windows")]
+    kill_tree(child.id());
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+/// 结束子进程并异步轮询 `try_wait` 直到退出，返回是否在 `timeout` 内退出
+pub async fn kill_child_and_wait(mut child: Child, timeout: Duration) -> bool {
+    #[cfg(target_os = "windows")]
+    kill_tree(child.id());
+    let _ = child.kill();
+    let deadline = Instant::now() + timeout;
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) | Err(_) => return true,
+            Ok(None) if Instant::now() >= deadline => return false,
+            Ok(None) => tokio::time::sleep(EXIT_POLL_INTERVAL).await,
+        }
+    }
+}
+
+/// `path` 是否位于某个 `roots` 目录下（Windows 路径不区分大小写）
+pub(crate) fn is_under(path: &Path, roots: &[PathBuf]) -> bool {
+    let normalize = |p: &Path| {
+        let s = p.to_string_lossy().replace('\\', "/");
+        if cfg!(target_os = "windows") {
+            s.to_lowercase()
+        } else {
+            s
+        }
+    };
+    let path = normalize(path);
+    roots.iter().any(|root| {
+        let root = normalize(root);
+        let root = root.trim_end_matches('/');
+        !root.is_empty() && path.starts_with(&format!("{}/", root))
+    })
+}
+
+/// 解析 PowerShell `ConvertTo-Json` 输出：单个进程为对象，多个为数组
+#[cfg_attr(not(target_os = "windows"), allow(dead_code))]
+fn parse_process_json(text: &str) -> Vec<(u32, String)> {
+    let Ok(value) = serde_json::from_str::<serde_json::Value>(text.trim()) else {
+        return Vec::new();
+    };
+    let items = match value {
+        serde_json::Value::Array(items) => items,
+        other => vec![other],
+    };
+    items
+        .iter()
+        .filter_map(|item| {
+            let pid = item["ProcessId"].as_u64()? as u32;
+            let path = item["ExecutablePath"].as_str()?.to_string();
+            Some((pid, path))
+        })
+        .collect()
+}
+
+/// 列出系统中所有 llama-server 进程的 (pid, 可执行文件路径)
+fn list_llama_servers() -> Vec<(u32, String)> {
+    #[cfg(target_os = "windows")]
+    {
+        let output = std::process::Command::new("powershell")
+            .args([
+                "-NoProfile",
+                "-NonInteractive",
+                "-Command",
+                "Get-CimInstance Win32_Process -Filter \"Name='llama-server.exe'\" | \
+                 Select-Object ProcessId,ExecutablePath | ConvertTo-Json -Compress",
+            ])
+            .creation_flags(0x08000000)
+            .output();
+        output
+            .map(|o| parse_process_json(&String::from_utf8_lossy(&o.stdout)))
+            .unwrap_or_default()
+    }
+    #[cfg(target_os = "linux")]
+    {
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+        entries
+            .flatten()
+            .filter_map(|entry| {
+                let pid: u32 = entry.file_name().to_str()?.parse().ok()?;
+                let exe = std::fs::read_link(entry.path().join("exe")).ok()?;
+                let is_llama = exe.file_name().and_then(|n| n.to_str()) == Some("llama-server");
+                is_llama.then(|| (pid, exe.to_string_lossy().to_string()))
+            })
+            .collect()
+    }
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        let output = std::process::Command::new("ps").args(["-axo", "pid=,comm="]).output();
+        let Ok(output) = output else {
+            return Vec::new();
+        };
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| {
+                let (pid, comm) = line.trim().split_once(char::is_whitespace)?;
+                let comm = comm.trim();
+                if !comm.ends_with("/llama-server") {
+                    return None;
+                }
+                Some((pid.parse().ok()?, comm.to_string()))
+            })
+            .collect()
+    }
+}
+
+/// 查找从 `engine_dirs` 启动、且不在 `managed_pids` 中的 llama-server 进程
+pub fn find_orphaned_servers(engine_dirs: &[PathBuf], managed_pids: &[u32]) -> Vec<OrphanedServer> {
+    list_llama_servers()
+        .into_iter()
+        .filter(|(pid, path)| !managed_pids.contains(pid) && is_under(Path::new(path), engine_dirs))
+        .map(|(pid, exe_path)| OrphanedServer { pid, exe_path })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_single_and_multiple_processes() {
+        let one = r#"{"ProcessId":42,"ExecutablePath":"C:\\AIO\\engines\\llama-cpp\\llama-server.exe"}"#;
+        assert_eq!(parse_process_json(one).len(), 1);
+        let many = r#"[{"ProcessId":1,"ExecutablePath":"a"},{"ProcessId":2,"ExecutablePath":null}]"#;
+        assert_eq!(parse_process_json(many), vec![(1, "a".to_string())]);
+        assert!(parse_process_json("").is_empty());
+    }
+
+    #[test]
+    fn matches_processes_inside_engine_dirs() {
+        let roots = vec![PathBuf::from("/data/aio/engines/llama-cpp")];
+        assert!(is_under(Path::new("/data/aio/engines/llama-cpp/llama-server"), &roots));
+        assert!(!is_under(Path::new("/data/aio/engines/llama-cpp2/llama-server"), &roots));
+        assert!(!is_under(Path::new("/usr/bin/llama-server"), &roots));
+    }
+}
@@ -0,0 +1,146 @@
+//! 本地服务器进程资源占用监控
+//!
+//! 服务器启动后在后台每隔几秒采样一次子进程的 CPU 占用与常驻内存（sysinfo），
+//! 系统装有 nvidia-smi 时再读取该 pid 占用的显存，发送 `local-server-resources` 事件，
+//! 最近一次采样同时通过 `get_local_server_status` 返回。服务器停止或更换进程后任务自行退出。
+
+use crate::core::state::LocalEngineState;
+use serde::Serialize;
+use std::sync::Mutex;
+use sysinfo::{Pid, ProcessRefreshKind, ProcessesToUpdate, System};
+use tauri::async_runtime::JoinHandle;
+use tauri::{AppHandle, Emitter, Manager};
+use tokio::time::Duration;
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
+
+/// 资源事件名
+pub const RESOURCES_EVENT: &str = "local-server-resources";
+/// 采样间隔
+const SAMPLE_INTERVAL: Duration = Duration::from_secs(3);
+
+/// 一次资源采样
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceSample {
+    pub pid: u32,
+    /// CPU 占用百分比（多核累加，可能超过 100）
+    pub cpu_percent: f32,
+    /// 常驻内存（字节）
+    pub rss_bytes: u64,
+    /// 该进程占用的显存（字节）；没有 nvidia-smi 或进程未使用 GPU 时为 None
+    pub gpu_memory_bytes: Option<u64>,
+}
+
+/// 解析 `nvidia-smi --query-compute-apps=pid,used_memory --format=csv,noheader,nounits`，
+/// 返回 `pid` 在各 GPU 上占用显存之和（MiB → 字节）
+fn parse_nvidia_smi(text: &str, pid: u32) -> Option<u64> {
+    let mib: u64 = text
+        .lines()
+        .filter_map(|line| {
+            let (p, used) = line.split_once(',')?;
+            (p.trim().parse::<u32>().ok()? == pid).then(|| used.trim().parse::<u64>().ok())?
+        })
+        .sum();
+    (mib > 0).then_some(mib * 1024 * 1024)
+}
+
+/// 通过 nvidia-smi 查询显存；命令不存在时返回 Err，以便之后不再尝试
+fn query_gpu_memory(pid: u32) -> Result<Option<u64>, ()> {
+    let mut cmd = std::process::Command::new("nvidia-smi");
+    cmd.args(["--query-compute-apps=pid,used_memory", "--format=csv,noheader,nounits"]);
+    #[cfg(target_os = "windows")]
+    cmd.creation_flags(0x08000000);
+    let output = cmd.output().map_err(|_| ())?;
+    if !output.status.success() {
+        return Err(());
+    }
+    Ok(parse_nvidia_smi(&String::from_utf8_lossy(&output.stdout), pid))
+}
+
+/// 当前服务器进程的 pid（自己启动的或接管的）
+fn current_pid(state: &LocalEngineState) -> Option<u32> {
+    let inner = state.lock();
+    inner.child_process.as_ref().map(|c| c.id()).or(inner.adopted_pid)
+}
+
+/// 资源监控任务与最近一次采样（Tauri 托管状态）
+#[derive(Default)]
+pub struct ResourceMonitor {
+    task: Mutex<Option<JoinHandle<()>>>,
+    latest: Mutex<Option<ResourceSample>>,
+}
+
+impl ResourceMonitor {
+    /// 最近一次采样（服务器已停止时为 None）
+    pub fn latest(&self) -> Option<ResourceSample> {
+        self.latest.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    fn set_latest(&self, sample: Option<ResourceSample>) {
+        *self.latest.lock().unwrap_or_else(|e| e.into_inner()) = sample;
+    }
+
+    /// 为当前服务器进程启动监控（已有任务时先停止）；可在 setup 阶段调用（走 Tauri 异步运行时）
+    pub fn start(&self, app: AppHandle) {
+        let Some(pid) = current_pid(&app.state::<LocalEngineState>()) else {
+            return;
+        };
+        self.set_latest(None);
+        let handle = tauri::async_runtime::spawn(async move {
+            let mut system = System::new();
+            let mut gpu_available = true;
+            let mut ticker = tokio::time::interval(SAMPLE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                if current_pid(&app.state::<LocalEngineState>()) != Some(pid) {
+                    break;
+                }
+                system.refresh_processes_specifics(
+                    ProcessesToUpdate::Some(&[Pid::from_u32(pid)]),
+                    true,
+                    ProcessRefreshKind::nothing().with_cpu().with_memory(),
+                );
+                let Some(process) = system.process(Pid::from_u32(pid)) else {
+                    break;
+                };
+                let (cpu_percent, rss_bytes) = (process.cpu_usage(), process.memory());
+                let gpu_memory_bytes = if gpu_available {
+                    let result = tokio::task::spawn_blocking(move || query_gpu_memory(pid))
+                        .await
+                        .unwrap_or(Err(()));
+                    gpu_available = result.is_ok();
+                    result.unwrap_or(None)
+                } else {
+                    None
+                };
+                let sample = ResourceSample {
+                    pid,
+                    cpu_percent,
+                    rss_bytes,
+                    gpu_memory_bytes,
+                };
+                app.state::<ResourceMonitor>().set_latest(Some(sample.clone()));
+                let _ = app.emit(RESOURCES_EVENT, sample);
+            }
+            app.state::<ResourceMonitor>().set_latest(None);
+        });
+        if let Some(old) = self.task.lock().unwrap_or_else(|e| e.into_inner()).replace(handle) {
+            old.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sums_gpu_memory_for_pid() {
+        let text = "1234, 2048\n5678, 512\n1234, 1024\n";
+        assert_eq!(parse_nvidia_smi(text, 1234), Some(3072 * 1024 * 1024));
+        assert_eq!(parse_nvidia_smi(text, 42), None);
+        assert_eq!(parse_nvidia_smi("", 1234), None);
+    }
+}
@@ -0,0 +1,184 @@
+//! 本地模型目录扫描
+//!
+//! 扫描目录（含一层子目录）下的 GGUF 文件，并把同目录下的 `*mmproj*.gguf`
+//! 多模态投影文件自动配对到最相近的基础模型上。
+//! GGUF 元数据 `general.type = "adapter"` 的 LoRA 文件单独列出，供挂载到基础模型上。
+
+use crate::utils::gguf;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// 扫描深度：根目录 + 一层子目录（常见的「每个模型一个文件夹」布局）
+const MAX_SCAN_DEPTH: usize = 2;
+/// 多个基础模型共存时，文件名公共前缀至少这么长才认为 mmproj 属于该模型
+const MIN_PAIR_PREFIX: usize = 4;
+
+/// 扫描到的本地模型
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalModelEntry {
+    pub path: String,
+    pub file_name: String,
+    pub size_bytes: u64,
+    /// 自动配对的多模态投影文件，None 表示纯文本模型
+    pub mmproj_path: Option<String>,
+}
+
+/// 扫描到的 LoRA 适配器
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalAdapterEntry {
+    pub path: String,
+    pub file_name: String,
+    pub size_bytes: u64,
+    /// 适配器对应的基础模型架构（`general.architecture`），如 "llama"
+    pub architecture: Option<String>,
+}
+
+/// 目录扫描结果
+#[derive(Serialize, Clone, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalModelScan {
+    pub models: Vec<LocalModelEntry>,
+    pub lora_adapters: Vec<LocalAdapterEntry>,
+}
+
+/// 文件名是否为 mmproj 投影文件
+pub fn is_mmproj_file(file_name: &str) -> bool {
+    file_name.to_lowercase().contains("mmproj")
+}
+
+/// 去掉扩展名与 "mmproj" 标记后的小写主干，用于比较相似度
+fn normalized_stem(file_name: &str) -> String {
+    let lower = file_name.to_lowercase();
+    let stem = lower.strip_suffix(".gguf").unwrap_or(&lower);
+    stem.replace("mmproj", "")
+        .trim_matches(|c: char| c == '-' || c == '_' || c == '.')
+        .to_string()
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(x, y)| x == y).count()
+}
+
+/// 为同一目录下的基础模型挑选 mmproj：
+/// 只有一个基础模型时直接取最相近的 mmproj；多个时要求公共前缀足够长。
+/// 返回与 `models` 等长的配对结果。
+pub fn pair_mmproj(models: &[String], projectors: &[String]) -> Vec<Option<String>> {
+    models
+        .iter()
+        .map(|model| {
+            let model_stem = normalized_stem(model);
+            let best = projectors
+                .iter()
+                .map(|proj| (common_prefix_len(&model_stem, &normalized_stem(proj)), proj))
+                .max_by_key(|(score, _)| *score)?;
+            if models.len() == 1 || best.0 >= MIN_PAIR_PREFIX {
+                Some(best.1.clone())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+fn collect_gguf(dir: &Path, depth: usize, out: &mut BTreeMap<PathBuf, Vec<(String, u64)>>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(meta) = entry.metadata() else {
+            continue;
+        };
+        if meta.is_dir() {
+            if depth + 1 < MAX_SCAN_DEPTH {
+                collect_gguf(&path, depth + 1, out);
+            }
+            continue;
+        }
+        let is_gguf = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("gguf"));
+        if !is_gguf {
+            continue;
+        }
+        if let Some(name) = path.file_name().and_then(|n| n.to_str()) {
+            out.entry(dir.to_path_buf())
+                .or_default()
+                .push((name.to_string(), meta.len()));
+        }
+    }
+}
+
+/// 扫描目录下的 GGUF 模型，mmproj 文件不单独列出而是挂在配对的模型上；
+/// LoRA 适配器按元数据识别后放入单独的列表
+pub fn scan_dir(root: &Path) -> LocalModelScan {
+    let mut by_dir = BTreeMap::new();
+    collect_gguf(root, 0, &mut by_dir);
+
+    let mut result = LocalModelScan::default();
+    for (dir, mut files) in by_dir {
+        files.sort();
+        let (projectors, files): (Vec<_>, Vec<_>) =
+            files.into_iter().partition(|(name, _)| is_mmproj_file(name));
+        let mut models = Vec::new();
+        for (name, size) in files {
+            let path = dir.join(&name);
+            match gguf::read_file(&path) {
+                Ok(header) if header.is_adapter() => result.lora_adapters.push(LocalAdapterEntry {
+                    path: path.to_string_lossy().to_string(),
+                    file_name: name,
+                    size_bytes: size,
+                    architecture: header.architecture().map(String::from),
+                }),
+                _ => models.push((name, size)),
+            }
+        }
+        let model_names: Vec<String> = models.iter().map(|(n, _)| n.clone()).collect();
+        let projector_names: Vec<String> = projectors.into_iter().map(|(n, _)| n).collect();
+        let pairs = pair_mmproj(&model_names, &projector_names);
+        for ((name, size), mmproj) in models.into_iter().zip(pairs) {
+            result.models.push(LocalModelEntry {
+                path: dir.join(&name).to_string_lossy().to_string(),
+                file_name: name,
+                size_bytes: size,
+                mmproj_path: mmproj.map(|p| dir.join(p).to_string_lossy().to_string()),
+            });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn pairs_single_model_with_any_projector() {
+        let pairs = pair_mmproj(&names(&["model-Q4_K_M.gguf"]), &names(&["mmproj-f16.gguf"]));
+        assert_eq!(pairs, vec![Some("mmproj-f16.gguf".to_string())]);
+    }
+
+    #[test]
+    fn pairs_by_common_prefix_when_several_models() {
+        let models = names(&["gemma-3-4b-it-Q4_K_M.gguf", "qwen2.5-vl-7b-Q4_K_M.gguf", "llama-3-8b.gguf"]);
+        let projs = names(&["mmproj-gemma-3-4b-it-f16.gguf", "qwen2.5-vl-7b-mmproj-f16.gguf"]);
+        let pairs = pair_mmproj(&models, &projs);
+        assert_eq!(pairs[0].as_deref(), Some("mmproj-gemma-3-4b-it-f16.gguf"));
+        assert_eq!(pairs[1].as_deref(), Some("qwen2.5-vl-7b-mmproj-f16.gguf"));
+        assert_eq!(pairs[2], None);
+    }
+
+    #[test]
+    fn no_projectors_means_no_pairing() {
+        assert_eq!(pair_mmproj(&names(&["a.gguf"]), &[]), vec![None]);
+    }
+}
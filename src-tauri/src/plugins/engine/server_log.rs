@@ -0,0 +1,123 @@
+//! 本地服务器日志环形缓冲
+//!
+//! 子进程 stdout / stderr 各起一个读取线程，按来源打标签后写入同一个缓冲区，
+//! 并逐行发送日志事件（llama.cpp 为 `llama-log`），供诊断面板展示。
+//! 未换行的片段也会回调给调用方，用于解析 `....` 形式的加载进度。
+
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::io::Read;
+use std::sync::Mutex;
+use tauri::{AppHandle, Emitter, Manager};
+
+/// 缓冲区最多保留的行数
+const MAX_LOG_LINES: usize = 1000;
+
+/// 日志行来源
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum LogSource {
+    Stdout,
+    Stderr,
+}
+
+/// 一行服务器日志
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerLogLine {
+    pub source: LogSource,
+    pub line: String,
+    /// Unix 毫秒时间戳
+    pub timestamp: u64,
+}
+
+/// 全局日志缓冲（Tauri 托管状态）
+#[derive(Default)]
+pub struct ServerLogBuffer(Mutex<VecDeque<ServerLogLine>>);
+
+impl ServerLogBuffer {
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<ServerLogLine>> {
+        self.0.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// 追加一行，超出上限时丢弃最旧的
+    pub fn push(&self, line: ServerLogLine) {
+        let mut buf = self.lock();
+        if buf.len() >= MAX_LOG_LINES {
+            buf.pop_front();
+        }
+        buf.push_back(line);
+    }
+
+    /// 当前缓冲内容快照
+    pub fn snapshot(&self) -> Vec<ServerLogLine> {
+        self.lock().iter().cloned().collect()
+    }
+
+    /// 新服务器启动时清空
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+}
+
+fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// 读取到的输出片段
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogChunk<'a> {
+    /// 完整的一行（不含换行符）
+    Line(&'a str),
+    /// 尚未换行的当前行（如 llama.cpp 加载张量时逐个打印的 `.`）
+    Partial(&'a str),
+}
+
+/// 在阻塞线程中按块读取子进程输出：完整行写入缓冲并发送 `event_name` 事件；
+/// 完整行与未换行的片段都交给 `on_chunk`（用于进度解析 / 就绪检测）
+pub fn spawn_reader<R, F>(app: AppHandle, event_name: &'static str, source: LogSource, mut reader: R, on_chunk: F)
+where
+    R: Read + Send + 'static,
+    F: Fn(LogChunk<'_>) + Send + 'static,
+{
+    tokio::task::spawn_blocking(move || {
+        let record = |line: String| {
+            on_chunk(LogChunk::Line(&line));
+            let entry = ServerLogLine {
+                source,
+                line,
+                timestamp: now_millis(),
+            };
+            if let Some(buffer) = app.try_state::<ServerLogBuffer>() {
+                buffer.push(entry.clone());
+            }
+            let _ = app.emit(event_name, entry);
+        };
+        let mut buf = [0u8; 4096];
+        let mut pending: Vec<u8> = Vec::new();
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => n,
+            };
+            pending.extend_from_slice(&buf[..n]);
+            while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+                let mut line: Vec<u8> = pending.drain(..=pos).collect();
+                line.pop();
+                if line.last() == Some(&b'\r') {
+                    line.pop();
+                }
+                record(String::from_utf8_lossy(&line).into_owned());
+            }
+            if !pending.is_empty() {
+                on_chunk(LogChunk::Partial(&String::from_utf8_lossy(&pending)));
+            }
+        }
+        if !pending.is_empty() {
+            record(String::from_utf8_lossy(&pending).into_owned());
+        }
+    });
+}
@@ -6,8 +6,9 @@
 /// 2. 若未安装但 resources/engines/vllm/ 下有 .whl 文件，自动 pip install
 /// 3. 通过 python -m vllm.entrypoints.openai.api_server 启动 OpenAI 兼容服务
 
-use crate::core::state::LocalEngineState;
-use crate::plugins::engine::LocalEnginePlugin;
+use crate::core::state::{HttpClientState, LocalEngineState};
+use crate::plugins::engine::options::DEFAULT_CTX_SIZE;
+use crate::plugins::engine::{detached, process_tree, LocalEnginePlugin, LocalServerOptions};
 use std::io::{BufRead, BufReader};
 use std::path::{Path, PathBuf};
 use tauri::path::BaseDirectory;
@@ -129,6 +130,7 @@ impl LocalEnginePlugin for VllmPlugin {
         _model_path: &str,
         _port: u16,
         _gpu_layers: i32,
+        _options: &LocalServerOptions,
     ) -> std::process::Command {
         std::process::Command::new("python")
     }
@@ -154,6 +156,7 @@ impl LocalEnginePlugin for VllmPlugin {
         model_path: &'a str,
         port: u16,
         gpu_layers: i32,
+        options: &'a LocalServerOptions,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = Result<String, String>> + Send + 'a>> {
         Box::pin(async move {
             debug!(
@@ -164,6 +167,9 @@ impl LocalEnginePlugin for VllmPlugin {
             if !Path::new(model_path).exists() {
                 return Err(format!("模型文件/目录不存在: {}", model_path));
             }
+            if !options.lora_adapters.is_empty() {
+                return Err("vLLM 引擎暂不支持加载 GGUF LoRA 适配器，请改用 llama.cpp".to_string());
+            }
 
             let _ = app.emit(self.progress_event_name(), 0.02);
 
@@ -198,6 +204,8 @@ impl LocalEnginePlugin for VllmPlugin {
 
             let _ = app.emit(self.progress_event_name(), 0.15);
 
+            let ctx_size = options.ctx_size.unwrap_or(DEFAULT_CTX_SIZE);
+
             let mut cmd = create_progress_cmd(
                 &python,
                 &[
@@ -212,10 +220,11 @@ impl LocalEnginePlugin for VllmPlugin {
                     "--dtype",
                     "auto",
                     "--max-model-len",
-                    "4096",
+                    &ctx_size.to_string(),
                     "--trust-remote-code",
                 ],
             );
+            let output_detached = detached::redirect_output_if_detachable(&mut cmd);
 
             let mut child = cmd.spawn().map_err(|e| {
                 format!(
@@ -223,39 +232,41 @@ impl LocalEnginePlugin for VllmPlugin {
                     e
                 )
             })?;
+            if !output_detached {
+                process_tree::bind_to_app_lifetime(&child);
+            }
 
             let _ = app.emit(self.progress_event_name(), 0.2);
 
-            let stderr = match child.stderr.take() {
-                Some(s) => s,
-                None => return Err("无法获取 vLLM 子进程 stderr".to_string()),
-            };
-            let app_clone = app.clone();
-            let event_name = self.progress_event_name().to_string();
-            task::spawn_blocking(move || {
-                let reader = BufReader::new(stderr);
-                for line in reader.lines() {
-                    if let Ok(line) = line {
-                        debug!("[vllm-server] {}", line);
-
-                        let progress = if line.contains("Loading model weights") {
-                            Some(0.3)
-                        } else if line.contains("Model loaded") || line.contains("model loaded") {
-                            Some(0.6)
-                        } else if line.contains("Uvicorn running on") {
-                            Some(0.8)
-                        } else if line.contains("Application startup complete") {
-                            Some(1.0)
-                        } else {
-                            None
-                        };
-
-                        if let Some(p) = progress {
-                            let _ = app_clone.emit(&event_name, p);
+            // 输出改写到日志文件时没有 stderr 管道，跳过进度解析
+            if let Some(stderr) = child.stderr.take() {
+                let app_clone = app.clone();
+                let event_name = self.progress_event_name().to_string();
+                task::spawn_blocking(move || {
+                    let reader = BufReader::new(stderr);
+                    for line in reader.lines() {
+                        if let Ok(line) = line {
+                            debug!("[vllm-server] {}", line);
+
+                            let progress = if line.contains("Loading model weights") {
+                                Some(0.3)
+                            } else if line.contains("Model loaded") || line.contains("model loaded") {
+                                Some(0.6)
+                            } else if line.contains("Uvicorn running on") {
+                                Some(0.8)
+                            } else if line.contains("Application startup complete") {
+                                Some(1.0)
+                            } else {
+                                None
+                            };
+
+                            if let Some(p) = progress {
+                                let _ = app_clone.emit(&event_name, p);
+                            }
                         }
                     }
-                }
-            });
+                });
+            }
 
             sleep(Duration::from_secs(5)).await;
             match child.try_wait() {
@@ -266,15 +277,12 @@ impl LocalEnginePlugin for VllmPlugin {
                 Err(e) => return Err(format!("无法检查进程状态: {}", e)),
             }
 
-            let client = reqwest::Client::builder()
-                .timeout(Duration::from_secs(15))
-                .connect_timeout(Duration::from_secs(3))
-                .build()
-                .unwrap_or_else(|_| reqwest::Client::new());
+            let client = app.state::<HttpClientState>().client();
             let health_url = format!("http://127.0.0.1:{}/health", port);
 
             match client
                 .get(&health_url)
+                .timeout(Duration::from_secs(15))
                 .send()
                 .await
             {
@@ -282,7 +290,7 @@ impl LocalEnginePlugin for VllmPlugin {
                     let _ = app.emit(self.progress_event_name(), 1.0);
                 }
                 Err(_) => {
-                    let _ = child.kill();
+                    process_tree::kill_child(&mut child);
                     return Err("vLLM 服务未响应健康检查，可能启动失败。\n请检查：1) CUDA 工具链是否正确安装 2) 显存是否充足 3) 模型路径是否有效".to_string());
                 }
             }
@@ -290,6 +298,15 @@ impl LocalEnginePlugin for VllmPlugin {
             let mut inner = state.lock();
             inner.engine_type = self.identifier().to_string();
             inner.child_process = Some(child);
+            inner.port = Some(port);
+            inner.output_detached = output_detached;
+            inner.supports_images = false;
+            inner.api_key = None;
+            inner.ctx_size = Some(ctx_size);
+            inner.cache_type_k = None;
+            inner.cache_type_v = None;
+            inner.lora_adapters.clear();
+            inner.draft_model_path = None;
 
             Ok(format!("http://127.0.0.1:{}/v1", port))
         })
@@ -17,18 +17,14 @@ use std::collections::BTreeMap;
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tauri::AppHandle;
+use tauri::{AppHandle, Manager};
+
+use crate::core::state::HttpClientState;
 
 pub struct HttpPlugin;
 
-/// 构造带超时的 reqwest 客户端
-fn http_client() -> reqwest::Client {
-    reqwest::Client::builder()
-        .connect_timeout(Duration::from_secs(5))
-        .timeout(Duration::from_secs(60))
-        .build()
-        .unwrap_or_else(|_| reqwest::Client::new())
-}
+/// 单次 JSON-RPC 请求（含读取 SSE 响应）的超时
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
 
 /// HTTP transport 内部状态：url + headers + id 计数器
 #[derive(Clone)]
@@ -91,7 +87,7 @@ impl super::McpServerPlugin for HttpPlugin {
         let state = HttpTransportState {
             base_url: url,
             headers: Arc::new(headers),
-            client: http_client(),
+            client: app.state::<HttpClientState>().client(),
             next_id: Arc::new(AtomicU64::new(1)),
             closed: Arc::new(AtomicBool::new(false)),
         };
@@ -251,6 +247,7 @@ async fn post_json(state: &HttpTransportState, body: &Value) -> McpResult<Value>
     let mut req = state
         .client
         .post(&url)
+        .timeout(REQUEST_TIMEOUT)
         .header("Content-Type", "application/json")
         .header("Accept", "application/json, text/event-stream")
         .json(body);
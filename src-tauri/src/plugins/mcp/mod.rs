@@ -11,13 +11,14 @@ pub mod http;
 pub mod stdio;
 
 use crate::core::models::*;
+use crate::core::paths;
 use async_trait::async_trait;
 use serde_json::Value;
 use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
-use tauri::{AppHandle, Manager};
+use tauri::AppHandle;
 
 pub use connection::McpConnection;
 pub use error::{McpError, McpResult};
@@ -113,7 +114,7 @@ impl McpServerManager {
 const MCP_FILE: &str = "mcp-servers.json";
 
 fn mcp_file_path(app: &AppHandle) -> Option<PathBuf> {
-    app.path().app_data_dir().ok().map(|d| d.join(MCP_FILE))
+    paths::app_data_root(app).ok().map(|d| d.join(MCP_FILE))
 }
 
 pub fn load_mcp_servers(app: &AppHandle) -> McpServersFile {
@@ -0,0 +1,214 @@
+//! 长文档分块
+//!
+//! 为 RAG 检索与上下文较小的模型，把提取出的文本切成大小相近、前后重叠的块：
+//! - 优先在段落（空行）处切分，段落过长时按句子切分，句子仍过长时按字符硬切
+//! - 块大小与重叠量按 [`tokens::estimate_text_tokens`](crate::utils::tokens::estimate_text_tokens)
+//!   估算的 token 数计算
+//! - 每块附带在原文中的字符偏移（按 char 计，左闭右开），便于回溯引用位置
+
+use crate::utils::tokens::{estimate_text_tokens, TokenCounter};
+use serde::Serialize;
+use std::ops::Range;
+
+/// 一个文本块
+#[derive(Serialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TextChunk {
+    pub index: usize,
+    pub text: String,
+    /// 在原文中的起始字符偏移
+    pub start: usize,
+    /// 在原文中的结束字符偏移（不含）
+    pub end: usize,
+    /// 估算的 token 数
+    pub token_count: usize,
+}
+
+/// 按段落切分：每段包含其后的空行，各段首尾相接覆盖全文
+fn paragraphs(text: &str) -> Vec<Range<usize>> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    let mut pos = 0;
+    let mut after_blank = false;
+    for line in text.split_inclusive('\n') {
+        let blank = line.trim().is_empty();
+        if !blank && after_blank && pos > start {
+            pieces.push(start..pos);
+            start = pos;
+        }
+        after_blank = blank;
+        pos += line.len();
+    }
+    if start < text.len() {
+        pieces.push(start..text.len());
+    }
+    pieces
+}
+
+/// 按句子切分：句末标点（或换行）及其后的空白归入前一句
+fn sentences(text: &str, range: Range<usize>) -> Vec<Range<usize>> {
+    let mut pieces = Vec::new();
+    let mut start = range.start;
+    let mut chars = text[range.clone()].char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|(_, n)| *n);
+        let ends = matches!(c, '。' | '！' | '？' | '；' | '!' | '?' | ';' | '\n')
+            || (c == '.' && next.map_or(true, char::is_whitespace));
+        if !ends {
+            continue;
+        }
+        let mut end = range.start + i + c.len_utf8();
+        while let Some((j, n)) = chars.next_if(|(_, n)| n.is_whitespace()) {
+            end = range.start + j + n.len_utf8();
+        }
+        pieces.push(start..end);
+        start = end;
+    }
+    if start < range.end {
+        pieces.push(start..range.end);
+    }
+    pieces
+}
+
+/// 按字符硬切，每段估算不超过 `max_tokens`
+fn hard_split(text: &str, range: Range<usize>, max_tokens: usize) -> Vec<Range<usize>> {
+    let mut pieces = Vec::new();
+    let mut start = range.start;
+    let mut counter = TokenCounter::default();
+    for (i, c) in text[range.clone()].char_indices() {
+        let next = counter.with(c);
+        if next.tokens() > max_tokens && range.start + i > start {
+            pieces.push(start..range.start + i);
+            start = range.start + i;
+            counter = TokenCounter::default().with(c);
+        } else {
+            counter = next;
+        }
+    }
+    if start < range.end {
+        pieces.push(start..range.end);
+    }
+    pieces
+}
+
+/// 把全文拆成估算不超过 `max_tokens` 的最小单元：段落 → 句子 → 字符
+fn units(text: &str, max_tokens: usize) -> Vec<(Range<usize>, usize)> {
+    let mut out = Vec::new();
+    for paragraph in paragraphs(text) {
+        let tokens = estimate_text_tokens(&text[paragraph.clone()]);
+        if tokens <= max_tokens {
+            out.push((paragraph, tokens));
+            continue;
+        }
+        for sentence in sentences(text, paragraph) {
+            let tokens = estimate_text_tokens(&text[sentence.clone()]);
+            if tokens <= max_tokens {
+                out.push((sentence, tokens));
+                continue;
+            }
+            for piece in hard_split(text, sentence, max_tokens) {
+                let tokens = estimate_text_tokens(&text[piece.clone()]);
+                out.push((piece, tokens));
+            }
+        }
+    }
+    out
+}
+
+/// 字节偏移 → 字符偏移（偏移基本单调递增，增量计数）
+struct CharCursor {
+    byte: usize,
+    chars: usize,
+}
+
+impl CharCursor {
+    fn char_offset(&mut self, text: &str, byte: usize) -> usize {
+        if byte < self.byte {
+            self.byte = 0;
+            self.chars = 0;
+        }
+        self.chars += text[self.byte..byte].chars().count();
+        self.byte = byte;
+        self.chars
+    }
+}
+
+/// 把文本切成估算不超过 `chunk_tokens` 的块，相邻块重叠约 `overlap_tokens`
+/// （重叠按完整的段落 / 句子计，最多为块大小的一半）
+pub fn chunk_text(text: &str, chunk_tokens: usize, overlap_tokens: usize) -> Vec<TextChunk> {
+    let max_tokens = chunk_tokens.max(1);
+    let overlap_tokens = overlap_tokens.min(max_tokens / 2);
+    let units = units(text, max_tokens);
+    let mut cursor = CharCursor { byte: 0, chars: 0 };
+    let mut chunks = Vec::new();
+
+    let mut first = 0;
+    while first < units.len() {
+        let mut last = first;
+        let mut total = units[first].1;
+        while last + 1 < units.len() && total + units[last + 1].1 <= max_tokens {
+            last += 1;
+            total += units[last].1;
+        }
+
+        let raw = &text[units[first].0.start..units[last].0.end];
+        let trimmed = raw.trim();
+        if !trimmed.is_empty() {
+            let start_byte = units[first].0.start + (raw.len() - raw.trim_start().len());
+            let start = cursor.char_offset(text, start_byte);
+            chunks.push(TextChunk {
+                index: chunks.len(),
+                text: trimmed.to_string(),
+                start,
+                end: start + trimmed.chars().count(),
+                token_count: estimate_text_tokens(trimmed),
+            });
+        }
+        if last + 1 >= units.len() {
+            break;
+        }
+
+        // 下一块从本块末尾往回若干单元开始，形成重叠；至少前进一个单元
+        let mut next = last + 1;
+        let mut overlap = 0;
+        while next > first + 1 && overlap + units[next - 1].1 <= overlap_tokens {
+            next -= 1;
+            overlap += units[next].1;
+        }
+        first = next;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_on_paragraphs_with_overlap_and_offsets() {
+        let text = "甲一二三。乙一二三。丙一二三。丁一二三。戊一二三。\n\n短段落。\n";
+        let chunks = chunk_text(text, 12, 5);
+        let texts: Vec<&str> = chunks.iter().map(|c| c.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "甲一二三。乙一二三。",
+                "乙一二三。丙一二三。",
+                "丙一二三。丁一二三。",
+                "丁一二三。戊一二三。",
+                "戊一二三。\n\n短段落。",
+            ]
+        );
+        let chars: Vec<char> = text.chars().collect();
+        for chunk in &chunks {
+            let slice: String = chars[chunk.start..chunk.end].iter().collect();
+            assert_eq!(slice, chunk.text);
+            assert!(chunk.token_count <= 12);
+        }
+
+        assert_eq!(chunk_text(" \n\n ", 100, 10), vec![]);
+        let long = chunk_text(&"word ".repeat(100), 10, 0);
+        assert!(long.len() >= 12);
+        assert!(long.iter().all(|c| c.token_count <= 10));
+    }
+}
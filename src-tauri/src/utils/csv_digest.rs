@@ -0,0 +1,255 @@
+//! CSV / TSV 附件摘要
+//!
+//! 大表格原样放进提示词会撑爆上下文。这里自动识别分隔符（逗号 / 分号 / 制表符），
+//! 生成一份 Markdown 摘要：列名与行数、前 [`HEAD_ROWS`] 行与后 [`TAIL_ROWS`] 行表格，
+//! 以及每列的不同值个数与数值列的最小 / 最大值。
+//!
+//! 不超过 [`FULL_CONTENT_MAX_BYTES`] 的小文件可以按调用方要求返回原文。
+
+use std::collections::HashSet;
+
+/// 摘要中保留的开头行数（不含表头）
+const HEAD_ROWS: usize = 20;
+/// 摘要中保留的结尾行数
+const TAIL_ROWS: usize = 5;
+/// 允许返回原文的文件大小上限
+pub const FULL_CONTENT_MAX_BYTES: usize = 32 * 1024;
+/// 表格单元格显示的最大字符数
+const MAX_CELL_CHARS: usize = 80;
+/// 识别分隔符时检查的行数
+const SNIFF_LINES: usize = 20;
+
+const DELIMITERS: [(char, &str); 3] = [(',', "逗号"), (';', "分号"), ('\t', "制表符")];
+
+/// 统计一行中引号外的分隔符个数
+fn count_outside_quotes(line: &str, delimiter: char) -> usize {
+    let mut in_quotes = false;
+    line.chars()
+        .filter(|c| {
+            if *c == '"' {
+                in_quotes = !in_quotes;
+            }
+            !in_quotes && *c == delimiter
+        })
+        .count()
+}
+
+/// 根据开头若干行识别分隔符：优先每行个数一致且不为 0 的候选，其次总数最多的，默认逗号
+pub fn sniff_delimiter(text: &str) -> char {
+    let lines: Vec<&str> = text
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .take(SNIFF_LINES)
+        .collect();
+    DELIMITERS
+        .iter()
+        .map(|(delimiter, _)| {
+            let counts: Vec<usize> = lines
+                .iter()
+                .map(|l| count_outside_quotes(l, *delimiter))
+                .collect();
+            let min = counts.iter().copied().min().unwrap_or(0);
+            let consistent = min > 0 && counts.iter().all(|c| *c == min);
+            (*delimiter, consistent, counts.iter().sum::<usize>())
+        })
+        .filter(|(_, _, total)| *total > 0)
+        .max_by_key(|(_, consistent, total)| (*consistent, *total))
+        .map_or(',', |(delimiter, _, _)| delimiter)
+}
+
+/// 按 RFC 4180 解析记录：支持引号包裹的字段、`""` 转义与字段内换行，跳过空行
+pub fn parse_rows(text: &str, delimiter: char) -> Vec<Vec<String>> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    field.push('"');
+                    chars.next();
+                }
+                '"' => in_quotes = false,
+                _ => field.push(c),
+            }
+        } else if c == '"' && field.is_empty() {
+            in_quotes = true;
+        } else if c == delimiter {
+            row.push(std::mem::take(&mut field));
+        } else if c == '\n' {
+            row.push(std::mem::take(&mut field));
+            if !(row.len() == 1 && row[0].trim().is_empty()) {
+                rows.push(std::mem::take(&mut row));
+            } else {
+                row.clear();
+            }
+        } else if c != '\r' {
+            field.push(c);
+        }
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    rows
+}
+
+/// 单元格转为可放进 Markdown 表格的文本：去换行、转义竖线、截断过长内容
+fn cell(value: &str) -> String {
+    let flat = value.replace(['\r', '\n'], " ").replace('|', "\\|");
+    if flat.chars().count() > MAX_CELL_CHARS {
+        let truncated: String = flat.chars().take(MAX_CELL_CHARS).collect();
+        format!("{}…", truncated)
+    } else {
+        flat
+    }
+}
+
+/// 渲染一组行为 Markdown 表格（行宽按表头补齐 / 截断）
+fn markdown_table(header: &[String], rows: &[Vec<String>]) -> String {
+    let mut out = format!(
+        "| {} |\n|{}\n",
+        header
+            .iter()
+            .map(|h| cell(h))
+            .collect::<Vec<_>>()
+            .join(" | "),
+        " --- |".repeat(header.len())
+    );
+    for row in rows {
+        let cells: Vec<String> = (0..header.len())
+            .map(|i| row.get(i).map(|v| cell(v)).unwrap_or_default())
+            .collect();
+        out.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    out
+}
+
+/// 数值显示：整数不带小数点
+fn format_number(n: f64) -> String {
+    if n.fract() == 0.0 && n.abs() < 1e15 {
+        format!("{}", n as i64)
+    } else {
+        format!("{}", n)
+    }
+}
+
+/// 每列的统计行：不同值个数；非空值全部可解析为数字时给出最小 / 最大值
+fn column_stats(header: &[String], rows: &[Vec<String>]) -> String {
+    let mut out = String::from("| 列 | 不同值数 | 最小值 | 最大值 |\n| --- | --- | --- | --- |\n");
+    for (i, name) in header.iter().enumerate() {
+        let values: Vec<&str> = rows
+            .iter()
+            .filter_map(|r| r.get(i))
+            .map(|v| v.trim())
+            .filter(|v| !v.is_empty())
+            .collect();
+        let distinct = values.iter().collect::<HashSet<_>>().len();
+        let numbers: Option<Vec<f64>> = values.iter().map(|v| v.parse::<f64>().ok()).collect();
+        let (min, max) = match numbers.filter(|n| !n.is_empty()) {
+            Some(n) => (
+                format_number(n.iter().copied().fold(f64::INFINITY, f64::min)),
+                format_number(n.iter().copied().fold(f64::NEG_INFINITY, f64::max)),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        out.push_str(&format!(
+            "| {} | {} | {} | {} |\n",
+            cell(name),
+            distinct,
+            min,
+            max
+        ));
+    }
+    out
+}
+
+/// 生成表格摘要（首行视为表头）
+pub fn digest(text: &str) -> String {
+    let delimiter = sniff_delimiter(text);
+    let delimiter_name = DELIMITERS
+        .iter()
+        .find(|(d, _)| *d == delimiter)
+        .map_or("逗号", |(_, name)| name);
+    let mut rows = parse_rows(text, delimiter);
+    if rows.is_empty() {
+        return "[表格摘要] 文件为空".to_string();
+    }
+    let header = rows.remove(0);
+    let mut out = format!(
+        "[表格摘要] 分隔符: {} | 行数: {}（不含表头） | 列数: {}\n列: {}\n\n",
+        delimiter_name,
+        rows.len(),
+        header.len(),
+        header.join(", ")
+    );
+    if rows.len() <= HEAD_ROWS + TAIL_ROWS {
+        out.push_str(&markdown_table(&header, &rows));
+    } else {
+        out.push_str(&format!("前 {} 行:\n", HEAD_ROWS));
+        out.push_str(&markdown_table(&header, &rows[..HEAD_ROWS]));
+        out.push_str(&format!(
+            "\n…（省略 {} 行）…\n\n后 {} 行:\n",
+            rows.len() - HEAD_ROWS - TAIL_ROWS,
+            TAIL_ROWS
+        ));
+        out.push_str(&markdown_table(&header, &rows[rows.len() - TAIL_ROWS..]));
+    }
+    out.push_str("\n列统计:\n");
+    out.push_str(&column_stats(&header, &rows));
+    out
+}
+
+/// 表格附件发给模型的内容：`full` 为 true 且文件足够小时返回原文，否则返回摘要
+pub fn csv_content(text: String, full: bool) -> String {
+    if full && text.len() <= FULL_CONTENT_MAX_BYTES {
+        text
+    } else {
+        digest(&text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_delimiter_and_parses_quoted_fields() {
+        assert_eq!(sniff_delimiter("a;b;c\n1;2,5;3\n"), ';');
+        assert_eq!(sniff_delimiter("a\tb\n1\t2\n"), '\t');
+        assert_eq!(sniff_delimiter("name,note\n\"x\",\"a;b\"\n"), ',');
+
+        let rows = parse_rows(
+            "a,b\r\n\"x, y\",\"he said \"\"hi\"\"\"\n\n1,\"two\nlines\"",
+            ',',
+        );
+        assert_eq!(
+            rows,
+            vec![
+                vec!["a", "b"],
+                vec!["x, y", "he said \"hi\""],
+                vec!["1", "two\nlines"],
+            ]
+        );
+    }
+
+    #[test]
+    fn digests_large_tables() {
+        let mut text = String::from("id,city\n");
+        for i in 1..=100 {
+            text.push_str(&format!("{},{}\n", i, if i % 2 == 0 { "A" } else { "B|C" }));
+        }
+        let out = digest(&text);
+        assert!(out.starts_with("[表格摘要] 分隔符: 逗号 | 行数: 100（不含表头） | 列数: 2"));
+        assert!(out.contains("| 20 | A |"));
+        assert!(!out.contains("| 21 | B\\|C |"));
+        assert!(out.contains("…（省略 75 行）…"));
+        assert!(out.contains("| 100 | A |"));
+        assert!(out.contains("| id | 100 | 1 | 100 |"));
+        assert!(out.contains("| city | 2 | - | - |"));
+
+        assert_eq!(csv_content("a,b\n1,2\n".into(), true), "a,b\n1,2\n");
+    }
+}
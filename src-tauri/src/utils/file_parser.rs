@@ -4,13 +4,35 @@
 /// - `process_file_content` 接受路径仅当满足：扩展名白名单 + 父目录在用户 home 或 AppData 内
 /// - `start_local_server` 接受的 `model_path` 仅允许用户 home 或 AppData/engines 内的文件
 /// - 限制文件大小（图片 10MB / 文档 30MB）防止 OOM DoS
+///
+/// CSV / TSV 默认返回表格摘要（见 [`csv_digest`]），而不是原文；HTML 返回提取的正文（见 [`html_text`]）。
+///
+/// 提取出的文本超过「附件提取上限」时保留开头与结尾、省略中间（见 [`text_limit`]）。
 
+use crate::commands::config::max_extracted_kb;
+use crate::core::state::FileJobManager;
+use crate::utils::chunking::{self, TextChunk};
+use crate::utils::{csv_digest, html_text, text_encoding, text_limit};
 use base64::{engine::general_purpose, Engine as _};
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::Read;
 use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter};
 use zip::ZipArchive;
 
+/// 文件解析被 `cancel_file_processing` 取消时返回的错误
+pub const FILE_PROCESSING_CANCELLED: &str = "文件处理已取消";
+
+/// `process_files` 每完成一个文件发出的进度事件
+pub const FILES_PROGRESS_EVENT: &str = "file-processing-progress";
+/// 带 `job_id` 的 PDF 解析每完成一页发出的进度事件（载荷见 [`PdfPageProgress`]）
+pub const PDF_PAGE_PROGRESS_EVENT: &str = "pdf-page-progress";
+/// `process_files` 同时解析的文件数上限
+const MAX_PARALLEL_FILES: usize = 4;
+
 /// 文件大小上限
 const MAX_IMAGE_BYTES: u64 = 10 * 1024 * 1024;
 const MAX_DOC_BYTES: u64 = 30 * 1024 * 1024;
@@ -18,7 +40,7 @@ const MAX_TEXT_BYTES: u64 = 5 * 1024 * 1024;
 
 /// 校验路径在沙箱内
 /// 允许的根：用户 home、AppData/config、AppData、临时目录
-fn path_in_sandbox(path: &Path) -> Result<(), String> {
+pub(crate) fn path_in_sandbox(path: &Path) -> Result<(), String> {
     // 必须为绝对路径且无 ParentDir 段
     if !path.is_absolute() {
         return Err("路径必须为绝对路径".into());
@@ -42,6 +64,10 @@ fn path_in_sandbox(path: &Path) -> Result<(), String> {
     if let Some(config) = dirs::config_dir() {
         allowed_roots.push(config);
     }
+    // 自定义数据目录（便携模式 / 同步盘）
+    if let Some(data_dir) = crate::core::paths::data_dir_override() {
+        allowed_roots.push(data_dir);
+    }
     if let Ok(xdg_data) = std::env::var("XDG_DATA_HOME") {
         allowed_roots.push(PathBuf::from(xdg_data));
     }
@@ -100,6 +126,7 @@ pub fn attachment_mime_type(extension: &str) -> &'static str {
         "pptx" => "application/vnd.openxmlformats-officedocument.presentationml.presentation",
         "txt" | "log" | "ini" => "text/plain",
         "md" => "text/markdown",
+        "html" | "htm" => "text/html",
         "json" => "application/json",
         "csv" => "text/csv",
         "xml" => "application/xml",
@@ -117,7 +144,7 @@ pub fn validate_attachment_path(path: &str) -> Result<PathBuf, String> {
         &path,
         &[
             "png", "jpg", "jpeg", "webp", "pdf", "docx", "pptx", "txt", "md", "json",
-            "csv", "log", "xml", "yaml", "yml", "ini", "tsv",
+            "csv", "log", "xml", "yaml", "yml", "ini", "tsv", "html", "htm",
         ],
     )?;
     let max = if ["png", "jpg", "jpeg", "webp"].contains(&extension.as_str()) {
@@ -141,13 +168,22 @@ pub fn extract_file_content(path: &Path, extension: &str) -> Result<Option<Strin
         "docx" | "pptx" => read_office_file(
             path.to_str().ok_or_else(|| "文件路径不是有效 UTF-8".to_string())?,
             extension,
+            None,
         )
         .map(Some),
-        "txt" | "md" | "json" | "csv" | "log" | "xml" | "yaml" | "yml" | "ini"
-        | "tsv" => {
+        "csv" | "tsv" => {
             let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
-            let (res, _, _) = encoding_rs::UTF_8.decode(&bytes);
-            Ok(Some(res.into_owned()))
+            let (text, _) = text_encoding::decode_text(&bytes);
+            Ok(Some(csv_digest::digest(&text)))
+        }
+        "html" | "htm" => {
+            let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+            let (text, _) = text_encoding::decode_text(&bytes);
+            Ok(Some(html_text::html_to_text(&text)))
+        }
+        "txt" | "md" | "json" | "log" | "xml" | "yaml" | "yml" | "ini" => {
+            let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+            Ok(Some(text_encoding::decode_text(&bytes).0))
         }
         _ => Err(format!("不支持的附件扩展名: {}", extension)),
     }
@@ -176,13 +212,78 @@ pub fn extract_text_from_xml(xml: &str) -> String {
     out
 }
 
-/// 读取并解析 OpenXML 格式（docx/pptx）的文件内容。
-pub fn read_office_file(path: &str, file_type: &str) -> Result<String, String> {
+/// 逐页解析的进度回调：(已完成页数, 总页数)
+pub type PageCallback = Box<dyn Fn(usize, usize) + Send>;
+
+/// 已请求取消时返回 [`FILE_PROCESSING_CANCELLED`]
+fn check_cancelled(cancel: Option<&AtomicBool>) -> Result<(), String> {
+    if cancel.is_some_and(|flag| flag.load(Ordering::Relaxed)) {
+        return Err(FILE_PROCESSING_CANCELLED.to_string());
+    }
+    Ok(())
+}
+
+/// 逐页提取 PDF 文本，每页之间检查取消标志，每完成一页调用 `on_page`。
+/// 单页解析失败时跳过该页并记入警告，全部页面都失败才返回错误
+fn extract_pdf_text(
+    path: &str,
+    cancel: Option<&AtomicBool>,
+    on_page: Option<&PageCallback>,
+) -> Result<Extraction, String> {
+    let mut doc = pdf_extract::Document::load(path).map_err(|e| format!("PDF解析失败: {}", e))?;
+    if doc.is_encrypted() {
+        doc.decrypt("").map_err(|e| format!("PDF解析失败: {}", e))?;
+    }
+    let pages: Vec<u32> = doc.get_pages().into_keys().collect();
+    let mut text = String::new();
+    let mut warnings = Vec::new();
+    {
+        let mut output = pdf_extract::PlainTextOutput::new(&mut text);
+        for (done, page) in pages.iter().enumerate() {
+            check_cancelled(cancel)?;
+            if let Err(e) = pdf_extract::output_doc_page(&doc, &mut output, *page) {
+                warnings.push(format!("第 {} 页解析失败: {}", page, e));
+            }
+            if let Some(on_page) = on_page {
+                on_page(done + 1, pages.len());
+            }
+        }
+    }
+    if !pages.is_empty() && warnings.len() == pages.len() {
+        return Err(format!("PDF解析失败: {}", warnings.join("; ")));
+    }
+    if text.trim().is_empty() {
+        warnings.push("未提取到文本，可能是扫描版 PDF（需要 OCR）".to_string());
+    }
+    Ok(Extraction {
+        page_count: Some(pages.len()),
+        warnings,
+        ..Extraction::new(ExtractionBranch::Pdf, text)
+    })
+}
+
+/// 读取并解析 OpenXML 格式（docx/pptx）的文件内容，每个 XML 部件之间检查取消标志。
+pub fn read_office_file(
+    path: &str,
+    file_type: &str,
+    cancel: Option<&AtomicBool>,
+) -> Result<String, String> {
+    read_office_parts(path, file_type, cancel).map(|(text, _)| text)
+}
+
+/// 同 [`read_office_file`]，额外返回解析的部件数（pptx 为幻灯片数）
+fn read_office_parts(
+    path: &str,
+    file_type: &str,
+    cancel: Option<&AtomicBool>,
+) -> Result<(String, usize), String> {
     let file = File::open(path).map_err(|e| e.to_string())?;
     let mut archive = ZipArchive::new(file).map_err(|e| e.to_string())?;
     let mut full_text = String::new();
+    let mut parts = 0;
 
     for i in 0..archive.len() {
+        check_cancelled(cancel)?;
         let mut file = archive.by_index(i).map_err(|e| e.to_string())?;
         let name = file.name().to_string();
 
@@ -197,61 +298,623 @@ pub fn read_office_file(path: &str, file_type: &str) -> Result<String, String> {
             file.read_to_string(&mut content).map_err(|e| e.to_string())?;
             full_text.push_str(&extract_text_from_xml(&content));
             full_text.push('\n');
+            parts += 1;
         }
     }
-    Ok(full_text)
+    Ok((full_text, parts))
 }
 
-/// 处理各种格式的文件内容（H8 路径沙箱加固）
-///
-/// 图像 (png/jpg/webp): 返回 Base64 DataURI。
-/// PDF: 返回提取内容文本。
-/// Office (docx/pptx): 返回提取内容文本。
-/// 其他: 尝试按 UTF-8 编码读取为纯文本。
-#[tauri::command]
-pub async fn process_file_content(path: String) -> Result<String, String> {
-    let path_obj = Path::new(&path);
+/// 文件内容提取所走的解析分支（用于诊断提取质量问题）
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtractionBranch {
+    Image,
+    Pdf,
+    Office,
+    Csv,
+    Html,
+    Text,
+}
 
-    // 沙箱校验
-    if let Err(e) = path_in_sandbox(path_obj) {
-        return Err(format!("文件路径沙箱拒绝: {}", e));
+/// 附件提取预览：仅返回前 [`PREVIEW_CHARS`] 个字符与诊断信息，不发送给 LLM
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ExtractionPreview {
+    /// 根据扩展名识别的 MIME 类型
+    pub mime_type: String,
+    /// 实际使用的解析分支
+    pub branch: ExtractionBranch,
+    /// 文本类文件识别出的编码（如 UTF-8、GBK、Shift_JIS），其他分支为 None
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// 提取文本的前 ~2000 个字符（图片为空）
+    pub preview: String,
+    /// 提取文本的总字符数（按 char 计，而非字节）
+    pub total_chars: usize,
+    /// 预览是否被截断
+    pub truncated: bool,
+}
+
+/// 预览截断长度（字符）
+const PREVIEW_CHARS: usize = 2000;
+
+/// 附件内容的大类
+#[derive(Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FileKind {
+    Text,
+    Image,
+    Document,
+}
+
+impl From<ExtractionBranch> for FileKind {
+    fn from(branch: ExtractionBranch) -> Self {
+        match branch {
+            ExtractionBranch::Image => FileKind::Image,
+            ExtractionBranch::Pdf | ExtractionBranch::Office => FileKind::Document,
+            ExtractionBranch::Csv | ExtractionBranch::Html | ExtractionBranch::Text => {
+                FileKind::Text
+            }
+        }
+    }
+}
+
+/// `process_file_content_v2` 的结构化提取结果
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileExtraction {
+    pub kind: FileKind,
+    /// 实际使用的解析分支
+    pub branch: ExtractionBranch,
+    /// 根据扩展名识别的 MIME 类型
+    pub mime_type: String,
+    /// 提取出的文本；图片为 Base64 DataURI
+    pub content: String,
+    /// 原文件大小（字节）
+    pub byte_size: u64,
+    /// 提取文本的字符数（按 char 计，图片为 0）
+    pub char_count: usize,
+    /// 内容是否不完整（如表格只返回了摘要、超过提取上限省略了中间部分）
+    pub truncated: bool,
+    /// PDF 页数 / PPTX 幻灯片数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page_count: Option<usize>,
+    /// 文本类文件识别出的编码
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    /// 提取过程中的非致命问题（跳过的页面、疑似乱码等）
+    pub warnings: Vec<String>,
+}
+
+/// 一次提取的结果
+struct Extraction {
+    branch: ExtractionBranch,
+    content: String,
+    /// 文本类分支识别出的编码名称
+    encoding: Option<&'static str>,
+    truncated: bool,
+    page_count: Option<usize>,
+    warnings: Vec<String>,
+}
+
+impl Extraction {
+    fn new(branch: ExtractionBranch, content: String) -> Self {
+        Self {
+            branch,
+            content,
+            encoding: None,
+            truncated: false,
+            page_count: None,
+            warnings: Vec::new(),
+        }
     }
 
-    let extension = path_obj
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("")
-        .to_lowercase();
+    /// 文本类分支的结果：记录编码，出现替换字符时提示可能乱码
+    fn text(branch: ExtractionBranch, content: String, encoding: &'static str) -> Self {
+        let mut warnings = Vec::new();
+        if content.contains('\u{FFFD}') {
+            warnings.push(format!("按 {} 解码时存在无法识别的字节，部分文字可能乱码", encoding));
+        }
+        Self {
+            encoding: Some(encoding),
+            warnings,
+            ..Self::new(branch, content)
+        }
+    }
+
+    /// 文本超过 `max_bytes` 时截去中间部分，保留开头与结尾
+    fn limited(mut self, max_bytes: Option<usize>) -> Self {
+        let Some(max_bytes) = max_bytes else {
+            return self;
+        };
+        if let Some(content) = text_limit::truncate_middle(&self.content, max_bytes) {
+            self.content = content;
+            self.truncated = true;
+            self.warnings.push(format!(
+                "提取的文本超过 {} KB 上限，已省略中间部分",
+                max_bytes / 1024
+            ));
+        }
+        self
+    }
+
+    /// 记录读取文件时跳过的字节数
+    fn mark_omitted(&mut self, omitted: u64) {
+        if omitted > 0 {
+            self.truncated = true;
+            self.warnings.push(format!(
+                "文件过大，未读取中间约 {} KB，仅保留开头与结尾",
+                omitted.div_ceil(1024)
+            ));
+        }
+    }
+}
+
+/// 读取文本类文件并识别编码，最多从磁盘读取 `max_bytes` 字节；
+/// 文件更大时只读开头与结尾，中间以省略标记连接。返回 (文本, 编码名称, 跳过的字节数)
+fn read_text_file(
+    path: &str,
+    cancel: Option<&AtomicBool>,
+    max_bytes: u64,
+) -> Result<(String, &'static str, u64), String> {
+    let read = text_limit::read_head_tail(Path::new(path), max_bytes).map_err(|e| e.to_string())?;
+    check_cancelled(cancel)?;
+    if read.omitted == 0 {
+        let (text, encoding) = text_encoding::decode_text(&read.head);
+        return Ok((text, encoding, 0));
+    }
 
-    match extension.as_str() {
+    let (mut head, mut tail) = (&read.head[..], &read.tail[..]);
+    let encoding = text_encoding::detect_encoding(head);
+    if encoding == encoding_rs::UTF_8 {
+        // 切分点可能落在多字节字符中间：去掉开头残缺的尾部与结尾残缺的首部
+        if let Err(e) = std::str::from_utf8(head) {
+            if e.error_len().is_none() {
+                head = &head[..e.valid_up_to()];
+            }
+        }
+        while tail.first().is_some_and(|b| b & 0xC0 == 0x80) {
+            tail = &tail[1..];
+        }
+    }
+    let (head, actual, _) = encoding.decode(head);
+    let (tail, _) = actual.decode_without_bom_handling(tail);
+    Ok((
+        text_limit::join_omitted(&head, read.omitted, &tail),
+        actual.name(),
+        read.omitted,
+    ))
+}
+
+/// 按扩展名分派到对应解析分支。
+/// 图片分支返回 Base64 DataURI，表格分支返回摘要（`full_csv` 且文件较小时返回原文），
+/// 其余分支返回提取出的文本；文本类分支附带识别出的编码。
+/// `max_bytes` 为提取文本的上限（None 表示不限制，但仍最多读取 [`text_limit::MAX_READ_BYTES`]）。
+/// `on_page` 仅用于 PDF 分支的逐页进度。
+fn extract_by_branch(
+    path: &str,
+    extension: &str,
+    cancel: Option<&AtomicBool>,
+    full_csv: bool,
+    max_bytes: Option<usize>,
+    on_page: Option<&PageCallback>,
+) -> Result<Extraction, String> {
+    let path_obj = Path::new(path);
+    match extension {
         "png" | "jpg" | "jpeg" | "webp" => {
             check_extension(path_obj, &["png", "jpg", "jpeg", "webp"])?;
             check_size(path_obj, MAX_IMAGE_BYTES)?;
-            let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+            let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
             let b64 = general_purpose::STANDARD.encode(bytes);
-            Ok(format!("data:image/{};base64,{}", extension, b64))
+            Ok(Extraction::new(
+                ExtractionBranch::Image,
+                format!("data:image/{};base64,{}", extension, b64),
+            ))
         }
         "pdf" => {
             check_size(path_obj, MAX_DOC_BYTES)?;
-            pdf_extract::extract_text(&path).map_err(|e| format!("PDF解析失败: {}", e))
+            extract_pdf_text(path, cancel, on_page).map(|e| e.limited(max_bytes))
         }
         "docx" | "pptx" => {
             check_size(path_obj, MAX_DOC_BYTES)?;
-            read_office_file(&path, &extension)
+            let (text, parts) = read_office_parts(path, extension, cancel)?;
+            let mut extraction = Extraction::new(ExtractionBranch::Office, text);
+            if extension == "pptx" {
+                extraction.page_count = Some(parts);
+            }
+            if extraction.content.trim().is_empty() {
+                extraction.warnings.push("文档中未找到文本内容".to_string());
+            }
+            Ok(extraction.limited(max_bytes))
+        }
+        "csv" | "tsv" => {
+            let (text, encoding, omitted) =
+                read_text_file(path, cancel, text_limit::MAX_READ_BYTES)?;
+            let digested = !(full_csv && text.len() <= csv_digest::FULL_CONTENT_MAX_BYTES);
+            let mut extraction = Extraction::text(
+                ExtractionBranch::Csv,
+                csv_digest::csv_content(text, full_csv),
+                encoding,
+            );
+            extraction.truncated = digested;
+            if digested && full_csv {
+                extraction.warnings.push(format!(
+                    "文件超过 {} KB，已返回表格摘要而非原文",
+                    csv_digest::FULL_CONTENT_MAX_BYTES / 1024
+                ));
+            }
+            extraction.mark_omitted(omitted);
+            Ok(extraction)
+        }
+        "html" | "htm" => {
+            let (text, encoding, omitted) =
+                read_text_file(path, cancel, text_limit::MAX_READ_BYTES)?;
+            let mut extraction = Extraction::text(
+                ExtractionBranch::Html,
+                html_text::html_to_text(&text),
+                encoding,
+            )
+            .limited(max_bytes);
+            extraction.mark_omitted(omitted);
+            Ok(extraction)
         }
-        "txt" | "md" | "json" | "csv" | "log" | "xml" | "yaml" | "yml" | "ini" | "tsv" => {
-            check_size(path_obj, MAX_TEXT_BYTES)?;
-            let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
-            let (res, _, _) = encoding_rs::UTF_8.decode(&bytes);
-            Ok(res.into_owned())
+        "txt" | "md" | "json" | "log" | "xml" | "yaml" | "yml" | "ini" => {
+            // 纯文本直接按上限读取开头与结尾，不把整个文件读进内存
+            let read_limit = max_bytes.map_or(text_limit::MAX_READ_BYTES, |max| {
+                (max as u64).min(text_limit::MAX_READ_BYTES)
+            });
+            let (content, encoding, omitted) = read_text_file(path, cancel, read_limit)?;
+            let mut extraction = Extraction::text(ExtractionBranch::Text, content, encoding);
+            if omitted > 0 {
+                extraction.mark_omitted(omitted);
+                Ok(extraction)
+            } else {
+                // 解码后可能比原始字节更长（如 GBK 转 UTF-8）
+                Ok(extraction.limited(max_bytes))
+            }
         }
+        "mp3" | "wav" | "m4a" | "mp4" | "mpeg" | "mpga" | "ogg" | "webm" | "flac" => Err(
+            "音频文件无法直接读取为文本，请使用语音转写（transcribe_audio）".to_string(),
+        ),
         _ => Err(format!(
-            "扩展名 {:?} 不在白名单内（支持 png/jpg/jpeg/webp/pdf/docx/pptx/txt/md/json/csv/log/xml/yaml/ini/tsv）",
+            "扩展名 {:?} 不在白名单内（支持 png/jpg/jpeg/webp/pdf/docx/pptx/txt/md/json/csv/log/xml/yaml/ini/tsv/html/htm）",
             extension
         )),
     }
 }
 
+/// 取路径的小写扩展名
+fn lowercase_extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .unwrap_or("")
+        .to_lowercase()
+}
+
+/// 提取文本的上限（字节）：单次调用传入的 `max_kb` 优先，0 表示不限制；
+/// 未传入时使用「附件提取上限」设置，未配置时为 [`text_limit::DEFAULT_MAX_EXTRACTED_BYTES`]
+pub fn resolve_max_bytes(max_kb: Option<u32>) -> Option<usize> {
+    match max_kb {
+        Some(0) => None,
+        Some(kb) => Some(kb as usize * 1024),
+        None => match max_extracted_kb() {
+            0 => Some(text_limit::DEFAULT_MAX_EXTRACTED_BYTES),
+            kb => Some(kb as usize * 1024),
+        },
+    }
+}
+
+/// 在阻塞线程池中提取文件内容（沙箱校验后按扩展名分支），`cancel` 置位后尽快返回取消错误
+pub async fn extract_content(
+    path: String,
+    cancel: Option<Arc<AtomicBool>>,
+    full_csv: bool,
+    max_bytes: Option<usize>,
+) -> Result<String, String> {
+    extract_structured(path, cancel, full_csv, max_bytes, None)
+        .await
+        .map(|extraction| extraction.content)
+}
+
+/// 同 [`extract_content`]，返回带元数据的结构化结果；`on_page` 接收 PDF 的逐页进度
+pub async fn extract_structured(
+    path: String,
+    cancel: Option<Arc<AtomicBool>>,
+    full_csv: bool,
+    max_bytes: Option<usize>,
+    on_page: Option<PageCallback>,
+) -> Result<FileExtraction, String> {
+    // 沙箱校验
+    if let Err(e) = path_in_sandbox(Path::new(&path)) {
+        return Err(format!("文件路径沙箱拒绝: {}", e));
+    }
+
+    tokio::task::spawn_blocking(move || {
+        extract_file(
+            &path,
+            cancel.as_deref(),
+            full_csv,
+            max_bytes,
+            on_page.as_ref(),
+        )
+    })
+    .await
+    .map_err(|e| e.to_string())?
+}
+
+/// 提取并汇总元数据（阻塞，调用方负责沙箱校验）
+fn extract_file(
+    path: &str,
+    cancel: Option<&AtomicBool>,
+    full_csv: bool,
+    max_bytes: Option<usize>,
+    on_page: Option<&PageCallback>,
+) -> Result<FileExtraction, String> {
+    let extension = lowercase_extension(Path::new(path));
+    let byte_size = std::fs::metadata(path).map_err(|e| e.to_string())?.len();
+    let extraction = extract_by_branch(path, &extension, cancel, full_csv, max_bytes, on_page)?;
+    let char_count = if extraction.branch == ExtractionBranch::Image {
+        0
+    } else {
+        extraction.content.chars().count()
+    };
+    Ok(FileExtraction {
+        kind: extraction.branch.into(),
+        branch: extraction.branch,
+        mime_type: attachment_mime_type(&extension).to_string(),
+        content: extraction.content,
+        byte_size,
+        char_count,
+        truncated: extraction.truncated,
+        page_count: extraction.page_count,
+        encoding: extraction.encoding.map(str::to_string),
+        warnings: extraction.warnings,
+    })
+}
+
+/// 处理各种格式的文件内容（H8 路径沙箱加固）
+///
+/// 图像 (png/jpg/webp): 返回 Base64 DataURI。
+/// PDF: 返回提取内容文本。
+/// Office (docx/pptx): 返回提取内容文本。
+/// CSV / TSV: 返回表格摘要；`full_content` 为 true 且文件不超过 32KB 时返回原文。
+/// 其他: 识别编码（BOM / UTF-16 / UTF-8 / chardetng 猜测）后读取为纯文本。
+///
+/// 文本超过上限时保留开头与结尾、省略中间；`max_kb` 覆盖「附件提取上限」设置（0 表示不限制）。
+///
+/// 传入 `job_id` 时可用 `cancel_file_processing(job_id)` 取消，取消后返回 [`FILE_PROCESSING_CANCELLED`]；
+/// 解析 PDF 时每完成一页发出 [`PDF_PAGE_PROGRESS_EVENT`]。
+///
+/// 仅返回内容字符串；需要类型、大小、截断等信息时使用 `process_file_content_v2`。
+#[tauri::command]
+pub async fn process_file_content(
+    app: AppHandle,
+    jobs: tauri::State<'_, FileJobManager>,
+    path: String,
+    job_id: Option<String>,
+    full_content: Option<bool>,
+    max_kb: Option<u32>,
+) -> Result<String, String> {
+    process_file_content_v2(app, jobs, path, job_id, full_content, max_kb)
+        .await
+        .map(|extraction| extraction.content)
+}
+
+/// 与 `process_file_content` 相同的提取逻辑，返回结构化结果：
+/// 内容大类、原始字节数、字符数、是否截断、页数 / 幻灯片数、识别出的编码与警告。
+#[tauri::command]
+pub async fn process_file_content_v2(
+    app: AppHandle,
+    jobs: tauri::State<'_, FileJobManager>,
+    path: String,
+    job_id: Option<String>,
+    full_content: Option<bool>,
+    max_kb: Option<u32>,
+) -> Result<FileExtraction, String> {
+    let cancel = Arc::new(AtomicBool::new(false));
+    if let Some(id) = &job_id {
+        jobs.0.insert(id.clone(), cancel.clone());
+    }
+    let on_page = job_id.clone().map(|job_id| {
+        let path = path.clone();
+        Box::new(move |pages_done, total_pages| {
+            let _ = app.emit(
+                PDF_PAGE_PROGRESS_EVENT,
+                PdfPageProgress {
+                    job_id: job_id.clone(),
+                    path: path.clone(),
+                    pages_done,
+                    total_pages,
+                },
+            );
+        }) as PageCallback
+    });
+    let result = extract_structured(
+        path,
+        Some(cancel),
+        full_content.unwrap_or(false),
+        resolve_max_bytes(max_kb),
+        on_page,
+    )
+    .await;
+    if let Some(id) = &job_id {
+        jobs.0.remove(id);
+    }
+    result
+}
+
+/// `chunk_document` 的输入：文件路径或已有的文本
+#[derive(Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub enum DocumentSource {
+    /// 走与 `process_file_content` 相同的沙箱校验与提取流程
+    Path(String),
+    Text(String),
+}
+
+/// 把长文档切成带重叠的块（供 RAG 检索与上下文较小的模型使用），见 [`chunking`]。
+///
+/// 文件输入不应用「附件提取上限」，表格尽量返回原文；图片无法分块。
+/// `overlap` 为相邻块的重叠 token 数，默认为块大小的 1/8。
+#[tauri::command]
+pub async fn chunk_document(
+    path_or_text: DocumentSource,
+    chunk_size_tokens: usize,
+    overlap: Option<usize>,
+) -> Result<Vec<TextChunk>, String> {
+    if chunk_size_tokens == 0 {
+        return Err("chunk_size_tokens 必须大于 0".into());
+    }
+    let text = match path_or_text {
+        DocumentSource::Path(path) => {
+            let extraction = extract_structured(path, None, true, None, None).await?;
+            if extraction.kind == FileKind::Image {
+                return Err("图片无法分块".into());
+            }
+            extraction.content
+        }
+        DocumentSource::Text(text) => text,
+    };
+    let overlap = overlap.unwrap_or(chunk_size_tokens / 8);
+    tokio::task::spawn_blocking(move || chunking::chunk_text(&text, chunk_size_tokens, overlap))
+        .await
+        .map_err(|e| e.to_string())
+}
+
+/// 取消正在进行的文件解析，返回是否找到该任务
+#[tauri::command]
+pub fn cancel_file_processing(jobs: tauri::State<'_, FileJobManager>, job_id: String) -> bool {
+    match jobs.0.get(&job_id) {
+        Some(flag) => {
+            flag.store(true, Ordering::Relaxed);
+            true
+        }
+        None => false,
+    }
+}
+
+/// `process_files` 中单个文件的结果：`content` 与 `error` 二者恰有其一
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct FileResult {
+    pub path: String,
+    pub content: Option<String>,
+    pub error: Option<String>,
+}
+
+/// [`PDF_PAGE_PROGRESS_EVENT`] 载荷：带 `job_id` 的 PDF 解析每完成一页发出
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PdfPageProgress {
+    pub job_id: String,
+    pub path: String,
+    pub pages_done: usize,
+    pub total_pages: usize,
+}
+
+/// [`FILES_PROGRESS_EVENT`] 载荷：`process_files` 每完成一个文件发出
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+struct FilesProgress {
+    path: String,
+    success: bool,
+    completed: usize,
+    total: usize,
+}
+
+/// 并发解析多个文件（最多 [`MAX_PARALLEL_FILES`] 个同时进行），按输入顺序返回每个文件的结果。
+///
+/// 单个文件失败不影响其他文件；每完成一个文件发出 [`FILES_PROGRESS_EVENT`]。
+#[tauri::command]
+pub async fn process_files(
+    app: AppHandle,
+    paths: Vec<String>,
+) -> Result<Vec<FileResult>, String> {
+    use futures_util::stream::{self, StreamExt};
+
+    let total = paths.len();
+    let completed = AtomicUsize::new(0);
+    let mut results: Vec<(usize, FileResult)> = stream::iter(paths.into_iter().enumerate())
+        .map(|(index, path)| {
+            let app = &app;
+            let completed = &completed;
+            async move {
+                let result =
+                    extract_content(path.clone(), None, false, resolve_max_bytes(None)).await;
+                let _ = app.emit(
+                    FILES_PROGRESS_EVENT,
+                    FilesProgress {
+                        path: path.clone(),
+                        success: result.is_ok(),
+                        completed: completed.fetch_add(1, Ordering::Relaxed) + 1,
+                        total,
+                    },
+                );
+                let (content, error) = match result {
+                    Ok(content) => (Some(content), None),
+                    Err(e) => (None, Some(e)),
+                };
+                (index, FileResult { path, content, error })
+            }
+        })
+        .buffer_unordered(MAX_PARALLEL_FILES)
+        .collect()
+        .await;
+    results.sort_by_key(|(index, _)| *index);
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+/// 预览附件提取结果（不发送给 LLM），用于排查「解析器问题还是模型问题」。
+///
+/// 与 `process_file_content` 走完全相同的沙箱校验与解析分支，
+/// 但只返回前 ~2000 个字符、总字符数、识别出的类型以及所用分支。
+/// 图片只校验扩展名与大小、不读取内容，`preview` 为空、`total_chars` 为 0。
+#[tauri::command]
+pub async fn preview_file_extraction(path: String) -> Result<ExtractionPreview, String> {
+    if let Err(e) = path_in_sandbox(Path::new(&path)) {
+        return Err(format!("文件路径沙箱拒绝: {}", e));
+    }
+    tokio::task::spawn_blocking(move || preview_extraction(&path))
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+/// 生成提取预览（阻塞，调用方负责沙箱校验）
+fn preview_extraction(path: &str) -> Result<ExtractionPreview, String> {
+    let path_obj = Path::new(path);
+    let extension = lowercase_extension(path_obj);
+    let mime_type = attachment_mime_type(&extension).to_string();
+    if matches!(extension.as_str(), "png" | "jpg" | "jpeg" | "webp") {
+        check_size(path_obj, MAX_IMAGE_BYTES)?;
+        return Ok(ExtractionPreview {
+            mime_type,
+            branch: ExtractionBranch::Image,
+            encoding: None,
+            preview: String::new(),
+            total_chars: 0,
+            truncated: false,
+        });
+    }
+
+    let Extraction {
+        branch,
+        content,
+        encoding,
+        ..
+    } = extract_by_branch(path, &extension, None, false, None, None)?;
+    let encoding = encoding.map(str::to_string);
+    let total_chars = content.chars().count();
+    let preview: String = content.chars().take(PREVIEW_CHARS).collect();
+    Ok(ExtractionPreview {
+        mime_type,
+        branch,
+        encoding,
+        preview,
+        total_chars,
+        truncated: total_chars > PREVIEW_CHARS,
+    })
+}
+
 /// 校验模型路径在沙箱内（H8 强化）
 pub fn validate_model_path(path: &str) -> Result<PathBuf, String> {
     let p = PathBuf::from(path);
@@ -269,3 +932,82 @@ pub fn validate_model_path(path: &str) -> Result<PathBuf, String> {
     path_in_sandbox(&p)?;
     Ok(p)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_extraction_metadata_for_text_and_csv() {
+        let dir = std::env::temp_dir().join(format!("aio-extract-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, content).unwrap();
+            path.to_string_lossy().into_owned()
+        };
+
+        let notes = write("notes.txt", &"你好，world\n".repeat(400));
+        let full = extract_file(&notes, None, false, None, None).unwrap();
+        assert_eq!(
+            (full.kind, full.branch),
+            (FileKind::Text, ExtractionBranch::Text)
+        );
+        assert_eq!(full.char_count, 9 * 400);
+        assert_eq!(full.byte_size, 15 * 400);
+        assert!(!full.truncated && full.warnings.is_empty());
+        assert!(full.encoding.is_some() && full.page_count.is_none());
+
+        let limited = extract_file(&notes, None, false, Some(1024), None).unwrap();
+        assert!(limited.truncated);
+        assert_eq!(limited.warnings.len(), 1);
+        assert_eq!(limited.char_count, limited.content.chars().count());
+        assert!(limited.char_count < full.char_count);
+
+        let table = write("table.csv", "name,score\nalice,90\nbob,85\n");
+        let digest = extract_file(&table, None, false, None, None).unwrap();
+        assert_eq!(
+            (digest.kind, digest.branch),
+            (FileKind::Text, ExtractionBranch::Csv)
+        );
+        assert!(digest.truncated && digest.warnings.is_empty());
+        let raw = extract_file(&table, None, true, None, None).unwrap();
+        assert_eq!(raw.content, "name,score\nalice,90\nbob,85\n");
+        assert!(!raw.truncated);
+
+        let large = write(
+            "large.csv",
+            &"a,b\n1,2\n".repeat(csv_digest::FULL_CONTENT_MAX_BYTES),
+        );
+        let large = extract_file(&large, None, true, None, None).unwrap();
+        assert!(large.truncated);
+        assert_eq!(large.warnings.len(), 1);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn previews_text_head_and_image_metadata() {
+        let dir = std::env::temp_dir().join(format!("aio-preview-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let notes = dir.join("notes.md");
+        std::fs::write(&notes, "字".repeat(PREVIEW_CHARS + 10)).unwrap();
+        let preview = preview_extraction(&notes.to_string_lossy()).unwrap();
+        assert_eq!(preview.branch, ExtractionBranch::Text);
+        assert_eq!(preview.mime_type, "text/markdown");
+        assert_eq!(preview.preview.chars().count(), PREVIEW_CHARS);
+        assert_eq!(preview.total_chars, PREVIEW_CHARS + 10);
+        assert!(preview.truncated && preview.encoding.is_some());
+
+        // 图片不解码：内容不是合法 PNG 也能得到元数据
+        let image = dir.join("photo.PNG");
+        std::fs::write(&image, b"not really a png").unwrap();
+        let preview = preview_extraction(&image.to_string_lossy()).unwrap();
+        assert_eq!(preview.branch, ExtractionBranch::Image);
+        assert_eq!((preview.preview.as_str(), preview.total_chars), ("", 0));
+        assert!(!preview.truncated && preview.encoding.is_none());
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
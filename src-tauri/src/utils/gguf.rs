@@ -0,0 +1,617 @@
+//! GGUF 文件头与元数据读取
+//!
+//! 只解析文件头和 key-value 元数据（不读张量数据），用于推断上下文长度、架构等信息。
+//! 数组类型的值（如 tokenizer 词表）体积很大，只记录长度，不保留内容。
+//!
+//! [`inspect_file`] 还会读取张量信息表，按各张量的类型与形状计算张量数据应有的长度，
+//! 与实际文件大小比较，用于在启动前发现下载中断的残缺文件。
+
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+/// GGUF 文件魔数
+pub const GGUF_MAGIC: &[u8; 4] = b"GGUF";
+/// 单个字符串值的长度上限，防止损坏文件导致超大分配
+const MAX_STRING_LEN: u64 = 16 * 1024 * 1024;
+/// 单个张量的维数上限（GGML_MAX_DIMS）
+const MAX_TENSOR_DIMS: u32 = 4;
+/// 未声明 `general.alignment` 时张量数据的对齐字节数
+const DEFAULT_ALIGNMENT: u64 = 32;
+
+/// 元数据值
+#[derive(Clone, Debug, PartialEq)]
+pub enum GgufValue {
+    UInt(u64),
+    Int(i64),
+    Float(f64),
+    Bool(bool),
+    String(String),
+    /// 数组只保留长度
+    Array { len: u64 },
+}
+
+impl GgufValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            GgufValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            GgufValue::UInt(v) => Some(v),
+            GgufValue::Int(v) => u64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn array_len(&self) -> Option<u64> {
+        match *self {
+            GgufValue::Array { len } => Some(len),
+            _ => None,
+        }
+    }
+}
+
+/// 文件头与元数据
+#[derive(Clone, Debug, PartialEq)]
+pub struct GgufHeader {
+    pub version: u32,
+    pub tensor_count: u64,
+    pub metadata: BTreeMap<String, GgufValue>,
+}
+
+impl GgufHeader {
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.metadata.get(key)?.as_str()
+    }
+
+    pub fn get_u64(&self, key: &str) -> Option<u64> {
+        self.metadata.get(key)?.as_u64()
+    }
+
+    /// `general.architecture`，如 "llama"、"qwen2"
+    pub fn architecture(&self) -> Option<&str> {
+        self.get_str("general.architecture")
+    }
+
+    /// 训练时的上下文长度（`<arch>.context_length`）
+    pub fn context_length(&self) -> Option<u64> {
+        self.get_u64(&format!("{}.context_length", self.architecture()?))
+    }
+
+    /// 分词器类型（`tokenizer.ggml.model`，如 "llama"、"gpt2"）
+    pub fn tokenizer_model(&self) -> Option<&str> {
+        self.get_str("tokenizer.ggml.model")
+    }
+
+    /// 预分词规则（`tokenizer.ggml.pre`，如 "qwen2"、"llama-bpe"），旧文件可能没有
+    pub fn tokenizer_pre(&self) -> Option<&str> {
+        self.get_str("tokenizer.ggml.pre")
+    }
+
+    /// 词表大小（`tokenizer.ggml.tokens` 数组长度）
+    pub fn vocab_size(&self) -> Option<u64> {
+        self.metadata.get("tokenizer.ggml.tokens")?.array_len()
+    }
+
+    /// 是否为适配器文件（LoRA 等，`general.type = "adapter"`），不能单独作为基础模型加载
+    pub fn is_adapter(&self) -> bool {
+        self.get_str("general.type") == Some("adapter")
+    }
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+struct GgufReader<R> {
+    inner: R,
+    /// v1 的长度 / 计数字段为 u32，v2 起为 u64
+    wide: bool,
+    /// 已读取的字节数（用于定位张量数据起点）
+    pos: u64,
+}
+
+impl<R: Read> GgufReader<R> {
+    fn bytes<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut buf = [0u8; N];
+        self.inner.read_exact(&mut buf)?;
+        self.pos += N as u64;
+        Ok(buf)
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes()?))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes()?))
+    }
+
+    /// 长度 / 计数字段
+    fn count(&mut self) -> io::Result<u64> {
+        if self.wide {
+            self.u64()
+        } else {
+            self.u32().map(u64::from)
+        }
+    }
+
+    fn string(&mut self) -> io::Result<String> {
+        let len = self.count()?;
+        if len > MAX_STRING_LEN {
+            return Err(invalid(format!("字符串长度异常: {}", len)));
+        }
+        let mut buf = vec![0u8; len as usize];
+        self.inner.read_exact(&mut buf)?;
+        self.pos += len;
+        Ok(String::from_utf8_lossy(&buf).into_owned())
+    }
+
+    fn skip(&mut self, n: u64) -> io::Result<()> {
+        let skipped = io::copy(&mut (&mut self.inner).take(n), &mut io::sink())?;
+        self.pos += skipped;
+        if skipped < n {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        Ok(())
+    }
+
+    /// 读取一个指定类型的值；数组元素逐个跳过
+    fn value(&mut self, ty: u32) -> io::Result<GgufValue> {
+        Ok(match ty {
+            0 => GgufValue::UInt(u8::from_le_bytes(self.bytes()?).into()),
+            1 => GgufValue::Int(i8::from_le_bytes(self.bytes()?).into()),
+            2 => GgufValue::UInt(u16::from_le_bytes(self.bytes()?).into()),
+            3 => GgufValue::Int(i16::from_le_bytes(self.bytes()?).into()),
+            4 => GgufValue::UInt(self.u32()?.into()),
+            5 => GgufValue::Int(i32::from_le_bytes(self.bytes()?).into()),
+            6 => GgufValue::Float(f32::from_le_bytes(self.bytes()?).into()),
+            7 => GgufValue::Bool(self.bytes::<1>()?[0] != 0),
+            8 => GgufValue::String(self.string()?),
+            9 => {
+                let item_ty = self.u32()?;
+                let len = self.count()?;
+                match scalar_size(item_ty) {
+                    Some(size) => self.skip(len.saturating_mul(size))?,
+                    None => {
+                        for _ in 0..len {
+                            self.value(item_ty)?;
+                        }
+                    }
+                }
+                GgufValue::Array { len }
+            }
+            10 => GgufValue::UInt(self.u64()?),
+            11 => GgufValue::Int(i64::from_le_bytes(self.bytes()?)),
+            12 => GgufValue::Float(f64::from_le_bytes(self.bytes()?)),
+            other => return Err(invalid(format!("未知的元数据类型: {}", other))),
+        })
+    }
+}
+
+/// 定长标量类型的字节数（字符串 / 嵌套数组为 None）
+fn scalar_size(ty: u32) -> Option<u64> {
+    match ty {
+        0 | 1 | 7 => Some(1),
+        2 | 3 => Some(2),
+        4..=6 => Some(4),
+        10..=12 => Some(8),
+        _ => None,
+    }
+}
+
+/// ggml 张量类型的 (每块元素数, 每块字节数)；未知类型返回 None
+fn ggml_type_size(ty: u32) -> Option<(u64, u64)> {
+    Some(match ty {
+        0 => (1, 4),           // F32
+        1 | 30 => (1, 2),      // F16 / BF16
+        2 => (32, 18),         // Q4_0
+        3 => (32, 20),         // Q4_1
+        6 => (32, 22),         // Q5_0
+        7 => (32, 24),         // Q5_1
+        8 => (32, 34),         // Q8_0
+        9 => (32, 36),         // Q8_1
+        10 => (256, 84),       // Q2_K
+        11 | 21 => (256, 110), // Q3_K / IQ3_S
+        12 => (256, 144),      // Q4_K
+        13 => (256, 176),      // Q5_K
+        14 => (256, 210),      // Q6_K
+        15 => (256, 292),      // Q8_K
+        16 | 35 => (256, 66),  // IQ2_XXS / TQ2_0
+        17 => (256, 74),       // IQ2_XS
+        18 => (256, 98),       // IQ3_XXS
+        19 => (256, 50),       // IQ1_S
+        20 => (32, 18),        // IQ4_NL
+        22 => (256, 82),       // IQ2_S
+        23 => (256, 136),      // IQ4_XS
+        24 => (1, 1),          // I8
+        25 => (1, 2),          // I16
+        26 => (1, 4),          // I32
+        27 | 28 => (1, 8),     // I64 / F64
+        29 => (256, 56),       // IQ1_M
+        34 => (256, 54),       // TQ1_0
+        39 => (32, 17),        // MXFP4
+        _ => return None,
+    })
+}
+
+/// `general.file_type` 对应的量化名称
+fn file_type_name(file_type: u64) -> Option<&'static str> {
+    Some(match file_type {
+        0 => "F32",
+        1 => "F16",
+        2 => "Q4_0",
+        3 => "Q4_1",
+        7 => "Q8_0",
+        8 => "Q5_0",
+        9 => "Q5_1",
+        10 => "Q2_K",
+        11 => "Q3_K_S",
+        12 => "Q3_K_M",
+        13 => "Q3_K_L",
+        14 => "Q4_K_S",
+        15 => "Q4_K_M",
+        16 => "Q5_K_S",
+        17 => "Q5_K_M",
+        18 => "Q6_K",
+        19 => "IQ2_XXS",
+        20 => "IQ2_XS",
+        21 => "Q2_K_S",
+        22 => "IQ3_XS",
+        23 => "IQ3_XXS",
+        24 => "IQ1_S",
+        25 => "IQ4_NL",
+        26 => "IQ3_S",
+        27 => "IQ3_M",
+        28 => "IQ2_S",
+        29 => "IQ2_M",
+        30 => "IQ4_XS",
+        31 => "IQ1_M",
+        32 => "BF16",
+        36 => "TQ1_0",
+        37 => "TQ2_0",
+        38 => "MXFP4_MOE",
+        _ => return None,
+    })
+}
+
+/// 张量信息表中的一项
+#[derive(Clone, Debug, PartialEq)]
+pub struct GgufTensorInfo {
+    pub name: String,
+    pub dims: Vec<u64>,
+    pub ggml_type: u32,
+    /// 相对张量数据起点的偏移
+    pub offset: u64,
+}
+
+impl GgufTensorInfo {
+    /// 元素个数；维度乘积溢出 u64 时返回 None
+    pub fn element_count(&self) -> Option<u64> {
+        self.dims
+            .iter()
+            .try_fold(1u64, |acc, &d| acc.checked_mul(d))
+    }
+
+    /// 张量数据字节数；未知类型或形状不是整块时返回 None
+    pub fn byte_size(&self) -> Option<u64> {
+        let (block, size) = ggml_type_size(self.ggml_type)?;
+        let elements = self.element_count()?;
+        if elements % block != 0 {
+            return None;
+        }
+        (elements / block).checked_mul(size)
+    }
+}
+
+/// 文件头、元数据与张量信息表
+#[derive(Clone, Debug, PartialEq)]
+pub struct GgufLayout {
+    pub header: GgufHeader,
+    pub tensors: Vec<GgufTensorInfo>,
+    /// 张量数据在文件中的起始偏移（已按 `general.alignment` 对齐）
+    pub data_offset: u64,
+}
+
+impl GgufLayout {
+    /// 按张量形状统计的参数量；溢出 u64 时返回 None（[`read_layout`] 已拒绝这类文件）
+    pub fn parameter_count(&self) -> Option<u64> {
+        self.tensors
+            .iter()
+            .try_fold(0u64, |acc, t| acc.checked_add(t.element_count()?))
+    }
+
+    /// 文件至少应有的字节数：张量数据起点 + 最靠后的张量末尾（跳过未知类型的张量）
+    pub fn expected_min_size(&self) -> u64 {
+        let data_len = self
+            .tensors
+            .iter()
+            .filter_map(|t| Some(t.offset.saturating_add(t.byte_size()?)))
+            .max()
+            .unwrap_or(0);
+        self.data_offset.saturating_add(data_len)
+    }
+
+    /// 是否有无法计算大小的张量（此时文件长度校验只是下限）
+    pub fn has_unknown_tensor_types(&self) -> bool {
+        self.tensors.iter().any(|t| t.byte_size().is_none())
+    }
+}
+
+/// 读取魔数并解析文件头与元数据（不含张量信息表）
+fn parse_header<R: Read>(r: &mut GgufReader<R>) -> Result<GgufHeader, String> {
+    let magic: [u8; 4] = r
+        .bytes()
+        .map_err(|_| "文件过短，不是有效的 GGUF 文件".to_string())?;
+    if &magic != GGUF_MAGIC {
+        return Err("不是 GGUF 文件（魔数不匹配）".into());
+    }
+    let parse = |r: &mut GgufReader<R>| -> io::Result<GgufHeader> {
+        let version = r.u32()?;
+        if !(1..=3).contains(&version) {
+            return Err(invalid(format!("不支持的 GGUF 版本: {}", version)));
+        }
+        r.wide = version >= 2;
+        let tensor_count = r.count()?;
+        let kv_count = r.count()?;
+        let mut metadata = BTreeMap::new();
+        for _ in 0..kv_count {
+            let key = r.string()?;
+            let ty = r.u32()?;
+            metadata.insert(key, r.value(ty)?);
+        }
+        Ok(GgufHeader {
+            version,
+            tensor_count,
+            metadata,
+        })
+    };
+    parse(r).map_err(|e| format!("GGUF 元数据解析失败: {}", e))
+}
+
+/// 从流中读取 GGUF 文件头与全部元数据
+pub fn read_header<R: Read>(reader: R) -> Result<GgufHeader, String> {
+    parse_header(&mut GgufReader {
+        inner: reader,
+        wide: true,
+        pos: 0,
+    })
+}
+
+/// 张量形状的元素数或参数总量溢出 u64
+const PARAMETER_OVERFLOW: &str = "GGUF 张量信息解析失败: 张量形状的元素数溢出";
+
+/// 从流中读取文件头、元数据与张量信息表；张量元素数或参数总量溢出时报解析错误
+pub fn read_layout<R: Read>(reader: R) -> Result<GgufLayout, String> {
+    let mut r = GgufReader {
+        inner: reader,
+        wide: true,
+        pos: 0,
+    };
+    let header = parse_header(&mut r)?;
+    let parse_tensors = |r: &mut GgufReader<R>| -> io::Result<Vec<GgufTensorInfo>> {
+        let mut tensors = Vec::new();
+        for _ in 0..header.tensor_count {
+            let name = r.string()?;
+            let n_dims = r.u32()?;
+            if n_dims > MAX_TENSOR_DIMS {
+                return Err(invalid(format!("张量 {} 的维数异常: {}", name, n_dims)));
+            }
+            let dims = (0..n_dims)
+                .map(|_| r.count())
+                .collect::<io::Result<Vec<_>>>()?;
+            let ggml_type = r.u32()?;
+            let offset = r.u64()?;
+            tensors.push(GgufTensorInfo {
+                name,
+                dims,
+                ggml_type,
+                offset,
+            });
+        }
+        Ok(tensors)
+    };
+    let tensors = parse_tensors(&mut r).map_err(|e| format!("GGUF 张量信息解析失败: {}", e))?;
+    let alignment = header
+        .get_u64("general.alignment")
+        .filter(|a| *a > 0)
+        .unwrap_or(DEFAULT_ALIGNMENT);
+    let data_offset = r
+        .pos
+        .div_ceil(alignment)
+        .checked_mul(alignment)
+        .ok_or_else(|| format!("GGUF 张量信息解析失败: 对齐值异常: {}", alignment))?;
+    let layout = GgufLayout {
+        header,
+        tensors,
+        data_offset,
+    };
+    if layout.parameter_count().is_none() {
+        return Err(PARAMETER_OVERFLOW.to_string());
+    }
+    Ok(layout)
+}
+
+/// 模型文件检查结果（发往前端展示模型详情）
+#[derive(Serialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GgufInfo {
+    pub version: u32,
+    pub architecture: Option<String>,
+    /// `general.name`
+    pub name: Option<String>,
+    /// 按张量形状统计的参数量
+    pub parameter_count: u64,
+    /// `general.size_label`（如 "7B"）
+    pub size_label: Option<String>,
+    /// 量化类型（`general.file_type`，如 "Q4_K_M"）
+    pub quantization: Option<String>,
+    pub context_length: Option<u64>,
+    pub chat_template: Option<String>,
+    pub is_adapter: bool,
+    pub tensor_count: u64,
+    pub file_size: u64,
+    /// 按张量信息计算的文件最小长度
+    pub expected_size: u64,
+    /// 文件完整性问题（如下载中断导致的截断），None 表示校验通过
+    pub integrity_error: Option<String>,
+}
+
+/// 解析 GGUF 文件并校验文件长度是否与张量信息表一致
+pub fn inspect_file(path: &Path) -> Result<GgufInfo, String> {
+    let file = File::open(path).map_err(|e| format!("无法打开模型文件: {}", e))?;
+    let file_size = file.metadata().map_err(|e| e.to_string())?.len();
+    let layout = read_layout(BufReader::new(file))?;
+
+    let expected_size = layout.expected_min_size();
+    let integrity_error = (file_size < expected_size).then(|| {
+        format!(
+            "模型文件不完整：按张量信息至少应有 {} 字节，实际只有 {} 字节（可能是下载中断）",
+            expected_size, file_size
+        )
+    });
+    if layout.has_unknown_tensor_types() {
+        tracing::debug!("{} 含未知类型的张量，文件长度只校验下限", path.display());
+    }
+
+    let header = &layout.header;
+    Ok(GgufInfo {
+        version: header.version,
+        architecture: header.architecture().map(String::from),
+        name: header.get_str("general.name").map(String::from),
+        parameter_count: layout.parameter_count().ok_or(PARAMETER_OVERFLOW)?,
+        size_label: header.get_str("general.size_label").map(String::from),
+        quantization: header
+            .get_u64("general.file_type")
+            .and_then(file_type_name)
+            .map(String::from),
+        context_length: header.context_length(),
+        chat_template: header.get_str("tokenizer.chat_template").map(String::from),
+        is_adapter: header.is_adapter(),
+        tensor_count: header.tensor_count,
+        file_size,
+        expected_size,
+        integrity_error,
+    })
+}
+
+/// 只检查文件开头的 GGUF 魔数（不解析元数据）
+pub fn check_magic(path: &Path) -> Result<(), String> {
+    let mut file = File::open(path).map_err(|e| format!("无法打开模型文件: {}", e))?;
+    let mut magic = [0u8; 4];
+    file.read_exact(&mut magic)
+        .map_err(|_| "文件过短，不是有效的 GGUF 文件".to_string())?;
+    if &magic != GGUF_MAGIC {
+        return Err("不是 GGUF 文件（魔数不匹配）".into());
+    }
+    Ok(())
+}
+
+/// 读取 GGUF 文件的元数据
+pub fn read_file(path: &Path) -> Result<GgufHeader, String> {
+    let file = File::open(path).map_err(|e| format!("无法打开模型文件: {}", e))?;
+    read_header(BufReader::new(file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn push_str(buf: &mut Vec<u8>, s: &str) {
+        buf.extend((s.len() as u64).to_le_bytes());
+        buf.extend(s.as_bytes());
+    }
+
+    fn sample() -> Vec<u8> {
+        let mut buf = GGUF_MAGIC.to_vec();
+        buf.extend(3u32.to_le_bytes());
+        buf.extend(0u64.to_le_bytes());
+        buf.extend(3u64.to_le_bytes());
+        push_str(&mut buf, "general.architecture");
+        buf.extend(8u32.to_le_bytes());
+        push_str(&mut buf, "llama");
+        push_str(&mut buf, "tokenizer.ggml.tokens");
+        buf.extend(9u32.to_le_bytes());
+        buf.extend(8u32.to_le_bytes());
+        buf.extend(2u64.to_le_bytes());
+        push_str(&mut buf, "<s>");
+        push_str(&mut buf, "</s>");
+        push_str(&mut buf, "llama.context_length");
+        buf.extend(4u32.to_le_bytes());
+        buf.extend(131072u32.to_le_bytes());
+        buf
+    }
+
+    #[test]
+    fn reads_metadata_and_skips_arrays() {
+        let header = read_header(sample().as_slice()).unwrap();
+        assert_eq!(header.version, 3);
+        assert_eq!(header.architecture(), Some("llama"));
+        assert_eq!(header.context_length(), Some(131072));
+        assert!(!header.is_adapter());
+        assert_eq!(header.vocab_size(), Some(2));
+        assert_eq!(
+            header.metadata.get("tokenizer.ggml.tokens"),
+            Some(&GgufValue::Array { len: 2 })
+        );
+    }
+
+    /// 一个 64×2 的 F32 张量（512 字节）加上一个 Q4_0 张量（32 元素，18 字节，偏移 512）
+    fn sample_with_tensors() -> Vec<u8> {
+        sample_with_dims(&[64, 2])
+    }
+
+    /// 第一个 F32 张量的形状为 `a_dims`
+    fn sample_with_dims(a_dims: &[u64]) -> Vec<u8> {
+        let mut buf = GGUF_MAGIC.to_vec();
+        buf.extend(3u32.to_le_bytes());
+        buf.extend(2u64.to_le_bytes());
+        buf.extend(1u64.to_le_bytes());
+        push_str(&mut buf, "general.file_type");
+        buf.extend(4u32.to_le_bytes());
+        buf.extend(2u32.to_le_bytes());
+        for (name, dims, ty, offset) in [("a", a_dims.to_vec(), 0u32, 0u64), ("b", vec![32], 2, 512)]
+        {
+            push_str(&mut buf, name);
+            buf.extend((dims.len() as u32).to_le_bytes());
+            for d in dims {
+                buf.extend(d.to_le_bytes());
+            }
+            buf.extend(ty.to_le_bytes());
+            buf.extend(offset.to_le_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn computes_tensor_data_layout() {
+        let data = sample_with_tensors();
+        let layout = read_layout(data.as_slice()).unwrap();
+        assert_eq!(layout.tensors.len(), 2);
+        assert_eq!(layout.tensors[1].byte_size(), Some(18));
+        assert_eq!(layout.parameter_count(), Some(160));
+        assert_eq!(layout.data_offset % DEFAULT_ALIGNMENT, 0);
+        assert!(layout.data_offset >= data.len() as u64);
+        assert_eq!(layout.expected_min_size(), layout.data_offset + 530);
+        assert!(!layout.has_unknown_tensor_types());
+        assert_eq!(file_type_name(2), Some("Q4_0"));
+        // 张量信息表被截断
+        assert!(read_layout(&data[..data.len() - 4]).is_err());
+        // 张量形状的元素数溢出
+        let err = read_layout(sample_with_dims(&[u64::MAX, 2]).as_slice()).unwrap_err();
+        assert!(err.contains("溢出"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_bad_magic_and_truncation() {
+        assert!(read_header(&b"GGML\x03\0\0\0"[..]).is_err());
+        let data = sample();
+        assert!(read_header(&data[..data.len() - 2]).is_err());
+    }
+}
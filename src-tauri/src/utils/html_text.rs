@@ -0,0 +1,213 @@
+//! HTML 正文提取
+//!
+//! 把 HTML 附件转换为适合放进提示词的纯文本：
+//! - 用容错的 HTML5 解析器构建 DOM，标签未闭合等不规范写法不会导致失败
+//! - 丢弃脚本、样式、导航栏、页脚等非正文元素
+//! - 优先取 `<main>` / `<article>` 中的内容，正文过短时退回整个 `<body>`
+//! - 保留标题层级（`#` 前缀）、列表项与链接文字，实体由解析器解码
+//! - `<title>` 放在结果第一行
+
+use dom_query::{Document, NodeRef};
+
+/// 提取前移除的非正文元素
+const NOISE_SELECTOR: &str =
+    "script, style, noscript, template, svg, canvas, iframe, nav, footer, aside, form";
+/// 可能包含正文的容器
+const MAIN_SELECTOR: &str = "main, article, [role=main]";
+/// 正文容器至少包含这么多字符才采用，否则使用整个 body
+const MIN_MAIN_CHARS: usize = 200;
+
+/// 需要换行分隔的块级元素
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "blockquote",
+    "dd",
+    "details",
+    "div",
+    "dl",
+    "dt",
+    "figcaption",
+    "figure",
+    "header",
+    "hr",
+    "li",
+    "main",
+    "ol",
+    "p",
+    "section",
+    "summary",
+    "table",
+    "tr",
+    "ul",
+];
+
+/// 提取结果：`<title>` 与正文分开返回
+pub struct HtmlText {
+    /// 页面标题（无 `<title>` 时为空）
+    pub title: String,
+    /// 正文纯文本
+    pub text: String,
+}
+
+/// 将 HTML 转为可读纯文本（标题在第一行）
+pub fn html_to_text(html: &str) -> String {
+    let HtmlText { title, text } = extract_html(html);
+    if title.is_empty() || text.is_empty() {
+        format!("{}{}", title, text)
+    } else {
+        format!("{}\n\n{}", title, text)
+    }
+}
+
+/// 提取页面标题与正文
+pub fn extract_html(html: &str) -> HtmlText {
+    let doc = Document::from(html);
+    let title = collapse_spaces(&doc.select("title").first().text());
+    doc.select(NOISE_SELECTOR).remove();
+
+    let main = doc.select(MAIN_SELECTOR).first();
+    let root = match main.nodes().first() {
+        Some(node) if node.text().trim().chars().count() >= MIN_MAIN_CHARS => Some(*node),
+        _ => doc.body(),
+    };
+
+    let mut out = String::new();
+    if let Some(root) = root {
+        walk(&root, &mut out);
+    }
+    HtmlText {
+        title,
+        text: tidy(&out),
+    }
+}
+
+/// 深度优先遍历，按元素类型插入换行与前缀
+fn walk(node: &NodeRef, out: &mut String) {
+    for child in node.children() {
+        if child.is_text() {
+            out.push_str(&collapse_spaces_keep_edges(&child.text()));
+            continue;
+        }
+        if !child.is_element() {
+            continue;
+        }
+        let name = child
+            .node_name()
+            .map(|n| n.to_ascii_lowercase())
+            .unwrap_or_default();
+        match name.as_str() {
+            "br" => out.push('\n'),
+            "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+                let level = name[1..].parse::<usize>().unwrap_or(1);
+                out.push_str("\n\n");
+                out.push_str(&"#".repeat(level));
+                out.push(' ');
+                walk(&child, out);
+                out.push_str("\n\n");
+            }
+            "li" => {
+                out.push_str("\n- ");
+                walk(&child, out);
+            }
+            "pre" => {
+                out.push_str("\n\n");
+                out.push_str(child.text().trim_matches('\n'));
+                out.push_str("\n\n");
+            }
+            "td" | "th" => {
+                walk(&child, out);
+                out.push_str(" | ");
+            }
+            "img" => {
+                if let Some(alt) = child.attr("alt").filter(|a| !a.trim().is_empty()) {
+                    out.push_str(&format!("[图片: {}]", alt.trim()));
+                }
+            }
+            _ if BLOCK_TAGS.contains(&name.as_str()) => {
+                out.push('\n');
+                walk(&child, out);
+                out.push('\n');
+            }
+            _ => walk(&child, out),
+        }
+    }
+}
+
+/// 连续空白折叠为单个空格并去掉首尾空白
+fn collapse_spaces(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// 连续空白折叠为单个空格，但保留首尾是否有空白（避免相邻行内元素粘连）
+fn collapse_spaces_keep_edges(text: &str) -> String {
+    let inner = collapse_spaces(text);
+    if inner.is_empty() {
+        return if text.is_empty() {
+            String::new()
+        } else {
+            " ".into()
+        };
+    }
+    let lead = if text.starts_with(char::is_whitespace) {
+        " "
+    } else {
+        ""
+    };
+    let trail = if text.ends_with(char::is_whitespace) {
+        " "
+    } else {
+        ""
+    };
+    format!("{}{}{}", lead, inner, trail)
+}
+
+/// 整理输出：去掉行首尾空格与表格行末分隔符，最多保留一个空行
+fn tidy(text: &str) -> String {
+    let mut lines: Vec<&str> = Vec::new();
+    for line in text.lines() {
+        let line = line.trim().trim_end_matches('|').trim_end();
+        if line.is_empty() && lines.last().map_or(true, |l| l.is_empty()) {
+            continue;
+        }
+        lines.push(line);
+    }
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_readable_text_with_title_first() {
+        let html = r#"<!DOCTYPE html><html><head><title> 示例 &amp; 测试 </title>
+            <style>body { color: red }</style><script>alert("x")</script></head>
+            <body><nav><a href="/">首页</a></nav>
+            <h1>标题</h1><p>第一段 <a href="https://example.com">链接文字</a>&nbsp;结束&lt;3</p>
+            <ul><li>甲</li><li>乙</li></ul>
+            <footer>版权所有</footer></body></html>"#;
+        let text = html_to_text(html);
+        assert_eq!(
+            text,
+            "示例 & 测试\n\n# 标题\n\n第一段 链接文字 结束<3\n\n- 甲\n- 乙"
+        );
+    }
+
+    #[test]
+    fn tolerates_malformed_html() {
+        let html = "<title>坏页面</title><body><p>未闭合段落<p>第二段<div><b>加粗</i> 文字</div><script>var a";
+        let text = html_to_text(html);
+        assert!(text.starts_with("坏页面\n"), "{}", text);
+        assert!(text.contains("未闭合段落\n"));
+        assert!(text.contains("第二段"));
+        assert!(text.contains("加粗 文字"));
+        assert!(!text.contains("var a"));
+
+        assert_eq!(html_to_text(""), "");
+        assert_eq!(html_to_text("纯文本 &lt;无标签&gt;"), "纯文本 <无标签>");
+    }
+}
@@ -0,0 +1,407 @@
+//! LLM 流式响应解码
+//!
+//! 把 [`SseEvent`] 解码成与前端事件一一对应的 [`StreamOutput`]，不直接 emit，
+//! 由 `call_llm_stream` / `replay_stream` 共用，也便于用录制的 SSE 文本做测试。
+//! 支持 OpenAI Chat Completions 与 Anthropic Messages 两种流格式。
+//!
+//! 可选的连续重复事件去重（[`StreamDecoder::with_dedup`]，默认关闭）：部分代理在重连时会重发
+//! 最后一个 SSE 事件，导致输出出现重复字符。判定依据是 `id:` 字段与 data 原文都和上一个事件相同；
+//! 服务端为每个事件下发不同 id 时不会误伤，但没有 id 时只能比较 data 原文，
+//! 模型连续输出完全相同的片段（如两次 "..."）且 JSON 其他字段也一致时会被误删，因此只在确有问题时开启。
+
+use crate::utils::sse::SseEvent;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// 流格式
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamFormat {
+    /// `data: {"choices":[{"delta":...}]}` + `data: [DONE]`
+    OpenAi,
+    /// `event: content_block_delta` 等具名事件 + `message_stop`
+    Anthropic,
+}
+
+impl StreamFormat {
+    /// 按内容嗅探格式：出现 Anthropic 特有的事件名即判定为 Anthropic
+    pub fn sniff(raw: &str) -> Self {
+        const MARKERS: &[&str] = &["message_start", "content_block_start", "content_block_delta"];
+        if MARKERS.iter().any(|m| raw.contains(m)) {
+            StreamFormat::Anthropic
+        } else {
+            StreamFormat::OpenAi
+        }
+    }
+}
+
+/// 单个候选 token 及其对数概率
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TopLogprob {
+    pub token: String,
+    pub logprob: f64,
+}
+
+/// 生成的单个 token 的对数概率与候选项（OpenAI `choices[0].logprobs.content[]`）
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct TokenLogprob {
+    pub token: String,
+    pub logprob: f64,
+    pub top_logprobs: Vec<TopLogprob>,
+}
+
+/// 解码结果，对应前端的 llm-chunk / llm-reasoning / llm-tool-call / llm-logprob 事件
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamOutput {
+    Chunk(String),
+    Reasoning(String),
+    ToolCall {
+        id: String,
+        name: String,
+        arguments: String,
+    },
+    /// 请求了 logprobs 时，本次增量中各 token 的对数概率（服务端未返回时不输出）
+    Logprobs(Vec<TokenLogprob>),
+    /// 流结束（只会出现一次）
+    Done,
+}
+
+/// 增量解码器：按 index 累积 tool_call 片段，在结束信号处一次性输出
+#[derive(Debug)]
+pub struct StreamDecoder {
+    format: StreamFormat,
+    /// index → (id, name, arguments)
+    tool_calls: BTreeMap<usize, (String, String, String)>,
+    done: bool,
+    /// 是否丢弃与上一个事件完全相同的事件
+    dedup: bool,
+    /// 上一个事件的 (id, data)，用于去重
+    last_event: Option<(Option<String>, String)>,
+}
+
+impl StreamDecoder {
+    pub fn new(format: StreamFormat) -> Self {
+        Self {
+            format,
+            tool_calls: BTreeMap::new(),
+            done: false,
+            dedup: false,
+            last_event: None,
+        }
+    }
+
+    /// 开启连续重复事件去重（取舍见模块文档）
+    pub fn with_dedup(mut self, enabled: bool) -> Self {
+        self.dedup = enabled;
+        self
+    }
+
+    /// 是否为与上一个事件完全相同的重发事件；同时记录本事件
+    fn is_duplicate(&mut self, ev: &SseEvent) -> bool {
+        if !self.dedup {
+            return false;
+        }
+        let key = (ev.id.clone(), ev.data.clone());
+        if self.last_event.as_ref() == Some(&key) {
+            return true;
+        }
+        self.last_event = Some(key);
+        false
+    }
+
+    /// 是否已收到结束信号
+    pub fn is_done(&self) -> bool {
+        self.done
+    }
+
+    /// 解码一个 SSE 事件；`error` 事件返回 Err
+    pub fn decode(&mut self, ev: &SseEvent) -> Result<Vec<StreamOutput>, String> {
+        if self.done {
+            return Ok(Vec::new());
+        }
+        if ev.event.as_deref() == Some("error") {
+            return Err(format!("LLM API 流错误: {}", error_message(&ev.data)));
+        }
+        if self.is_duplicate(ev) {
+            tracing::debug!("丢弃重复的 SSE 事件: {}", ev.data);
+            return Ok(Vec::new());
+        }
+        let mut out = Vec::new();
+        match self.format {
+            StreamFormat::OpenAi => self.decode_openai(ev, &mut out),
+            StreamFormat::Anthropic => self.decode_anthropic(ev, &mut out)?,
+        }
+        Ok(out)
+    }
+
+    /// 流自然结束（未收到结束信号）：flush 残余 tool_calls 并输出 Done
+    pub fn finish(&mut self) -> Vec<StreamOutput> {
+        let mut out = Vec::new();
+        if !self.done {
+            self.finish_into(&mut out);
+        }
+        out
+    }
+
+    fn finish_into(&mut self, out: &mut Vec<StreamOutput>) {
+        self.flush_tool_calls(out);
+        out.push(StreamOutput::Done);
+        self.done = true;
+    }
+
+    fn flush_tool_calls(&mut self, out: &mut Vec<StreamOutput>) {
+        for (_idx, (id, name, arguments)) in std::mem::take(&mut self.tool_calls) {
+            if !id.is_empty() && !name.is_empty() {
+                out.push(StreamOutput::ToolCall { id, name, arguments });
+            }
+        }
+    }
+
+    fn decode_openai(&mut self, ev: &SseEvent, out: &mut Vec<StreamOutput>) {
+        if ev.data == "[DONE]" {
+            self.finish_into(out);
+            return;
+        }
+        let Ok(val) = serde_json::from_str::<Value>(&ev.data) else {
+            return;
+        };
+        let delta = &val["choices"][0]["delta"];
+        // 文本片段
+        if let Some(content) = delta["content"].as_str() {
+            out.push(StreamOutput::Chunk(content.to_string()));
+        }
+        // 思维链片段：GLM/DeepSeek-R1/Qwen3 等通过 reasoning_content 单独返回
+        // 部分实现用 reasoning 作为别名，两者择一即可
+        if let Some(reasoning) = delta["reasoning_content"]
+            .as_str()
+            .or_else(|| delta["reasoning"].as_str())
+        {
+            if !reasoning.is_empty() {
+                out.push(StreamOutput::Reasoning(reasoning.to_string()));
+            }
+        }
+        let logprobs = parse_logprobs(&val["choices"][0]["logprobs"]);
+        if !logprobs.is_empty() {
+            out.push(StreamOutput::Logprobs(logprobs));
+        }
+        // tool_calls 累积
+        if let Some(tcs) = delta["tool_calls"].as_array() {
+            for tc in tcs {
+                let index = tc.get("index").and_then(|v| v.as_u64()).unwrap_or(0) as usize;
+                let entry = self.tool_calls.entry(index).or_default();
+                if let Some(id) = tc.get("id").and_then(|v| v.as_str()) {
+                    entry.0 = id.to_string();
+                }
+                if let Some(name) = tc["function"]["name"].as_str() {
+                    entry.1 = name.to_string();
+                }
+                if let Some(args) = tc["function"]["arguments"].as_str() {
+                    entry.2.push_str(args);
+                }
+            }
+        }
+        // finish_reason="tool_calls" 触发 flush
+        if val["choices"][0]["finish_reason"].as_str() == Some("tool_calls") {
+            self.flush_tool_calls(out);
+        }
+    }
+
+    fn decode_anthropic(&mut self, ev: &SseEvent, out: &mut Vec<StreamOutput>) -> Result<(), String> {
+        let val = serde_json::from_str::<Value>(&ev.data).unwrap_or(Value::Null);
+        // 事件名优先，缺失时回退到 data.type（两者在 Anthropic 流中一致）
+        let kind = ev
+            .event
+            .as_deref()
+            .or_else(|| val["type"].as_str())
+            .unwrap_or("");
+        let index = val["index"].as_u64().unwrap_or(0) as usize;
+        match kind {
+            "content_block_start" => {
+                let block = &val["content_block"];
+                if block["type"].as_str() == Some("tool_use") {
+                    self.tool_calls.insert(
+                        index,
+                        (
+                            block["id"].as_str().unwrap_or_default().to_string(),
+                            block["name"].as_str().unwrap_or_default().to_string(),
+                            String::new(),
+                        ),
+                    );
+                }
+            }
+            "content_block_delta" => {
+                let delta = &val["delta"];
+                match delta["type"].as_str() {
+                    Some("text_delta") => {
+                        if let Some(text) = delta["text"].as_str() {
+                            out.push(StreamOutput::Chunk(text.to_string()));
+                        }
+                    }
+                    Some("thinking_delta") => {
+                        if let Some(thinking) = delta["thinking"].as_str().filter(|t| !t.is_empty()) {
+                            out.push(StreamOutput::Reasoning(thinking.to_string()));
+                        }
+                    }
+                    Some("input_json_delta") => {
+                        if let (Some(entry), Some(partial)) =
+                            (self.tool_calls.get_mut(&index), delta["partial_json"].as_str())
+                        {
+                            entry.2.push_str(partial);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            "message_delta" if val["delta"]["stop_reason"].as_str() == Some("tool_use") => {
+                self.flush_tool_calls(out);
+            }
+            "message_stop" => self.finish_into(out),
+            "error" => return Err(format!("LLM API 流错误: {}", error_message(&ev.data))),
+            // message_start / content_block_stop / ping / 其他 message_delta 无需处理
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// 解析 `logprobs.content[]`；字段缺失或格式不符时返回空列表
+pub fn parse_logprobs(logprobs: &Value) -> Vec<TokenLogprob> {
+    let Some(content) = logprobs["content"].as_array() else {
+        return Vec::new();
+    };
+    let entry = |item: &Value| Some((item["token"].as_str()?.to_string(), item["logprob"].as_f64()?));
+    content
+        .iter()
+        .filter_map(|item| {
+            let (token, logprob) = entry(item)?;
+            let top_logprobs = item["top_logprobs"]
+                .as_array()
+                .map(|top| {
+                    top.iter()
+                        .filter_map(entry)
+                        .map(|(token, logprob)| TopLogprob { token, logprob })
+                        .collect()
+                })
+                .unwrap_or_default();
+            Some(TokenLogprob { token, logprob, top_logprobs })
+        })
+        .collect()
+}
+
+/// 从错误事件 data 中提取可读信息（`{"error":{"message":...}}`），失败时原样返回
+fn error_message(data: &str) -> String {
+    serde_json::from_str::<Value>(data)
+        .ok()
+        .and_then(|v| v["error"]["message"].as_str().map(String::from))
+        .unwrap_or_else(|| data.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::sse::SseParser;
+
+    fn decode_all(raw: &str) -> Result<Vec<StreamOutput>, String> {
+        let mut parser = SseParser::new();
+        let mut decoder = StreamDecoder::new(StreamFormat::sniff(raw));
+        let mut out = Vec::new();
+        for ev in parser.push(raw.as_bytes()).into_iter().chain(parser.finish()) {
+            out.extend(decoder.decode(&ev)?);
+        }
+        out.extend(decoder.finish());
+        Ok(out)
+    }
+
+    #[test]
+    fn decodes_openai_dump() {
+        let raw = concat!(
+            ": keep-alive\n\n",
+            "data: {\"choices\":[{\"delta\":{\"reasoning_content\":\"hmm\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"id\":\"c1\",\"function\":{\"name\":\"f\",\"arguments\":\"{\\\"a\\\"\"}}]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"tool_calls\":[{\"index\":0,\"function\":{\"arguments\":\":1}\"}}]},\"finish_reason\":\"tool_calls\"}]}\n\n",
+            "data: [DONE]\n\n",
+        );
+        assert_eq!(StreamFormat::sniff(raw), StreamFormat::OpenAi);
+        assert_eq!(
+            decode_all(raw).unwrap(),
+            vec![
+                StreamOutput::Reasoning("hmm".into()),
+                StreamOutput::Chunk("Hi".into()),
+                StreamOutput::ToolCall { id: "c1".into(), name: "f".into(), arguments: "{\"a\":1}".into() },
+                StreamOutput::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_anthropic_dump() {
+        let raw = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\"}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"Hello\"}}\n\n",
+            "event: ping\ndata: {\"type\":\"ping\"}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"index\":1,\"content_block\":{\"type\":\"tool_use\",\"id\":\"t1\",\"name\":\"g\"}}\n\n",
+            "event: content_block_delta\ndata: {\"type\":\"content_block_delta\",\"index\":1,\"delta\":{\"type\":\"input_json_delta\",\"partial_json\":\"{}\"}}\n\n",
+            "event: message_delta\ndata: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"tool_use\"}}\n\n",
+            "event: message_stop\ndata: {\"type\":\"message_stop\"}\n\n",
+        );
+        assert_eq!(StreamFormat::sniff(raw), StreamFormat::Anthropic);
+        assert_eq!(
+            decode_all(raw).unwrap(),
+            vec![
+                StreamOutput::Chunk("Hello".into()),
+                StreamOutput::ToolCall { id: "t1".into(), name: "g".into(), arguments: "{}".into() },
+                StreamOutput::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn decodes_openai_logprobs() {
+        let raw = concat!(
+            "data: {\"choices\":[{\"delta\":{\"content\":\"Hi\"},\"logprobs\":{\"content\":[",
+            "{\"token\":\"Hi\",\"logprob\":-0.5,\"top_logprobs\":[{\"token\":\"Hi\",\"logprob\":-0.5},{\"token\":\"Hey\",\"logprob\":-1.25}]}",
+            "]}}]}\n\n",
+            "data: {\"choices\":[{\"delta\":{\"content\":\"!\"},\"logprobs\":null}]}\n\n",
+            "data: [DONE]\n\n",
+        );
+        assert_eq!(
+            decode_all(raw).unwrap(),
+            vec![
+                StreamOutput::Chunk("Hi".into()),
+                StreamOutput::Logprobs(vec![TokenLogprob {
+                    token: "Hi".into(),
+                    logprob: -0.5,
+                    top_logprobs: vec![
+                        TopLogprob { token: "Hi".into(), logprob: -0.5 },
+                        TopLogprob { token: "Hey".into(), logprob: -1.25 },
+                    ],
+                }]),
+                StreamOutput::Chunk("!".into()),
+                StreamOutput::Done,
+            ]
+        );
+    }
+
+    #[test]
+    fn dedup_drops_only_repeated_events() {
+        let chunk = |id: &str, text: &str| SseEvent {
+            event: None,
+            id: Some(id.into()),
+            data: format!("{{\"choices\":[{{\"delta\":{{\"content\":\"{}\"}}}}]}}", text),
+        };
+        let events = [chunk("1", "."), chunk("2", "."), chunk("2", ".")];
+        let count = |dedup: bool| {
+            let mut decoder = StreamDecoder::new(StreamFormat::OpenAi).with_dedup(dedup);
+            events.iter().map(|ev| decoder.decode(ev).unwrap().len()).sum::<usize>()
+        };
+        assert_eq!(count(false), 3);
+        assert_eq!(count(true), 2);
+    }
+
+    #[test]
+    fn error_event_is_surfaced() {
+        let raw = "event: error\ndata: {\"error\":{\"message\":\"overloaded\"}}\n\n";
+        assert_eq!(decode_all(raw).unwrap_err(), "LLM API 流错误: overloaded");
+    }
+}
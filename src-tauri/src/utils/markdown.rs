@@ -0,0 +1,115 @@
+//! 保存前整理模型回复的 Markdown 文本
+//!
+//! 用户中途停止生成时，回复可能停在代码块或行内代码中间，未闭合的 ``` 会让前端
+//! 把之后的全部内容渲染成代码。这里只补全结尾处明确未闭合的围栏和行内反引号，不改动其他内容。
+
+/// 围栏代码块的开始标记：字符（` 或 ~）与长度
+#[derive(Clone, Copy, PartialEq, Debug)]
+struct Fence {
+    ch: char,
+    len: usize,
+}
+
+/// 行首（允许至多 3 个空格缩进）的围栏标记
+fn fence_marker(line: &str) -> Option<(Fence, &str)> {
+    let indent = line.len() - line.trim_start_matches(' ').len();
+    if indent > 3 {
+        return None;
+    }
+    let rest = &line[indent..];
+    let ch = rest.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = rest.chars().take_while(|c| *c == ch).count();
+    (len >= 3).then(|| (Fence { ch, len }, &rest[len..]))
+}
+
+/// 扫描全文，返回结尾仍未闭合的围栏
+fn open_fence(text: &str) -> Option<Fence> {
+    let mut open: Option<Fence> = None;
+    for line in text.lines() {
+        let Some((fence, info)) = fence_marker(line) else {
+            continue;
+        };
+        match open {
+            // 闭合标记：同字符、长度不短于开始标记，且后面没有其他内容
+            Some(current) => {
+                if fence.ch == current.ch && fence.len >= current.len && info.trim().is_empty() {
+                    open = None;
+                }
+            }
+            // 反引号围栏的 info string 不能再含反引号
+            None if fence.ch == '`' && info.contains('`') => {}
+            None => open = Some(fence),
+        }
+    }
+    open
+}
+
+/// 最后一段（空行之后）中未闭合的行内代码反引号数量
+fn open_inline_ticks(paragraph: &str) -> Option<usize> {
+    let mut open: Option<usize> = None;
+    let mut chars = paragraph.chars().peekable();
+    let mut prev = None;
+    while let Some(c) = chars.next() {
+        if c != '`' {
+            prev = Some(c);
+            continue;
+        }
+        let mut len = 1;
+        while chars.peek() == Some(&'`') {
+            chars.next();
+            len += 1;
+        }
+        match open {
+            Some(n) if n == len => open = None,
+            Some(_) => {}
+            None if prev == Some('\\') => {}
+            None => open = Some(len),
+        }
+        prev = Some('`');
+    }
+    open
+}
+
+/// 补全结尾处未闭合的代码围栏与行内代码，用于保存最终回复
+pub fn finalize_display_text(raw: &str) -> String {
+    let mut text = raw.to_string();
+    if let Some(fence) = open_fence(&text) {
+        if !text.ends_with('\n') {
+            text.push('\n');
+        }
+        text.push_str(&fence.ch.to_string().repeat(fence.len));
+        return text;
+    }
+    let paragraph = text
+        .rsplit_once("\n\n")
+        .map_or(text.as_str(), |(_, last)| last);
+    if let Some(n) = open_inline_ticks(paragraph).filter(|n| *n < 3) {
+        text.push_str(&"`".repeat(n));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closes_code_block_stopped_mid_stream() {
+        let raw = "示例如下：\n\n```python\ndef main():\n    print(";
+        assert_eq!(
+            finalize_display_text(raw),
+            "示例如下：\n\n```python\ndef main():\n    print(\n```"
+        );
+        let tilde = "~~~~\ncode\n```\n";
+        assert_eq!(finalize_display_text(tilde), "~~~~\ncode\n```\n~~~~");
+    }
+
+    #[test]
+    fn leaves_balanced_text_untouched() {
+        let done = "```rust\nfn main() {}\n```\n使用 `cargo run` 运行";
+        assert_eq!(finalize_display_text(done), done);
+        assert_eq!(finalize_display_text("转义 \\` 不算"), "转义 \\` 不算");
+        assert_eq!(finalize_display_text("调用 `foo(bar"), "调用 `foo(bar`");
+        assert_eq!(finalize_display_text("`a`\n\n``b"), "`a`\n\n``b``");
+    }
+}
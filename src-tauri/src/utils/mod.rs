@@ -1,2 +1,12 @@
+pub mod chunking;
+pub mod csv_digest;
 pub mod file_parser;
+pub mod gguf;
+pub mod html_text;
+pub mod llm_stream;
+pub mod markdown;
+pub mod sse;
+pub mod text_encoding;
+pub mod text_limit;
+pub mod tokens;
 pub use file_parser::process_file_content;
@@ -0,0 +1,166 @@
+//! Server-Sent Events 增量解析器
+//!
+//! 按 WHATWG 规范处理字段：`:` 开头为注释（心跳 `: keep-alive` / `: ping`），
+//! `event:` / `id:` 记录到当前事件，`data:` 多行以 `\n` 拼接，空行分发事件。
+//! 以字节缓冲，跨 chunk 截断的多字节 UTF-8 字符不会被替换成 U+FFFD。
+//!
+//! 兼容不规范的服务端 / 代理：事件之间只有单个换行、没有空行时，若已累积的 data
+//! 本身是完整的 JSON（或 `[DONE]`），遇到新的 `data:` / `event:` 行先分发它，而不是拼接成一条。
+
+/// 一条完整的 SSE 事件
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SseEvent {
+    /// `event:` 字段；未指定时为 None（规范中等价于 "message"）
+    pub event: Option<String>,
+    /// 最近一次 `id:` 字段（规范要求跨事件保留）
+    pub id: Option<String>,
+    /// 所有 `data:` 行按 `\n` 拼接后的内容
+    pub data: String,
+}
+
+/// 增量解析器：不断 `push` 网络分块，取出已完整的事件
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buf: Vec<u8>,
+    event: Option<String>,
+    last_id: Option<String>,
+    data: Option<String>,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 追加一个网络分块，返回其中已完整分发的事件
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buf.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        while let Some(pos) = self.buf.iter().position(|&b| b == b'\n') {
+            let mut line: Vec<u8> = self.buf.drain(..=pos).collect();
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+            let line = String::from_utf8_lossy(&line);
+            if let Some(ev) = self.process_line(&line) {
+                events.push(ev);
+            }
+        }
+        events
+    }
+
+    /// 流结束：处理缓冲中最后一行，并分发未以空行结尾的事件
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buf.is_empty() {
+            let rest = std::mem::take(&mut self.buf);
+            let line = String::from_utf8_lossy(&rest);
+            let line = line.trim_end_matches('\r');
+            if let Some(ev) = self.process_line(line) {
+                return Some(ev);
+            }
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        // 注释 / 心跳
+        if line.starts_with(':') {
+            return None;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((f, v)) => (f, v.strip_prefix(' ').unwrap_or(v)),
+            None => (line, ""),
+        };
+        let flushed = if matches!(field, "data" | "event") && self.pending_is_complete() {
+            self.dispatch()
+        } else {
+            None
+        };
+        match field {
+            "data" => match &mut self.data {
+                Some(data) => {
+                    data.push('\n');
+                    data.push_str(value);
+                }
+                None => self.data = Some(value.to_string()),
+            },
+            "event" => self.event = Some(value.to_string()),
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            // retry 与未知字段按规范忽略
+            _ => {}
+        }
+        flushed
+    }
+
+    /// 已累积的 data 是否已是一条完整的负载（JSON 对象 / 数组或 `[DONE]`）
+    fn pending_is_complete(&self) -> bool {
+        let Some(data) = self.data.as_deref().map(str::trim) else {
+            return false;
+        };
+        data == "[DONE]"
+            || (data.starts_with(['{', '['])
+                && serde_json::from_str::<serde::de::IgnoredAny>(data).is_ok())
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = self.event.take();
+        let data = self.data.take()?;
+        Some(SseEvent {
+            event,
+            id: self.last_id.clone(),
+            data,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_comments_and_keeps_event_names() {
+        let mut p = SseParser::new();
+        let events = p.push(
+            b": keep-alive\n\nevent: content_block_delta\nid: 7\ndata: {\"a\":1}\n\n: ping\n\ndata: [DONE]\n\n",
+        );
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].event.as_deref(), Some("content_block_delta"));
+        assert_eq!(events[0].id.as_deref(), Some("7"));
+        assert_eq!(events[0].data, "{\"a\":1}");
+        assert_eq!(events[1].event, None);
+        assert_eq!(events[1].id.as_deref(), Some("7"));
+        assert_eq!(events[1].data, "[DONE]");
+    }
+
+    #[test]
+    fn joins_multiline_data_and_handles_crlf() {
+        let mut p = SseParser::new();
+        let events = p.push(b"data: a\r\ndata:b\r\n\r\n");
+        assert_eq!(events, vec![SseEvent { event: None, id: None, data: "a\nb".into() }]);
+    }
+
+    #[test]
+    fn splits_complete_json_events_separated_by_single_newline() {
+        let mut p = SseParser::new();
+        let mut events =
+            p.push(b"data: {\"a\":1}\nevent: delta\ndata: {\"b\":\ndata: 2}\ndata: [DONE]\n");
+        events.extend(p.finish());
+        let data: Vec<_> = events.iter().map(|e| e.data.as_str()).collect();
+        assert_eq!(data, vec!["{\"a\":1}", "{\"b\":\n2}", "[DONE]"]);
+        assert_eq!(events[0].event, None);
+        assert_eq!(events[1].event.as_deref(), Some("delta"));
+    }
+
+    #[test]
+    fn reassembles_split_utf8_and_flushes_on_finish() {
+        let bytes = "data: 你好".as_bytes();
+        let mut p = SseParser::new();
+        assert!(p.push(&bytes[..8]).is_empty());
+        assert!(p.push(&bytes[8..]).is_empty());
+        assert_eq!(p.finish().map(|e| e.data), Some("你好".to_string()));
+    }
+}
@@ -0,0 +1,105 @@
+//! 文本文件编码识别
+//!
+//! 附件中的文本不一定是 UTF-8：GBK / GB18030 源码、Big5 文档、Shift-JIS 日志都很常见。
+//! 识别顺序：
+//! 1. BOM（UTF-8 / UTF-16LE / UTF-16BE）
+//! 2. 无 BOM 但高 / 低字节大量为 0 的 UTF-16
+//! 3. 合法 UTF-8
+//! 4. 交给 chardetng 按字节分布猜测（GBK、Big5、Shift-JIS、EUC-KR、Windows-1252 等）
+
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+
+/// 判断无 BOM UTF-16 时检查的字节数
+const UTF16_SNIFF_BYTES: usize = 4096;
+
+/// 无 BOM 的 UTF-16 判断：ASCII 为主的 UTF-16 文本中，每个码元有一半字节为 0
+fn sniff_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(UTF16_SNIFF_BYTES) & !1];
+    if sample.len() < 4 {
+        return None;
+    }
+    let units = sample.len() / 2;
+    let even_zeros = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd_zeros = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|b| **b == 0)
+        .count();
+    if odd_zeros * 10 >= units * 3 && even_zeros * 10 < units {
+        Some(UTF_16LE)
+    } else if even_zeros * 10 >= units * 3 && odd_zeros * 10 < units {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// 识别字节内容的编码
+pub fn detect_encoding(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+    if let Some(encoding) = sniff_utf16(bytes) {
+        return encoding;
+    }
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+/// 按识别出的编码解码，返回 (文本, 编码名称)；BOM 会被去掉
+pub fn decode_text(bytes: &[u8]) -> (String, &'static str) {
+    let encoding = detect_encoding(bytes);
+    let (text, actual, _) = encoding.decode(bytes);
+    (text.into_owned(), actual.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZH_HANS: &str =
+        "配置文件位于系统配置目录下，便携模式下位于数据目录。保存前校验路径可写，重启后生效。";
+    const ZH_HANT: &str = "設定檔位於系統設定目錄下，可攜模式下位於資料目錄。儲存前檢查路徑是否可寫入，重新啟動後生效。";
+    const JA: &str = "設定ファイルはシステムの設定ディレクトリにあります。保存する前に書き込み可能かどうかを確認してください。";
+
+    fn encode(text: &str, encoding: &'static Encoding) -> Vec<u8> {
+        let (bytes, _, unmappable) = encoding.encode(text);
+        assert!(!unmappable);
+        bytes.into_owned()
+    }
+
+    #[test]
+    fn detects_legacy_cjk_encodings() {
+        for (text, encoding) in [
+            (ZH_HANS, encoding_rs::GBK),
+            (ZH_HANT, encoding_rs::BIG5),
+            (JA, encoding_rs::SHIFT_JIS),
+        ] {
+            let (decoded, name) = decode_text(&encode(text, encoding));
+            assert_eq!(decoded, text, "{}", encoding.name());
+            assert_eq!(name, encoding.name());
+        }
+        assert_eq!(
+            decode_text("普通 UTF-8 文本".as_bytes()),
+            ("普通 UTF-8 文本".into(), "UTF-8")
+        );
+    }
+
+    #[test]
+    fn detects_utf16_with_and_without_bom() {
+        let text = "log line 1: 启动完成\nlog line 2: ok\n";
+        let units: Vec<u8> = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let with_bom = [&[0xFF, 0xFE][..], &units].concat();
+        assert_eq!(decode_text(&with_bom), (text.into(), "UTF-16LE"));
+        assert_eq!(decode_text(&units), (text.into(), "UTF-16LE"));
+
+        let be: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(decode_text(&be), (text.into(), "UTF-16BE"));
+    }
+}
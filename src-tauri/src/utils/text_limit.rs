@@ -0,0 +1,116 @@
+//! 超大附件的读取与截断
+//!
+//! 几百 MB 的日志整体读进内存再塞进提示词，既占内存又会撑爆上下文：
+//! - 从磁盘读取的字节数不超过调用方给出的上限（最多 [`MAX_READ_BYTES`]）；
+//!   文件更大时只读开头与结尾各一半，中间直接跳过
+//! - 提取出的文本超过上限（默认 [`DEFAULT_MAX_EXTRACTED_BYTES`]）时，
+//!   同样保留开头与结尾各一半，中间替换为 `[... N KB omitted ...]`
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// 单个文件最多从磁盘读取的字节数（硬上限，不受设置影响）
+pub const MAX_READ_BYTES: u64 = 16 * 1024 * 1024;
+/// 未配置「附件提取上限」时的默认值
+pub const DEFAULT_MAX_EXTRACTED_BYTES: usize = 256 * 1024;
+
+/// 省略标记，`bytes` 为省略的字节数（向上取整为 KB）
+pub fn omitted_marker(bytes: u64) -> String {
+    format!("[... {} KB omitted ...]", bytes.div_ceil(1024))
+}
+
+/// 用省略标记连接开头与结尾
+pub fn join_omitted(head: &str, omitted: u64, tail: &str) -> String {
+    format!("{}\n\n{}\n\n{}", head, omitted_marker(omitted), tail)
+}
+
+/// 按上限读取的文件内容：文件不超过上限时 `tail` 为空、`omitted` 为 0
+pub struct HeadTail {
+    pub head: Vec<u8>,
+    pub tail: Vec<u8>,
+    /// 跳过未读的字节数
+    pub omitted: u64,
+}
+
+/// 最多读取 `max_bytes` 字节：超出时读开头与结尾各一半
+pub fn read_head_tail(path: &Path, max_bytes: u64) -> std::io::Result<HeadTail> {
+    let mut file = File::open(path)?;
+    let len = file.metadata()?.len();
+    if len <= max_bytes {
+        let mut head = Vec::with_capacity(len as usize);
+        // 读取期间文件可能仍在增长（如正在写入的日志），同样按上限截止
+        (&mut file).take(max_bytes).read_to_end(&mut head)?;
+        return Ok(HeadTail {
+            head,
+            tail: Vec::new(),
+            omitted: 0,
+        });
+    }
+
+    let half = max_bytes / 2;
+    let mut head = Vec::with_capacity(half as usize);
+    (&mut file).take(half).read_to_end(&mut head)?;
+    file.seek(SeekFrom::Start(len - half))?;
+    let mut tail = Vec::with_capacity(half as usize);
+    file.take(half).read_to_end(&mut tail)?;
+    Ok(HeadTail {
+        head,
+        tail,
+        omitted: len - half * 2,
+    })
+}
+
+/// 文本超过 `max_bytes` 时保留开头与结尾各约一半（按字符边界切分），中间替换为省略标记；
+/// 未超出时返回 None
+pub fn truncate_middle(text: &str, max_bytes: usize) -> Option<String> {
+    if text.len() <= max_bytes {
+        return None;
+    }
+    let half = max_bytes / 2;
+    let mut head_end = half;
+    while !text.is_char_boundary(head_end) {
+        head_end -= 1;
+    }
+    let mut tail_start = text.len() - half;
+    while !text.is_char_boundary(tail_start) {
+        tail_start += 1;
+    }
+    Some(join_omitted(
+        &text[..head_end],
+        (tail_start - head_end) as u64,
+        &text[tail_start..],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_head_and_tail_around_marker() {
+        assert_eq!(truncate_middle("short", 10), None);
+
+        let text = format!(
+            "{}{}{}",
+            "开头".repeat(100),
+            "x".repeat(4096),
+            "结尾".repeat(100)
+        );
+        let out = truncate_middle(&text, 1200).unwrap();
+        assert!(out.starts_with("开头开头"));
+        assert!(out.ends_with("结尾结尾"));
+        assert!(out.contains("\n\n[... 4 KB omitted ...]\n\n"));
+        assert!(out.len() < 1300);
+
+        let path = std::env::temp_dir().join(format!("aio-head-tail-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, b"0123456789abcdefghij").unwrap();
+        let read = read_head_tail(&path, 8).unwrap();
+        assert_eq!(
+            (&read.head[..], &read.tail[..], read.omitted),
+            (&b"0123"[..], &b"ghij"[..], 12)
+        );
+        assert_eq!(read_head_tail(&path, 64).unwrap().head.len(), 20);
+        let _ = std::fs::remove_file(&path);
+    }
+}
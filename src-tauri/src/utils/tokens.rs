@@ -0,0 +1,236 @@
+//! 粗略 token 估算与按上下文预算裁剪消息
+//!
+//! 不依赖具体 tokenizer：CJK 字符按 1 token/字，其他字符按 4 字符/token 估算，
+//! 误差在 ±20% 以内，足以用于「发送前是否会超出上下文窗口」的预判。
+
+use serde_json::Value;
+
+/// 每条消息的结构开销（role / 分隔符等）
+const PER_MESSAGE_OVERHEAD: usize = 4;
+/// 单张图片按 OpenAI high-detail 512px tile 的典型值估算
+const IMAGE_TOKENS: usize = 765;
+/// 为模型输出预留的 token 数上限（实际取 min(此值, 上下文 1/4)）
+const RESPONSE_TOKEN_RESERVE: usize = 1024;
+
+/// 是否按 1 token/字估算的 CJK 字符
+pub(crate) fn is_cjk(c: char) -> bool {
+    matches!(c as u32,
+        0x3040..=0x30FF     // 日文假名
+        | 0x3400..=0x4DBF   // CJK 扩展 A
+        | 0x4E00..=0x9FFF   // CJK 统一汉字
+        | 0xAC00..=0xD7AF   // 韩文音节
+        | 0xF900..=0xFAFF   // CJK 兼容汉字
+        | 0xFF00..=0xFFEF)  // 全角符号
+}
+
+/// 逐字符累计的 token 估算（分块时按字符切分也用它，与 [`estimate_text_tokens`] 口径一致）
+#[derive(Default, Clone, Copy)]
+pub(crate) struct TokenCounter {
+    cjk: usize,
+    other: usize,
+}
+
+impl TokenCounter {
+    pub(crate) fn push(&mut self, c: char) {
+        if is_cjk(c) {
+            self.cjk += 1;
+        } else {
+            self.other += 1;
+        }
+    }
+
+    /// 加上 `c` 之后的计数
+    pub(crate) fn with(mut self, c: char) -> Self {
+        self.push(c);
+        self
+    }
+
+    pub(crate) fn tokens(&self) -> usize {
+        self.cjk + self.other.div_ceil(4)
+    }
+}
+
+/// 估算一段文本的 token 数
+pub fn estimate_text_tokens(text: &str) -> usize {
+    let mut counter = TokenCounter::default();
+    text.chars().for_each(|c| counter.push(c));
+    counter.tokens()
+}
+
+/// 估算一条 OpenAI 格式消息（`{role, content, tool_calls?}`）的 token 数
+pub fn estimate_message_tokens(message: &Value) -> usize {
+    let content_tokens = match message.get("content") {
+        Some(Value::String(text)) => estimate_text_tokens(text),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .map(|part| match part.get("type").and_then(Value::as_str) {
+                Some("text") => part
+                    .get("text")
+                    .and_then(Value::as_str)
+                    .map(estimate_text_tokens)
+                    .unwrap_or(0),
+                Some("image_url") => IMAGE_TOKENS,
+                _ => 0,
+            })
+            .sum(),
+        _ => 0,
+    };
+    let tool_call_tokens = message
+        .get("tool_calls")
+        .map(|calls| estimate_text_tokens(&calls.to_string()))
+        .unwrap_or(0);
+    PER_MESSAGE_OVERHEAD + content_tokens + tool_call_tokens
+}
+
+/// 估算整段消息列表的 token 数
+pub fn estimate_messages_tokens(messages: &[Value]) -> usize {
+    messages.iter().map(estimate_message_tokens).sum()
+}
+
+/// 上下文窗口扣除输出预留后，可用于输入的 token 预算
+pub fn input_budget(context_length: usize) -> usize {
+    context_length.saturating_sub(RESPONSE_TOKEN_RESERVE.min(context_length / 4))
+}
+
+fn role_of(message: &Value) -> &str {
+    message.get("role").and_then(Value::as_str).unwrap_or("")
+}
+
+/// 受保护的前缀长度：开头连续的 system 消息及其后 `pinned` 条置顶消息
+fn protected_prefix(messages: &[Value], pinned: usize) -> usize {
+    let system_prefix = messages
+        .iter()
+        .take_while(|m| role_of(m) == "system")
+        .count();
+    (system_prefix + pinned).min(messages.len())
+}
+
+/// 裁剪消息直到估算 token 数不超过 `budget`，返回被丢弃的消息条数。
+///
+/// 规则：
+/// - 开头连续的 system 消息及紧随其后的 `pinned` 条置顶消息永不丢弃
+/// - 最后一条 user 消息（及其之后的消息）永不丢弃
+/// - 从最旧的非 system 消息开始丢弃；丢弃后若开头残留孤立的 tool 消息一并丢弃，
+///   避免 provider 因 tool 消息缺少对应 assistant.tool_calls 而报 400
+///
+/// 无法裁剪到预算内时（受保护消息本身就超预算），尽量裁剪后返回，由调用方再次校验。
+pub fn trim_to_budget(messages: &mut Vec<Value>, budget: usize, pinned: usize) -> usize {
+    let system_prefix = protected_prefix(messages, pinned);
+    let last_user = messages
+        .iter()
+        .rposition(|m| role_of(m) == "user")
+        .unwrap_or(messages.len().saturating_sub(1));
+
+    let mut total = estimate_messages_tokens(messages);
+    let mut dropped = 0usize;
+    // 可丢弃区间为 [system_prefix, last_user - dropped)
+    while total > budget && system_prefix + dropped < last_user {
+        let removed = messages.remove(system_prefix);
+        total -= estimate_message_tokens(&removed);
+        dropped += 1;
+    }
+    while system_prefix < messages.len()
+        && system_prefix + dropped < last_user
+        && role_of(&messages[system_prefix]) == "tool"
+    {
+        messages.remove(system_prefix);
+        dropped += 1;
+    }
+    dropped
+}
+
+/// 只保留开头连续的 system 消息、其后 `pinned` 条置顶消息与最近 `limit` 条其他消息（至少保留最后一条），
+/// 返回被丢弃的消息条数。与 [`trim_to_budget`] 一样，保留区间开头的孤立 tool 消息会一并丢弃
+pub fn trim_to_count(messages: &mut Vec<Value>, limit: usize, pinned: usize) -> usize {
+    let system_prefix = protected_prefix(messages, pinned);
+    let history = messages.len() - system_prefix;
+    let mut drop = history.saturating_sub(limit.max(1));
+    while system_prefix + drop < messages.len().saturating_sub(1)
+        && role_of(&messages[system_prefix + drop]) == "tool"
+    {
+        drop += 1;
+    }
+    messages.drain(system_prefix..system_prefix + drop);
+    drop
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn estimates_cjk_and_latin_text() {
+        assert_eq!(estimate_text_tokens(""), 0);
+        assert_eq!(estimate_text_tokens("abcd"), 1);
+        assert_eq!(estimate_text_tokens("abcde"), 2);
+        assert_eq!(estimate_text_tokens("你好"), 2);
+    }
+
+    #[test]
+    fn trim_keeps_system_and_latest_user() {
+        let long = "x".repeat(4000);
+        let mut messages = vec![
+            json!({ "role": "system", "content": "sys" }),
+            json!({ "role": "user", "content": long }),
+            json!({ "role": "assistant", "content": long }),
+            json!({ "role": "user", "content": "latest" }),
+        ];
+        let dropped = trim_to_budget(&mut messages, 100, 0);
+        assert_eq!(dropped, 2);
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages[1]["content"], "latest");
+    }
+
+    #[test]
+    fn count_limit_keeps_system_prefix() {
+        let mut messages = vec![
+            json!({ "role": "system", "content": "global" }),
+            json!({ "role": "system", "content": "assistant" }),
+            json!({ "role": "user", "content": "q1" }),
+            json!({ "role": "assistant", "content": "", "tool_calls": [] }),
+            json!({ "role": "tool", "content": "result" }),
+            json!({ "role": "assistant", "content": "a1" }),
+            json!({ "role": "user", "content": "q2" }),
+        ];
+        assert_eq!(trim_to_count(&mut messages.clone(), 10, 0), 0);
+        // 最近 3 条的开头是孤立的 tool 结果，一并丢弃
+        assert_eq!(trim_to_count(&mut messages, 3, 0), 3);
+        let roles: Vec<_> = messages.iter().map(|m| m["role"].as_str().unwrap()).collect();
+        assert_eq!(roles, ["system", "system", "assistant", "user"]);
+    }
+
+    #[test]
+    fn trim_drops_orphaned_tool_messages() {
+        let long = "x".repeat(4000);
+        let mut messages = vec![
+            json!({ "role": "assistant", "content": long, "tool_calls": [] }),
+            json!({ "role": "tool", "content": "result" }),
+            json!({ "role": "user", "content": "latest" }),
+        ];
+        let dropped = trim_to_budget(&mut messages, 100, 0);
+        assert_eq!(dropped, 2);
+        assert_eq!(messages[0]["content"], "latest");
+    }
+
+    #[test]
+    fn pinned_messages_survive_trimming() {
+        let long = "x".repeat(4000);
+        let messages = vec![
+            json!({ "role": "system", "content": "sys" }),
+            json!({ "role": "user", "content": "pinned" }),
+            json!({ "role": "user", "content": long }),
+            json!({ "role": "assistant", "content": long }),
+            json!({ "role": "user", "content": "latest" }),
+        ];
+        let mut by_budget = messages.clone();
+        assert_eq!(trim_to_budget(&mut by_budget, 100, 1), 2);
+        let contents: Vec<_> = by_budget.iter().map(|m| m["content"].as_str().unwrap()).collect();
+        assert_eq!(contents, ["sys", "pinned", "latest"]);
+
+        let mut by_count = messages;
+        assert_eq!(trim_to_count(&mut by_count, 1, 1), 2);
+        assert_eq!(by_count, by_budget);
+    }
+}
@@ -137,12 +137,15 @@ const LocalEngineSection: Component = () => {
                 await invoke('save_app_config', { config: { ...currentCfg, localModelPath: localModelPath() } });
                 setLocalSaveStatus('正在启动本地引擎...');
                 const engine = ENGINE_OPTIONS[0];
-                const serverUrl: string = await invoke('start_local_server', {
+                const server: { url: string; apiKey?: string | null } = await invoke('start_local_server', {
                     modelPath: localModelPath(),
                     port: 8080,
                     gpuLayers: 99,
                     engineType: engine.id,
                 });
+                const serverUrl = server.url;
+                // 每次启动生成新 key；未启用鉴权时保留旧占位值
+                const serverKey = server.apiKey || 'local-no-key';
                 setIsLocalRunning(true);
                 setLocalSaveStatus('本地引擎已就绪');
                 const fullPath = localModelPath();
@@ -152,14 +155,15 @@ const LocalEngineSection: Component = () => {
                     model_id: modelName,
                     owned_by: engine.ownedBy,
                     api_url: serverUrl,
-                    api_key: 'local-no-key',
+                    api_key: serverKey,
                     engine_type: engine.id,
                 };
-                if (!localActivatedModels().some(m => m.model_id === modelName && m.api_url === serverUrl)) {
-                    const newList = [...localActivatedModels(), newLocal];
-                    setLocalActivatedModels(newList);
-                    await invoke('save_activated_models', { models: newList });
-                }
+                const sameEntry = (m: LocalModel) => m.model_id === modelName && m.api_url === serverUrl;
+                const newList = localActivatedModels().some(sameEntry)
+                    ? localActivatedModels().map(m => (sameEntry(m) ? { ...m, api_key: serverKey } : m))
+                    : [...localActivatedModels(), newLocal];
+                setLocalActivatedModels(newList);
+                await invoke('save_activated_models', { models: newList });
                 setLocalSaveStatus(`本地模型 ${modelName} 已启动 (${engine.name})`);
             } catch (err) {
                 alert('启动失败: ' + err);
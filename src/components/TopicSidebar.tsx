@@ -1,5 +1,6 @@
 import { Component, For, Show, createSignal, onMount, onCleanup } from 'solid-js';
 import { Portal } from 'solid-js/web';
+import { invoke } from '@tauri-apps/api/core';
 import {
     Assistant, Topic, datas, setDatas, currentTopicId, setCurrentTopicId, saveSingleAssistantToBackend,
     requestRenameTopic
@@ -45,7 +46,8 @@ const TopicSidebar: Component<TopicSidebarProps> = (props) => {
             name: newName,
             renamed: true
         });
-        await saveSingleAssistantToBackend(asstId);
+        // 只更新话题名称，不重写整个助手与消息
+        await invoke('rename_topic', { id: topicId, name: newName });
         props.setEditingTopicId(null);
     };
 
@@ -1,10 +1,10 @@
-import { Component, createSignal, onMount, onCleanup, createEffect } from 'solid-js';
+import { Component, createSignal, onMount, onCleanup, createEffect, untrack } from 'solid-js';
 import { convertFileSrc, invoke } from '@tauri-apps/api/core';
 import { listen } from '@tauri-apps/api/event';
 import {
   datas, setDatas, currentAssistantId, setCurrentAssistantId, currentTopicId, setCurrentTopicId,
   saveSingleAssistantToBackend, Assistant, Topic, Message, PendingAttachment, StoredAttachment, selectedModel, setSelectedModel,
-  resolveAssistantModel, modelKey, reasoningLevel,
+  resolveAssistantModel, allAvailableModels, modelKey, reasoningLevel,
   pendingRenameRequest, setPendingRenameRequest,
   mcpServers, mcpServerStatus, TOOL_CALL_MAX_ROUNDS, resolveAssistantSkills,
 } from '../store/store';
@@ -146,7 +146,7 @@ const ChatPage: Component = () => {
     const fileName = filePath.split(/[\\/]/).pop() || '未知文件';
     const ext = (fileName.split('.').pop() || '').toLowerCase();
     const ALLOWED_IMG = ['png', 'jpg', 'jpeg', 'webp'];
-    const ALLOWED_DOC = ['pdf', 'docx', 'pptx', 'txt', 'md', 'json', 'csv', 'log', 'xml', 'yaml', 'yml', 'ini', 'tsv'];
+    const ALLOWED_DOC = ['pdf', 'docx', 'pptx', 'txt', 'md', 'json', 'csv', 'log', 'xml', 'yaml', 'yml', 'ini', 'tsv', 'html', 'htm'];
     const isImg = fileType === 'image' || ALLOWED_IMG.includes(ext);
     const isDoc = ALLOWED_DOC.includes(ext);
     if (!isImg && !isDoc) {
@@ -185,13 +185,19 @@ const ChatPage: Component = () => {
       console.log("正在通过 SQLite 触发历史总结...");
       // 取前 15 条消息进行总结（保留后 10 条保持上下文连贯性）
       const messagesToSummarize = topic.history.slice(0, 15);
+      // 流式生成过程中后端会把部分摘要写回数据库，这里记录最新内容以便中断时同步到界面
+      let partialSummary = '';
+      const unlisten = await listen<{ topic_id: string | null; summary: string }>('llm-summary-progress', (event) => {
+        if (event.payload.topic_id === topic.id) partialSummary = event.payload.summary;
+      });
       try {
         // 调用后端 LLM 接口生成摘要
         const newSummarySnippet = await invoke<string>('summarize_history', {
           apiUrl: currentMdl.api_url,
           apiKey: currentMdl.api_key,
           model: currentMdl.model_id,
-          messages: messagesToSummarize
+          messages: messagesToSummarize,
+          topicId: topic.id
         });
 
         // 重新获取最新话题状态（防止期间已切换）
@@ -215,6 +221,12 @@ const ChatPage: Component = () => {
         await saveSingleAssistantToBackend(currentAssistantId()!);
       } catch (e) {
         console.error("生成总结失败:", e);
+        // 连接中断：数据库中已是部分摘要，同步到界面（历史不裁剪），避免之后保存时被旧摘要覆盖
+        if (partialSummary) {
+          setDatas('assistants', a => a.id === currentAssistantId(), 'topics', t => t.id === topic.id, 'summary', partialSummary);
+        }
+      } finally {
+        unlisten();
       }
     }
   };
@@ -564,9 +576,8 @@ const ChatPage: Component = () => {
 
     try {
       await invoke('call_llm_stream', {
-        apiUrl: currentMdl.api_url,
-        apiKey: currentMdl.api_key,
-        model: currentMdl.model_id,
+        primary: { apiUrl: currentMdl.api_url, apiKey: currentMdl.api_key, model: currentMdl.model_id },
+        modelKey: modelKey(currentMdl),
         assistantId: asstId,
         topicId,
         messages: messagesForAI,
@@ -683,14 +694,15 @@ const ChatPage: Component = () => {
 
       // 调用 Tauri 后端流式接口（非阻塞，通过事件监听接收数据）
       await invoke('call_llm_stream', {
-        apiUrl: currentMdl.api_url,
-        apiKey: currentMdl.api_key,
-        model: currentMdl.model_id,
+        primary: { apiUrl: currentMdl.api_url, apiKey: currentMdl.api_key, model: currentMdl.model_id },
+        modelKey: modelKey(currentMdl),
         assistantId: asstId,
         topicId: topicId,
         messages: messagesForAI,
         tools: mcpTools.length > 0 ? mcpTools : null,
       });
+      // 请求通过校验后后端写入 topics.last_model_id，这里保持本地状态一致
+      setDatas('assistants', a => a.id === asstId, 'topics', t => t.id === topicId, 'lastModelId', modelKey(currentMdl));
 
     } catch (err) {
       alert(err); // 调用失败时提示错误
@@ -932,6 +944,20 @@ const ChatPage: Component = () => {
     }
   });
 
+  /**
+   * 话题记住上次使用的模型：切换到话题时，若其 lastModelId 对应的模型仍可用则预选该模型。
+   * 不跟踪 selectedModel，避免用户在话题内手动切换模型后被改回。
+   */
+  createEffect(() => {
+    const last = activeTopic()?.lastModelId;
+    const current = untrack(selectedModel);
+    if (!last || (current && modelKey(current) === last)) return;
+    // 兼容早期只记录 model_id 的话题
+    const model = allAvailableModels().find(m => modelKey(m) === last)
+      ?? allAvailableModels().find(m => m.model_id === last);
+    if (model) setSelectedModel(model);
+  });
+
   return (
     <div class="h-full flex gap-[3px] p-[1px]" style="background: transparent;"
       classList={{ 'is-resizing': isResizing() }} ref={chatPageRef}>
@@ -16,6 +16,7 @@ export interface Message {
     displayFiles?: AttachmentMeta[];    // 消息关联的附件元数据
     displayText?: string;               // 用于界面显示的纯文本内容（已脱敏或解析处理）
     reasoning?: string;                 // 模型原生思维链（reasoning_content），仅 assistant 消息可能携带
+    status?: 'pending' | 'complete' | 'error';  // 消息状态，失败的回复可通过 retry_message 重新生成
 }
 
 export interface AttachmentMeta {
@@ -50,6 +51,8 @@ export interface Topic {
      * - 缺省：兼容旧数据，等价于 false
      */
     renamed?: boolean;
+    /** 该话题最近一次生成回复所用的模型（modelKey 复合键，早期数据为 model_id）；切换到该话题时据此预选模型，缺省表示沿用助手 / 全局模型 */
+    lastModelId?: string;
 }
 
  /* 助手接口，定义 AI 助手的数据结构 */